@@ -0,0 +1,58 @@
+// a proxy printing the RESP frames between the clients and a server:
+//
+//   cargo run --example ruis-dump -- 127.0.0.1:6380 127.0.0.1:6379 [--hex]
+//
+// then point the clients at 127.0.0.1:6380.
+
+use std::env;
+use std::io::{self, BufReader};
+use std::net::{TcpListener, TcpStream};
+use std::process;
+use std::sync::Arc;
+use std::thread;
+
+use ruis::dump::{Direction, DumpReader, FrameSink, TextSink};
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let hex = args.iter().any(|a| a == "--hex");
+    let addrs: Vec<&String> = args.iter().filter(|a| !a.starts_with("--")).collect();
+    if addrs.len() != 2 {
+        eprintln!("usage: ruis-dump <listen addr> <server addr> [--hex]");
+        process::exit(2);
+    }
+    let (listen, server) = (addrs[0].clone(), addrs[1].clone());
+
+    let sink: Arc<dyn FrameSink> = Arc::new(TextSink::new(io::stdout()).hexdump(hex));
+    let listener = TcpListener::bind(&listen).unwrap_or_else(|e| {
+        eprintln!("listen on {}: {}", listen, e);
+        process::exit(1);
+    });
+    for client in listener.incoming() {
+        let client = match client {
+            Ok(c) => c,
+            Err(e) => {
+                eprintln!("accept: {}", e);
+                continue;
+            },
+        };
+        let upstream = match TcpStream::connect(&server) {
+            Ok(s) => s,
+            Err(e) => {
+                eprintln!("connect to {}: {}", server, e);
+                continue;
+            },
+        };
+        pipe(client.try_clone().unwrap(), upstream.try_clone().unwrap(), Direction::Sent, sink.clone());
+        pipe(upstream, client, Direction::Received, sink.clone());
+    }
+}
+
+// copies from one side to the other until either closes.
+fn pipe(from: TcpStream, mut to: TcpStream, direction: Direction, sink: Arc<dyn FrameSink>) {
+    thread::spawn(move || {
+        let mut r = DumpReader::new(BufReader::new(from), direction, sink);
+        let _ = io::copy(&mut r, &mut to);
+        let _ = to.shutdown(std::net::Shutdown::Both);
+    });
+}
@@ -0,0 +1,53 @@
+/* the C ABI of ruis, built with: cargo build --release --features ffi
+ *
+ * the failed calls return NULL, and ruis_last_error() tells why. */
+
+#ifndef RUIS_H
+#define RUIS_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct RuisConnection RuisConnection;
+
+typedef enum {
+    RUIS_INT = 0,
+    RUIS_NIL = 1,
+    RUIS_BULK = 2,
+    RUIS_ARRAY = 3,
+    RUIS_ERROR = 4,
+} RuisValueKind;
+
+/* data and len are the bytes of RUIS_BULK and RUIS_ERROR, elements and len
+ * the items of RUIS_ARRAY, integer the value of RUIS_INT. */
+typedef struct RuisValue {
+    RuisValueKind kind;
+    int64_t integer;
+    uint8_t *data;
+    struct RuisValue *elements;
+    size_t len;
+} RuisValue;
+
+/* password might be NULL. */
+RuisConnection *ruis_connect(const char *addr, const char *password);
+
+/* the arguments are binary safe, given with their lengths. the error replies
+ * are returned as RUIS_ERROR values, NULL is for the connection errors. */
+RuisValue *ruis_execute(RuisConnection *conn, size_t argc, const uint8_t *const *argv, const size_t *argvlen);
+
+void ruis_value_free(RuisValue *value);
+
+void ruis_close(RuisConnection *conn);
+
+/* valid until the next failed call on the thread. */
+const char *ruis_last_error(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
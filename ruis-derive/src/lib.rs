@@ -0,0 +1,113 @@
+// the derives of ruis::hash::ToRedisHash and FromRedisHash, mapping the named
+// fields of a struct to the fields of a hash. a field is renamed with
+// #[ruis(rename = "name")], the Option fields are left out of the hash when
+// None.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{Data, DeriveInput, Error, Fields, LitStr, Type, parse_macro_input};
+
+struct Field {
+    ident: syn::Ident,
+    name: String,
+    optional: bool,
+}
+
+#[proc_macro_derive(ToRedisHash, attributes(ruis))]
+pub fn derive_to_redis_hash(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_to(&input).unwrap_or_else(Error::into_compile_error).into()
+}
+
+#[proc_macro_derive(FromRedisHash, attributes(ruis))]
+pub fn derive_from_redis_hash(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_from(&input).unwrap_or_else(Error::into_compile_error).into()
+}
+
+fn expand_to(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let pushes = fields(input)?.into_iter().map(|f| {
+        let (ident, name) = (f.ident, f.name);
+        if f.optional {
+            quote! {
+                if let ::std::option::Option::Some(ref value) = self.#ident {
+                    ::ruis::hash::push_field(&mut fields, #name, value)?;
+                }
+            }
+        } else {
+            quote! {
+                ::ruis::hash::push_field(&mut fields, #name, &self.#ident)?;
+            }
+        }
+    });
+    let ty = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::ruis::hash::ToRedisHash for #ty #ty_generics #where_clause {
+            fn to_redis_hash(&self) -> ::std::result::Result<::ruis::hash::HashFields, ::ruis::RuisError> {
+                let mut fields = ::std::vec::Vec::new();
+                #(#pushes)*
+                ::std::result::Result::Ok(fields)
+            }
+        }
+    })
+}
+
+fn expand_from(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let takes = fields(input)?.into_iter().map(|f| {
+        let (ident, name) = (f.ident, f.name);
+        quote! {
+            #ident: ::ruis::hash::take_field(&mut fields, #name)?,
+        }
+    });
+    let ty = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::ruis::hash::FromRedisHash for #ty #ty_generics #where_clause {
+            fn from_redis_hash(mut fields: ::std::collections::HashMap<::std::vec::Vec<u8>, ::std::vec::Vec<u8>>) -> ::std::result::Result<Self, ::ruis::RuisError> {
+                ::std::result::Result::Ok(#ty {
+                    #(#takes)*
+                })
+            }
+        }
+    })
+}
+
+fn fields(input: &DeriveInput) -> syn::Result<Vec<Field>> {
+    let named = match input.data {
+        Data::Struct(ref data) => match data.fields {
+            Fields::Named(ref named) => &named.named,
+            _ => return Err(Error::new_spanned(&input.ident, "only the structs with named fields map to a hash")),
+        },
+        _ => return Err(Error::new_spanned(&input.ident, "only the structs map to a hash")),
+    };
+    named.iter().map(|f| {
+        let ident = f.ident.clone().unwrap();
+        let mut name = ident.to_string();
+        for attr in f.attrs.iter().filter(|a| a.path().is_ident("ruis")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("rename") {
+                    name = meta.value()?.parse::<LitStr>()?.value();
+                    Ok(())
+                } else {
+                    Err(meta.error("unknown ruis attribute, expected rename"))
+                }
+            })?;
+        }
+        Ok(Field {
+            ident,
+            name,
+            optional: is_option(&f.ty),
+        })
+    }).collect()
+}
+
+// the type is only known by its name here, so an alias of Option is not
+// taken as one.
+fn is_option(ty: &Type) -> bool {
+    match ty {
+        Type::Path(p) if p.qself.is_none() => p.path.segments.last().is_some_and(|s| s.ident == "Option"),
+        _ => false,
+    }
+}
@@ -0,0 +1,49 @@
+use std::collections::HashMap;
+
+use ruis::connection::TcpConnection;
+use ruis::hash::{FromRedisHash, ToRedisHash};
+use ruis::testing::TestServer;
+use ruis::{FromRedisHash, RuisError, ToRedisHash};
+
+#[derive(Debug, PartialEq, ToRedisHash, FromRedisHash)]
+struct User {
+    name: String,
+    #[ruis(rename = "n")]
+    visits: i64,
+    email: Option<String>,
+}
+
+#[test]
+fn test_to_redis_hash() {
+    let user = User { name: "ruis".to_string(), visits: 3, email: None };
+    let fields = user.to_redis_hash().unwrap();
+    assert_eq!(fields, vec![(b"name".to_vec(), b"ruis".to_vec()), (b"n".to_vec(), b"3".to_vec())]);
+}
+
+#[test]
+fn test_from_redis_hash() {
+    let mut fields = HashMap::new();
+    fields.insert(b"name".to_vec(), b"ruis".to_vec());
+    fields.insert(b"n".to_vec(), b"3".to_vec());
+    fields.insert(b"email".to_vec(), b"me@example.com".to_vec());
+    fields.insert(b"other".to_vec(), b"x".to_vec());
+    let user = User::from_redis_hash(fields).unwrap();
+    assert_eq!(user, User { name: "ruis".to_string(), visits: 3, email: Some("me@example.com".to_string()) });
+
+    let mut fields = HashMap::new();
+    fields.insert(b"name".to_vec(), b"ruis".to_vec());
+    match User::from_redis_hash(fields) {
+        Err(RuisError::CodecError(msg)) => assert!(msg.starts_with("hash field n:"), "{}", msg),
+        r => panic!("expected a codec error, got {:?}", r),
+    }
+}
+
+#[test]
+fn test_hset_struct() {
+    let server = TestServer::new();
+    let mut conn = TcpConnection::connect(&server.addr(), None).unwrap();
+    let user = User { name: "ruis".to_string(), visits: 3, email: Some("me@example.com".to_string()) };
+    assert_eq!(conn.hset_struct(b"user:1", &user).unwrap(), 3);
+    assert_eq!(conn.hget_struct::<User>(b"user:1").unwrap(), Some(user));
+    assert_eq!(conn.hget_struct::<User>(b"user:2").unwrap(), None);
+}
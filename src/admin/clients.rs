@@ -0,0 +1,99 @@
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+
+use super::super::connection::GenericConnection;
+use super::super::types::{RespValue, RuisError};
+
+// a client connection as reported by CLIENT LIST and CLIENT INFO, one line of
+// space separated field=value pairs like:
+//
+//   id=3 addr=127.0.0.1:52046 laddr=127.0.0.1:6379 fd=8 name= age=12 idle=0 flags=N db=0 ... cmd=client|list resp=2
+//
+// the fields not typed here are kept in fields, as each version adds some.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientInfo {
+    pub id: u64,
+    pub addr: String,
+    pub name: String,
+    // the seconds since the connection was opened.
+    pub age: u64,
+    // the seconds since the last command.
+    pub idle: u64,
+    pub flags: String,
+    pub db: u32,
+    // the last command, like "client|list" since redis 7.
+    pub command: String,
+    // the protocol version, since redis 7.
+    pub resp: Option<u8>,
+    pub fields: HashMap<String, String>,
+}
+
+pub fn parse_client_info(line: &str) -> Result<ClientInfo, RuisError> {
+    let mut fields = HashMap::new();
+    for pair in line.split_whitespace() {
+        if let Some((k, v)) = pair.split_once('=') {
+            fields.insert(k.to_string(), v.to_string());
+        }
+    }
+    let unexpected = || RuisError::Unexpected(format!("client info: {}", line));
+    let text = |k: &str| fields.get(k).cloned().ok_or_else(unexpected);
+    let number = |k: &str| fields.get(k).and_then(|v| v.parse::<u64>().ok()).ok_or_else(unexpected);
+    Ok(ClientInfo {
+        id: number("id")?,
+        addr: text("addr")?,
+        name: fields.get("name").cloned().unwrap_or_default(),
+        age: number("age")?,
+        idle: number("idle")?,
+        flags: text("flags")?,
+        db: number("db")? as u32,
+        command: text("cmd")?,
+        resp: fields.get("resp").and_then(|v| v.parse().ok()),
+        fields: fields.clone(),
+    })
+}
+
+pub fn parse_client_list(text: &str) -> Result<Vec<ClientInfo>, RuisError> {
+    text.lines().filter(|l| !l.trim().is_empty()).map(parse_client_info).collect()
+}
+
+impl<W: Write, R: BufRead> GenericConnection<W, R> {
+    pub fn client_list(&mut self) -> Result<Vec<ClientInfo>, RuisError> {
+        match self.execute(&[b"client", b"list"])?.into_result()? {
+            RespValue::Bulk(text) => parse_client_list(&String::from_utf8_lossy(&text)),
+            v => Err(RuisError::Unexpected(format!("client list: {:?}", v))),
+        }
+    }
+
+    // the info of this connection, since redis 6.2.
+    pub fn client_info(&mut self) -> Result<ClientInfo, RuisError> {
+        match self.execute(&[b"client", b"info"])?.into_result()? {
+            RespValue::Bulk(text) => parse_client_info(&String::from_utf8_lossy(&text)),
+            v => Err(RuisError::Unexpected(format!("client info: {:?}", v))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_client_list() {
+        let text = "id=3 addr=127.0.0.1:52046 laddr=127.0.0.1:6379 fd=8 name=worker age=12 idle=0 flags=N db=0 sub=0 psub=0 multi=-1 cmd=client|list user=default resp=3\n\
+                    id=4 addr=127.0.0.1:52048 fd=9 name= age=300 idle=280 flags=S db=2 sub=0 psub=0 multi=-1 cmd=replconf\n";
+        let clients = parse_client_list(text).unwrap();
+        assert_eq!(clients.len(), 2);
+        assert_eq!(clients[0].id, 3);
+        assert_eq!(clients[0].addr, "127.0.0.1:52046");
+        assert_eq!(clients[0].name, "worker");
+        assert_eq!(clients[0].command, "client|list");
+        assert_eq!(clients[0].resp, Some(3));
+        assert_eq!(clients[0].fields.get("user").map(|s| s.as_str()), Some("default"));
+        assert_eq!((clients[1].age, clients[1].idle, clients[1].db), (300, 280, 2));
+        assert_eq!(clients[1].name, "");
+        assert_eq!(clients[1].flags, "S");
+        assert_eq!(clients[1].resp, None);
+
+        assert!(parse_client_info("id=x addr=127.0.0.1:1").is_err());
+    }
+}
@@ -0,0 +1,42 @@
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+
+use super::super::connection::GenericConnection;
+use super::super::types::{RespValue, RuisError};
+
+// the field:value lines of INFO, the "# Section" headers are skipped.
+pub fn parse_info(text: &str) -> HashMap<String, String> {
+    text.lines()
+        .filter(|l| !l.starts_with('#'))
+        .filter_map(|l| l.split_once(':'))
+        .map(|(k, v)| (k.to_string(), v.trim_end().to_string()))
+        .collect()
+}
+
+// the comma separated k=v values of the fields like slave0 or db0.
+pub fn parse_info_value(value: &str) -> HashMap<String, String> {
+    value.split(',').filter_map(|kv| kv.split_once('=')).map(|(k, v)| (k.to_string(), v.to_string())).collect()
+}
+
+impl<W: Write, R: BufRead> GenericConnection<W, R> {
+    pub fn info(&mut self, section: &str) -> Result<HashMap<String, String>, RuisError> {
+        match self.execute(&[b"info", section.as_bytes()])?.into_result()? {
+            RespValue::Bulk(text) => Ok(parse_info(&String::from_utf8_lossy(&text))),
+            v => Err(RuisError::Unexpected(format!("info: {:?}", v))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_info() {
+        let info = parse_info("# Replication\r\nrole:master\r\nslave0:ip=127.0.0.1,port=6380,state=online,offset=42,lag=1\r\n\r\n");
+        assert_eq!(info.get("role").map(|s| s.as_str()), Some("master"));
+        let replica = parse_info_value(&info["slave0"]);
+        assert_eq!(replica.get("port").map(|s| s.as_str()), Some("6380"));
+        assert_eq!(replica.get("offset").map(|s| s.as_str()), Some("42"));
+    }
+}
@@ -0,0 +1,121 @@
+use std::io::{BufRead, Write};
+
+use super::super::connection::GenericConnection;
+use super::super::types::{RespValue, RuisError};
+
+// https://redis.io/docs/management/optimization/latency-monitor/
+//
+// the events are only recorded once latency-monitor-threshold is set.
+
+// the latest spike of an event, as reported by LATENCY LATEST.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LatencyEvent {
+    // like "command", "fast-command" or "expire-cycle".
+    pub event: String,
+    // the unix time of the latest spike, in seconds.
+    pub timestamp: u64,
+    pub latest_ms: u64,
+    pub max_ms: u64,
+}
+
+// a spike in the LATENCY HISTORY of an event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LatencySample {
+    pub timestamp: u64,
+    pub latency_ms: u64,
+}
+
+impl<W: Write, R: BufRead> GenericConnection<W, R> {
+    pub fn latency_latest(&mut self) -> Result<Vec<LatencyEvent>, RuisError> {
+        let mut events = vec![];
+        for entry in latency_entries(self.execute(&[b"latency", b"latest"])?)? {
+            match entry.as_slice() {
+                [RespValue::Bulk(event), timestamp, latest, max] => events.push(LatencyEvent {
+                    event: String::from_utf8_lossy(event).into_owned(),
+                    timestamp: int(timestamp)?,
+                    latest_ms: int(latest)?,
+                    max_ms: int(max)?,
+                }),
+                _ => return Err(RuisError::Unexpected(format!("latency latest: {:?}", entry))),
+            }
+        }
+        Ok(events)
+    }
+
+    // the spikes of the event, the oldest first.
+    pub fn latency_history(&mut self, event: &str) -> Result<Vec<LatencySample>, RuisError> {
+        let mut samples = vec![];
+        for entry in latency_entries(self.execute(&[b"latency", b"history", event.as_bytes()])?)? {
+            match entry.as_slice() {
+                [timestamp, latency] => samples.push(LatencySample {
+                    timestamp: int(timestamp)?,
+                    latency_ms: int(latency)?,
+                }),
+                _ => return Err(RuisError::Unexpected(format!("latency history: {:?}", entry))),
+            }
+        }
+        Ok(samples)
+    }
+
+    // resets the given events, or all of them if none is given. returns the
+    // number of the events reset.
+    pub fn latency_reset(&mut self, events: &[&str]) -> Result<u64, RuisError> {
+        let mut cmd: Vec<&[u8]> = vec![b"latency", b"reset"];
+        cmd.extend(events.iter().map(|e| e.as_bytes()));
+        int(&self.execute(&cmd)?.into_result()?)
+    }
+
+    // the human readable analysis of the latency events.
+    pub fn latency_doctor(&mut self) -> Result<String, RuisError> {
+        match self.execute(&[b"latency", b"doctor"])?.into_result()? {
+            RespValue::Bulk(text) => Ok(String::from_utf8_lossy(&text).into_owned()),
+            v => Err(RuisError::Unexpected(format!("latency doctor: {:?}", v))),
+        }
+    }
+}
+
+fn latency_entries(reply: RespValue) -> Result<Vec<Vec<RespValue>>, RuisError> {
+    match reply.into_result()? {
+        RespValue::Array(entries) => entries.into_iter().map(|e| match e {
+            RespValue::Array(fields) => Ok(fields),
+            v => Err(RuisError::Unexpected(format!("latency entry: {:?}", v))),
+        }).collect(),
+        v => Err(RuisError::Unexpected(format!("latency: {:?}", v))),
+    }
+}
+
+fn int(v: &RespValue) -> Result<u64, RuisError> {
+    match v {
+        RespValue::Int(n) if *n >= 0 => Ok(*n as u64),
+        v => Err(RuisError::Unexpected(format!("latency: expected a positive integer, got {:?}", v))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use super::*;
+    use super::super::super::resp::{RespReader, RespWriter};
+
+    fn replying(replies: &[u8]) -> GenericConnection<Vec<u8>, Cursor<Vec<u8>>> {
+        let r = RespReader::new(Cursor::new(replies.to_vec()));
+        GenericConnection::new(r, RespWriter::new(vec![]))
+    }
+
+    #[test]
+    fn test_latency() {
+        let mut conn = replying(b"*2\r\n*4\r\n$7\r\ncommand\r\n:1700000000\r\n:250\r\n:1000\r\n*4\r\n$12\r\nexpire-cycle\r\n:1700000010\r\n:12\r\n:40\r\n");
+        assert_eq!(conn.latency_latest().unwrap(), vec![
+            LatencyEvent { event: "command".to_string(), timestamp: 1700000000, latest_ms: 250, max_ms: 1000 },
+            LatencyEvent { event: "expire-cycle".to_string(), timestamp: 1700000010, latest_ms: 12, max_ms: 40 },
+        ]);
+
+        let mut conn = replying(b"*2\r\n*2\r\n:1700000000\r\n:1000\r\n*2\r\n:1700000005\r\n:250\r\n:2\r\n$8\r\nhealthy\n\r\n");
+        assert_eq!(conn.latency_history("command").unwrap(), vec![
+            LatencySample { timestamp: 1700000000, latency_ms: 1000 },
+            LatencySample { timestamp: 1700000005, latency_ms: 250 },
+        ]);
+        assert_eq!(conn.latency_reset(&[]).unwrap(), 2);
+        assert_eq!(conn.latency_doctor().unwrap(), "healthy\n");
+    }
+}
@@ -0,0 +1,192 @@
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+
+use super::super::connection::GenericConnection;
+use super::super::types::{RespValue, RuisError};
+
+// the reply of MEMORY STATS, in bytes unless noted. the fields missing in the
+// older versions are left 0, the fields not typed here are kept in fields.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MemoryStats {
+    pub peak_allocated: u64,
+    pub total_allocated: u64,
+    pub startup_allocated: u64,
+    pub replication_backlog: u64,
+    pub clients_replicas: u64,
+    pub clients_normal: u64,
+    pub aof_buffer: u64,
+    pub lua_caches: u64,
+    pub overhead_total: u64,
+    pub keys_count: u64,
+    pub keys_bytes_per_key: u64,
+    pub dataset_bytes: u64,
+    // the percentages are of total_allocated minus startup_allocated, and of
+    // peak_allocated.
+    pub dataset_percentage: f64,
+    pub peak_percentage: f64,
+    // the ratio of the rss to the allocated memory.
+    pub fragmentation: f64,
+    pub fragmentation_bytes: i64,
+    pub dbs: Vec<DbMemory>,
+    pub fields: HashMap<String, String>,
+}
+
+// the overhead of the dictionaries of a database, the db.<n> fields.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DbMemory {
+    pub db: u32,
+    pub overhead_hashtable_main: u64,
+    pub overhead_hashtable_expires: u64,
+}
+
+impl MemoryStats {
+    fn set(&mut self, name: &str, value: f64) {
+        match name {
+            "peak.allocated" => self.peak_allocated = value as u64,
+            "total.allocated" => self.total_allocated = value as u64,
+            "startup.allocated" => self.startup_allocated = value as u64,
+            "replication.backlog" => self.replication_backlog = value as u64,
+            "clients.slaves" => self.clients_replicas = value as u64,
+            "clients.normal" => self.clients_normal = value as u64,
+            "aof.buffer" => self.aof_buffer = value as u64,
+            "lua.caches" => self.lua_caches = value as u64,
+            "overhead.total" => self.overhead_total = value as u64,
+            "keys.count" => self.keys_count = value as u64,
+            "keys.bytes-per-key" => self.keys_bytes_per_key = value as u64,
+            "dataset.bytes" => self.dataset_bytes = value as u64,
+            "dataset.percentage" => self.dataset_percentage = value,
+            "peak.percentage" => self.peak_percentage = value,
+            "fragmentation" => self.fragmentation = value,
+            "fragmentation.bytes" => self.fragmentation_bytes = value as i64,
+            _ => {
+                self.fields.insert(name.to_string(), value.to_string());
+            },
+        }
+    }
+}
+
+pub fn parse_memory_stats(reply: RespValue) -> Result<MemoryStats, RuisError> {
+    let pairs = match reply.into_result()? {
+        RespValue::Array(v) => v,
+        v => return Err(RuisError::Unexpected(format!("memory stats: {:?}", v))),
+    };
+    let mut stats = MemoryStats::default();
+    for pair in pairs.chunks(2) {
+        let (name, value) = match pair {
+            [RespValue::Bulk(name), value] => (String::from_utf8_lossy(name).into_owned(), value),
+            _ => return Err(RuisError::Unexpected(format!("memory stats: {:?}", pair))),
+        };
+        match (name.strip_prefix("db.").and_then(|n| n.parse().ok()), value) {
+            (Some(db), RespValue::Array(fields)) => stats.dbs.push(parse_db_memory(db, fields)),
+            (_, value) => match number(value) {
+                Some(n) => stats.set(&name, n),
+                None => {
+                    stats.fields.insert(name, format!("{:?}", value));
+                },
+            },
+        }
+    }
+    Ok(stats)
+}
+
+fn parse_db_memory(db: u32, fields: &[RespValue]) -> DbMemory {
+    let mut mem = DbMemory { db, ..DbMemory::default() };
+    for pair in fields.chunks(2) {
+        if let [RespValue::Bulk(name), value] = pair {
+            let value = number(value).unwrap_or(0.0) as u64;
+            match name.as_slice() {
+                b"overhead.hashtable.main" => mem.overhead_hashtable_main = value,
+                b"overhead.hashtable.expires" => mem.overhead_hashtable_expires = value,
+                _ => {},
+            }
+        }
+    }
+    mem
+}
+
+// the ratios and the percentages are replied as bulk strings.
+fn number(v: &RespValue) -> Option<f64> {
+    match v {
+        RespValue::Int(n) => Some(*n as f64),
+        RespValue::Bulk(s) => std::str::from_utf8(s).ok()?.parse().ok(),
+        _ => None,
+    }
+}
+
+impl<W: Write, R: BufRead> GenericConnection<W, R> {
+    // the bytes taken by the key and its value, None if the key does not
+    // exist. the elements of the nested values are sampled, samples of them
+    // or 5 by default, Some(0) takes them all.
+    pub fn memory_usage(&mut self, key: &[u8], samples: Option<u64>) -> Result<Option<u64>, RuisError> {
+        let samples = samples.map(|n| n.to_string());
+        let mut cmd: Vec<&[u8]> = vec![b"memory", b"usage", key];
+        if let Some(ref n) = samples {
+            cmd.extend_from_slice(&[b"samples", n.as_bytes()]);
+        }
+        match self.execute(&cmd)?.into_result()? {
+            RespValue::Int(n) if n >= 0 => Ok(Some(n as u64)),
+            RespValue::NilBulk => Ok(None),
+            v => Err(RuisError::Unexpected(format!("memory usage: {:?}", v))),
+        }
+    }
+
+    pub fn memory_stats(&mut self) -> Result<MemoryStats, RuisError> {
+        parse_memory_stats(self.execute(&[b"memory", b"stats"])?)
+    }
+
+    // the human readable advice about the memory issues.
+    pub fn memory_doctor(&mut self) -> Result<String, RuisError> {
+        match self.execute(&[b"memory", b"doctor"])?.into_result()? {
+            RespValue::Bulk(text) => Ok(String::from_utf8_lossy(&text).into_owned()),
+            v => Err(RuisError::Unexpected(format!("memory doctor: {:?}", v))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::super::commands::Commands;
+    use super::super::super::connection::TcpConnection;
+    use super::super::super::testing::TestServer;
+
+    fn bulk(s: &str) -> RespValue {
+        RespValue::Bulk(s.as_bytes().to_vec())
+    }
+
+    #[test]
+    fn test_parse_memory_stats() {
+        let reply = RespValue::Array(vec![
+            bulk("peak.allocated"), RespValue::Int(2000000),
+            bulk("total.allocated"), RespValue::Int(1000000),
+            bulk("startup.allocated"), RespValue::Int(800000),
+            bulk("db.0"), RespValue::Array(vec![
+                bulk("overhead.hashtable.main"), RespValue::Int(72),
+                bulk("overhead.hashtable.expires"), RespValue::Int(32),
+            ]),
+            bulk("keys.count"), RespValue::Int(3),
+            bulk("dataset.percentage"), bulk("37.5"),
+            bulk("fragmentation"), bulk("1.25"),
+            bulk("allocator.resident"), RespValue::Int(4096),
+        ]);
+        let stats = parse_memory_stats(reply).unwrap();
+        assert_eq!((stats.peak_allocated, stats.total_allocated, stats.startup_allocated), (2000000, 1000000, 800000));
+        assert_eq!(stats.keys_count, 3);
+        assert_eq!(stats.dataset_percentage, 37.5);
+        assert_eq!(stats.fragmentation, 1.25);
+        assert_eq!(stats.dbs, vec![DbMemory { db: 0, overhead_hashtable_main: 72, overhead_hashtable_expires: 32 }]);
+        assert_eq!(stats.fields.get("allocator.resident").map(|s| s.as_str()), Some("4096"));
+
+        assert!(parse_memory_stats(RespValue::Error(b"ERR unknown".to_vec())).is_err());
+    }
+
+    #[test]
+    fn test_memory_usage() {
+        let server = TestServer::new();
+        let mut conn = TcpConnection::connect(&server.addr(), None).unwrap();
+        conn.set(b"k", b"value").unwrap();
+        assert_eq!(conn.memory_usage(b"k", None).unwrap(), Some(1 + 16 + 5 + 16));
+        assert_eq!(conn.memory_usage(b"k", Some(0)).unwrap(), Some(1 + 16 + 5 + 16));
+        assert_eq!(conn.memory_usage(b"missing", None).unwrap(), None);
+    }
+}
@@ -0,0 +1,14 @@
+// typed wrappers of the introspection commands, for the tooling watching the
+// servers.
+
+mod clients;
+mod info;
+mod latency;
+mod memory;
+mod replication;
+
+pub use self::clients::{ClientInfo, parse_client_info, parse_client_list};
+pub use self::info::{parse_info, parse_info_value};
+pub use self::latency::{LatencyEvent, LatencySample};
+pub use self::memory::{DbMemory, MemoryStats, parse_memory_stats};
+pub use self::replication::{ReplicaLag, ReplicaOffset, ReplicationLag, ReplicationOffsets, parse_replication_offsets, replication_lag};
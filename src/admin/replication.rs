@@ -0,0 +1,265 @@
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+use std::time::Duration;
+
+use super::info::parse_info_value;
+use super::super::blocking::BlockingCommands;
+use super::super::connection::{GenericConnection, TcpConnection};
+use super::super::types::{RespValue, RuisError};
+
+// a replica as its master sees it in INFO replication.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplicaOffset {
+    pub addr: String,
+    // "online" once the initial sync is done.
+    pub state: String,
+    // the last offset the replica acked.
+    pub offset: u64,
+    // the seconds since the last ack.
+    pub lag_seconds: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplicationOffsets {
+    // "master" or "slave".
+    pub role: String,
+    // master_repl_offset, the offset of the replication stream written by a
+    // master, or received from its master by a replica.
+    pub master_offset: u64,
+    // slave_repl_offset, the offset processed by a replica, None on a master.
+    pub replica_offset: Option<u64>,
+    // the replicas of a master.
+    pub replicas: Vec<ReplicaOffset>,
+}
+
+impl ReplicationOffsets {
+    // the number of the online replicas which acked the offset, like read
+    // from a master after a write, to poll for the acks rather than blocking
+    // in WAIT.
+    pub fn acked(&self, offset: u64) -> usize {
+        self.replicas.iter().filter(|r| r.state == "online" && r.offset >= offset).count()
+    }
+}
+
+// the offsets in the fields of INFO replication.
+pub fn parse_replication_offsets(info: &HashMap<String, String>) -> Result<ReplicationOffsets, RuisError> {
+    let role = info.get("role").cloned().ok_or_else(|| RuisError::Unexpected("info replication without role".to_string()))?;
+    let master_offset = number(info, "master_repl_offset")?;
+    let replica_offset = match role.as_str() {
+        "slave" => Some(number(info, "slave_repl_offset")?),
+        _ => None,
+    };
+    let mut replicas = vec![];
+    let mut i = 0;
+    while let Some(value) = info.get(&format!("slave{}", i)) {
+        i += 1;
+        let fields = parse_info_value(value);
+        let addr = match (fields.get("ip"), fields.get("port")) {
+            (Some(ip), Some(port)) => format!("{}:{}", ip, port),
+            _ => return Err(RuisError::Unexpected(format!("replica without address: {}", value))),
+        };
+        replicas.push(ReplicaOffset {
+            addr,
+            state: fields.get("state").cloned().unwrap_or_default(),
+            offset: number(&fields, "offset")?,
+            lag_seconds: fields.get("lag").and_then(|l| l.parse().ok()),
+        });
+    }
+    Ok(ReplicationOffsets { role, master_offset, replica_offset, replicas })
+}
+
+impl<W: Write, R: BufRead> GenericConnection<W, R> {
+    pub fn replication_offsets(&mut self) -> Result<ReplicationOffsets, RuisError> {
+        parse_replication_offsets(&self.info("replication")?)
+    }
+}
+
+impl TcpConnection {
+    // blocks until the writes of this connection so far are acked by
+    // numreplicas replicas or the timeout passes, 0 waits forever. returns
+    // the number of the replicas which acked, it's for the durability of the
+    // writes to the replicas, not for the consistency: the writes acked are
+    // still lost on a failover to a replica which missed them. the read
+    // timeout is extended as with the blocking commands.
+    pub fn wait(&mut self, numreplicas: u64, timeout: Duration) -> Result<u64, RuisError> {
+        let n = numreplicas.to_string();
+        // the ones under a millisecond are rounded up, as 0 waits forever.
+        let millis = match timeout.as_millis() {
+            0 if timeout > Duration::from_secs(0) => 1,
+            ms => ms,
+        };
+        let millis = millis.to_string();
+        match self.execute_blocking(&[b"wait", n.as_bytes(), millis.as_bytes()], timeout)?.into_result()? {
+            RespValue::Int(acked) if acked >= 0 => Ok(acked as u64),
+            v => Err(RuisError::Unexpected(format!("wait: {:?}", v))),
+        }
+    }
+}
+
+// how far a replica is behind its master.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplicaLag {
+    pub addr: String,
+    // "online" once the initial sync is done, as seen by the master.
+    pub state: String,
+    // the replication offset of the replica, the offset acked to the master
+    // when the replica is not reachable.
+    pub offset: u64,
+    pub lag_bytes: u64,
+    // the seconds since the replica heard from the master, or since the
+    // master got the last ack when the replica is not reachable.
+    pub lag_seconds: Option<u64>,
+    // whether master_link_status is up on the replica, None if it is not
+    // reachable.
+    pub link_up: Option<bool>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplicationLag {
+    pub master_addr: String,
+    pub master_offset: u64,
+    pub replicas: Vec<ReplicaLag>,
+}
+
+impl ReplicationLag {
+    // whether all the replicas are online, linked, and within max_lag_bytes,
+    // to check before failing over or taking the master down.
+    pub fn is_caught_up(&self, max_lag_bytes: u64) -> bool {
+        !self.replicas.is_empty() && self.replicas.iter().all(|r| {
+            r.state == "online" && r.link_up == Some(true) && r.lag_bytes <= max_lag_bytes
+        })
+    }
+}
+
+// compares the master_repl_offset of the master with the slave_repl_offset of
+// each replica it reports in INFO replication. the master offset is read first,
+// so a replica can only look further behind than it is.
+pub fn replication_lag(master_addr: &str, password: Option<&str>) -> Result<ReplicationLag, RuisError> {
+    let mut master = TcpConnection::connect(master_addr, password)?;
+    let info = master.info("replication")?;
+    if info.get("role").map(|r| r.as_str()) != Some("master") {
+        return Err(RuisError::Unexpected(format!("{} is not a master: {:?}", master_addr, info.get("role"))));
+    }
+    let offsets = parse_replication_offsets(&info)?;
+    let master_offset = offsets.master_offset;
+
+    let mut replicas = vec![];
+    for r in offsets.replicas {
+        let mut lag = ReplicaLag {
+            addr: r.addr,
+            state: r.state,
+            offset: r.offset,
+            lag_bytes: 0,
+            lag_seconds: r.lag_seconds,
+            link_up: None,
+        };
+        if let Ok(replica) = TcpConnection::connect(&lag.addr, password).and_then(|mut c| c.info("replication")) {
+            lag.offset = number(&replica, "slave_repl_offset")?;
+            lag.lag_seconds = replica.get("master_last_io_seconds_ago").and_then(|s| s.parse().ok());
+            lag.link_up = Some(replica.get("master_link_status").map(|s| s.as_str()) == Some("up"));
+        }
+        lag.lag_bytes = master_offset.saturating_sub(lag.offset);
+        replicas.push(lag);
+    }
+    Ok(ReplicationLag {
+        master_addr: master_addr.to_string(),
+        master_offset,
+        replicas,
+    })
+}
+
+fn number(fields: &HashMap<String, String>, name: &str) -> Result<u64, RuisError> {
+    fields.get(name).and_then(|v| v.parse().ok()).ok_or_else(|| RuisError::Unexpected(format!("info replication without {}", name)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, BufReader};
+    use std::net::TcpListener;
+    use std::thread;
+    use super::*;
+    use super::super::info::parse_info;
+    use super::super::super::resp::{RespReader, RespWriter};
+    use super::super::super::testing::fake_node;
+
+    // replies the INFO text to everything.
+    fn info_node(info: String) -> String {
+        fake_node(move |_, _| RespValue::Bulk(info.clone().into_bytes()))
+    }
+
+    #[test]
+    fn test_replication_lag() {
+        let replica = info_node("# Replication\r\nrole:slave\r\nmaster_link_status:up\r\nmaster_last_io_seconds_ago:1\r\nslave_repl_offset:900\r\n".to_string());
+        let (ip, port) = replica.rsplit_once(':').unwrap();
+        let gone = {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            listener.local_addr().unwrap().port()
+        };
+        let master = info_node(format!(
+            "# Replication\r\nrole:master\r\nconnected_slaves:2\r\n\
+             slave0:ip={},port={},state=online,offset=800,lag=0\r\n\
+             slave1:ip=127.0.0.1,port={},state=wait_bgsave,offset=0,lag=7\r\n\
+             master_repl_offset:1000\r\n", ip, port, gone));
+
+        let lag = replication_lag(&master, None).unwrap();
+        assert_eq!(lag.master_offset, 1000);
+        assert_eq!(lag.replicas[0], ReplicaLag {
+            addr: replica.clone(),
+            state: "online".to_string(),
+            offset: 900,
+            lag_bytes: 100,
+            lag_seconds: Some(1),
+            link_up: Some(true),
+        });
+        assert_eq!(lag.replicas[1].lag_bytes, 1000);
+        assert_eq!(lag.replicas[1].lag_seconds, Some(7));
+        assert_eq!(lag.replicas[1].link_up, None);
+        assert!(!lag.is_caught_up(100));
+        assert!(replication_lag(&replica, None).is_err());
+    }
+
+    #[test]
+    fn test_replication_offsets() {
+        let info = "# Replication\r\nrole:master\r\nconnected_slaves:2\r\n\
+                    slave0:ip=10.0.0.1,port=6379,state=online,offset=900,lag=0\r\n\
+                    slave1:ip=10.0.0.2,port=6379,state=online,offset=1000,lag=1\r\n\
+                    master_repl_offset:1000\r\n";
+        let reply = format!("${}\r\n{}\r\n", info.len(), info);
+        let mut c = GenericConnection::new(RespReader::new(io::Cursor::new(reply.into_bytes())), RespWriter::new(vec![]));
+        let offsets = c.replication_offsets().unwrap();
+        assert_eq!(offsets.role, "master");
+        assert_eq!(offsets.master_offset, 1000);
+        assert_eq!(offsets.replica_offset, None);
+        assert_eq!(offsets.replicas[1], ReplicaOffset {
+            addr: "10.0.0.2:6379".to_string(),
+            state: "online".to_string(),
+            offset: 1000,
+            lag_seconds: Some(1),
+        });
+        assert_eq!(offsets.acked(900), 2);
+        assert_eq!(offsets.acked(1000), 1);
+
+        let replica = parse_info("role:slave\r\nmaster_repl_offset:1000\r\nslave_repl_offset:990\r\n");
+        assert_eq!(parse_replication_offsets(&replica).unwrap().replica_offset, Some(990));
+        assert!(parse_replication_offsets(&parse_info("role:slave\r\n")).is_err());
+    }
+
+    #[test]
+    fn test_wait() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut r = RespReader::new(BufReader::new(stream.try_clone().unwrap()));
+            let cmd = r.read().unwrap();
+            thread::sleep(Duration::from_millis(200));
+            stream.write_all(b":1\r\n").unwrap();
+            cmd
+        });
+        let mut conn = TcpConnection::connect(&addr, None).unwrap();
+        conn.stream().set_read_timeout(Some(Duration::from_millis(50))).unwrap();
+        assert_eq!(conn.wait(2, Duration::from_millis(500)).unwrap(), 1);
+        let args = [&b"wait"[..], b"2", b"500"].iter().map(|a| RespValue::Bulk(a.to_vec())).collect();
+        assert_eq!(server.join().unwrap(), RespValue::Array(args));
+    }
+}
@@ -0,0 +1,150 @@
+// the async connections of the tokio feature, the counterpart of
+// GenericConnection for the async services.
+
+use std::io;
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::{TcpStream, ToSocketAddrs};
+
+use super::pipeline::Pipeline;
+use super::resp::{RespReader, RespWriter};
+use super::types::{RespValue, RuisError};
+
+const READ_BUF_SIZE: usize = 8 * 1024;
+
+pub struct GenericAsyncConnection<W: AsyncWrite + Unpin, R: AsyncRead + Unpin> {
+    w: W,
+    r: R,
+    // the bytes read but not parsed yet, like the start of the next reply.
+    buf: Vec<u8>,
+    // the commands encoded and not written yet.
+    out: Vec<u8>,
+}
+
+pub type AsyncConnection = GenericAsyncConnection<OwnedWriteHalf, OwnedReadHalf>;
+
+// the reply at the start of buf and its length, None if it did not arrive in
+// full yet. the replies are parsed by RespReader, which takes the end of buf
+// for the end of the stream.
+fn parse_reply(buf: &[u8]) -> Result<Option<(RespValue, usize)>, RuisError> {
+    let mut rest = buf;
+    match RespReader::new(&mut rest).read() {
+        Ok(v) => Ok(Some((v, buf.len() - rest.len()))),
+        Err(RuisError::IoError(ref e)) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+impl<W: AsyncWrite + Unpin, R: AsyncRead + Unpin> GenericAsyncConnection<W, R> {
+    pub fn new(r: R, w: W) -> Self {
+        Self {
+            w,
+            r,
+            buf: Vec::with_capacity(READ_BUF_SIZE),
+            out: vec![],
+        }
+    }
+
+    pub async fn auth(&mut self, password: &str) -> Result<RespValue, RuisError> {
+        self.execute(&[b"auth", password.as_bytes()]).await
+    }
+
+    pub async fn execute(&mut self, cmd: &[&[u8]]) -> Result<RespValue, RuisError> {
+        self.send(cmd).await?;
+        self.receive().await
+    }
+
+    pub async fn send(&mut self, cmd: &[&[u8]]) -> Result<(), RuisError> {
+        self.write(cmd);
+        self.flush().await
+    }
+
+    // the error replies are returned in place, only the io and parse errors
+    // fail the whole pipeline.
+    pub async fn execute_pipeline(&mut self, pipeline: &Pipeline) -> Result<Vec<RespValue>, RuisError> {
+        for cmd in pipeline.commands() {
+            self.write(&cmd);
+        }
+        self.flush().await?;
+        let mut replies = Vec::with_capacity(pipeline.len());
+        for _ in 0..pipeline.len() {
+            replies.push(self.receive().await?);
+        }
+        Ok(replies)
+    }
+
+    pub async fn receive(&mut self) -> Result<RespValue, RuisError> {
+        loop {
+            if !self.buf.is_empty() {
+                if let Some((v, n)) = parse_reply(&self.buf)? {
+                    self.buf.drain(..n);
+                    return Ok(v);
+                }
+            }
+            self.buf.reserve(READ_BUF_SIZE);
+            if self.r.read_buf(&mut self.buf).await? == 0 {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed").into());
+            }
+        }
+    }
+
+    fn write(&mut self, cmd: &[&[u8]]) {
+        // writing into a Vec does not fail.
+        let _ = RespWriter::new(&mut self.out).write_bulks(cmd);
+    }
+
+    async fn flush(&mut self) -> Result<(), RuisError> {
+        self.w.write_all(&self.out).await?;
+        self.out.clear();
+        self.w.flush().await?;
+        Ok(())
+    }
+}
+
+impl AsyncConnection {
+    pub async fn connect<A: ToSocketAddrs>(addr: A, password_opt: Option<&str>) -> Result<AsyncConnection, RuisError> {
+        let stream = TcpStream::connect(addr).await?;
+        stream.set_nodelay(true)?;
+        let (r, w) = stream.into_split();
+        let mut conn = GenericAsyncConnection::new(r, w);
+        if let Some(password) = password_opt {
+            if let RespValue::Error(msg) = conn.auth(password).await? {
+                return Err(RuisError::Auth(String::from_utf8_lossy(&msg).into_owned()));
+            }
+        }
+        Ok(conn)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::testing::TestServer;
+
+    #[tokio::test]
+    async fn test_async_connection() {
+        let server = TestServer::with_password("secret");
+        assert!(matches!(AsyncConnection::connect(server.addr(), Some("wrong")).await, Err(RuisError::Auth(_))));
+
+        let mut conn = AsyncConnection::connect(server.addr(), Some("secret")).await.unwrap();
+        let big = vec![b'x'; 100_000];
+        assert_eq!(conn.execute(&[b"set", b"k", &big]).await.unwrap(), RespValue::Bulk(b"OK".to_vec()));
+        assert_eq!(conn.execute(&[b"get", b"k"]).await.unwrap(), RespValue::Bulk(big));
+
+        let mut pipeline = Pipeline::new();
+        pipeline.cmd(&[b"set", b"n", b"1"]).cmd(&[b"incr", b"n"]).cmd(&[b"hlen", b"n"]).cmd(&[b"get", b"missing"]);
+        let replies = conn.execute_pipeline(&pipeline).await.unwrap();
+        assert_eq!(replies[1], RespValue::Int(2));
+        assert!(matches!(replies[2], RespValue::Error(_)));
+        assert_eq!(replies[3], RespValue::NilBulk);
+    }
+
+    #[test]
+    fn test_parse_reply() {
+        assert_eq!(parse_reply(b"*2\r\n:1\r\n$3\r\nab").unwrap(), None);
+        assert_eq!(parse_reply(b"+OK\r").unwrap(), None);
+        assert_eq!(parse_reply(b":1\r\n:2\r\n").unwrap(), Some((RespValue::Int(1), 4)));
+        assert!(parse_reply(b"?\r\n").is_err());
+    }
+}
@@ -0,0 +1,167 @@
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+
+use super::connection::GenericConnection;
+use super::types::{RespValue, RuisError};
+
+// ToArgs turns the values into the arguments of a command, each argument is
+// sent as a bulk string, so the bytes are passed as they are.
+//
+//     conn.execute_args(&("set", "counter", 42))?;
+//     conn.execute_args(&("del", &keys[..]))?;
+pub trait ToArgs {
+    fn append_args(&self, args: &mut Vec<Vec<u8>>);
+
+    fn to_args(&self) -> Vec<Vec<u8>> {
+        let mut args = vec![];
+        self.append_args(&mut args);
+        args
+    }
+}
+
+impl<T: ToArgs + ?Sized> ToArgs for &T {
+    fn append_args(&self, args: &mut Vec<Vec<u8>>) {
+        (**self).append_args(args)
+    }
+}
+
+impl ToArgs for str {
+    fn append_args(&self, args: &mut Vec<Vec<u8>>) {
+        args.push(self.as_bytes().to_vec());
+    }
+}
+
+impl ToArgs for String {
+    fn append_args(&self, args: &mut Vec<Vec<u8>>) {
+        args.push(self.as_bytes().to_vec());
+    }
+}
+
+impl ToArgs for [u8] {
+    fn append_args(&self, args: &mut Vec<Vec<u8>>) {
+        args.push(self.to_vec());
+    }
+}
+
+impl<const N: usize> ToArgs for [u8; N] {
+    fn append_args(&self, args: &mut Vec<Vec<u8>>) {
+        args.push(self.to_vec());
+    }
+}
+
+impl ToArgs for Vec<u8> {
+    fn append_args(&self, args: &mut Vec<Vec<u8>>) {
+        args.push(self.clone());
+    }
+}
+
+// the paths are sent as their bytes on unix, as they might not be UTF-8.
+impl ToArgs for Path {
+    #[cfg(unix)]
+    fn append_args(&self, args: &mut Vec<Vec<u8>>) {
+        use std::os::unix::ffi::OsStrExt;
+        args.push(self.as_os_str().as_bytes().to_vec());
+    }
+
+    #[cfg(not(unix))]
+    fn append_args(&self, args: &mut Vec<Vec<u8>>) {
+        args.push(self.to_string_lossy().as_bytes().to_vec());
+    }
+}
+
+impl ToArgs for PathBuf {
+    fn append_args(&self, args: &mut Vec<Vec<u8>>) {
+        self.as_path().append_args(args)
+    }
+}
+
+macro_rules! display_to_args {
+    ($($t:ty),+) => {
+        $(
+            impl ToArgs for $t {
+                fn append_args(&self, args: &mut Vec<Vec<u8>>) {
+                    args.push(self.to_string().into_bytes());
+                }
+            }
+        )+
+    };
+}
+
+// u8 is left out, as [u8] is a single argument and not a list of numbers.
+display_to_args!(i8, i16, i32, i64, isize, u16, u32, u64, usize, f32, f64, bool);
+
+// the items of a slice are flattened into the arguments, like the keys of a
+// DEL or the field-value pairs of a HSET.
+impl<T: ToArgs> ToArgs for [T] {
+    fn append_args(&self, args: &mut Vec<Vec<u8>>) {
+        for item in self {
+            item.append_args(args);
+        }
+    }
+}
+
+impl<T: ToArgs> ToArgs for Vec<T> {
+    fn append_args(&self, args: &mut Vec<Vec<u8>>) {
+        self.as_slice().append_args(args)
+    }
+}
+
+impl<T: ToArgs, const N: usize> ToArgs for [T; N] {
+    fn append_args(&self, args: &mut Vec<Vec<u8>>) {
+        self.as_slice().append_args(args)
+    }
+}
+
+macro_rules! tuple_to_args {
+    ($($t:ident),+) => {
+        impl<$($t: ToArgs),+> ToArgs for ($($t,)+) {
+            #[allow(non_snake_case)]
+            fn append_args(&self, args: &mut Vec<Vec<u8>>) {
+                let ($($t,)+) = self;
+                $($t.append_args(args);)+
+            }
+        }
+    };
+}
+
+tuple_to_args!(A);
+tuple_to_args!(A, B);
+tuple_to_args!(A, B, C);
+tuple_to_args!(A, B, C, D);
+tuple_to_args!(A, B, C, D, E);
+tuple_to_args!(A, B, C, D, E, F);
+tuple_to_args!(A, B, C, D, E, F, G);
+tuple_to_args!(A, B, C, D, E, F, G, H);
+
+impl<W: Write, R: BufRead> GenericConnection<W, R> {
+    pub fn execute_args<A: ToArgs + ?Sized>(&mut self, args: &A) -> Result<RespValue, RuisError> {
+        let args = args.to_args();
+        let cmd: Vec<&[u8]> = args.iter().map(|a| a.as_slice()).collect();
+        self.execute(&cmd)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::connection::TcpConnection;
+    use super::super::testing::TestServer;
+
+    #[test]
+    fn test_to_args() {
+        let keys = ["a", "b"];
+        let args = ("del", &keys[..], b"c\0d", -1i64, 1.5f64, Path::new("/tmp/x")).to_args();
+        let expected: Vec<&[u8]> = vec![b"del", b"a", b"b", b"c\0d", b"-1", b"1.5", b"/tmp/x"];
+        assert_eq!(args, expected);
+        assert_eq!(vec![b"x".to_vec()].to_args(), vec![b"x".to_vec()]);
+    }
+
+    #[test]
+    fn test_execute_args() {
+        let server = TestServer::new();
+        let mut conn = TcpConnection::connect(&server.addr(), None).unwrap();
+        conn.execute_args(&("set", "n", 41)).unwrap();
+        assert_eq!(conn.execute_args(&("incrby", "n", 1u32)).unwrap(), RespValue::Int(42));
+        assert_eq!(conn.execute_args(&("hset", "h", &[("a", 1), ("b", 2)][..])).unwrap(), RespValue::Int(2));
+    }
+}
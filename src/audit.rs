@@ -0,0 +1,126 @@
+use std::time::Duration;
+
+use super::hooks::{CommandHook, CommandInfo};
+use super::metrics::Outcome;
+use super::types::{RespValue, RuisError};
+
+// the key recorded in place of the keys matching a redacted pattern.
+pub const REDACTED: &str = "***";
+
+// the commands carrying credentials, recorded by their name only even if the
+// key lookup changes.
+const CREDENTIAL_COMMANDS: &[&str] = &["auth", "hello"];
+
+// an executed command, the values are never recorded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditRecord {
+    pub name: String,
+    pub key: Option<String>,
+    pub peer: Option<String>,
+    pub elapsed: Duration,
+    pub outcome: Outcome,
+}
+
+pub trait AuditSink: Send + Sync {
+    fn record(&self, record: &AuditRecord);
+}
+
+impl<F: Fn(&AuditRecord) + Send + Sync> AuditSink for F {
+    fn record(&self, record: &AuditRecord) {
+        self(record)
+    }
+}
+
+// AuditHook records the name and the key of each command to the sink. the
+// keys matching any of the redacted glob-style patterns, like "session:*",
+// are recorded as REDACTED.
+pub struct AuditHook<S> {
+    sink: S,
+    redacted: Vec<Vec<u8>>,
+}
+
+impl<S: AuditSink> AuditHook<S> {
+    pub fn new(sink: S) -> Self {
+        Self {
+            sink,
+            redacted: vec![],
+        }
+    }
+
+    // supports "*", "?" and "\" escapes.
+    pub fn redact_keys(mut self, pattern: &str) -> Self {
+        self.redacted.push(pattern.as_bytes().to_vec());
+        self
+    }
+
+    fn audited_key(&self, cmd: &CommandInfo) -> Option<String> {
+        if CREDENTIAL_COMMANDS.contains(&cmd.name().as_str()) {
+            return None;
+        }
+        let key = cmd.key()?;
+        if self.redacted.iter().any(|p| glob_match(p, key)) {
+            return Some(REDACTED.to_string());
+        }
+        Some(String::from_utf8_lossy(key).into_owned())
+    }
+}
+
+impl<S: AuditSink> CommandHook for AuditHook<S> {
+    fn on_complete(&self, cmd: &CommandInfo, elapsed: Duration, result: Result<&RespValue, &RuisError>) {
+        self.sink.record(&AuditRecord {
+            name: cmd.name(),
+            key: self.audited_key(cmd),
+            peer: cmd.peer.map(|p| p.to_string()),
+            elapsed,
+            outcome: Outcome::of(result),
+        });
+    }
+}
+
+pub(crate) fn glob_match(pattern: &[u8], s: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => s.is_empty(),
+        Some((b'*', rest)) => (0..=s.len()).any(|i| glob_match(rest, &s[i..])),
+        Some((b'?', rest)) => !s.is_empty() && glob_match(rest, &s[1..]),
+        Some((b'\\', [c, rest @ ..])) | Some((c, rest)) => s.first() == Some(c) && glob_match(rest, &s[1..]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+    use super::*;
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match(b"session:*", b"session:42"));
+        assert!(glob_match(b"user:?:token", b"user:1:token"));
+        assert!(glob_match(b"a\\*", b"a*"));
+        assert!(!glob_match(b"a\\*", b"ab"));
+        assert!(!glob_match(b"session:*", b"user:42"));
+        assert!(!glob_match(b"user:?:token", b"user:12:token"));
+    }
+
+    #[test]
+    fn test_audit_hook() {
+        let records = Mutex::new(vec![]);
+        let hook = AuditHook::new(|r: &AuditRecord| records.lock().unwrap().push((r.name.clone(), r.key.clone())))
+            .redact_keys("session:*");
+        let cmds: Vec<&[&[u8]]> = vec![
+            &[b"SET", b"user:1", b"secret value"],
+            &[b"get", b"session:abc"],
+            &[b"auth", b"admin", b"password"],
+            &[b"hello", b"3", b"auth", b"admin", b"password"],
+        ];
+        for args in cmds {
+            let info = CommandInfo { args, peer: None };
+            hook.on_complete(&info, Duration::ZERO, Ok(&RespValue::Bulk(b"OK".to_vec())));
+        }
+        assert_eq!(*records.lock().unwrap(), vec![
+            ("set".to_string(), Some("user:1".to_string())),
+            ("get".to_string(), Some(REDACTED.to_string())),
+            ("auth".to_string(), None),
+            ("hello".to_string(), None),
+        ]);
+    }
+}
@@ -0,0 +1,336 @@
+// a load generator like redis-benchmark, also stressing the client itself:
+//
+//   ruis-bench --addr 127.0.0.1:6379 -c 50 -n 100000 -P 16 -t set:1,get:9 -r 100000 -d 16-1024
+//
+// the commands of the mix are picked by their weights. in the custom commands
+// given by --command, __rand_key__ is replaced by a key of the key space and
+// __data__ by a value of the value size.
+
+use std::env;
+use std::process;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use ruis::connection::TcpConnection;
+use ruis::pipeline::Pipeline;
+use ruis::tools::LatencyStats;
+use ruis::types::RespValue;
+
+const USAGE: &str = "usage: ruis-bench [options]
+  --addr <host:port>      server address (default 127.0.0.1:6379)
+  -a <password>           password for AUTH
+  -c <connections>        parallel connections (default 50)
+  -n <requests>           total requests (default 100000)
+  -P <depth>              commands per pipeline (default 1)
+  -t <mix>                command mix like set:1,get:9 of set, get and incr (default set,get)
+  --command <cmd>         a custom command added to the mix, like \"hset h __rand_key__ __data__\"
+  --weight <weight>       the weight of the last --command (default 1)
+  -r <keyspace>           keys picked from key:0 up to the keyspace (default 1)
+  --key-dist <dist>       uniform or zipf (default uniform)
+  -d <size>               value size in bytes, or a min-max range (default 3)";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum KeyDist {
+    Uniform,
+    // the ranks follow roughly a zipf distribution with s = 1, the low keys
+    // being the hot ones.
+    Zipf,
+}
+
+#[derive(Debug, Clone)]
+struct Op {
+    name: String,
+    args: Vec<String>,
+    weight: u32,
+}
+
+#[derive(Debug, Clone)]
+struct Config {
+    addr: String,
+    password: Option<String>,
+    connections: usize,
+    requests: usize,
+    pipeline: usize,
+    ops: Vec<Op>,
+    keyspace: u64,
+    key_dist: KeyDist,
+    value_size: (usize, usize),
+}
+
+fn main() {
+    let cfg = parse_args(env::args().skip(1).collect()).unwrap_or_else(|e| {
+        eprintln!("{}\n\n{}", e, USAGE);
+        process::exit(2);
+    });
+    if let Err(e) = run(&cfg) {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
+
+fn parse_args(args: Vec<String>) -> Result<Config, String> {
+    let mut cfg = Config {
+        addr: "127.0.0.1:6379".to_string(),
+        password: None,
+        connections: 50,
+        requests: 100_000,
+        pipeline: 1,
+        ops: vec![],
+        keyspace: 1,
+        key_dist: KeyDist::Uniform,
+        value_size: (3, 3),
+    };
+    let mut mix = None;
+    let mut customs: Vec<(String, u32)> = vec![];
+    let mut it = args.into_iter();
+    while let Some(flag) = it.next() {
+        if flag == "--help" {
+            println!("{}", USAGE);
+            process::exit(0);
+        }
+        let value = it.next().ok_or_else(|| format!("missing the value of {}", flag))?;
+        match flag.as_str() {
+            "--addr" => cfg.addr = value,
+            "-a" => cfg.password = Some(value),
+            "-c" => cfg.connections = parse_num(&flag, &value)?,
+            "-n" => cfg.requests = parse_num(&flag, &value)?,
+            "-P" => cfg.pipeline = parse_num(&flag, &value)?,
+            "-t" => mix = Some(value),
+            "--command" => customs.push((value, 1)),
+            "--weight" => match customs.last_mut() {
+                Some(c) => c.1 = parse_num(&flag, &value)?,
+                None => return Err("--weight should follow a --command".to_string()),
+            },
+            "-r" => cfg.keyspace = parse_num(&flag, &value)?,
+            "--key-dist" => cfg.key_dist = match value.as_str() {
+                "uniform" => KeyDist::Uniform,
+                "zipf" => KeyDist::Zipf,
+                _ => return Err(format!("unknown key distribution {}", value)),
+            },
+            "-d" => cfg.value_size = match value.split_once('-') {
+                Some((min, max)) => (parse_num(&flag, min)?, parse_num(&flag, max)?),
+                None => (parse_num(&flag, &value)?, parse_num(&flag, &value)?),
+            },
+            _ => return Err(format!("unknown option {}", flag)),
+        }
+    }
+    if cfg.connections == 0 || cfg.pipeline == 0 || cfg.keyspace == 0 {
+        return Err("-c, -P and -r should be positive".to_string());
+    }
+    if cfg.value_size.0 > cfg.value_size.1 {
+        return Err(format!("bad value size range {}-{}", cfg.value_size.0, cfg.value_size.1));
+    }
+    // the default mix is only run without custom commands.
+    if let Some(mix) = mix.or_else(|| if customs.is_empty() { Some("set,get".to_string()) } else { None }) {
+        for item in mix.split(',').filter(|s| !s.is_empty()) {
+            let (name, weight) = split_weight(item)?;
+            let args = match name.to_ascii_lowercase().as_str() {
+                "set" => "set key:__rand_key__ __data__",
+                "get" => "get key:__rand_key__",
+                "incr" => "incr counter:__rand_key__",
+                _ => return Err(format!("unknown test {}, use --command for the others", name)),
+            };
+            cfg.ops.push(Op { name: name.to_ascii_uppercase(), args: split_words(args), weight });
+        }
+    }
+    for (cmd, weight) in customs {
+        let args = split_words(&cmd);
+        if args.is_empty() {
+            return Err("empty --command".to_string());
+        }
+        cfg.ops.push(Op { name: args[0].to_ascii_uppercase(), args, weight });
+    }
+    if cfg.ops.iter().all(|op| op.weight == 0) {
+        return Err("no command to run".to_string());
+    }
+    Ok(cfg)
+}
+
+fn parse_num<T: std::str::FromStr>(flag: &str, s: &str) -> Result<T, String> {
+    s.parse().map_err(|_| format!("bad value {:?} of {}", s, flag))
+}
+
+// "get:9" is get with a weight of 9, the weight is 1 if not given.
+fn split_weight(s: &str) -> Result<(&str, u32), String> {
+    match s.split_once(':') {
+        Some((name, w)) => Ok((name, parse_num("-t", w)?)),
+        None => Ok((s, 1)),
+    }
+}
+
+fn split_words(s: &str) -> Vec<String> {
+    s.split_whitespace().map(|w| w.to_string()).collect()
+}
+
+// xorshift64*, good enough to pick the keys and the commands.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Rng(seed | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    // in [0, n).
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+
+    fn unit(&mut self) -> f64 {
+        (self.next() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+struct Worker<'a> {
+    cfg: &'a Config,
+    rng: Rng,
+    total_weight: u64,
+    data: Vec<u8>,
+}
+
+impl<'a> Worker<'a> {
+    fn new(cfg: &'a Config, seed: u64) -> Self {
+        Self {
+            cfg,
+            rng: Rng::new(seed),
+            total_weight: cfg.ops.iter().map(|op| op.weight as u64).sum(),
+            data: vec![b'x'; cfg.value_size.1],
+        }
+    }
+
+    fn pick_op(&mut self) -> usize {
+        let mut n = self.rng.below(self.total_weight);
+        for (i, op) in self.cfg.ops.iter().enumerate() {
+            if n < op.weight as u64 {
+                return i;
+            }
+            n -= op.weight as u64;
+        }
+        unreachable!()
+    }
+
+    fn pick_key(&mut self) -> u64 {
+        let n = self.cfg.keyspace;
+        match self.cfg.key_dist {
+            KeyDist::Uniform => self.rng.below(n),
+            // log-uniform over the ranks, which is the continuous zipf with s = 1.
+            KeyDist::Zipf => (((n as f64 + 1.0).powf(self.rng.unit()) - 1.0) as u64).min(n - 1),
+        }
+    }
+
+    fn build(&mut self, op: usize) -> Vec<Vec<u8>> {
+        let (min, max) = self.cfg.value_size;
+        let size = min + self.rng.below((max - min + 1) as u64) as usize;
+        let key = format!("{:012}", self.pick_key());
+        let cfg = self.cfg;
+        cfg.ops[op].args.iter().map(|arg| {
+            match arg.as_str() {
+                "__data__" => self.data[..size].to_vec(),
+                arg => arg.replace("__rand_key__", &key).into_bytes(),
+            }
+        }).collect()
+    }
+}
+
+#[derive(Default)]
+struct Stats {
+    latency: LatencyStats,
+    errors: usize,
+}
+
+fn run(cfg: &Config) -> Result<(), String> {
+    let issued = Arc::new(AtomicUsize::new(0));
+    let failed = Arc::new(AtomicBool::new(false));
+    let seed = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos() as u64;
+
+    // all the connections are made before the clock starts.
+    let mut conns = Vec::with_capacity(cfg.connections);
+    for _ in 0..cfg.connections {
+        conns.push(TcpConnection::connect(&cfg.addr, cfg.password.as_deref())
+            .map_err(|e| format!("connect to {}: {}", cfg.addr, e))?);
+    }
+
+    let started = Instant::now();
+    let results: Vec<Result<Vec<Stats>, String>> = thread::scope(|s| {
+        let handles: Vec<_> = conns.into_iter().enumerate().map(|(i, mut conn)| {
+            let (issued, failed) = (issued.clone(), failed.clone());
+            s.spawn(move || {
+                let mut worker = Worker::new(cfg, seed.wrapping_add(i as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15));
+                let mut stats: Vec<Stats> = cfg.ops.iter().map(|_| Stats::default()).collect();
+                let mut pipeline = Pipeline::new();
+                let mut ops = Vec::with_capacity(cfg.pipeline);
+                while !failed.load(Ordering::Relaxed) {
+                    let from = issued.fetch_add(cfg.pipeline, Ordering::Relaxed);
+                    if from >= cfg.requests {
+                        break;
+                    }
+                    pipeline.clear();
+                    ops.clear();
+                    for _ in from..cfg.requests.min(from + cfg.pipeline) {
+                        let op = worker.pick_op();
+                        let args = worker.build(op);
+                        let args: Vec<&[u8]> = args.iter().map(|a| a.as_slice()).collect();
+                        pipeline.cmd(&args);
+                        ops.push(op);
+                    }
+                    let t = Instant::now();
+                    let replies = conn.execute_pipeline(&pipeline).map_err(|e| {
+                        failed.store(true, Ordering::Relaxed);
+                        format!("connection #{}: {}", i, e)
+                    })?;
+                    // the commands in a pipeline all wait for the last reply.
+                    let elapsed = t.elapsed();
+                    for (&op, reply) in ops.iter().zip(replies.iter()) {
+                        stats[op].latency.record(elapsed);
+                        if let RespValue::Error(_) = reply {
+                            stats[op].errors += 1;
+                        }
+                    }
+                }
+                Ok(stats)
+            })
+        }).collect();
+        handles.into_iter().map(|h| h.join().unwrap_or_else(|_| Err("worker panicked".to_string()))).collect()
+    });
+    let elapsed = started.elapsed();
+
+    let mut merged: Vec<Stats> = cfg.ops.iter().map(|_| Stats::default()).collect();
+    for r in results {
+        for (m, s) in merged.iter_mut().zip(r?) {
+            m.latency.merge(&s.latency);
+            m.errors += s.errors;
+        }
+    }
+    report(cfg, elapsed, merged);
+    Ok(())
+}
+
+fn report(cfg: &Config, elapsed: Duration, stats: Vec<Stats>) {
+    let mut all = Stats::default();
+    for s in &stats {
+        all.latency.merge(&s.latency);
+        all.errors += s.errors;
+    }
+    let secs = elapsed.as_secs_f64().max(f64::EPSILON);
+    println!("{} requests in {:.3}s, {} connections, pipeline {}, {} keys, {}-{} bytes values",
+        all.latency.count(), secs, cfg.connections, cfg.pipeline, cfg.keyspace, cfg.value_size.0, cfg.value_size.1);
+    println!("throughput: {:.2} requests per second\n", all.latency.count() as f64 / secs);
+    println!("{:<12} {:>10} {:>8} {:>10} {:>10} {:>10} {:>10} {:>10}", "command", "requests", "errors", "p50 ms", "p90 ms", "p99 ms", "p99.9 ms", "max ms");
+    for (op, mut s) in cfg.ops.iter().map(|op| op.name.as_str()).zip(stats).chain(Some(("all", all))) {
+        if s.latency.count() == 0 {
+            continue;
+        }
+        let mut ms = |p: f64| s.latency.percentile(p).map(|d| d.as_secs_f64() * 1000.0).unwrap_or(0.0);
+        let (p50, p90, p99, p999, max) = (ms(50.0), ms(90.0), ms(99.0), ms(99.9), ms(100.0));
+        println!("{:<12} {:>10} {:>8} {:>10.3} {:>10.3} {:>10.3} {:>10.3} {:>10.3}",
+            op, s.latency.count(), s.errors, p50, p90, p99, p999, max);
+    }
+}
@@ -0,0 +1,485 @@
+// the redis commands and the operational tools of ruis::tools on the command
+// line:
+//
+//   ruis-cli --addr 127.0.0.1:6379 set greeting hello
+//   ruis-cli --addr 127.0.0.1:6379 bigkeys --pattern 'user:*' --rate 1000
+//   echo 'lrange "my list" 0 -1' | ruis-cli
+
+use std::env;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::process;
+use std::time::Duration;
+
+use ruis::connection::TcpConnection;
+use ruis::types::RespValue;
+use ruis::tools::{
+    self, BigKeysOptions, DeleteOptions, DiffOptions, LatencyMonitorOptions, LatencySummary, MemKeysOptions,
+    MigrateOptions, OnExisting, ValueCheck,
+};
+
+const USAGE: &str = "usage: ruis-cli [--addr <host:port>] [-a <password>] [<command> [options] | <redis command> [args]]
+
+the redis commands are sent as given and their replies printed like redis-cli does. without
+a command they are read from stdin, one per line, quoted like in redis-cli. the commands
+below are run by ruis-cli itself, even if redis has one of the same name.
+
+commands:
+  bigkeys     the biggest keys of each type
+              [--pattern <glob>] [--count <scan count>] [--top <n>] [--rate <keys/s>]
+  memkeys     the memory used by the groups of keys, like user:* for user:42
+              [--pattern <glob>] [--count <scan count>] [--limit <keys sampled>]
+              [--samples <n>] [--delimiter <char>] [--depth <n>] [--rate <keys/s>]
+              [--group <name>=<glob>]...
+  delete      deletes the keys matching the pattern with SCAN and UNLINK
+              --pattern <glob> [--count <scan count>] [--rate <keys/s>] [--dry-run]
+  latency     PINGs continuously and prints the round trips, like redis-cli --latency
+              [--interval <ms>] [--window <secs>] [--every <secs>] [--reports <n>]
+  migrate     copies the keys to another server with DUMP and RESTORE, keeping the TTLs
+              --to <host:port> [--to-password <password>] [--pattern <glob>]
+              [--count <scan count>] [--workers <n>] [--retries <n>] [--rate <keys/s>] [--replace]
+  diff        compares the keys, values and TTLs with another server, exits 1 on differences
+              --with <host:port> [--with-password <password>] [--pattern <glob>]
+              [--count <scan count>] [--values none|dump|digest] [--ttl-tolerance <ms>] [--rate <keys/s>]";
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if let Err(e) = run(args) {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
+
+fn usage(msg: &str) -> ! {
+    eprintln!("{}\n\n{}", msg, USAGE);
+    process::exit(2);
+}
+
+// the "--name value" options of a command in the order given, and the
+// switches like "--dry-run" taking no value.
+struct Flags(Vec<(String, String)>);
+
+impl Flags {
+    fn parse(args: &[String], known: &[&str], switches: &[&str]) -> Flags {
+        let mut flags = vec![];
+        let mut it = args.iter();
+        while let Some(flag) = it.next() {
+            if switches.contains(&flag.as_str()) {
+                flags.push((flag.clone(), String::new()));
+                continue;
+            }
+            if !known.contains(&flag.as_str()) {
+                usage(&format!("unknown option {}", flag));
+            }
+            match it.next() {
+                Some(value) => flags.push((flag.clone(), value.clone())),
+                None => usage(&format!("missing the value of {}", flag)),
+            };
+        }
+        Flags(flags)
+    }
+
+    // the last one if given more than once.
+    fn get(&self, flag: &str) -> Option<&str> {
+        self.0.iter().rev().find(|(f, _)| f == flag).map(|(_, v)| v.as_str())
+    }
+
+    fn all<'a>(&'a self, flag: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.0.iter().filter(move |(f, _)| f == flag).map(|(_, v)| v.as_str())
+    }
+
+    fn has(&self, switch: &str) -> bool {
+        self.0.iter().any(|(f, _)| f == switch)
+    }
+
+    fn num<T: std::str::FromStr>(&self, flag: &str) -> Option<T> {
+        self.get(flag).map(|s| s.parse().unwrap_or_else(|_| usage(&format!("bad value {:?} of {}", s, flag))))
+    }
+}
+
+fn run(args: Vec<String>) -> Result<(), String> {
+    let mut addr = "127.0.0.1:6379".to_string();
+    let mut password = None;
+    let mut it = args.into_iter();
+    let command = loop {
+        match it.next().as_deref() {
+            Some("--addr") => addr = it.next().unwrap_or_else(|| usage("missing the value of --addr")),
+            Some("-a") => password = Some(it.next().unwrap_or_else(|| usage("missing the value of -a"))),
+            Some("--help") => {
+                println!("{}", USAGE);
+                return Ok(());
+            },
+            Some(command) => break Some(command.to_string()),
+            None => break None,
+        }
+    };
+    let rest: Vec<String> = it.collect();
+    let command = match command {
+        Some(command) => command,
+        None => {
+            let mut conn = TcpConnection::connect(&addr, password.as_deref()).map_err(|e| format!("connect to {}: {}", addr, e))?;
+            return repl(&mut conn, &addr);
+        },
+    };
+    // connects to both servers itself.
+    if command == "migrate" {
+        let flags = Flags::parse(&rest, &["--to", "--to-password", "--pattern", "--count", "--workers", "--retries", "--rate"], &["--replace"]);
+        return migrate(&addr, password.as_deref(), flags);
+    }
+    let mut conn = TcpConnection::connect(&addr, password.as_deref()).map_err(|e| format!("connect to {}: {}", addr, e))?;
+    match command.as_str() {
+        "bigkeys" => bigkeys(&mut conn, Flags::parse(&rest, &["--pattern", "--count", "--top", "--rate"], &[])),
+        "memkeys" => memkeys(&mut conn, Flags::parse(&rest, &[
+            "--pattern", "--count", "--limit", "--samples", "--delimiter", "--depth", "--rate", "--group",
+        ], &[])),
+        "latency" => latency(&mut conn, Flags::parse(&rest, &["--interval", "--window", "--every", "--reports"], &[])),
+        "diff" => diff(&mut conn, Flags::parse(&rest, &[
+            "--with", "--with-password", "--pattern", "--count", "--values", "--ttl-tolerance", "--rate",
+        ], &[])),
+        "delete" => delete(&mut conn, Flags::parse(&rest, &["--pattern", "--count", "--rate"], &["--dry-run"])),
+        _ => {
+            let mut cmd = vec![command.into_bytes()];
+            cmd.extend(rest.into_iter().map(String::into_bytes));
+            send(&mut conn, &cmd)
+        },
+    }
+}
+
+fn send(conn: &mut TcpConnection, cmd: &[Vec<u8>]) -> Result<(), String> {
+    let cmd: Vec<&[u8]> = cmd.iter().map(|arg| arg.as_slice()).collect();
+    let reply = conn.execute(&cmd).map_err(|e| e.to_string())?;
+    println!("{}", format_reply(&reply, 0));
+    Ok(())
+}
+
+// the commands read from stdin, with a prompt if it's a terminal.
+fn repl(conn: &mut TcpConnection, addr: &str) -> Result<(), String> {
+    let stdin = io::stdin();
+    let interactive = stdin.is_terminal();
+    let mut lines = stdin.lock().lines();
+    loop {
+        if interactive {
+            print!("{}> ", addr);
+            io::stdout().flush().map_err(|e| e.to_string())?;
+        }
+        let line = match lines.next() {
+            Some(line) => line.map_err(|e| e.to_string())?,
+            None => return Ok(()),
+        };
+        let cmd = match split_args(&line) {
+            Ok(cmd) => cmd,
+            Err(e) => {
+                eprintln!("{}", e);
+                continue;
+            },
+        };
+        match cmd.first().map(|name| name.to_ascii_lowercase()) {
+            None => continue,
+            Some(name) if name == b"quit" || name == b"exit" => return Ok(()),
+            Some(_) => send(conn, &cmd)?,
+        }
+    }
+}
+
+// splits a line into the arguments like redis-cli, the double quoted ones
+// take the escapes like \n or \x41, the single quoted ones only \'.
+fn split_args(line: &str) -> Result<Vec<Vec<u8>>, String> {
+    let mut args = vec![];
+    let mut chars = line.chars().peekable();
+    loop {
+        while chars.peek().is_some_and(|c| c.is_whitespace()) {
+            chars.next();
+        }
+        let quote = match chars.peek() {
+            None => return Ok(args),
+            Some(&c) if c == '"' || c == '\'' => chars.next(),
+            Some(_) => None,
+        };
+        let mut arg = vec![];
+        loop {
+            let c = match (chars.next(), quote) {
+                (None, None) => break,
+                (None, Some(_)) => return Err("unbalanced quotes".to_string()),
+                (Some(c), None) if c.is_whitespace() => break,
+                (Some(c), Some(q)) if c == q => {
+                    if chars.peek().is_some_and(|c| !c.is_whitespace()) {
+                        return Err("closing quote must be followed by a space".to_string());
+                    }
+                    break;
+                },
+                (Some('\\'), Some('"')) => match chars.next() {
+                    Some('n') => '\n',
+                    Some('r') => '\r',
+                    Some('t') => '\t',
+                    Some('b') => '\u{8}',
+                    Some('a') => '\u{7}',
+                    Some('x') => {
+                        let hex: String = chars.by_ref().take(2).collect();
+                        match u8::from_str_radix(&hex, 16) {
+                            Ok(b) if hex.len() == 2 => {
+                                arg.push(b);
+                                continue;
+                            },
+                            _ => return Err(format!("bad escape \\x{}", hex)),
+                        }
+                    },
+                    Some(c) => c,
+                    None => return Err("unbalanced quotes".to_string()),
+                },
+                (Some('\\'), Some('\'')) if chars.peek() == Some(&'\'') => {
+                    chars.next();
+                    '\''
+                },
+                (Some(c), _) => c,
+            };
+            let mut buf = [0u8; 4];
+            arg.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+        }
+        args.push(arg);
+    }
+}
+
+// the replies as redis-cli prints them, the nested arrays indented under
+// their index. the status replies like OK are read as bulks, so are quoted.
+fn format_reply(v: &RespValue, indent: usize) -> String {
+    match v {
+        RespValue::Int(n) => format!("(integer) {}", n),
+        RespValue::Bulk(bs) => quote(bs),
+        RespValue::NilBulk | RespValue::NilArray => "(nil)".to_string(),
+        RespValue::Error(bs) => format!("(error) {}", String::from_utf8_lossy(bs)),
+        RespValue::Array(items) if items.is_empty() => "(empty array)".to_string(),
+        RespValue::Array(items) => {
+            let width = items.len().to_string().len();
+            let mut out = String::new();
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push('\n');
+                    out.push_str(&" ".repeat(indent));
+                }
+                out.push_str(&format!("{:>w$}) {}", i + 1, format_reply(item, indent + width + 2), w = width));
+            }
+            out
+        },
+    }
+}
+
+fn quote(bs: &[u8]) -> String {
+    let mut out = String::from("\"");
+    for &b in bs {
+        match b {
+            b'\\' => out.push_str("\\\\"),
+            b'"' => out.push_str("\\\""),
+            b'\n' => out.push_str("\\n"),
+            b'\r' => out.push_str("\\r"),
+            b'\t' => out.push_str("\\t"),
+            0x07 => out.push_str("\\a"),
+            0x08 => out.push_str("\\b"),
+            b if b.is_ascii_graphic() || b == b' ' => out.push(b as char),
+            b => out.push_str(&format!("\\x{:02x}", b)),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn bigkeys(conn: &mut TcpConnection, flags: Flags) -> Result<(), String> {
+    let mut opts = BigKeysOptions::new();
+    if let Some(pattern) = flags.get("--pattern") {
+        opts = opts.pattern(pattern);
+    }
+    if let Some(n) = flags.num("--count") {
+        opts = opts.scan_count(n);
+    }
+    if let Some(n) = flags.num("--top") {
+        opts = opts.top(n);
+    }
+    if let Some(n) = flags.num("--rate") {
+        opts = opts.keys_per_sec(n);
+    }
+    let report = conn.big_keys(&opts).map_err(|e| e.to_string())?;
+    println!("{} keys scanned", report.keys_scanned);
+    for t in &report.types {
+        println!("\n{}: {} keys, {} {} in total, p50 {} p90 {} p99 {} max {}",
+            t.key_type, t.keys, t.total_size, t.unit(), t.p50, t.p90, t.p99, t.max);
+        for k in &t.biggest {
+            println!("  {:>12} {}", k.size, String::from_utf8_lossy(&k.key));
+        }
+    }
+    Ok(())
+}
+
+fn memkeys(conn: &mut TcpConnection, flags: Flags) -> Result<(), String> {
+    let mut opts = MemKeysOptions::new();
+    if let Some(pattern) = flags.get("--pattern") {
+        opts = opts.pattern(pattern);
+    }
+    if let Some(n) = flags.num("--count") {
+        opts = opts.scan_count(n);
+    }
+    if let Some(n) = flags.num("--limit") {
+        opts = opts.sample_limit(n);
+    }
+    if let Some(n) = flags.num("--samples") {
+        opts = opts.usage_samples(n);
+    }
+    if let Some(d) = flags.get("--delimiter") {
+        match d.as_bytes() {
+            [b] => opts = opts.delimiter(*b),
+            _ => usage("--delimiter should be a single byte"),
+        }
+    }
+    if let Some(n) = flags.num("--depth") {
+        opts = opts.depth(n);
+    }
+    if let Some(n) = flags.num("--rate") {
+        opts = opts.keys_per_sec(n);
+    }
+    for group in flags.all("--group") {
+        match group.split_once('=') {
+            Some((name, glob)) => opts = opts.group(name, glob),
+            None => usage(&format!("bad --group {}, expected <name>=<glob>", group)),
+        }
+    }
+    let report = conn.mem_keys(&opts).map_err(|e| e.to_string())?;
+    println!("{} keys sampled, {} bytes\n", report.keys_sampled, report.bytes_sampled);
+    println!("{:<32} {:>10} {:>14} {:>7}  biggest key", "group", "keys", "bytes", "share");
+    for g in &report.groups {
+        println!("{:<32} {:>10} {:>14} {:>6.2}%  {} ({} bytes)",
+            g.group, g.keys, g.bytes, g.share * 100.0, String::from_utf8_lossy(&g.biggest_key), g.biggest_bytes);
+    }
+    Ok(())
+}
+
+fn delete(conn: &mut TcpConnection, flags: Flags) -> Result<(), String> {
+    let pattern = flags.get("--pattern").unwrap_or_else(|| usage("delete needs a --pattern"));
+    let mut opts = DeleteOptions::new(pattern).dry_run(flags.has("--dry-run"));
+    if let Some(n) = flags.num("--count") {
+        opts = opts.scan_count(n);
+    }
+    if let Some(n) = flags.num("--rate") {
+        opts = opts.keys_per_sec(n);
+    }
+    let summary = conn.delete_matching(&opts, |p| {
+        if p.dry_run {
+            for key in p.batch {
+                println!("{}", String::from_utf8_lossy(key));
+            }
+        } else {
+            eprint!("\r{} matched, {} deleted", p.matched, p.deleted);
+        }
+    }).map_err(|e| e.to_string())?;
+    if flags.has("--dry-run") {
+        eprintln!("{} keys would be deleted", summary.matched);
+    } else {
+        eprintln!("\r{} matched, {} deleted", summary.matched, summary.deleted);
+    }
+    Ok(())
+}
+
+fn latency(conn: &mut TcpConnection, flags: Flags) -> Result<(), String> {
+    let mut opts = LatencyMonitorOptions::new();
+    if let Some(ms) = flags.num("--interval") {
+        opts = opts.interval(Duration::from_millis(ms));
+    }
+    if let Some(secs) = flags.num("--window") {
+        opts = opts.window(Duration::from_secs_f64(secs));
+    }
+    if let Some(secs) = flags.num("--every") {
+        opts = opts.report_every(Duration::from_secs_f64(secs));
+    }
+    let reports: Option<usize> = flags.num("--reports");
+    let mut n = 0;
+    let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+    let line = |s: &LatencySummary| format!("min {:.2} avg {:.2} p50 {:.2} p90 {:.2} p99 {:.2} max {:.2} ms ({} samples)",
+        ms(s.min), ms(s.avg), ms(s.p50), ms(s.p90), ms(s.p99), ms(s.max), s.samples);
+    conn.latency_monitor(&opts, |r| {
+        println!("{:>7.1}s window: {} | total: {}", r.elapsed.as_secs_f64(), line(&r.window), line(&r.total));
+        n += 1;
+        reports.is_none_or(|max| n < max)
+    }).map_err(|e| e.to_string())
+}
+
+fn migrate(addr: &str, password: Option<&str>, flags: Flags) -> Result<(), String> {
+    let target = flags.get("--to").unwrap_or_else(|| usage("migrate needs --to"));
+    let mut opts = MigrateOptions::new();
+    if let Some(password) = password {
+        opts = opts.source_password(password);
+    }
+    if let Some(password) = flags.get("--to-password") {
+        opts = opts.target_password(password);
+    }
+    if let Some(pattern) = flags.get("--pattern") {
+        opts = opts.pattern(pattern);
+    }
+    if let Some(n) = flags.num("--count") {
+        opts = opts.scan_count(n);
+    }
+    if let Some(n) = flags.num("--workers") {
+        opts = opts.workers(n);
+    }
+    if let Some(n) = flags.num("--retries") {
+        opts = opts.retries(n, Duration::from_millis(100));
+    }
+    if let Some(n) = flags.num("--rate") {
+        opts = opts.keys_per_sec(n);
+    }
+    if flags.has("--replace") {
+        opts = opts.on_existing(OnExisting::Replace);
+    }
+    let summary = tools::migrate(addr, target, &opts, |s| {
+        eprint!("\r{} scanned, {} copied, {} skipped, {} missing, {} failed", s.scanned, s.copied, s.skipped, s.missing, s.failed.len());
+    }).map_err(|e| e.to_string())?;
+    eprintln!();
+    for (key, reason) in &summary.failed {
+        println!("failed {}: {}", String::from_utf8_lossy(key), reason);
+    }
+    if !summary.failed.is_empty() {
+        return Err(format!("{} keys not copied", summary.failed.len()));
+    }
+    Ok(())
+}
+
+fn diff(conn: &mut TcpConnection, flags: Flags) -> Result<(), String> {
+    let other = flags.get("--with").unwrap_or_else(|| usage("diff needs --with"));
+    let mut target = TcpConnection::connect(other, flags.get("--with-password")).map_err(|e| format!("connect to {}: {}", other, e))?;
+    let mut opts = DiffOptions::new();
+    if let Some(pattern) = flags.get("--pattern") {
+        opts = opts.pattern(pattern);
+    }
+    if let Some(n) = flags.num("--count") {
+        opts = opts.scan_count(n);
+    }
+    if let Some(check) = flags.get("--values") {
+        opts = opts.values(match check {
+            "none" => ValueCheck::None,
+            "dump" => ValueCheck::Dump,
+            "digest" => ValueCheck::Digest,
+            _ => usage(&format!("unknown --values {}", check)),
+        });
+    }
+    if let Some(ms) = flags.num("--ttl-tolerance") {
+        opts = opts.ttl_tolerance(Duration::from_millis(ms));
+    }
+    if let Some(n) = flags.num("--rate") {
+        opts = opts.keys_per_sec(n);
+    }
+    let report = tools::diff(conn, &mut target, &opts).map_err(|e| e.to_string())?;
+    let show = |key: &[u8]| String::from_utf8_lossy(key).into_owned();
+    for key in &report.missing_in_target {
+        println!("missing in {}: {}", other, show(key));
+    }
+    for key in &report.missing_in_source {
+        println!("only in {}: {}", other, show(key));
+    }
+    for key in &report.value_mismatches {
+        println!("value differs: {}", show(key));
+    }
+    for m in &report.ttl_mismatches {
+        println!("ttl differs: {} {:?} ms vs {:?} ms", show(&m.key), m.source_ttl, m.target_ttl);
+    }
+    eprintln!("{} keys compared", report.compared);
+    if report.is_empty() {
+        Ok(())
+    } else {
+        Err("the keyspaces differ".to_string())
+    }
+}
@@ -0,0 +1,204 @@
+use std::io::{BufRead, Write};
+
+use super::connection::GenericConnection;
+use super::types::RuisError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BitOp {
+    And,
+    Or,
+    Xor,
+    // takes a single key.
+    Not,
+}
+
+impl BitOp {
+    fn as_arg(self) -> &'static [u8] {
+        match self {
+            BitOp::And => b"and",
+            BitOp::Or => b"or",
+            BitOp::Xor => b"xor",
+            BitOp::Not => b"not",
+        }
+    }
+}
+
+// the integer type of a BITFIELD field, up to i64 or u63.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BitType {
+    Signed(u8),
+    Unsigned(u8),
+}
+
+impl BitType {
+    fn to_arg(self) -> String {
+        match self {
+            BitType::Signed(bits) => format!("i{}", bits),
+            BitType::Unsigned(bits) => format!("u{}", bits),
+        }
+    }
+}
+
+// where a BITFIELD field starts, in bits, or in fields of its type, like the
+// third u8 of an array of u8.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BitOffset {
+    Bits(u64),
+    Fields(u64),
+}
+
+impl From<u64> for BitOffset {
+    fn from(bits: u64) -> Self {
+        BitOffset::Bits(bits)
+    }
+}
+
+impl BitOffset {
+    fn to_arg(self) -> String {
+        match self {
+            BitOffset::Bits(n) => n.to_string(),
+            BitOffset::Fields(n) => format!("#{}", n),
+        }
+    }
+}
+
+// what SET and INCRBY do on overflow, the ones after it in the BITFIELD are
+// affected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Overflow {
+    // the default of redis.
+    Wrap,
+    Sat,
+    // the operation is not done, and its result is nil.
+    Fail,
+}
+
+impl Overflow {
+    fn as_arg(self) -> &'static str {
+        match self {
+            Overflow::Wrap => "wrap",
+            Overflow::Sat => "sat",
+            Overflow::Fail => "fail",
+        }
+    }
+}
+
+// the sub-commands of a BITFIELD, run in order:
+//
+//   let ops = BitField::new()
+//       .overflow(Overflow::Sat)
+//       .incrby(BitType::Unsigned(8), BitOffset::Fields(2), 10)
+//       .get(BitType::Signed(4), 0);
+//   let results = conn.bitfield(b"k", &ops)?;
+//
+// there is a result per GET, SET and INCRBY, the old value for SET.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BitField {
+    args: Vec<String>,
+}
+
+impl BitField {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get<O: Into<BitOffset>>(mut self, ty: BitType, offset: O) -> Self {
+        self.args.extend(["get".to_string(), ty.to_arg(), offset.into().to_arg()]);
+        self
+    }
+
+    pub fn set<O: Into<BitOffset>>(mut self, ty: BitType, offset: O, value: i64) -> Self {
+        self.args.extend(["set".to_string(), ty.to_arg(), offset.into().to_arg(), value.to_string()]);
+        self
+    }
+
+    pub fn incrby<O: Into<BitOffset>>(mut self, ty: BitType, offset: O, increment: i64) -> Self {
+        self.args.extend(["incrby".to_string(), ty.to_arg(), offset.into().to_arg(), increment.to_string()]);
+        self
+    }
+
+    pub fn overflow(mut self, overflow: Overflow) -> Self {
+        self.args.extend(["overflow".to_string(), overflow.as_arg().to_string()]);
+        self
+    }
+}
+
+impl<W: Write, R: BufRead> GenericConnection<W, R> {
+    // returns the bit before.
+    pub fn setbit(&mut self, key: &[u8], offset: u64, value: bool) -> Result<bool, RuisError> {
+        let offset = offset.to_string();
+        let bit: &[u8] = if value { b"1" } else { b"0" };
+        self.execute_as::<i64>(&[b"setbit", key, offset.as_bytes(), bit]).map(|n| n == 1)
+    }
+
+    // the bits past the end of the string are 0.
+    pub fn getbit(&mut self, key: &[u8], offset: u64) -> Result<bool, RuisError> {
+        let offset = offset.to_string();
+        self.execute_as::<i64>(&[b"getbit", key, offset.as_bytes()]).map(|n| n == 1)
+    }
+
+    // the bits set in the whole string, or in the range of bytes, the
+    // negative indexes count from the end.
+    pub fn bitcount(&mut self, key: &[u8], range: Option<(i64, i64)>) -> Result<i64, RuisError> {
+        match range {
+            Some((start, end)) => {
+                let (start, end) = (start.to_string(), end.to_string());
+                self.execute_as(&[b"bitcount", key, start.as_bytes(), end.as_bytes()])
+            },
+            None => self.execute_as(&[b"bitcount", key]),
+        }
+    }
+
+    // stores the result into dest, returns its length in bytes, the length
+    // of the longest key.
+    pub fn bitop(&mut self, op: BitOp, dest: &[u8], keys: &[&[u8]]) -> Result<i64, RuisError> {
+        let mut cmd: Vec<&[u8]> = vec![b"bitop", op.as_arg(), dest];
+        cmd.extend_from_slice(keys);
+        self.execute_as(&cmd)
+    }
+
+    // returns a result per sub-command, None for the ones not done on an
+    // Overflow::Fail.
+    pub fn bitfield(&mut self, key: &[u8], ops: &BitField) -> Result<Vec<Option<i64>>, RuisError> {
+        let mut cmd: Vec<&[u8]> = vec![b"bitfield", key];
+        cmd.extend(ops.args.iter().map(|a| a.as_bytes()));
+        self.execute_as(&cmd)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::testing::Mock;
+    use super::super::types::RespValue;
+
+    #[test]
+    fn test_bits() {
+        let mock = Mock::new();
+        mock.expect(&[b"setbit", b"k", b"7", b"1"], RespValue::Int(0))
+            .expect(&[b"getbit", b"k", b"7"], RespValue::Int(1))
+            .expect_args(&("bitcount", "k", 0, -1), RespValue::Int(3))
+            .expect(&[b"bitop", b"or", b"dest", b"a", b"b"], RespValue::Int(2));
+        let mut c = mock.connection();
+        assert!(!c.setbit(b"k", 7, true).unwrap());
+        assert!(c.getbit(b"k", 7).unwrap());
+        assert_eq!(c.bitcount(b"k", Some((0, -1))).unwrap(), 3);
+        assert_eq!(c.bitop(BitOp::Or, b"dest", &[b"a", b"b"]).unwrap(), 2);
+        mock.assert_done();
+    }
+
+    #[test]
+    fn test_bitfield() {
+        let mock = Mock::new();
+        let args = ["bitfield", "k", "set", "u8", "#1", "200", "overflow", "fail", "incrby", "u8", "#1", "100", "get", "i4", "0"];
+        mock.expect_args(&args, RespValue::Array(vec![RespValue::Int(0), RespValue::NilBulk, RespValue::Int(-3)]));
+        let mut c = mock.connection();
+        let ops = BitField::new()
+            .set(BitType::Unsigned(8), BitOffset::Fields(1), 200)
+            .overflow(Overflow::Fail)
+            .incrby(BitType::Unsigned(8), BitOffset::Fields(1), 100)
+            .get(BitType::Signed(4), 0);
+        assert_eq!(c.bitfield(b"k", &ops).unwrap(), vec![Some(0), None, Some(-3)]);
+        mock.assert_done();
+    }
+}
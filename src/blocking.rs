@@ -0,0 +1,235 @@
+use std::net::Shutdown;
+use std::time::Duration;
+
+use super::client::Client;
+use super::connection::TcpConnection;
+use super::convert::FromResp;
+use super::pool::PooledConnection;
+use super::types::{RespValue, RuisError};
+
+// the end of a list BLMOVE pops from or pushes to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListSide {
+    Left,
+    Right,
+}
+
+impl ListSide {
+    fn as_arg(self) -> &'static [u8] {
+        match self {
+            ListSide::Left => b"left",
+            ListSide::Right => b"right",
+        }
+    }
+}
+
+// the key and the value popped by BLPOP or BRPOP.
+pub type Popped = (Vec<u8>, Vec<u8>);
+
+// a member popped by BZPOPMIN or BZPOPMAX.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ZPopped {
+    pub key: Vec<u8>,
+    pub member: Vec<u8>,
+    pub score: f64,
+}
+
+// BlockingCommands wraps the commands blocking the connection until a value
+// is there or the timeout passes. the read timeout of the socket is extended
+// by the timeout of the command while it blocks, so waiting for the value
+// is not taken as a dead server, and a zero timeout, which waits forever,
+// lifts it. the typed commands return None on timeout.
+//
+// a read timing out still means the reply might arrive later, the connection
+// is broken then and is not put back to its pool.
+pub trait BlockingCommands {
+    fn execute_blocking(&mut self, cmd: &[&[u8]], timeout: Duration) -> Result<RespValue, RuisError>;
+
+    // returns the key and the value popped from the first non empty list.
+    fn blpop(&mut self, keys: &[&[u8]], timeout: Duration) -> Result<Option<Popped>, RuisError> {
+        pop_pair(self.execute_blocking(&keyed(b"blpop", keys, &timeout_arg(timeout)), timeout)?)
+    }
+
+    fn brpop(&mut self, keys: &[&[u8]], timeout: Duration) -> Result<Option<Popped>, RuisError> {
+        pop_pair(self.execute_blocking(&keyed(b"brpop", keys, &timeout_arg(timeout)), timeout)?)
+    }
+
+    // returns the value moved, redis 6.2 and later.
+    fn blmove(&mut self, src: &[u8], dst: &[u8], from: ListSide, to: ListSide, timeout: Duration) -> Result<Option<Vec<u8>>, RuisError> {
+        let t = timeout_arg(timeout);
+        FromResp::from_resp(self.execute_blocking(&[b"blmove", src, dst, from.as_arg(), to.as_arg(), &t], timeout)?.into_result()?)
+    }
+
+    fn bzpopmin(&mut self, keys: &[&[u8]], timeout: Duration) -> Result<Option<ZPopped>, RuisError> {
+        zpopped(self.execute_blocking(&keyed(b"bzpopmin", keys, &timeout_arg(timeout)), timeout)?)
+    }
+
+    fn bzpopmax(&mut self, keys: &[&[u8]], timeout: Duration) -> Result<Option<ZPopped>, RuisError> {
+        zpopped(self.execute_blocking(&keyed(b"bzpopmax", keys, &timeout_arg(timeout)), timeout)?)
+    }
+}
+
+// the timeouts are in seconds, the fractional ones need redis 6.0, so they
+// are only sent for the timeouts not in whole seconds. the ones under a
+// millisecond are rounded up, as 0 waits forever.
+fn timeout_arg(timeout: Duration) -> Vec<u8> {
+    let millis = timeout.subsec_millis();
+    if timeout.subsec_nanos() == 0 {
+        timeout.as_secs().to_string().into_bytes()
+    } else if timeout < Duration::from_millis(1) {
+        b"0.001".to_vec()
+    } else {
+        format!("{}.{:03}", timeout.as_secs(), millis).into_bytes()
+    }
+}
+
+fn keyed<'a>(name: &'a [u8], keys: &[&'a [u8]], timeout: &'a [u8]) -> Vec<&'a [u8]> {
+    let mut cmd = Vec::with_capacity(keys.len() + 2);
+    cmd.push(name);
+    cmd.extend_from_slice(keys);
+    cmd.push(timeout);
+    cmd
+}
+
+fn pop_pair(reply: RespValue) -> Result<Option<Popped>, RuisError> {
+    let v: Option<Vec<Vec<u8>>> = FromResp::from_resp(reply.into_result()?)?;
+    match v {
+        None => Ok(None),
+        Some(v) if v.len() == 2 => {
+            let mut v = v.into_iter();
+            Ok(Some((v.next().unwrap(), v.next().unwrap())))
+        },
+        Some(v) => Err(RuisError::Unexpected(format!("blocking pop: {} elements in the reply", v.len()))),
+    }
+}
+
+fn zpopped(reply: RespValue) -> Result<Option<ZPopped>, RuisError> {
+    let v: Option<Vec<Vec<u8>>> = FromResp::from_resp(reply.into_result()?)?;
+    match v {
+        None => Ok(None),
+        Some(v) if v.len() == 3 => {
+            let mut v = v.into_iter();
+            let key = v.next().unwrap();
+            let member = v.next().unwrap();
+            let score = v.next().unwrap();
+            let score = std::str::from_utf8(&score).ok().and_then(|s| s.parse().ok())
+                .ok_or_else(|| RuisError::Unexpected(format!("blocking zpop: score {:?}", String::from_utf8_lossy(&score))))?;
+            Ok(Some(ZPopped { key, member, score }))
+        },
+        Some(v) => Err(RuisError::Unexpected(format!("blocking zpop: {} elements in the reply", v.len()))),
+    }
+}
+
+// runs the command with the read timeout of the connection extended by the
+// timeout of the command. the second value is false if the read timeout
+// could not be restored after the reply.
+fn execute_extended(conn: &mut TcpConnection, cmd: &[&[u8]], timeout: Duration) -> (Result<RespValue, RuisError>, bool) {
+    let prev = match conn.stream().read_timeout() {
+        Ok(prev) => prev,
+        Err(e) => return (Err(e.into()), true),
+    };
+    if let Some(prev) = prev {
+        let extended = if timeout == Duration::from_secs(0) { None } else { Some(prev + timeout) };
+        if let Err(e) = conn.stream().set_read_timeout(extended) {
+            return (Err(e.into()), true);
+        }
+    }
+    let r = conn.execute(cmd);
+    let restored = prev.is_none() || conn.stream().set_read_timeout(prev).is_ok();
+    (r, restored)
+}
+
+impl BlockingCommands for TcpConnection {
+    fn execute_blocking(&mut self, cmd: &[&[u8]], timeout: Duration) -> Result<RespValue, RuisError> {
+        let (r, restored) = execute_extended(self, cmd, timeout);
+        if !restored {
+            // the next commands would wait as long, so the connection is shut
+            // down, and fails them instead.
+            let _ = self.stream().shutdown(Shutdown::Both);
+        }
+        r
+    }
+}
+
+impl BlockingCommands for PooledConnection<'_> {
+    fn execute_blocking(&mut self, cmd: &[&[u8]], timeout: Duration) -> Result<RespValue, RuisError> {
+        let (r, restored) = execute_extended(self, cmd, timeout);
+        if !restored {
+            self.mark_broken();
+        }
+        self.track(r)
+    }
+}
+
+// the commands block a connection of the pool, the other commands of the
+// client go to the other connections meanwhile. only the standalone
+// deployments are supported, as get_connection().
+impl BlockingCommands for Client {
+    fn execute_blocking(&mut self, cmd: &[&[u8]], timeout: Duration) -> Result<RespValue, RuisError> {
+        self.get_connection()?.execute_blocking(cmd, timeout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufReader, Write};
+    use std::net::TcpListener;
+    use std::thread;
+    use super::*;
+    use super::super::resp::RespReader;
+
+    // replies each command after the delay, with the commands read sent back
+    // to the test.
+    fn slow_server(replies: Vec<&'static [u8]>, delay: Duration) -> (String, thread::JoinHandle<Vec<RespValue>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut r = RespReader::new(BufReader::new(stream.try_clone().unwrap()));
+            let mut cmds = vec![];
+            for reply in replies {
+                cmds.push(r.read().unwrap());
+                thread::sleep(delay);
+                stream.write_all(reply).unwrap();
+            }
+            cmds
+        });
+        (addr, server)
+    }
+
+    #[test]
+    fn test_timeout_arg() {
+        assert_eq!(timeout_arg(Duration::from_secs(0)), b"0".to_vec());
+        assert_eq!(timeout_arg(Duration::from_secs(2)), b"2".to_vec());
+        assert_eq!(timeout_arg(Duration::from_millis(1500)), b"1.500".to_vec());
+        assert_eq!(timeout_arg(Duration::from_micros(10)), b"0.001".to_vec());
+    }
+
+    #[test]
+    fn test_blocking() {
+        let replies: Vec<&'static [u8]> = vec![
+            b"*2\r\n$1\r\nq\r\n$1\r\nv\r\n",
+            b"*-1\r\n",
+            b"*3\r\n$1\r\nz\r\n$1\r\nm\r\n$3\r\n1.5\r\n",
+        ];
+        let (addr, server) = slow_server(replies, Duration::from_millis(200));
+        let mut conn = TcpConnection::connect(&addr, None).unwrap();
+        conn.stream().set_read_timeout(Some(Duration::from_millis(50))).unwrap();
+        // as rounded by the kernel.
+        let read_timeout = conn.stream().read_timeout().unwrap();
+
+        assert_eq!(conn.blpop(&[b"p", b"q"], Duration::from_secs(1)).unwrap(), Some((b"q".to_vec(), b"v".to_vec())));
+        assert_eq!(conn.stream().read_timeout().unwrap(), read_timeout);
+        assert_eq!(conn.brpop(&[b"q"], Duration::from_millis(500)).unwrap(), None);
+        let popped = ZPopped { key: b"z".to_vec(), member: b"m".to_vec(), score: 1.5 };
+        assert_eq!(conn.bzpopmin(&[b"z"], Duration::from_secs(0)).unwrap(), Some(popped));
+        assert_eq!(conn.stream().read_timeout().unwrap(), read_timeout);
+
+        let cmd = |args: &[&[u8]]| RespValue::Array(args.iter().map(|a| RespValue::Bulk(a.to_vec())).collect());
+        assert_eq!(server.join().unwrap(), vec![
+            cmd(&[b"blpop", b"p", b"q", b"1"]),
+            cmd(&[b"brpop", b"q", b"0.500"]),
+            cmd(&[b"bzpopmin", b"z", b"0"]),
+        ]);
+    }
+}
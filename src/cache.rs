@@ -0,0 +1,255 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::net::Shutdown;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use super::connection::TcpConnection;
+use super::tracking::{Invalidation, InvalidationListener, TrackingOptions};
+use super::types::{RespValue, RuisError};
+
+// a bounded map evicting the least recently used entry on overflow.
+pub struct LruCache {
+    capacity: usize,
+    entries: HashMap<Vec<u8>, (Vec<u8>, u64)>,
+    recency: BTreeMap<u64, Vec<u8>>,
+    tick: u64,
+}
+
+impl LruCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: HashMap::new(),
+            recency: BTreeMap::new(),
+            tick: 0,
+        }
+    }
+
+    pub fn get(&mut self, key: &[u8]) -> Option<&Vec<u8>> {
+        self.tick += 1;
+        let tick = self.tick;
+        match self.entries.get_mut(key) {
+            Some(entry) => {
+                let key = self.recency.remove(&entry.1).unwrap();
+                self.recency.insert(tick, key);
+                entry.1 = tick;
+                Some(&entry.0)
+            },
+            None => None,
+        }
+    }
+
+    pub fn insert(&mut self, key: Vec<u8>, value: Vec<u8>) {
+        if self.capacity == 0 {
+            return;
+        }
+        self.remove(&key);
+        while self.entries.len() >= self.capacity {
+            let oldest = *self.recency.keys().next().unwrap();
+            let k = self.recency.remove(&oldest).unwrap();
+            self.entries.remove(&k);
+        }
+        self.tick += 1;
+        self.recency.insert(self.tick, key.clone());
+        self.entries.insert(key, (value, self.tick));
+    }
+
+    pub fn remove(&mut self, key: &[u8]) -> Option<Vec<u8>> {
+        let (value, tick) = self.entries.remove(key)?;
+        self.recency.remove(&tick);
+        Some(value)
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.recency.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub invalidations: u64,
+}
+
+struct CacheState {
+    lru: LruCache,
+    // keys with a GET in flight. an invalidation arriving before the reply is
+    // cached removes the key from here, so the stale reply is not cached.
+    pending: HashSet<Vec<u8>>,
+    // false once the invalidation connection is lost, the cache can not be
+    // trusted anymore and every read goes to the server.
+    listening: bool,
+    stats: CacheStats,
+}
+
+impl CacheState {
+    fn invalidate(&mut self, inv: Invalidation) {
+        self.stats.invalidations += 1;
+        match inv {
+            Invalidation::Keys(keys) => {
+                for key in keys {
+                    self.lru.remove(&key);
+                    self.pending.remove(&key);
+                }
+            },
+            Invalidation::FlushAll => {
+                self.lru.clear();
+                self.pending.clear();
+            },
+        }
+    }
+}
+
+// CachingClient serves GETs from a local LRU, and relies on the server's
+// CLIENT TRACKING invalidations to evict the entries modified elsewhere.
+pub struct CachingClient {
+    conn: TcpConnection,
+    state: Arc<Mutex<CacheState>>,
+    listener_stream: std::net::TcpStream,
+}
+
+impl CachingClient {
+    pub fn connect(addr: &str, password: Option<&str>, capacity: usize) -> Result<CachingClient, RuisError> {
+        Self::connect_with_tracking(addr, password, capacity, TrackingOptions::new())
+    }
+
+    // the tracking options are applied to the data connection, eg. BCAST with
+    // prefixes to only cache a part of the keyspace. the redirect is always set
+    // to the invalidation connection.
+    pub fn connect_with_tracking(addr: &str, password: Option<&str>, capacity: usize, opts: TrackingOptions) -> Result<CachingClient, RuisError> {
+        let listener_conn = TcpConnection::connect(addr, password)?;
+        let listener_stream = listener_conn.stream().try_clone()?;
+        let mut listener = InvalidationListener::new(listener_conn)?;
+
+        let mut conn = TcpConnection::connect(addr, password)?;
+        conn.client_tracking_on(&opts.redirect(listener.client_id()))?;
+
+        let state = Arc::new(Mutex::new(CacheState {
+            lru: LruCache::new(capacity),
+            pending: HashSet::new(),
+            listening: true,
+            stats: CacheStats::default(),
+        }));
+        let thread_state = state.clone();
+        thread::spawn(move || {
+            loop {
+                let r = listener.next_invalidation();
+                let mut state = thread_state.lock().unwrap();
+                match r {
+                    Ok(inv) => state.invalidate(inv),
+                    Err(_) => {
+                        state.listening = false;
+                        state.lru.clear();
+                        state.pending.clear();
+                        return;
+                    },
+                }
+            }
+        });
+
+        Ok(CachingClient {
+            conn,
+            state,
+            listener_stream,
+        })
+    }
+
+    pub fn get(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>, RuisError> {
+        {
+            let mut state = self.state.lock().unwrap();
+            if state.listening {
+                if let Some(v) = state.lru.get(key) {
+                    let v = v.clone();
+                    state.stats.hits += 1;
+                    return Ok(Some(v));
+                }
+                state.pending.insert(key.to_vec());
+            }
+            state.stats.misses += 1;
+        }
+
+        let r = self.conn.execute(&[b"get", key]).and_then(|v| v.into_result());
+        let mut state = self.state.lock().unwrap();
+        let still_pending = state.pending.remove(key);
+        match r? {
+            RespValue::Bulk(v) => {
+                if still_pending && state.listening {
+                    state.lru.insert(key.to_vec(), v.clone());
+                }
+                Ok(Some(v))
+            },
+            RespValue::NilBulk => Ok(None),
+            v => Err(RuisError::Unexpected(format!("get: {:?}", v))),
+        }
+    }
+
+    // the other commands are passed through to the server, the writes are
+    // invalidated by the server like any other client's.
+    pub fn execute(&mut self, cmd: &[&[u8]]) -> Result<RespValue, RuisError> {
+        self.conn.execute(cmd)
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.state.lock().unwrap().stats
+    }
+
+    pub fn cached_len(&self) -> usize {
+        self.state.lock().unwrap().lru.len()
+    }
+}
+
+impl Drop for CachingClient {
+    fn drop(&mut self) {
+        // unblocks the listener thread.
+        let _ = self.listener_stream.shutdown(Shutdown::Both);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lru_eviction() {
+        let mut lru = LruCache::new(2);
+        lru.insert(b"a".to_vec(), b"1".to_vec());
+        lru.insert(b"b".to_vec(), b"2".to_vec());
+        assert_eq!(lru.get(b"a"), Some(&b"1".to_vec()));
+        lru.insert(b"c".to_vec(), b"3".to_vec());
+        assert_eq!(lru.len(), 2);
+        assert_eq!(lru.get(b"b"), None);
+        assert_eq!(lru.get(b"a"), Some(&b"1".to_vec()));
+        assert_eq!(lru.get(b"c"), Some(&b"3".to_vec()));
+
+        lru.insert(b"a".to_vec(), b"4".to_vec());
+        assert_eq!(lru.len(), 2);
+        assert_eq!(lru.remove(b"a"), Some(b"4".to_vec()));
+        assert_eq!(lru.len(), 1);
+    }
+
+    #[test]
+    fn test_invalidate_pending() {
+        let mut state = CacheState {
+            lru: LruCache::new(4),
+            pending: HashSet::new(),
+            listening: true,
+            stats: CacheStats::default(),
+        };
+        state.lru.insert(b"a".to_vec(), b"1".to_vec());
+        state.pending.insert(b"b".to_vec());
+        state.invalidate(Invalidation::Keys(vec![b"a".to_vec(), b"b".to_vec()]));
+        assert!(state.lru.is_empty());
+        assert!(state.pending.is_empty());
+        assert_eq!(state.stats.invalidations, 1);
+    }
+}
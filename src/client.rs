@@ -1,15 +1,698 @@
-struct Client {
-    addr: String,
-    password: Option<String>,
-    max_idle_conns: usize,
+use std::fmt;
+use std::io::{BufRead, Write};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::cluster::{ClusterClient, ClusterClientBuilder};
+use super::commands::Commands;
+use super::connection::{ClientIdentity, GenericConnection, redacted};
+use super::hooks::{CommandHook, CommandInfo, SlowCommand, SlowCommandHook};
+use super::metrics::{MetricsSink, Outcome};
+use super::pipeline::Pipeline;
+use super::pool::{ConnectionPool, PooledConnection, checkin};
+use super::retry::{Failure, RetryPolicy, Stage};
+use super::sentinel::{SentinelClient, SentinelClientBuilder};
+#[cfg(feature = "tls")]
+use super::tls::{TlsConfig, TlsConnection};
+use super::types::{RespValue, RuisError};
+use super::url::ConnectionInfo;
+
+const DEFAULT_MAX_IDLE_CONNS: usize = 4;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Deployment {
+    Standalone { addr: String },
+    Sentinel { sentinels: Vec<String>, master_name: String },
+    Cluster { seeds: Vec<String> },
+}
+
+#[derive(Clone)]
+pub struct ClientConfig {
+    pub deployment: Deployment,
+    // the password of the data nodes, the sentinels have their own.
+    pub password: Option<String>,
+    // the password of the sentinels, only used by the sentinel deployments.
+    pub sentinel_password: Option<String>,
+    // the logical database, only 0 on the cluster deployments.
+    pub db: i64,
+    // the idle connections kept per server.
+    pub max_idle_conns: usize,
+    // set on each connection opened, see ClientIdentity.
+    pub identity: Option<ClientIdentity>,
+    pub metrics: Option<Arc<dyn MetricsSink>>,
+    pub retry: RetryPolicy,
+}
+
+impl fmt::Debug for ClientConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ClientConfig")
+            .field("deployment", &self.deployment)
+            .field("password", &redacted(&self.password))
+            .field("sentinel_password", &redacted(&self.sentinel_password))
+            .field("db", &self.db)
+            .field("max_idle_conns", &self.max_idle_conns)
+            .field("identity", &self.identity)
+            .field("metrics", &self.metrics.is_some())
+            .field("retry", &self.retry)
+            .finish()
+    }
+}
+
+impl ClientConfig {
+    pub fn new(deployment: Deployment) -> Self {
+        Self {
+            deployment,
+            password: None,
+            sentinel_password: None,
+            db: 0,
+            max_idle_conns: DEFAULT_MAX_IDLE_CONNS,
+            identity: None,
+            metrics: None,
+            retry: RetryPolicy::default(),
+        }
+    }
+
+    pub fn password(mut self, password: &str) -> Self {
+        self.password = Some(password.to_string());
+        self
+    }
+
+    pub fn sentinel_password(mut self, password: &str) -> Self {
+        self.sentinel_password = Some(password.to_string());
+        self
+    }
+
+    pub fn db(mut self, db: i64) -> Self {
+        self.db = db;
+        self
+    }
+
+    pub fn max_idle_conns(mut self, n: usize) -> Self {
+        self.max_idle_conns = n;
+        self
+    }
+
+    pub fn identity(mut self, identity: ClientIdentity) -> Self {
+        self.identity = Some(identity);
+        self
+    }
+
+    // names the connections, with the library told as ruis.
+    pub fn client_name(self, name: &str) -> Self {
+        self.identity(ClientIdentity::new().name(name))
+    }
+
+    pub fn metrics(mut self, sink: Arc<dyn MetricsSink>) -> Self {
+        self.metrics = Some(sink);
+        self
+    }
+
+    pub fn retry(mut self, policy: RetryPolicy) -> Self {
+        self.retry = policy;
+        self
+    }
+
+    // the config as a url with the password masked, for the logs:
+    //
+    //   redis://:***@host:port[/db]
+    //   redis+sentinel://:***@host1:port1,host2:port2/master_name[?sentinel_password=***]
+    //   redis+cluster://:***@host1:port1,host2:port2
+    pub fn redacted_url(&self) -> String {
+        let auth = match self.password {
+            Some(_) => ":***@",
+            None => "",
+        };
+        match self.deployment {
+            Deployment::Standalone { ref addr } if self.db != 0 => format!("redis://{}{}/{}", auth, addr, self.db),
+            Deployment::Standalone { ref addr } => format!("redis://{}{}", auth, addr),
+            Deployment::Sentinel { ref sentinels, ref master_name } => {
+                let query = match self.sentinel_password {
+                    Some(_) => "?sentinel_password=***",
+                    None => "",
+                };
+                format!("redis+sentinel://{}{}/{}{}", auth, sentinels.join(","), master_name, query)
+            },
+            Deployment::Cluster { ref seeds } => format!("redis+cluster://{}{}", auth, seeds.join(",")),
+        }
+    }
+}
+
+// Client sends the commands to the deployment described by the config, the
+// same Commands API works on all of them.
+pub struct Client {
+    backend: Backend,
+    hooks: Vec<Box<dyn CommandHook>>,
+    metrics: Option<Arc<dyn MetricsSink>>,
+    retry: RetryPolicy,
+}
+
+#[derive(Debug)]
+enum Backend {
+    Standalone(ConnectionPool),
+    #[cfg(feature = "tls")]
+    Tls(ConnectionPool<TlsConnection>),
+    Sentinel(SentinelClient),
+    Cluster(ClusterClient),
+}
+
+impl fmt::Debug for Client {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Client")
+            .field("backend", &self.backend)
+            .field("hooks", &self.hooks.len())
+            .field("metrics", &self.metrics.is_some())
+            .field("retry", &self.retry)
+            .finish()
+    }
 }
 
 impl Client {
-    fn new(addr: String, password: Option<String>) -> Client {
+    // connects to a single server, lazily on the first command.
+    pub fn new(addr: String, password: Option<String>) -> Client {
+        let pool = ConnectionPool::new(&addr, password.as_deref()).max_idle(DEFAULT_MAX_IDLE_CONNS);
         Client {
-            addr: addr,
-            password: password,
-            max_idle_conns: 4,
+            backend: Backend::Standalone(pool),
+            hooks: vec![],
+            metrics: None,
+            retry: RetryPolicy::default(),
+        }
+    }
+
+    // connects to the single server of a redis:// url, lazily on the first
+    // command, see ConnectionInfo for the format. the rediss:// urls need the
+    // tls feature, the certificate is checked against the webpki roots and
+    // the host of the url, see ConnectionPool::new_tls() for the other CAs.
+    pub fn open(url: &str) -> Result<Client, RuisError> {
+        let info = ConnectionInfo::parse(url)?;
+        let backend = if info.tls {
+            #[cfg(feature = "tls")]
+            {
+                let config = TlsConfig::new().server_name(info.host());
+                Backend::Tls(url_pool(ConnectionPool::new_tls(&info.addr, info.password.as_deref(), config), &info))
+            }
+            #[cfg(not(feature = "tls"))]
+            return Err(RuisError::Unexpected("rediss:// urls need the tls feature".to_string()));
+        } else {
+            Backend::Standalone(url_pool(ConnectionPool::new(&info.addr, info.password.as_deref()), &info))
+        };
+        Ok(Client {
+            backend,
+            hooks: vec![],
+            metrics: None,
+            retry: RetryPolicy::default(),
+        })
+    }
+
+    // the sentinel and cluster deployments are connected on creation, as the
+    // master or the slots have to be discovered first.
+    pub fn from_config(config: ClientConfig) -> Result<Client, RuisError> {
+        let password = config.password.as_deref();
+        let backend = match config.deployment {
+            Deployment::Standalone { ref addr } => {
+                let mut pool = ConnectionPool::new(addr, password).max_idle(config.max_idle_conns);
+                if config.db != 0 {
+                    pool = pool.db(config.db);
+                }
+                if let Some(ref identity) = config.identity {
+                    pool = pool.identity(identity.clone());
+                }
+                if let Some(ref m) = config.metrics {
+                    pool = pool.metrics(m.clone());
+                }
+                Backend::Standalone(pool)
+            },
+            Deployment::Sentinel { ref sentinels, ref master_name } => {
+                let sentinels: Vec<&str> = sentinels.iter().map(|s| s.as_str()).collect();
+                let mut builder = SentinelClientBuilder::new(&sentinels, master_name);
+                if let Some(password) = password {
+                    builder = builder.password(password);
+                }
+                if let Some(ref password) = config.sentinel_password {
+                    builder = builder.sentinel_password(password);
+                }
+                if config.db != 0 {
+                    builder = builder.db(config.db);
+                }
+                if let Some(ref identity) = config.identity {
+                    builder = builder.identity(identity.clone());
+                }
+                if let Some(ref m) = config.metrics {
+                    builder = builder.metrics(m.clone());
+                }
+                Backend::Sentinel(builder.connect()?)
+            },
+            Deployment::Cluster { .. } if config.db != 0 => {
+                return Err(RuisError::Cluster("the cluster only has the database 0".to_string()));
+            },
+            Deployment::Cluster { ref seeds } => {
+                let seeds: Vec<&str> = seeds.iter().map(|s| s.as_str()).collect();
+                let mut builder = ClusterClientBuilder::new(&seeds).pool_size(config.max_idle_conns);
+                if let Some(password) = password {
+                    builder = builder.password(password);
+                }
+                if let Some(ref identity) = config.identity {
+                    builder = builder.identity(identity.clone());
+                }
+                if let Some(ref m) = config.metrics {
+                    builder = builder.metrics(m.clone());
+                }
+                Backend::Cluster(builder.connect()?)
+            },
+        };
+        Ok(Client {
+            backend,
+            hooks: vec![],
+            metrics: config.metrics,
+            retry: config.retry,
+        })
+    }
+
+    // checks out a connection of the pool, opened on demand, which is put
+    // back when dropped. the client can be shared by the threads this way, as
+    // execute() needs it mutably. only the standalone deployments pool their
+    // connections.
+    pub fn get_connection(&self) -> Result<PooledConnection<'_>, RuisError> {
+        match self.backend {
+            Backend::Standalone(ref pool) => Ok(PooledConnection::new(pool, pool.get()?)),
+            #[cfg(feature = "tls")]
+            Backend::Tls(_) => Err(RuisError::Unexpected("get_connection() is only supported on the standalone deployments over tcp".to_string())),
+            Backend::Sentinel(_) | Backend::Cluster(_) => {
+                Err(RuisError::Unexpected("get_connection() is only supported on the standalone deployments".to_string()))
+            },
+        }
+    }
+
+    // the hooks are called in the order they are added.
+    pub fn hook<H: CommandHook + 'static>(mut self, hook: H) -> Self {
+        self.hooks.push(Box::new(hook));
+        self
+    }
+
+    // the failed commands are retried by the policy, the hooks and the
+    // metrics only see the last attempt.
+    pub fn retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry = policy;
+        self
+    }
+
+    // calls back on the commands taking longer than the threshold.
+    pub fn slow_commands<F>(self, threshold: Duration, callback: F) -> Self
+        where F: Fn(&SlowCommand) + Send + Sync + 'static {
+        self.hook(SlowCommandHook::new(threshold, callback))
+    }
+
+    // the server a command is expected to be sent to.
+    fn peer(&self, cmd: &[&[u8]]) -> Option<String> {
+        match self.backend {
+            Backend::Standalone(ref pool) => Some(pool.addr().to_string()),
+            #[cfg(feature = "tls")]
+            Backend::Tls(ref pool) => Some(pool.addr().to_string()),
+            Backend::Sentinel(ref client) => client.master_addr().map(|a| a.to_string()),
+            Backend::Cluster(ref client) => client.node_for(cmd),
+        }
+    }
+
+    fn report(&self, info: &CommandInfo, start: Instant, result: Result<&RespValue, &RuisError>) {
+        let elapsed = start.elapsed();
+        if let Some(ref m) = self.metrics {
+            m.command_completed(&info.name(), elapsed, Outcome::of(result));
         }
+        for hook in &self.hooks {
+            hook.on_complete(info, elapsed, result);
+        }
+    }
+
+    fn execute_backend(&mut self, cmd: &[&[u8]]) -> Result<RespValue, RuisError> {
+        let mut attempt = 0;
+        loop {
+            let (r, stage) = self.try_execute(cmd);
+            match Failure::classify(stage, &r) {
+                Some(failure) if self.retry.should_retry(failure, attempt) => {
+                    thread::sleep(self.retry.backoff_for(attempt));
+                    attempt += 1;
+                },
+                _ => return r,
+            }
+        }
+    }
+
+    // the stage is only known on the pooled connections, the failures of the
+    // other deployments are taken as happened while reading the reply.
+    fn try_execute(&mut self, cmd: &[&[u8]]) -> (Result<RespValue, RuisError>, Stage) {
+        match self.backend {
+            Backend::Standalone(ref pool) => execute_pooled(pool, cmd),
+            #[cfg(feature = "tls")]
+            Backend::Tls(ref pool) => execute_pooled(pool, cmd),
+            Backend::Sentinel(ref mut client) => (client.execute(cmd), Stage::Read),
+            Backend::Cluster(ref mut client) => (client.execute(cmd), Stage::Read),
+        }
+    }
+
+    // the first commands of a pipeline failed while written might have run,
+    // so only the failures to connect are retried as such. the error replies
+    // are left to the caller, as retrying them would run the others again.
+    fn execute_pipeline_backend(&mut self, pipeline: &Pipeline) -> Result<Vec<RespValue>, RuisError> {
+        let mut attempt = 0;
+        loop {
+            let (r, stage) = self.try_execute_pipeline(pipeline);
+            let failure = match r {
+                Err(ref e) => Failure::of_error(stage, e),
+                Ok(_) => None,
+            };
+            match failure {
+                Some(failure) if self.retry.should_retry(failure, attempt) => {
+                    thread::sleep(self.retry.backoff_for(attempt));
+                    attempt += 1;
+                },
+                _ => return r,
+            }
+        }
+    }
+
+    fn try_execute_pipeline(&mut self, pipeline: &Pipeline) -> (Result<Vec<RespValue>, RuisError>, Stage) {
+        match self.backend {
+            Backend::Standalone(ref pool) => execute_pipeline_pooled(pool, pipeline),
+            #[cfg(feature = "tls")]
+            Backend::Tls(ref pool) => execute_pipeline_pooled(pool, pipeline),
+            Backend::Sentinel(ref mut client) => (client.execute_pipeline(pipeline), Stage::Read),
+            Backend::Cluster(ref mut client) => (client.execute_pipeline(pipeline), Stage::Read),
+        }
+    }
+}
+
+fn url_pool<C>(pool: ConnectionPool<C>, info: &ConnectionInfo) -> ConnectionPool<C> {
+    let mut pool = pool.max_idle(DEFAULT_MAX_IDLE_CONNS);
+    if let Some(ref username) = info.username {
+        pool = pool.username(username);
+    }
+    if info.db != 0 {
+        pool = pool.db(info.db);
+    }
+    pool
+}
+
+fn execute_pooled<W: Write, R: BufRead>(pool: &ConnectionPool<GenericConnection<W, R>>, cmd: &[&[u8]]) -> (Result<RespValue, RuisError>, Stage) {
+    let mut conn = match pool.get() {
+        Ok(conn) => conn,
+        Err(e) => return (Err(e), Stage::Connect),
+    };
+    let (r, stage) = match conn.send(cmd) {
+        Ok(()) => (conn.receive(), Stage::Read),
+        Err(e) => (Err(e), Stage::Write),
+    };
+    checkin(pool, conn, &r);
+    (r, stage)
+}
+
+fn execute_pipeline_pooled<W: Write, R: BufRead>(pool: &ConnectionPool<GenericConnection<W, R>>, pipeline: &Pipeline) -> (Result<Vec<RespValue>, RuisError>, Stage) {
+    let mut conn = match pool.get() {
+        Ok(conn) => conn,
+        Err(e) => return (Err(e), Stage::Connect),
+    };
+    let r = conn.execute_pipeline(pipeline);
+    checkin(pool, conn, &r);
+    (r, Stage::Read)
+}
+
+impl Commands for Client {
+    fn execute(&mut self, cmd: &[&[u8]]) -> Result<RespValue, RuisError> {
+        if self.hooks.is_empty() && self.metrics.is_none() {
+            return self.execute_backend(cmd);
+        }
+        let peer = self.peer(cmd);
+        let info = CommandInfo { args: cmd, peer: peer.as_deref() };
+        if let Some(ref m) = self.metrics {
+            m.command_started(&info.name());
+        }
+        let start = Instant::now();
+        let r = self.execute_backend(cmd);
+        self.report(&info, start, r.as_ref());
+        r
+    }
+
+    fn execute_pipeline(&mut self, pipeline: &Pipeline) -> Result<Vec<RespValue>, RuisError> {
+        if self.hooks.is_empty() && self.metrics.is_none() {
+            return self.execute_pipeline_backend(pipeline);
+        }
+        let cmds: Vec<Vec<&[u8]>> = pipeline.commands().collect();
+        let peers: Vec<Option<String>> = cmds.iter().map(|cmd| self.peer(cmd)).collect();
+        if let Some(ref m) = self.metrics {
+            for cmd in &cmds {
+                m.command_started(&CommandInfo { args: cmd, peer: None }.name());
+            }
+        }
+        let start = Instant::now();
+        let r = self.execute_pipeline_backend(pipeline);
+        for (i, cmd) in cmds.iter().enumerate() {
+            let info = CommandInfo { args: cmd, peer: peers[i].as_deref() };
+            let result = match r {
+                Ok(ref replies) => Ok(&replies[i]),
+                Err(ref e) => Err(e),
+            };
+            self.report(&info, start, result);
+        }
+        r
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufReader, Write};
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use super::*;
+    use super::super::resp::RespReader;
+    use super::super::testing::TestServer;
+
+    // replies the first argument of each command.
+    fn echo_server() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                thread::spawn(move || {
+                    let mut r = RespReader::new(BufReader::new(stream.try_clone().unwrap()));
+                    while let Ok(RespValue::Array(args)) = r.read() {
+                        match args.get(1) {
+                            Some(RespValue::Bulk(arg)) => {
+                                stream.write_all(format!("${}\r\n", arg.len()).as_bytes()).unwrap();
+                                stream.write_all(arg).unwrap();
+                                stream.write_all(b"\r\n").unwrap();
+                            },
+                            _ => stream.write_all(b"+PONG\r\n").unwrap(),
+                        }
+                    }
+                });
+            }
+        });
+        addr
+    }
+
+    // the application code only depends on Commands.
+    fn get_all<C: Commands>(c: &mut C, keys: &[&[u8]]) -> Vec<RespValue> {
+        let mut pipe = Pipeline::new();
+        for key in keys {
+            pipe.cmd(&[b"get", key]);
+        }
+        c.execute_pipeline(&pipe).unwrap()
+    }
+
+    #[test]
+    fn test_standalone() {
+        let addr = echo_server();
+        let mut client = Client::from_config(ClientConfig::new(Deployment::Standalone { addr })).unwrap();
+        assert_eq!(client.execute(&[b"ping"]).unwrap(), RespValue::Bulk(b"PONG".to_vec()));
+        assert_eq!(get_all(&mut client, &[b"a", b"b"]), vec![RespValue::Bulk(b"a".to_vec()), RespValue::Bulk(b"b".to_vec())]);
+    }
+
+    #[test]
+    fn test_get_connection() {
+        let client = Client::new(echo_server(), None);
+        thread::scope(|s| {
+            for i in 0..4 {
+                let client = &client;
+                s.spawn(move || {
+                    let mut conn = client.get_connection().unwrap();
+                    let key = format!("k{}", i);
+                    assert_eq!(get_all(&mut conn, &[key.as_bytes()]), vec![RespValue::Bulk(key.into_bytes())]);
+                });
+            }
+        });
+        let pool = match client.backend {
+            Backend::Standalone(ref pool) => pool,
+            _ => unreachable!(),
+        };
+        let (a, b) = (client.get_connection().unwrap(), client.get_connection().unwrap());
+        drop((a, b));
+        let idle = pool.idle_len();
+        assert!(idle >= 2);
+        // the idle connections are reused.
+        drop(client.get_connection().unwrap());
+        assert_eq!(pool.idle_len(), idle);
+        client.get_connection().unwrap().discard();
+        assert_eq!(pool.idle_len(), idle - 1);
+        let conn = client.get_connection().unwrap().detach();
+        drop(conn);
+        assert_eq!(pool.idle_len(), idle - 2);
+        assert!(pool.is_healthy());
+    }
+
+    #[test]
+    fn test_unreachable() {
+        let addr = {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            listener.local_addr().unwrap().to_string()
+        };
+        let mut client = Client::new(addr.clone(), None);
+        assert!(client.execute(&[b"ping"]).is_err());
+        let config = ClientConfig::new(Deployment::Cluster { seeds: vec![addr] });
+        assert!(Client::from_config(config.clone()).is_err());
+        assert!(matches!(Client::from_config(config.db(1)), Err(RuisError::Cluster(_))));
+    }
+
+    #[test]
+    fn test_open() {
+        let server = TestServer::with_password("p@ss");
+        let mut client = Client::open(&format!("redis://:p%40ss@{}", server.addr())).unwrap();
+        assert_eq!(client.execute(&[b"ping"]).unwrap(), RespValue::Bulk(b"PONG".to_vec()));
+        let mut client = Client::open(&format!("redis://app:p%40ss@{}", server.addr())).unwrap();
+        assert_eq!(client.execute(&[b"ping"]).unwrap(), RespValue::Bulk(b"PONG".to_vec()));
+        client.execute(&[b"set", b"k", b"0"]).unwrap();
+        let mut client = Client::open(&format!("redis://:p%40ss@{}/2", server.addr())).unwrap();
+        assert_eq!(client.execute(&[b"get", b"k"]).unwrap(), RespValue::NilBulk);
+        // the pool of a rediss:// url connects lazily too.
+        assert_eq!(Client::open("rediss://host").is_ok(), cfg!(feature = "tls"));
+        assert!(Client::open("ftp://host").is_err());
+    }
+
+    #[test]
+    fn test_redacted() {
+        let config = ClientConfig::new(Deployment::Standalone { addr: "127.0.0.1:6379".to_string() }).password("hunter2");
+        assert_eq!(config.redacted_url(), "redis://:***@127.0.0.1:6379");
+        assert_eq!(config.clone().db(2).redacted_url(), "redis://:***@127.0.0.1:6379/2");
+        assert!(!format!("{:?}", config).contains("hunter2"));
+        let client = Client::new("127.0.0.1:6379".to_string(), Some("hunter2".to_string()));
+        assert!(!format!("{:?}", client).contains("hunter2"));
+
+        let sentinels = vec!["s1:26379".to_string(), "s2:26379".to_string()];
+        let config = ClientConfig::new(Deployment::Sentinel { sentinels, master_name: "mymaster".to_string() });
+        assert_eq!(config.redacted_url(), "redis+sentinel://s1:26379,s2:26379/mymaster");
+        let config = config.password("hunter2").sentinel_password("hunter3");
+        assert_eq!(config.redacted_url(), "redis+sentinel://:***@s1:26379,s2:26379/mymaster?sentinel_password=***");
+        assert!(!format!("{:?}", config).contains("hunter3"));
+        let builder = SentinelClientBuilder::new(&["s1:26379"], "mymaster").password("hunter2").sentinel_password("hunter3");
+        let debug = format!("{:?}", builder);
+        assert!(!debug.contains("hunter2") && !debug.contains("hunter3"));
+        assert!(!format!("{:?}", ClusterClientBuilder::new(&["n1:7000"]).password("hunter2")).contains("hunter2"));
+    }
+
+    // the name, the peer and whether it succeeded.
+    type Recorded = Vec<(String, Option<String>, bool)>;
+
+    struct Recorder(Arc<Mutex<Recorded>>);
+
+    impl CommandHook for Recorder {
+        fn on_complete(&self, cmd: &CommandInfo, _: Duration, result: Result<&RespValue, &RuisError>) {
+            self.0.lock().unwrap().push((cmd.name(), cmd.peer.map(|p| p.to_string()), result.is_ok()));
+        }
+    }
+
+    #[test]
+    fn test_hooks() {
+        let addr = echo_server();
+        let recorded = Arc::new(Mutex::new(vec![]));
+        let mut client = Client::new(addr.clone(), None).hook(Recorder(recorded.clone()));
+        client.execute(&[b"PING"]).unwrap();
+        let mut pipe = Pipeline::new();
+        pipe.cmd(&[b"get", b"a"]).cmd(&[b"get", b"b"]);
+        client.execute_pipeline(&pipe).unwrap();
+
+        let peer = Some(addr);
+        assert_eq!(*recorded.lock().unwrap(), vec![
+            ("ping".to_string(), peer.clone(), true),
+            ("get".to_string(), peer.clone(), true),
+            ("get".to_string(), peer, true),
+        ]);
+    }
+
+    #[test]
+    fn test_slow_commands() {
+        let addr = echo_server();
+        let slow = Arc::new(Mutex::new(vec![]));
+        let slow2 = slow.clone();
+        let mut client = Client::new(addr, None).slow_commands(Duration::ZERO, move |c| slow2.lock().unwrap().push(c.key.clone()));
+        client.execute(&[b"get", b"a"]).unwrap();
+        assert_eq!(*slow.lock().unwrap(), vec![Some("a".to_string())]);
+    }
+
+    #[derive(Default)]
+    struct Timings(Mutex<Vec<(String, Outcome)>>);
+
+    impl MetricsSink for Timings {
+        fn command_completed(&self, name: &str, _: Duration, outcome: Outcome) {
+            self.0.lock().unwrap().push((name.to_string(), outcome));
+        }
+    }
+
+    #[test]
+    fn test_metrics() {
+        let addr = echo_server();
+        let timings = Arc::new(Timings::default());
+        let config = ClientConfig::new(Deployment::Standalone { addr }).metrics(timings.clone());
+        let mut client = Client::from_config(config).unwrap();
+        client.execute(&[b"ping"]).unwrap();
+        get_all(&mut client, &[b"a", b"b"]);
+        assert_eq!(*timings.0.lock().unwrap(), vec![
+            ("ping".to_string(), Outcome::Success),
+            ("get".to_string(), Outcome::Success),
+            ("get".to_string(), Outcome::Success),
+        ]);
+    }
+
+    // replies to the commands in order, over the connections accepted one
+    // after another, a None closes the connection without a reply.
+    fn scripted_server(replies: Vec<Option<&'static [u8]>>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        thread::spawn(move || {
+            let mut replies = replies.into_iter();
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut r = RespReader::new(BufReader::new(stream.try_clone().unwrap()));
+                while r.read().is_ok() {
+                    match replies.next() {
+                        Some(Some(reply)) => stream.write_all(reply).unwrap(),
+                        Some(None) => break,
+                        None => return,
+                    }
+                }
+            }
+        });
+        addr
+    }
+
+    #[test]
+    fn test_retry() {
+        let fast = RetryPolicy::new().backoff(Duration::from_millis(1), Duration::from_millis(1));
+        let addr = scripted_server(vec![Some(b"-LOADING Redis is loading the dataset in memory\r\n"), Some(b"$1\r\nv\r\n")]);
+        let mut client = Client::new(addr, None).retry_policy(fast.clone());
+        assert_eq!(client.get(b"k").unwrap(), Some(b"v".to_vec()));
+
+        let addr = scripted_server(vec![Some(b"-LOADING Redis is loading the dataset in memory\r\n")]);
+        let mut client = Client::new(addr, None).retry_policy(RetryPolicy::none());
+        assert!(client.get(b"k").is_err());
+
+        // the INCR might have run before the connection dropped.
+        let addr = scripted_server(vec![None, Some(b":1\r\n")]);
+        let mut client = Client::new(addr, None).retry_policy(fast.clone());
+        assert!(client.incr(b"n").unwrap_err().is_connection_dropped());
+        assert_eq!(client.incr(b"n").unwrap(), 1);
+
+        let addr = scripted_server(vec![None, Some(b"$1\r\nv\r\n")]);
+        let mut client = Client::new(addr, None).retry_policy(fast.retry_on(&[Failure::Dropped]));
+        assert_eq!(client.get(b"k").unwrap(), Some(b"v".to_vec()));
     }
 }
@@ -0,0 +1,203 @@
+use std::collections::HashMap;
+
+use super::{ClusterClient, cluster_slot};
+use super::super::scan::parse_page;
+use super::super::types::{RespValue, RuisError};
+
+const DEFAULT_SAMPLE_LIMIT: usize = 10000;
+const DEFAULT_SCAN_COUNT: usize = 1000;
+const DEFAULT_MEMORY_SAMPLES: usize = 100;
+const DEFAULT_TOP_SLOTS: usize = 10;
+
+#[derive(Debug, Clone)]
+pub struct DistributionOptions {
+    sample_limit: usize,
+    scan_count: usize,
+    memory_samples: usize,
+    top_slots: usize,
+}
+
+impl Default for DistributionOptions {
+    fn default() -> Self {
+        Self {
+            sample_limit: DEFAULT_SAMPLE_LIMIT,
+            scan_count: DEFAULT_SCAN_COUNT,
+            memory_samples: DEFAULT_MEMORY_SAMPLES,
+            top_slots: DEFAULT_TOP_SLOTS,
+        }
+    }
+}
+
+impl DistributionOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // the keys scanned at most per node, the scan stops early on big nodes.
+    pub fn sample_limit(mut self, n: usize) -> Self {
+        self.sample_limit = n;
+        self
+    }
+
+    // the COUNT hint of each SCAN.
+    pub fn scan_count(mut self, n: usize) -> Self {
+        self.scan_count = n;
+        self
+    }
+
+    // the keys sized by MEMORY USAGE per node, 0 skips the memory estimates.
+    pub fn memory_samples(mut self, n: usize) -> Self {
+        self.memory_samples = n;
+        self
+    }
+
+    pub fn top_slots(mut self, n: usize) -> Self {
+        self.top_slots = n;
+        self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NodeDistribution {
+    pub addr: String,
+    // the DBSIZE of the node.
+    pub keys: u64,
+    pub keys_sampled: u64,
+    // the average MEMORY USAGE of the keys sized, times the keys.
+    pub memory_estimate: u64,
+}
+
+// the counts are of the keys sampled, which are all the keys if no node hit
+// the sample limit.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SlotDistribution {
+    pub slot: u16,
+    pub keys_sampled: u64,
+    pub memory_sampled: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyDistribution {
+    pub nodes: Vec<NodeDistribution>,
+    // the slots with the most keys sampled, the biggest first.
+    pub top_slots: Vec<SlotDistribution>,
+}
+
+impl ClusterClient {
+    // SCANs the masters to find out how the keys spread over the nodes and
+    // the slots, to spot the hot slots.
+    pub fn key_distribution(&mut self, opts: &DistributionOptions) -> Result<KeyDistribution, RuisError> {
+        let masters: Vec<String> = self.topology.masters().map(|n| n.addr()).collect();
+        let mut nodes = vec![];
+        let mut slots: HashMap<u16, SlotDistribution> = HashMap::new();
+        for addr in masters {
+            let keys = match self.execute_on_node(&addr, &[b"dbsize"])? {
+                RespValue::Int(n) => n as u64,
+                v => return Err(RuisError::Unexpected(format!("dbsize: {:?}", v))),
+            };
+            let sampled = self.scan_node(&addr, opts)?;
+            let sizes = self.memory_usages(&addr, &sampled[..sampled.len().min(opts.memory_samples)])?;
+
+            for (i, key) in sampled.iter().enumerate() {
+                let slot = cluster_slot(key);
+                let s = slots.entry(slot).or_insert(SlotDistribution { slot, keys_sampled: 0, memory_sampled: 0 });
+                s.keys_sampled += 1;
+                s.memory_sampled += sizes.get(i).copied().unwrap_or(0);
+            }
+            // multiplied before divided, not to lose the fraction of the
+            // average.
+            let memory_estimate = match sizes.len() {
+                0 => 0,
+                n => (sizes.iter().map(|&s| s as u128).sum::<u128>() * keys as u128 / n as u128).min(u64::MAX as u128) as u64,
+            };
+            nodes.push(NodeDistribution {
+                addr,
+                keys,
+                keys_sampled: sampled.len() as u64,
+                memory_estimate,
+            });
+        }
+
+        let mut top_slots: Vec<SlotDistribution> = slots.into_values().collect();
+        top_slots.sort_by(|a, b| (b.keys_sampled, b.memory_sampled, a.slot).cmp(&(a.keys_sampled, a.memory_sampled, b.slot)));
+        top_slots.truncate(opts.top_slots);
+        Ok(KeyDistribution {
+            nodes,
+            top_slots,
+        })
+    }
+
+    fn scan_node(&mut self, addr: &str, opts: &DistributionOptions) -> Result<Vec<Vec<u8>>, RuisError> {
+        let count = opts.scan_count.to_string();
+        let mut cursor = b"0".to_vec();
+        let mut keys = vec![];
+        loop {
+            let (next, batch) = parse_page(self.execute_on_node(addr, &[b"scan", &cursor, b"count", count.as_bytes()])?)?;
+            keys.extend(batch);
+            cursor = next;
+            if cursor == b"0" || keys.len() >= opts.sample_limit {
+                keys.truncate(opts.sample_limit);
+                return Ok(keys);
+            }
+        }
+    }
+
+    // the keys expired or deleted since the scan count as 0.
+    fn memory_usages(&mut self, addr: &str, keys: &[Vec<u8>]) -> Result<Vec<u64>, RuisError> {
+        if keys.is_empty() {
+            return Ok(vec![]);
+        }
+        let mut conn = self.checkout(addr)?;
+        let r = conn.execute_batch(keys.iter().map(|k| [&b"memory"[..], b"usage", k]));
+        match r {
+            Ok(_) => self.checkin(addr, conn),
+            Err(_) => self.pool(addr).mark_failed(),
+        }
+        Ok(r?.into_iter().map(|v| match v {
+            RespValue::Int(n) => n as u64,
+            _ => 0,
+        }).collect())
+    }
+
+    fn execute_on_node(&mut self, addr: &str, cmd: &[&[u8]]) -> Result<RespValue, RuisError> {
+        let conn = self.checkout(addr)?;
+        self.execute_on(addr, conn, cmd, false)?.into_result()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::tests::slots_reply;
+    use super::super::super::testing::fake_node;
+
+    #[test]
+    fn test_key_distribution() {
+        // two pages of keys, sized 100 bytes each but {a}3 of 101.
+        let a = fake_node(|me, args| {
+            let bulks = |keys: &[&[u8]]| RespValue::Array(keys.iter().map(|k| RespValue::Bulk(k.to_vec())).collect());
+            match args[0].as_slice() {
+                b"cluster" => slots_reply(&[(0, 16383, me)]),
+                b"dbsize" => RespValue::Int(4),
+                b"scan" if args[1] == b"0" => RespValue::Array(vec![RespValue::Bulk(b"7".to_vec()), bulks(&[b"{a}1", b"{a}2"])]),
+                b"scan" => RespValue::Array(vec![RespValue::Bulk(b"0".to_vec()), bulks(&[b"{a}3", b"b"])]),
+                b"memory" if args[2] == b"{a}3" => RespValue::Int(101),
+                b"memory" => RespValue::Int(100),
+                _ => RespValue::Error(b"ERR unknown command".to_vec()),
+            }
+        });
+
+        let mut client = ClusterClient::connect(&[&a], None).unwrap();
+        let dist = client.key_distribution(&DistributionOptions::new().memory_samples(3)).unwrap();
+        // 301 bytes over the 3 keys sized, times the 4 keys.
+        assert_eq!(dist.nodes, vec![NodeDistribution { addr: a.clone(), keys: 4, keys_sampled: 4, memory_estimate: 401 }]);
+        assert_eq!(dist.top_slots, vec![
+            SlotDistribution { slot: cluster_slot(b"a"), keys_sampled: 3, memory_sampled: 301 },
+            SlotDistribution { slot: cluster_slot(b"b"), keys_sampled: 1, memory_sampled: 0 },
+        ]);
+
+        let dist = client.key_distribution(&DistributionOptions::new().sample_limit(2).top_slots(1)).unwrap();
+        assert_eq!(dist.nodes[0].keys_sampled, 2);
+        assert_eq!(dist.top_slots.len(), 1);
+    }
+}
@@ -0,0 +1,100 @@
+use super::{ClusterClient, cluster_slot};
+use super::super::pipeline::Pipeline;
+use super::super::types::{RespValue, RuisError};
+
+// the keys of a multi-key command have to be in the same slot, the helpers
+// here split the keys by slot into one command per slot, send them as a
+// pipeline, and merge the replies. unlike the single commands, they are not
+// atomic across the slots.
+impl ClusterClient {
+    // returns the values in the order of the keys.
+    pub fn multi_slot_mget(&mut self, keys: &[&[u8]]) -> Result<Vec<RespValue>, RuisError> {
+        let groups = group_by_slot(keys.len(), |i| keys[i]);
+        let mut pipe = Pipeline::new();
+        for idxs in &groups {
+            let mut cmd: Vec<&[u8]> = vec![b"mget"];
+            cmd.extend(idxs.iter().map(|&i| keys[i]));
+            pipe.cmd(&cmd);
+        }
+
+        let mut values = vec![RespValue::NilBulk; keys.len()];
+        for (idxs, reply) in groups.iter().zip(self.execute_pipeline(&pipe)?) {
+            match reply.into_result()? {
+                RespValue::Array(vs) if vs.len() == idxs.len() => {
+                    for (&i, v) in idxs.iter().zip(vs) {
+                        values[i] = v;
+                    }
+                },
+                v => return Err(RuisError::Unexpected(format!("mget: {:?}", v))),
+            }
+        }
+        Ok(values)
+    }
+
+    pub fn multi_slot_mset(&mut self, pairs: &[(&[u8], &[u8])]) -> Result<(), RuisError> {
+        let groups = group_by_slot(pairs.len(), |i| pairs[i].0);
+        let mut pipe = Pipeline::new();
+        for idxs in &groups {
+            let mut cmd: Vec<&[u8]> = vec![b"mset"];
+            for &i in idxs {
+                cmd.push(pairs[i].0);
+                cmd.push(pairs[i].1);
+            }
+            pipe.cmd(&cmd);
+        }
+        for reply in self.execute_pipeline(&pipe)? {
+            reply.into_result()?;
+        }
+        Ok(())
+    }
+
+    // returns the number of the keys deleted.
+    pub fn multi_slot_del(&mut self, keys: &[&[u8]]) -> Result<i64, RuisError> {
+        let groups = group_by_slot(keys.len(), |i| keys[i]);
+        let mut pipe = Pipeline::new();
+        for idxs in &groups {
+            let mut cmd: Vec<&[u8]> = vec![b"del"];
+            cmd.extend(idxs.iter().map(|&i| keys[i]));
+            pipe.cmd(&cmd);
+        }
+        let mut deleted = 0;
+        for reply in self.execute_pipeline(&pipe)? {
+            match reply.into_result()? {
+                RespValue::Int(n) => deleted += n,
+                v => return Err(RuisError::Unexpected(format!("del: {:?}", v))),
+            }
+        }
+        Ok(deleted)
+    }
+}
+
+// the indexes of the keys grouped by slot, in the order the slots are first
+// seen.
+fn group_by_slot<'a, F>(n: usize, key: F) -> Vec<Vec<usize>>
+    where F: Fn(usize) -> &'a [u8] {
+    let mut slots: Vec<u16> = vec![];
+    let mut groups: Vec<Vec<usize>> = vec![];
+    for i in 0..n {
+        let slot = cluster_slot(key(i));
+        match slots.iter().position(|&s| s == slot) {
+            Some(g) => groups[g].push(i),
+            None => {
+                slots.push(slot);
+                groups.push(vec![i]);
+            },
+        }
+    }
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_group_by_slot() {
+        let keys: Vec<&[u8]> = vec![b"{a}1", b"b", b"{a}2", b"c", b"b"];
+        let groups = group_by_slot(keys.len(), |i| keys[i]);
+        assert_eq!(groups, vec![vec![0, 2], vec![1, 4], vec![3]]);
+    }
+}
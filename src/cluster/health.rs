@@ -0,0 +1,142 @@
+use std::time::{Duration, Instant};
+
+use super::{ClusterClient, NodeRole, SLOT_COUNT, SlotRange};
+use super::super::types::RuisError;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NodeStatus {
+    pub addr: String,
+    pub id: String,
+    pub role: NodeRole,
+    // None if the node was never contacted by this client.
+    pub reachable: Option<bool>,
+    // the last ping on ping_all(), otherwise the average round trip of the
+    // commands sent to the node.
+    pub latency: Option<Duration>,
+    pub error: Option<String>,
+    // flagged as failed by the cluster itself.
+    pub failed: bool,
+    pub slots: Vec<SlotRange>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClusterHealth {
+    pub nodes: Vec<NodeStatus>,
+    // the slots not served by any node.
+    pub uncovered: Vec<SlotRange>,
+}
+
+impl ClusterHealth {
+    // all the slots are covered, and no node is known to be unreachable or
+    // failed.
+    pub fn is_healthy(&self) -> bool {
+        self.uncovered.is_empty() && self.nodes.iter().all(|n| n.reachable != Some(false) && !n.failed)
+    }
+}
+
+impl ClusterClient {
+    // pings every node of the topology, the replicas included.
+    pub fn ping_all(&mut self) -> ClusterHealth {
+        let mut health = self.node_status();
+        for node in &mut health.nodes {
+            let start = Instant::now();
+            let r = self.checkout(&node.addr)
+                .and_then(|conn| self.execute_on(&node.addr, conn, &[b"ping"], false))
+                .and_then(|v| v.into_result());
+            match r {
+                Ok(_) => {
+                    node.reachable = Some(true);
+                    node.latency = Some(start.elapsed());
+                    node.error = None;
+                },
+                // the node answered, only the command failed, eg. on LOADING.
+                Err(e @ RuisError::ServerError(_)) => {
+                    node.reachable = Some(true);
+                    node.error = Some(e.to_string());
+                },
+                Err(e) => {
+                    node.reachable = Some(false);
+                    node.latency = None;
+                    node.error = Some(e.to_string());
+                },
+            }
+        }
+        health
+    }
+
+    // reports what the client knows without sending any command.
+    pub fn node_status(&self) -> ClusterHealth {
+        let nodes = self.topology.nodes.iter().map(|n| {
+            let addr = n.addr();
+            let pool = self.pools.get(&addr);
+            NodeStatus {
+                reachable: pool.map(|p| p.is_healthy()),
+                latency: self.latencies.get(&addr).map(|l| l.get()),
+                error: None,
+                id: n.id.clone(),
+                role: n.role,
+                failed: n.is_failed(),
+                slots: n.slots.clone(),
+                addr,
+            }
+        }).collect();
+        ClusterHealth {
+            nodes,
+            uncovered: self.uncovered_slots(),
+        }
+    }
+
+    fn uncovered_slots(&self) -> Vec<SlotRange> {
+        let mut ranges: Vec<SlotRange> = vec![];
+        for slot in 0..SLOT_COUNT as u16 {
+            if self.slots[slot as usize].is_some() {
+                continue;
+            }
+            match ranges.last_mut() {
+                Some(r) if r.end + 1 == slot => r.end = slot,
+                _ => ranges.push(SlotRange { start: slot, end: slot }),
+            }
+        }
+        ranges
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+    use super::*;
+    use super::super::tests::slots_reply;
+    use super::super::super::testing::fake_node;
+    use super::super::super::types::RespValue;
+
+    #[test]
+    fn test_ping_all() {
+        let down = {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            listener.local_addr().unwrap().to_string()
+        };
+        let down_addr = down.clone();
+        let a = fake_node(move |me, args| {
+            match args[0].as_slice() {
+                b"cluster" => slots_reply(&[(0, 99, me), (200, 16383, &down_addr)]),
+                _ => RespValue::Bulk(b"PONG".to_vec()),
+            }
+        });
+
+        let mut client = ClusterClient::connect(&[&a], None).unwrap();
+        let status = client.node_status();
+        assert_eq!(status.uncovered, vec![SlotRange { start: 100, end: 199 }]);
+        assert_eq!(status.nodes.iter().find(|n| n.addr == down).unwrap().reachable, None);
+
+        let health = client.ping_all();
+        assert!(!health.is_healthy());
+        let node_a = health.nodes.iter().find(|n| n.addr == a).unwrap();
+        assert_eq!(node_a.reachable, Some(true));
+        assert!(node_a.latency.is_some());
+        assert_eq!(node_a.slots, vec![SlotRange { start: 0, end: 99 }]);
+        let node_down = health.nodes.iter().find(|n| n.addr == down).unwrap();
+        assert_eq!(node_down.reachable, Some(false));
+        assert!(node_down.error.is_some());
+        assert_eq!(client.node_status().nodes.iter().find(|n| n.addr == down).unwrap().reachable, Some(false));
+    }
+}
@@ -0,0 +1,1030 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::connection::{ClientIdentity, RemapFn, TcpConnection, redacted};
+use super::metrics::MetricsSink;
+use super::pipeline::Pipeline;
+use super::pool::ConnectionPool;
+use super::types::{ErrorKind, RespValue, RuisError};
+
+mod distribution;
+mod fanout;
+mod health;
+mod routing;
+mod slot;
+mod topology;
+
+pub use self::distribution::{DistributionOptions, KeyDistribution, NodeDistribution, SlotDistribution};
+pub use self::health::{ClusterHealth, NodeStatus};
+pub use self::routing::{ReadFrom, check_slots, command_key, command_keys, is_read_only};
+pub use self::slot::{SLOT_COUNT, cluster_slot, hash_tag};
+pub use self::topology::{ClusterNode, ClusterTopology, NodeRole, SlotRange};
+
+pub(crate) use self::routing::Latency;
+
+// https://redis.io/topics/cluster-spec
+
+const DEFAULT_MAX_REDIRECTS: usize = 5;
+const DEFAULT_REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+const DEFAULT_MIN_REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+// how long a failed replica is skipped for the reads.
+const REPLICA_DOWN_PERIOD: Duration = Duration::from_secs(5);
+const DEFAULT_POOL_SIZE: usize = 2;
+
+pub struct ClusterClientBuilder {
+    seeds: Vec<String>,
+    password: Option<String>,
+    max_redirects: usize,
+    refresh_interval: Option<Duration>,
+    min_refresh_interval: Duration,
+    read_from: ReadFrom,
+    remap: Option<RemapFn>,
+    pool_size: usize,
+    identity: Option<ClientIdentity>,
+    metrics: Option<Arc<dyn MetricsSink>>,
+}
+
+impl fmt::Debug for ClusterClientBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ClusterClientBuilder")
+            .field("seeds", &self.seeds)
+            .field("password", &redacted(&self.password))
+            .field("max_redirects", &self.max_redirects)
+            .field("refresh_interval", &self.refresh_interval)
+            .field("min_refresh_interval", &self.min_refresh_interval)
+            .field("read_from", &self.read_from)
+            .field("pool_size", &self.pool_size)
+            .field("identity", &self.identity)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ClusterClientBuilder {
+    pub fn new(seeds: &[&str]) -> Self {
+        Self {
+            seeds: seeds.iter().map(|s| s.to_string()).collect(),
+            password: None,
+            max_redirects: DEFAULT_MAX_REDIRECTS,
+            refresh_interval: Some(DEFAULT_REFRESH_INTERVAL),
+            min_refresh_interval: DEFAULT_MIN_REFRESH_INTERVAL,
+            read_from: ReadFrom::Master,
+            remap: None,
+            pool_size: DEFAULT_POOL_SIZE,
+            identity: None,
+            metrics: None,
+        }
+    }
+
+    pub fn password(mut self, password: &str) -> Self {
+        self.password = Some(password.to_string());
+        self
+    }
+
+    // how many MOVED/ASK redirects are followed for a single command before
+    // giving up.
+    pub fn max_redirects(mut self, n: usize) -> Self {
+        self.max_redirects = n;
+        self
+    }
+
+    // reloads the slots periodically even if nothing went wrong, None only
+    // reloads on MOVED redirects and node failures.
+    pub fn refresh_interval(mut self, interval: Option<Duration>) -> Self {
+        self.refresh_interval = interval;
+        self
+    }
+
+    // the slots are reloaded at most once in this interval, so a burst of
+    // MOVED redirects during a resharding results in a single reload.
+    pub fn min_refresh_interval(mut self, interval: Duration) -> Self {
+        self.min_refresh_interval = interval;
+        self
+    }
+
+    // sends the read-only commands to the replicas, the replica connections
+    // are put in READONLY mode.
+    pub fn read_from(mut self, read_from: ReadFrom) -> Self {
+        self.read_from = read_from;
+        self
+    }
+
+    // the nodes behind NAT or in containers advertise the addresses which are
+    // not reachable from the client. the hook is applied on every address
+    // before connecting, including the seeds, while the slots, the redirects
+    // and the topology keep the advertised addresses.
+    pub fn remap<F>(mut self, f: F) -> Self
+        where F: Fn(&str) -> String + Send + Sync + 'static {
+        self.remap = Some(Box::new(f));
+        self
+    }
+
+    // the idle connections kept per node.
+    pub fn pool_size(mut self, n: usize) -> Self {
+        self.pool_size = n;
+        self
+    }
+
+    // the name and the library info set on the connections to the nodes.
+    pub fn identity(mut self, identity: ClientIdentity) -> Self {
+        self.identity = Some(identity);
+        self
+    }
+
+    // reports the checkouts and the reconnects of the node pools.
+    pub fn metrics(mut self, sink: Arc<dyn MetricsSink>) -> Self {
+        self.metrics = Some(sink);
+        self
+    }
+
+    pub fn connect(self) -> Result<ClusterClient, RuisError> {
+        let mut client = ClusterClient {
+            seeds: self.seeds,
+            password: self.password,
+            max_redirects: self.max_redirects,
+            refresh_interval: self.refresh_interval,
+            min_refresh_interval: self.min_refresh_interval,
+            last_refresh: Instant::now(),
+            refresh_pending: false,
+            read_from: self.read_from,
+            remap: self.remap,
+            pool_size: self.pool_size,
+            identity: self.identity,
+            metrics: self.metrics,
+            round_robin: 0,
+            topology: ClusterTopology::default(),
+            nodes: vec![],
+            slots: vec![None; SLOT_COUNT],
+            replicas: HashMap::new(),
+            down_until: HashMap::new(),
+            latencies: HashMap::new(),
+            pools: HashMap::new(),
+        };
+        client.load_slots()?;
+        Ok(client)
+    }
+}
+
+// ClusterClient routes each command to the master serving the slot of its key,
+// and follows the MOVED/ASK redirects when the slots are being migrated.
+pub struct ClusterClient {
+    seeds: Vec<String>,
+    password: Option<String>,
+    max_redirects: usize,
+    refresh_interval: Option<Duration>,
+    min_refresh_interval: Duration,
+    last_refresh: Instant,
+    // set on MOVED redirects and node failures, the slots are reloaded before
+    // the next command.
+    refresh_pending: bool,
+    read_from: ReadFrom,
+    remap: Option<RemapFn>,
+    pool_size: usize,
+    identity: Option<ClientIdentity>,
+    metrics: Option<Arc<dyn MetricsSink>>,
+    round_robin: usize,
+    topology: ClusterTopology,
+    nodes: Vec<String>,
+    // the index in nodes of the master serving each slot.
+    slots: Vec<Option<usize>>,
+    // the replicas of each master, by address.
+    replicas: HashMap<String, Vec<String>>,
+    down_until: HashMap<String, Instant>,
+    latencies: HashMap<String, Latency>,
+    // by the advertised address of the nodes.
+    pools: HashMap<String, ConnectionPool>,
+}
+
+impl fmt::Debug for ClusterClient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ClusterClient")
+            .field("seeds", &self.seeds)
+            .field("password", &redacted(&self.password))
+            .field("read_from", &self.read_from)
+            .field("nodes", &self.nodes)
+            .field("pools", &self.pools)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ClusterClient {
+    pub fn connect(seeds: &[&str], password: Option<&str>) -> Result<ClusterClient, RuisError> {
+        let mut builder = ClusterClientBuilder::new(seeds);
+        if let Some(password) = password {
+            builder = builder.password(password);
+        }
+        builder.connect()
+    }
+
+    pub fn execute(&mut self, cmd: &[&[u8]]) -> Result<RespValue, RuisError> {
+        self.refresh_if_due();
+        let mut addr = self.route_read(cmd)?;
+        let mut asking = false;
+        let mut rerouted = false;
+
+        for _ in 0..=self.max_redirects {
+            // the command is not sent yet if the node can not be connected, so
+            // it's safe to send it to the new owner of the slot, if any.
+            let conn = match self.checkout(&addr) {
+                Ok(conn) => conn,
+                Err(_) if self.is_replica(&addr) => {
+                    self.mark_down(&addr);
+                    addr = self.route(cmd)?;
+                    continue;
+                },
+                Err(e) => {
+                    self.refresh_pending = true;
+                    if !rerouted && !asking && self.refresh_if_due() {
+                        rerouted = true;
+                        addr = self.route(cmd)?;
+                        continue;
+                    }
+                    return Err(e);
+                },
+            };
+
+            let reply = match self.execute_on(&addr, conn, cmd, asking) {
+                Ok(reply) => reply,
+                // only the read-only commands are sent to the replicas, they
+                // are safe to be resent to the master.
+                Err(RuisError::IoError(_)) if self.is_replica(&addr) => {
+                    self.mark_down(&addr);
+                    addr = self.route(cmd)?;
+                    continue;
+                },
+                Err(e @ RuisError::IoError(_)) => {
+                    self.refresh_pending = true;
+                    return Err(e);
+                },
+                Err(e) => return Err(e),
+            };
+            match parse_redirect(&reply, &addr) {
+                Some(Redirect::Moved { slot, addr: to }) => {
+                    self.set_slot_node(slot, &to);
+                    self.refresh_pending = true;
+                    addr = to;
+                    asking = false;
+                },
+                Some(Redirect::Ask { addr: to, .. }) => {
+                    addr = to;
+                    asking = true;
+                },
+                None => return Ok(reply),
+            }
+        }
+        Err(RuisError::Cluster(format!("too many redirects on {}", String::from_utf8_lossy(cmd[0]))))
+    }
+
+    // splits the pipeline by the nodes serving the commands, and sends the sub
+    // pipelines to the nodes concurrently. the replies are returned in the
+    // order of the commands. the commands redirected by MOVED/ASK are resent
+    // one by one after the sub pipelines finished.
+    pub fn execute_pipeline(&mut self, pipeline: &Pipeline) -> Result<Vec<RespValue>, RuisError> {
+        self.refresh_if_due();
+        let cmds: Vec<Vec<&[u8]>> = pipeline.commands().collect();
+
+        let mut groups: Vec<(String, Vec<usize>)> = vec![];
+        for (i, cmd) in cmds.iter().enumerate() {
+            let addr = self.route_read(cmd)?;
+            match groups.iter_mut().find(|(a, _)| *a == addr) {
+                Some((_, idxs)) => idxs.push(i),
+                None => groups.push((addr, vec![i])),
+            }
+        }
+
+        let mut batches = vec![];
+        for (addr, idxs) in groups {
+            match self.checkout(&addr) {
+                Ok(conn) => batches.push((addr, idxs, conn)),
+                Err(e) => {
+                    if self.is_replica(&addr) {
+                        self.mark_down(&addr);
+                    } else {
+                        self.refresh_pending = true;
+                    }
+                    for (addr, _, conn) in batches {
+                        self.checkin(&addr, conn);
+                    }
+                    return Err(e);
+                },
+            }
+        }
+
+        let cmds = &cmds;
+        let results: Vec<_> = thread::scope(|s| {
+            let handles: Vec<_> = batches.into_iter().map(|(addr, idxs, mut conn)| {
+                s.spawn(move || {
+                    let r = conn.execute_batch(idxs.iter().map(|&i| &cmds[i][..]));
+                    (addr, idxs, conn, r)
+                })
+            }).collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+
+        let mut replies: Vec<Option<(RespValue, String)>> = vec![None; cmds.len()];
+        let mut first_err = None;
+        for (addr, idxs, conn, r) in results {
+            match r {
+                Ok(vs) => {
+                    for (i, v) in idxs.into_iter().zip(vs) {
+                        replies[i] = Some((v, addr.clone()));
+                    }
+                    self.checkin(&addr, conn);
+                },
+                Err(e) => {
+                    self.pool(&addr).mark_failed();
+                    if self.is_replica(&addr) {
+                        self.mark_down(&addr);
+                    } else {
+                        self.refresh_pending = true;
+                    }
+                    first_err.get_or_insert(e);
+                },
+            }
+        }
+        if let Some(e) = first_err {
+            return Err(e);
+        }
+
+        let mut r = vec![];
+        for (i, reply) in replies.into_iter().enumerate() {
+            let (reply, addr) = reply.unwrap();
+            match parse_redirect(&reply, &addr) {
+                Some(Redirect::Moved { slot, addr }) => {
+                    self.set_slot_node(slot, &addr);
+                    self.refresh_pending = true;
+                },
+                Some(Redirect::Ask { .. }) => {},
+                None => {
+                    r.push(reply);
+                    continue;
+                },
+            }
+            // the moved slot is routed to its new node now, and the ASK is
+            // followed again by the redirect handling of execute().
+            r.push(self.execute(&cmds[i])?);
+        }
+        Ok(r)
+    }
+
+    // picks a replica for the read-only commands if configured so, falls back
+    // to the master when no replica of the slot is up.
+    fn route_read(&mut self, cmd: &[&[u8]]) -> Result<String, RuisError> {
+        let master = self.route(cmd)?;
+        if self.read_from == ReadFrom::Master || !is_read_only(cmd) {
+            return Ok(master);
+        }
+
+        let now = Instant::now();
+        let down_until = &self.down_until;
+        let candidates: Vec<&String> = self.replicas.get(&master).into_iter().flatten()
+            .filter(|r| down_until.get(*r).is_none_or(|t| *t <= now))
+            .collect();
+        if candidates.is_empty() {
+            return Ok(master);
+        }
+        let picked = match self.read_from {
+            ReadFrom::ReplicaLowestLatency => {
+                let latencies = &self.latencies;
+                candidates.into_iter().min_by_key(|r| latencies.get(*r).map(|l| l.get()).unwrap_or_default()).unwrap()
+            },
+            _ => {
+                self.round_robin = self.round_robin.wrapping_add(1);
+                candidates[self.round_robin % candidates.len()]
+            },
+        };
+        Ok(picked.clone())
+    }
+
+    fn is_replica(&self, addr: &str) -> bool {
+        self.replicas.values().any(|rs| rs.iter().any(|r| r == addr))
+    }
+
+    fn mark_down(&mut self, addr: &str) {
+        self.pools.remove(addr);
+        self.down_until.insert(addr.to_string(), Instant::now() + REPLICA_DOWN_PERIOD);
+    }
+
+    // the master the command would be routed to, the reads served by the
+    // replicas and the redirects aside.
+    pub fn node_for(&self, cmd: &[&[u8]]) -> Option<String> {
+        self.route(cmd).ok()
+    }
+
+    // the keys in different slots are rejected before being sent, the server
+    // would only reply a CROSSSLOT error without naming the keys.
+    fn route(&self, cmd: &[&[u8]]) -> Result<String, RuisError> {
+        match check_slots(cmd)? {
+            Some(slot) => self.node_for_slot(slot),
+            None => self.any_node(),
+        }
+    }
+
+    // reloads the slots now, regardless of the refresh intervals.
+    pub fn refresh_topology(&mut self) -> Result<(), RuisError> {
+        self.load_slots()?;
+        self.refresh_pending = false;
+        Ok(())
+    }
+
+    // returns true if the slots got reloaded.
+    fn refresh_if_due(&mut self) -> bool {
+        let elapsed = self.last_refresh.elapsed();
+        let due = self.refresh_pending || self.refresh_interval.is_some_and(|i| elapsed >= i);
+        if !due || elapsed < self.min_refresh_interval {
+            return false;
+        }
+        // on failures the old slots are kept until the next try.
+        self.refresh_topology().is_ok()
+    }
+
+    // the connection is only put back once the reply is read whole, or on the
+    // error reply to ASKING, the next command reconnects otherwise.
+    fn execute_on(&mut self, addr: &str, mut conn: TcpConnection, cmd: &[&[u8]], asking: bool) -> Result<RespValue, RuisError> {
+        let start = Instant::now();
+        let r = if asking {
+            conn.execute(&[b"asking"]).and_then(|v| v.into_result()).and_then(|_| conn.execute(cmd))
+        } else {
+            conn.execute(cmd)
+        };
+        match r {
+            Ok(_) => {
+                self.latencies.entry(addr.to_string()).or_default().observe(start.elapsed());
+                self.checkin(addr, conn);
+            },
+            Err(RuisError::ServerError(_)) => self.checkin(addr, conn),
+            Err(_) => self.pool(addr).mark_failed(),
+        }
+        r
+    }
+
+    // the pools are created on the first use, the replica connections are put
+    // in READONLY mode.
+    fn pool(&mut self, addr: &str) -> &ConnectionPool {
+        if !self.pools.contains_key(addr) {
+            let connect_addr = match self.remap {
+                Some(ref remap) => remap(addr),
+                None => addr.to_string(),
+            };
+            let mut pool = ConnectionPool::new(&connect_addr, self.password.as_deref()).max_idle(self.pool_size);
+            if self.is_replica(addr) {
+                pool = pool.init_cmd(&[b"readonly"]);
+            }
+            if let Some(ref identity) = self.identity {
+                pool = pool.identity(identity.clone());
+            }
+            if let Some(ref m) = self.metrics {
+                pool = pool.metrics(m.clone());
+            }
+            self.pools.insert(addr.to_string(), pool);
+        }
+        &self.pools[addr]
+    }
+
+    fn checkout(&mut self, addr: &str) -> Result<TcpConnection, RuisError> {
+        self.pool(addr).get()
+    }
+
+    fn checkin(&mut self, addr: &str, conn: TcpConnection) {
+        self.pool(addr).put(conn);
+    }
+
+    fn node_for_slot(&self, slot: u16) -> Result<String, RuisError> {
+        match self.slots[slot as usize] {
+            Some(i) => Ok(self.nodes[i].clone()),
+            None => Err(RuisError::Cluster(format!("slot {} is not served by any node", slot))),
+        }
+    }
+
+    fn any_node(&self) -> Result<String, RuisError> {
+        self.slots.iter().flatten().next()
+            .map(|&i| self.nodes[i].clone())
+            .ok_or_else(|| RuisError::Cluster("no node is serving slots".to_string()))
+    }
+
+    fn set_slot_node(&mut self, slot: u16, addr: &str) {
+        let i = match self.nodes.iter().position(|n| n == addr) {
+            Some(i) => i,
+            None => {
+                self.nodes.push(addr.to_string());
+                self.nodes.len() - 1
+            },
+        };
+        self.slots[slot as usize] = Some(i);
+    }
+
+    // the topology as of the last time the slots were loaded.
+    pub fn topology(&self) -> &ClusterTopology {
+        &self.topology
+    }
+
+    // fetches CLUSTER SLOTS from the known nodes and then the seeds, until one
+    // of them answers.
+    fn load_slots(&mut self) -> Result<(), RuisError> {
+        self.last_refresh = Instant::now();
+        let mut candidates: Vec<String> = self.topology.nodes.iter()
+            .filter(|n| !n.is_failed())
+            .map(|n| n.addr())
+            .collect();
+        for seed in &self.seeds {
+            if !candidates.contains(seed) {
+                candidates.push(seed.clone());
+            }
+        }
+
+        let mut last_err = RuisError::Cluster("no seed nodes".to_string());
+        for seed in candidates {
+            let r = self.checkout(&seed)
+                .and_then(|conn| self.execute_on(&seed, conn, &[b"cluster", b"slots"], false))
+                .and_then(|v| v.into_result())
+                .and_then(ClusterTopology::from_cluster_slots);
+            match r {
+                Ok(mut topology) => {
+                    topology.resolve_empty_hosts(addr_host(&seed));
+                    self.set_topology(topology);
+                    return Ok(());
+                },
+                Err(e) => last_err = e,
+            }
+        }
+        Err(last_err)
+    }
+
+    fn set_topology(&mut self, topology: ClusterTopology) {
+        self.nodes.clear();
+        self.slots = vec![None; SLOT_COUNT];
+        self.replicas.clear();
+        for node in topology.masters() {
+            let addr = node.addr();
+            let replicas = topology.replicas_of(node).filter(|r| !r.is_failed()).map(|r| r.addr()).collect();
+            self.replicas.insert(addr.clone(), replicas);
+            for range in &node.slots {
+                for slot in range.start..=range.end {
+                    self.set_slot_node(slot, &addr);
+                }
+            }
+        }
+        // the nodes removed from the topology are forgotten, the seeds are
+        // reconnected on the next refresh if needed.
+        let known = |addr: &String| topology.nodes.iter().any(|n| n.addr() == *addr);
+        self.latencies.retain(|addr, _| known(addr));
+        self.pools.retain(|addr, _| known(addr));
+        self.topology = topology;
+    }
+}
+
+fn addr_host(addr: &str) -> &str {
+    addr.rsplit_once(':').map(|(host, _)| host).unwrap_or(addr)
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Redirect {
+    Moved { slot: u16, addr: String },
+    Ask { slot: u16, addr: String },
+}
+
+// the -MOVED 3999 127.0.0.1:6381 and -ASK 3999 127.0.0.1:6381 errors, the
+// address without the host is on the host of the current node.
+fn parse_redirect(reply: &RespValue, current: &str) -> Option<Redirect> {
+    let with_host = |addr: String| if addr.starts_with(':') {
+        format!("{}{}", addr_host(current), addr)
+    } else {
+        addr
+    };
+    match reply.error_kind()? {
+        ErrorKind::Moved { slot, addr } => Some(Redirect::Moved { slot, addr: with_host(addr) }),
+        ErrorKind::Ask { slot, addr } => Some(Redirect::Ask { slot, addr: with_host(addr) }),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::thread;
+    use super::*;
+    use super::super::testing::fake_node;
+
+    pub(super) fn slots_reply(ranges: &[(i64, i64, &str)]) -> RespValue {
+        RespValue::Array(ranges.iter().map(|&(start, end, addr)| {
+            let (host, port) = addr.split_at(addr.rfind(':').unwrap());
+            RespValue::Array(vec![
+                RespValue::Int(start),
+                RespValue::Int(end),
+                RespValue::Array(vec![
+                    RespValue::Bulk(host.as_bytes().to_vec()),
+                    RespValue::Int(port[1..].parse().unwrap()),
+                ]),
+            ])
+        }).collect())
+    }
+
+    // all the slots served by the master, with the node ids to link the
+    // replicas.
+    fn replicated_slots_reply(master: &str, replicas: &[&str]) -> RespValue {
+        let node = |addr: &str| {
+            let (host, port) = addr.split_at(addr.rfind(':').unwrap());
+            RespValue::Array(vec![
+                RespValue::Bulk(host.as_bytes().to_vec()),
+                RespValue::Int(port[1..].parse().unwrap()),
+                RespValue::Bulk(format!("id-{}", addr).into_bytes()),
+            ])
+        };
+        let mut range = vec![RespValue::Int(0), RespValue::Int(16383), node(master)];
+        range.extend(replicas.iter().map(|r| node(r)));
+        RespValue::Array(vec![RespValue::Array(range)])
+    }
+
+    fn fake_replica(readonly: Arc<AtomicUsize>, delay: Duration) -> String {
+        fake_node(move |me, args| {
+            match args[0].as_slice() {
+                b"readonly" => {
+                    readonly.fetch_add(1, Ordering::SeqCst);
+                    RespValue::Bulk(b"OK".to_vec())
+                },
+                _ => {
+                    thread::sleep(delay);
+                    RespValue::Bulk(me.as_bytes().to_vec())
+                },
+            }
+        })
+    }
+
+    #[test]
+    fn test_parse_redirect() {
+        let r = parse_redirect(&RespValue::Error(b"MOVED 3999 127.0.0.1:6381".to_vec()), "127.0.0.1:6380");
+        assert_eq!(r, Some(Redirect::Moved { slot: 3999, addr: "127.0.0.1:6381".to_string() }));
+        let r = parse_redirect(&RespValue::Error(b"ASK 3999 :6381".to_vec()), "10.0.0.1:6380");
+        assert_eq!(r, Some(Redirect::Ask { slot: 3999, addr: "10.0.0.1:6381".to_string() }));
+        assert_eq!(parse_redirect(&RespValue::Error(b"ERR unknown".to_vec()), "127.0.0.1:6380"), None);
+        // the slots out of range are not redirects.
+        assert_eq!(parse_redirect(&RespValue::Error(b"MOVED 20000 127.0.0.1:6381".to_vec()), "127.0.0.1:6380"), None);
+        assert_eq!(parse_redirect(&RespValue::Error(b"ASK 16384 127.0.0.1:6381".to_vec()), "127.0.0.1:6380"), None);
+        assert_eq!(parse_redirect(&RespValue::Int(1), "127.0.0.1:6380"), None);
+    }
+
+    #[test]
+    fn test_redirects() {
+        let b = fake_node(|_, args| {
+            match args[0].as_slice() {
+                b"asking" => RespValue::Bulk(b"OK".to_vec()),
+                b"get" => RespValue::Bulk(args[1].clone()),
+                _ => RespValue::Error(b"ERR unknown command".to_vec()),
+            }
+        });
+        let b_addr = b.clone();
+        let a = fake_node(move |me, args| {
+            match args[0].as_slice() {
+                b"cluster" => slots_reply(&[(0, 16383, me)]),
+                b"get" if args[1] == b"moved" => RespValue::Error(format!("MOVED {} {}", cluster_slot(b"moved"), b_addr).into_bytes()),
+                b"get" if args[1] == b"ask" => RespValue::Error(format!("ASK {} {}", cluster_slot(b"ask"), b_addr).into_bytes()),
+                b"get" if args[1] == b"loop" => RespValue::Error(format!("ASK {} 127.0.0.1:{}", cluster_slot(b"loop"), 1).into_bytes()),
+                _ => RespValue::Bulk(b"a".to_vec()),
+            }
+        });
+
+        let mut client = ClusterClient::connect(&[&a], None).unwrap();
+        assert_eq!(client.execute(&[b"get", b"foo"]).unwrap(), RespValue::Bulk(b"a".to_vec()));
+        assert_eq!(client.execute(&[b"get", b"ask"]).unwrap(), RespValue::Bulk(b"ask".to_vec()));
+        assert_eq!(client.node_for_slot(cluster_slot(b"ask")).unwrap(), a);
+        assert_eq!(client.execute(&[b"get", b"moved"]).unwrap(), RespValue::Bulk(b"moved".to_vec()));
+        assert_eq!(client.node_for_slot(cluster_slot(b"moved")).unwrap(), b);
+        assert!(client.execute(&[b"get", b"loop"]).is_err());
+    }
+
+    #[test]
+    fn test_bootstrap_from_seeds() {
+        let seed = fake_node(|_, _| RespValue::Error(b"ERR This instance has cluster support disabled".to_vec()));
+        let r = ClusterClient::connect(&[&seed], None);
+        assert_eq!(format!("{}", r.err().unwrap()), "server err: ERR This instance has cluster support disabled");
+
+        let node = fake_node(|_, _| slots_reply(&[(0, 16383, "127.0.0.1:7000")]));
+        let client = ClusterClientBuilder::new(&["127.0.0.1:1", &node]).max_redirects(2).connect().unwrap();
+        assert_eq!(client.node_for_slot(100).unwrap(), "127.0.0.1:7000");
+    }
+
+    #[test]
+    fn test_refresh_on_moved() {
+        let b = fake_node(|_, _| RespValue::Bulk(b"b".to_vec()));
+        let resharded = Arc::new(AtomicBool::new(false));
+        let loads = Arc::new(AtomicUsize::new(0));
+        let (a_resharded, a_loads, b_addr) = (resharded.clone(), loads.clone(), b.clone());
+        let a = fake_node(move |me, args| {
+            let owner = if a_resharded.load(Ordering::SeqCst) { b_addr.as_str() } else { me };
+            match args[0].as_slice() {
+                b"cluster" => {
+                    a_loads.fetch_add(1, Ordering::SeqCst);
+                    slots_reply(&[(0, 16383, owner)])
+                },
+                _ if owner != me => RespValue::Error(format!("MOVED {} {}", cluster_slot(&args[1]), owner).into_bytes()),
+                _ => RespValue::Bulk(b"a".to_vec()),
+            }
+        });
+
+        let mut client = ClusterClientBuilder::new(&[&a])
+            .refresh_interval(None)
+            .min_refresh_interval(Duration::from_secs(0))
+            .connect().unwrap();
+        assert_eq!(client.execute(&[b"get", b"foo"]).unwrap(), RespValue::Bulk(b"a".to_vec()));
+        assert_eq!(client.execute(&[b"get", b"bar"]).unwrap(), RespValue::Bulk(b"a".to_vec()));
+        assert_eq!(loads.load(Ordering::SeqCst), 1);
+
+        resharded.store(true, Ordering::SeqCst);
+        assert_eq!(client.execute(&[b"get", b"foo"]).unwrap(), RespValue::Bulk(b"b".to_vec()));
+        assert_eq!(loads.load(Ordering::SeqCst), 1);
+        // the MOVED of foo reloads the slots before the next command, so bar
+        // goes to b directly.
+        assert_eq!(client.execute(&[b"get", b"bar"]).unwrap(), RespValue::Bulk(b"b".to_vec()));
+        assert_eq!(loads.load(Ordering::SeqCst), 2);
+        assert_eq!(client.node_for_slot(0).unwrap(), b);
+    }
+
+    #[test]
+    fn test_refresh_coalescing() {
+        let loads = Arc::new(AtomicUsize::new(0));
+        let a_loads = loads.clone();
+        let a = fake_node(move |me, args| {
+            match args[0].as_slice() {
+                b"cluster" => {
+                    a_loads.fetch_add(1, Ordering::SeqCst);
+                    slots_reply(&[(0, 16383, me)])
+                },
+                b"ping" => RespValue::Bulk(b"PONG".to_vec()),
+                _ => RespValue::Error(format!("MOVED {} {}", cluster_slot(&args[1]), me).into_bytes()),
+            }
+        });
+
+        let mut client = ClusterClientBuilder::new(&[&a])
+            .max_redirects(1)
+            .refresh_interval(None)
+            .min_refresh_interval(Duration::from_secs(3600))
+            .connect().unwrap();
+        for _ in 0..5 {
+            assert!(client.execute(&[b"get", b"foo"]).is_err());
+        }
+        assert_eq!(loads.load(Ordering::SeqCst), 1);
+
+        let mut client = ClusterClientBuilder::new(&[&a])
+            .refresh_interval(Some(Duration::from_secs(0)))
+            .min_refresh_interval(Duration::from_secs(0))
+            .connect().unwrap();
+        let before = loads.load(Ordering::SeqCst);
+        client.execute(&[b"ping"]).unwrap();
+        client.execute(&[b"ping"]).unwrap();
+        assert_eq!(loads.load(Ordering::SeqCst), before + 2);
+    }
+
+    #[test]
+    fn test_refresh_on_node_failure() {
+        let loads = Arc::new(AtomicUsize::new(0));
+        let a_loads = loads.clone();
+        let a = fake_node(move |me, args| {
+            match args[0].as_slice() {
+                // the first load reports a node which can not be connected.
+                b"cluster" if a_loads.fetch_add(1, Ordering::SeqCst) == 0 => slots_reply(&[(0, 16383, "127.0.0.1:1")]),
+                b"cluster" => slots_reply(&[(0, 16383, me)]),
+                _ => RespValue::Bulk(b"a".to_vec()),
+            }
+        });
+
+        let mut client = ClusterClientBuilder::new(&[&a])
+            .min_refresh_interval(Duration::from_secs(0))
+            .connect().unwrap();
+        assert_eq!(client.node_for_slot(0).unwrap(), "127.0.0.1:1");
+        assert_eq!(client.execute(&[b"get", b"foo"]).unwrap(), RespValue::Bulk(b"a".to_vec()));
+        assert_eq!(client.node_for_slot(0).unwrap(), a);
+    }
+
+    #[test]
+    fn test_execute_pipeline() {
+        let c = fake_node(|_, args| RespValue::Bulk([b"c:", &args[1][..]].concat()));
+        let b = fake_node(move |_, args| RespValue::Bulk([b"b:", &args[1][..]].concat()));
+        let (b_addr, c_addr) = (b.clone(), c.clone());
+        let a = fake_node(move |me, args| {
+            match args[0].as_slice() {
+                b"cluster" => slots_reply(&[(0, 8191, me), (8192, 16383, &b_addr)]),
+                b"ping" => RespValue::Bulk(b"PONG".to_vec()),
+                b"get" if args[1] == b"moved" => RespValue::Error(format!("MOVED {} {}", cluster_slot(b"moved"), c_addr).into_bytes()),
+                _ => RespValue::Bulk([b"a:", &args[1][..]].concat()),
+            }
+        });
+
+        let mut client = ClusterClient::connect(&[&a], None).unwrap();
+        let keys: Vec<&[u8]> = vec![b"foo", b"bar", b"moved", b"baz", b"qux"];
+        let mut pipe = Pipeline::new();
+        for key in &keys {
+            pipe.cmd(&[b"get", key]);
+        }
+        pipe.cmd(&[b"ping"]);
+        let replies = client.execute_pipeline(&pipe).unwrap();
+
+        let mut expected = vec![];
+        for key in &keys {
+            let owner: &[u8] = if *key == b"moved" {
+                b"c:"
+            } else if cluster_slot(key) < 8192 {
+                b"a:"
+            } else {
+                b"b:"
+            };
+            expected.push(RespValue::Bulk([owner, key].concat()));
+        }
+        expected.push(RespValue::Bulk(b"PONG".to_vec()));
+        assert_eq!(replies, expected);
+        assert_eq!(client.node_for_slot(cluster_slot(b"moved")).unwrap(), c);
+    }
+
+    #[test]
+    fn test_read_from_replicas() {
+        let readonly = Arc::new(AtomicUsize::new(0));
+        let r1 = fake_replica(readonly.clone(), Duration::from_millis(0));
+        let r2 = fake_replica(readonly.clone(), Duration::from_millis(0));
+        let (r1_addr, r2_addr) = (r1.clone(), r2.clone());
+        let a = fake_node(move |me, args| {
+            match args[0].as_slice() {
+                b"cluster" => replicated_slots_reply(me, &[&r1_addr, &r2_addr]),
+                _ => RespValue::Bulk(me.as_bytes().to_vec()),
+            }
+        });
+
+        let mut client = ClusterClientBuilder::new(&[&a]).read_from(ReadFrom::ReplicaRoundRobin).connect().unwrap();
+        let mut served = vec![];
+        for _ in 0..4 {
+            match client.execute(&[b"get", b"foo"]).unwrap() {
+                RespValue::Bulk(addr) => served.push(String::from_utf8(addr).unwrap()),
+                v => panic!("unexpected {:?}", v),
+            }
+        }
+        assert_eq!(served.iter().filter(|addr| **addr == r1).count(), 2);
+        assert_eq!(served.iter().filter(|addr| **addr == r2).count(), 2);
+        assert_eq!(readonly.load(Ordering::SeqCst), 2);
+
+        // the writes always go to the master.
+        assert_eq!(client.execute(&[b"set", b"foo", b"bar"]).unwrap(), RespValue::Bulk(a.as_bytes().to_vec()));
+    }
+
+    #[test]
+    fn test_read_from_lowest_latency() {
+        let readonly = Arc::new(AtomicUsize::new(0));
+        let slow = fake_replica(readonly.clone(), Duration::from_millis(50));
+        let fast = fake_replica(readonly.clone(), Duration::from_millis(0));
+        let (slow_addr, fast_addr) = (slow.clone(), fast.clone());
+        let a = fake_node(move |me, args| {
+            match args[0].as_slice() {
+                b"cluster" => replicated_slots_reply(me, &[&slow_addr, &fast_addr]),
+                _ => RespValue::Bulk(me.as_bytes().to_vec()),
+            }
+        });
+
+        let mut client = ClusterClientBuilder::new(&[&a]).read_from(ReadFrom::ReplicaLowestLatency).connect().unwrap();
+        // the replicas never measured are tried first.
+        for _ in 0..2 {
+            client.execute(&[b"get", b"foo"]).unwrap();
+        }
+        for _ in 0..3 {
+            assert_eq!(client.execute(&[b"get", b"foo"]).unwrap(), RespValue::Bulk(fast.as_bytes().to_vec()));
+        }
+    }
+
+    #[test]
+    fn test_read_fallback_to_master() {
+        let down = {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            listener.local_addr().unwrap().to_string()
+        };
+        let down_addr = down.clone();
+        let a = fake_node(move |me, args| {
+            match args[0].as_slice() {
+                b"cluster" => replicated_slots_reply(me, &[&down_addr]),
+                _ => RespValue::Bulk(me.as_bytes().to_vec()),
+            }
+        });
+
+        let mut client = ClusterClientBuilder::new(&[&a]).read_from(ReadFrom::ReplicaRoundRobin).connect().unwrap();
+        assert_eq!(client.execute(&[b"get", b"foo"]).unwrap(), RespValue::Bulk(a.as_bytes().to_vec()));
+        assert!(client.down_until.contains_key(&down));
+        assert_eq!(client.execute(&[b"get", b"foo"]).unwrap(), RespValue::Bulk(a.as_bytes().to_vec()));
+    }
+
+    #[test]
+    fn test_cross_slot() {
+        let sent = Arc::new(AtomicUsize::new(0));
+        let sent_mget = sent.clone();
+        let a = fake_node(move |me, args| {
+            match args[0].as_slice() {
+                b"cluster" => slots_reply(&[(0, 16383, me)]),
+                _ => {
+                    sent_mget.fetch_add(1, Ordering::SeqCst);
+                    RespValue::Array(vec![])
+                },
+            }
+        });
+
+        let mut client = ClusterClient::connect(&[&a], None).unwrap();
+        match client.execute(&[b"mget", b"foo", b"bar", b"{foo}.baz"]) {
+            Err(RuisError::CrossSlot { keys, .. }) => assert_eq!(keys, vec![b"bar".to_vec()]),
+            r => panic!("unexpected {:?}", r),
+        }
+        assert_eq!(sent.load(Ordering::SeqCst), 0);
+        client.execute(&[b"mget", b"foo", b"{foo}.baz"]).unwrap();
+        assert_eq!(sent.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_remap() {
+        let b = fake_node(|me, _| RespValue::Bulk(me.as_bytes().to_vec()));
+        let b_port = b.rsplit_once(':').unwrap().1.to_string();
+        // the nodes advertise the addresses unreachable from the client.
+        let a = fake_node(move |_, args| {
+            match args[0].as_slice() {
+                b"cluster" => slots_reply(&[(0, 16383, &format!("10.0.0.2:{}", b_port))]),
+                _ => RespValue::Error(b"ERR not a node".to_vec()),
+            }
+        });
+
+        let mut client = ClusterClientBuilder::new(&[&a])
+            .remap(|addr| addr.replace("10.0.0.2", "127.0.0.1"))
+            .connect()
+            .unwrap();
+        assert_eq!(client.execute(&[b"get", b"foo"]).unwrap(), RespValue::Bulk(b.as_bytes().to_vec()));
+        assert!(client.node_for_slot(0).unwrap().starts_with("10.0.0.2:"));
+    }
+
+    #[test]
+    fn test_pool_eviction() {
+        let b = fake_node(|me, _| RespValue::Bulk(me.as_bytes().to_vec()));
+        let resharded = Arc::new(AtomicBool::new(false));
+        let (b_addr, resharded2) = (b.clone(), resharded.clone());
+        let a = fake_node(move |me, args| {
+            match args[0].as_slice() {
+                b"cluster" if resharded2.load(Ordering::SeqCst) => slots_reply(&[(0, 16383, me)]),
+                b"cluster" => slots_reply(&[(0, 8191, me), (8192, 16383, &b_addr)]),
+                _ => RespValue::Bulk(me.as_bytes().to_vec()),
+            }
+        });
+
+        let mut client = ClusterClientBuilder::new(&[&a]).pool_size(1).connect().unwrap();
+        let key_on_b: &[u8] = b"foo";
+        assert!(cluster_slot(key_on_b) >= 8192);
+        client.execute(&[b"get", key_on_b]).unwrap();
+        assert_eq!(client.pools[&b].idle_len(), 1);
+
+        resharded.store(true, Ordering::SeqCst);
+        client.refresh_topology().unwrap();
+        assert!(!client.pools.contains_key(&b));
+        assert_eq!(client.execute(&[b"get", key_on_b]).unwrap(), RespValue::Bulk(a.as_bytes().to_vec()));
+    }
+
+    #[test]
+    fn test_multi_slot() {
+        // the nodes share the store, but reject the commands spanning slots
+        // like the real ones.
+        let store: Arc<Mutex<HashMap<Vec<u8>, Vec<u8>>>> = Arc::new(Mutex::new(HashMap::new()));
+        let serve = move |store: Arc<Mutex<HashMap<Vec<u8>, Vec<u8>>>>, args: &[Vec<u8>]| {
+            let keys: Vec<&Vec<u8>> = match args[0].as_slice() {
+                b"mset" => args[1..].iter().step_by(2).collect(),
+                _ => args[1..].iter().collect(),
+            };
+            if keys.iter().any(|k| cluster_slot(k) != cluster_slot(keys[0])) {
+                return RespValue::Error(b"CROSSSLOT Keys in request don't hash to the same slot".to_vec());
+            }
+            let mut store = store.lock().unwrap();
+            match args[0].as_slice() {
+                b"mset" => {
+                    for kv in args[1..].chunks(2) {
+                        store.insert(kv[0].clone(), kv[1].clone());
+                    }
+                    RespValue::Bulk(b"OK".to_vec())
+                },
+                b"mget" => RespValue::Array(keys.iter().map(|k| match store.get(*k) {
+                    Some(v) => RespValue::Bulk(v.clone()),
+                    None => RespValue::NilBulk,
+                }).collect()),
+                b"del" => RespValue::Int(keys.iter().filter(|k| store.remove(**k).is_some()).count() as i64),
+                _ => RespValue::Error(b"ERR unknown command".to_vec()),
+            }
+        };
+        let b_store = store.clone();
+        let b = fake_node(move |_, args| serve(b_store.clone(), args));
+        let b_addr = b.clone();
+        let a = fake_node(move |me, args| {
+            match args[0].as_slice() {
+                b"cluster" => slots_reply(&[(0, 8191, me), (8192, 16383, &b_addr)]),
+                _ => serve(store.clone(), args),
+            }
+        });
+
+        let mut client = ClusterClient::connect(&[&a], None).unwrap();
+        let pairs: Vec<(&[u8], &[u8])> = vec![(b"foo", b"1"), (b"bar", b"2"), (b"{foo}.x", b"3"), (b"baz", b"4")];
+        client.multi_slot_mset(&pairs).unwrap();
+        let values = client.multi_slot_mget(&[b"baz", b"missing", b"foo", b"bar", b"{foo}.x"]).unwrap();
+        let bulk = |v: &[u8]| RespValue::Bulk(v.to_vec());
+        assert_eq!(values, vec![bulk(b"4"), RespValue::NilBulk, bulk(b"1"), bulk(b"2"), bulk(b"3")]);
+        assert_eq!(client.multi_slot_del(&[b"foo", b"bar", b"missing", b"{foo}.x"]).unwrap(), 3);
+        assert!(client.execute(&[b"mget", b"foo", b"bar"]).is_err());
+    }
+}
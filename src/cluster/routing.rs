@@ -0,0 +1,227 @@
+use std::time::Duration;
+
+use super::slot::cluster_slot;
+use super::super::types::RuisError;
+
+// ReadFrom decides which node serves the read-only commands, the others
+// always go to the master of the slot. when all the replicas of a slot are
+// down, the reads fall back to the master.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReadFrom {
+    #[default]
+    Master,
+    // spreads the reads over the replicas of the slot in turn.
+    ReplicaRoundRobin,
+    // reads from the replica with the lowest round trip time measured.
+    ReplicaLowestLatency,
+}
+
+// the commands safe to be served by a replica which is not in sync yet.
+const READ_ONLY_COMMANDS: &[&str] = &[
+    "get", "mget", "getrange", "strlen", "exists", "ttl", "pttl", "expiretime",
+    "pexpiretime", "type", "dump", "object", "touch", "substr", "lcs",
+    "hget", "hmget", "hgetall", "hkeys", "hvals", "hlen", "hexists", "hstrlen",
+    "hrandfield", "hscan", "lrange", "llen", "lindex", "lpos", "smembers",
+    "sismember", "smismember", "scard", "srandmember", "sinter", "sintercard",
+    "sunion", "sdiff", "sscan", "zrange", "zrangebyscore", "zrangebylex",
+    "zrevrange", "zrevrangebyscore", "zrevrangebylex", "zscore", "zmscore",
+    "zcard", "zcount", "zlexcount", "zrank", "zrevrank", "zrandmember", "zscan",
+    "zinter", "zunion", "zdiff", "xrange", "xrevrange", "xlen", "xread", "xinfo",
+    "bitcount", "bitpos", "getbit", "bitfield_ro", "pfcount", "geopos",
+    "geodist", "geohash", "georadius_ro", "georadiusbymember_ro", "geosearch",
+    "eval_ro", "evalsha_ro", "fcall_ro", "sort_ro",
+];
+
+pub fn is_read_only(cmd: &[&[u8]]) -> bool {
+    match cmd.first() {
+        Some(name) => {
+            let name = String::from_utf8_lossy(name).to_lowercase();
+            READ_ONLY_COMMANDS.contains(&name.as_str())
+        },
+        None => false,
+    }
+}
+
+// an exponentially weighted moving average of the round trips of a node.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct Latency {
+    avg: Option<Duration>,
+}
+
+impl Latency {
+    pub fn observe(&mut self, rtt: Duration) {
+        self.avg = Some(match self.avg {
+            Some(avg) => (avg * 4 + rtt) / 5,
+            None => rtt,
+        });
+    }
+
+    // the nodes never measured sort first, so each node gets sampled.
+    pub fn get(&self) -> Duration {
+        self.avg.unwrap_or_default()
+    }
+}
+
+// the commands which do not take keys are sent to an arbitrary node.
+const KEYLESS_COMMANDS: &[&str] = &[
+    "ping", "echo", "info", "time", "dbsize", "cluster", "config", "client",
+    "command", "script", "function", "flushall", "flushdb", "keys", "scan",
+    "randomkey", "publish", "pubsub", "readonly", "readwrite", "wait", "lastsave",
+    "auth", "hello", "select", "quit", "reset", "multi", "exec", "discard",
+    "unwatch", "acl", "role", "slowlog", "latency", "save", "bgsave",
+    "bgrewriteaof", "subscribe", "psubscribe", "unsubscribe", "punsubscribe",
+];
+
+// returns the key deciding the slot a command is routed by.
+pub fn command_key<'a>(cmd: &[&'a [u8]]) -> Option<&'a [u8]> {
+    command_keys(cmd).first().copied()
+}
+
+// returns all the keys of a command, which have to be in the same slot in
+// cluster mode. the commands not listed take their first argument as the key,
+// the ones with a subcommand the argument after it.
+pub fn command_keys<'a>(cmd: &[&'a [u8]]) -> Vec<&'a [u8]> {
+    let name = match cmd.first() {
+        Some(name) => String::from_utf8_lossy(name).to_lowercase(),
+        None => return vec![],
+    };
+    if KEYLESS_COMMANDS.contains(&name.as_str()) {
+        return vec![];
+    }
+    let args = &cmd[1..];
+    match name.as_str() {
+        "mget" | "del" | "unlink" | "exists" | "touch" | "watch" | "sinter" | "sunion"
+        | "sdiff" | "sinterstore" | "sunionstore" | "sdiffstore" | "pfcount" | "pfmerge"
+        | "rename" | "renamenx" => args.to_vec(),
+        "mset" | "msetnx" => args.iter().step_by(2).copied().collect(),
+        "smove" | "lmove" | "rpoplpush" | "blmove" | "brpoplpush" | "copy" | "lcs" => {
+            args.iter().take(2).copied().collect()
+        },
+        // the timeout comes last.
+        "blpop" | "brpop" | "bzpopmin" | "bzpopmax" => {
+            args[..args.len().saturating_sub(1)].to_vec()
+        },
+        "eval" | "evalsha" | "eval_ro" | "evalsha_ro" | "fcall" | "fcall_ro" => numkeys_at(args, 1),
+        "sintercard" | "zintercard" | "lmpop" | "zmpop" | "zdiff" | "zinter" | "zunion" => numkeys_at(args, 0),
+        "blmpop" | "bzmpop" => numkeys_at(args, 1),
+        "zunionstore" | "zinterstore" | "zdiffstore" => {
+            let mut keys: Vec<&[u8]> = args.iter().take(1).copied().collect();
+            keys.extend(numkeys_at(args, 1));
+            keys
+        },
+        // OBJECT ENCODING k, XGROUP CREATE k g $ and the like, none for HELP.
+        "object" | "memory" | "xinfo" | "xgroup" => args.get(1).into_iter().copied().collect(),
+        // the ids follow the keys after STREAMS, as many as the keys.
+        "xread" | "xreadgroup" => {
+            match args.iter().position(|a| a.eq_ignore_ascii_case(b"streams")) {
+                Some(i) => {
+                    let rest = &args[i + 1..];
+                    rest[..rest.len() / 2].to_vec()
+                },
+                None => vec![],
+            }
+        },
+        _ => args.iter().take(1).copied().collect(),
+    }
+}
+
+// the keys following the number of the keys at args[i].
+fn numkeys_at<'a>(args: &[&'a [u8]], i: usize) -> Vec<&'a [u8]> {
+    let numkeys = args.get(i)
+        .and_then(|n| std::str::from_utf8(n).ok())
+        .and_then(|n| n.parse::<usize>().ok())
+        .unwrap_or(0);
+    args.iter().skip(i + 1).take(numkeys).copied().collect()
+}
+
+// returns the slot of the keys of a command, or a CrossSlot error naming the
+// keys outside the slot of the first key.
+pub fn check_slots(cmd: &[&[u8]]) -> Result<Option<u16>, RuisError> {
+    let keys = command_keys(cmd);
+    let first = match keys.first() {
+        Some(key) => cluster_slot(key),
+        None => return Ok(None),
+    };
+    let mut slots = vec![first];
+    let mut offending = vec![];
+    for key in &keys[1..] {
+        let slot = cluster_slot(key);
+        if slot != first {
+            offending.push(key.to_vec());
+            if !slots.contains(&slot) {
+                slots.push(slot);
+            }
+        }
+    }
+    if offending.is_empty() {
+        Ok(Some(first))
+    } else {
+        Err(RuisError::CrossSlot { slots, keys: offending })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_command_key() {
+        assert_eq!(command_key(&[b"GET", b"foo"]), Some(&b"foo"[..]));
+        assert_eq!(command_key(&[b"ping"]), None);
+        assert_eq!(command_key(&[b"info", b"replication"]), None);
+        assert_eq!(command_key(&[b"AUTH", b"user", b"password"]), None);
+        assert_eq!(command_key(&[b"eval", b"return 1", b"1", b"k1"]), Some(&b"k1"[..]));
+        assert_eq!(command_key(&[b"eval", b"return 1", b"0"]), None);
+        assert_eq!(command_key(&[b"xread", b"count", b"2", b"STREAMS", b"s1", b"0"]), Some(&b"s1"[..]));
+        assert_eq!(command_key(&[b"OBJECT", b"ENCODING", b"k"]), Some(&b"k"[..]));
+        assert_eq!(command_key(&[b"memory", b"usage", b"k", b"samples", b"5"]), Some(&b"k"[..]));
+        assert_eq!(command_key(&[b"xinfo", b"stream", b"s"]), Some(&b"s"[..]));
+        assert_eq!(command_key(&[b"xgroup", b"create", b"s", b"g", b"$"]), Some(&b"s"[..]));
+        assert_eq!(command_key(&[b"object", b"help"]), None);
+    }
+
+    #[test]
+    fn test_command_keys() {
+        let keys = |cmd: &[&[u8]]| command_keys(cmd).iter().map(|k| k.to_vec()).collect::<Vec<_>>();
+        assert_eq!(keys(&[b"mget", b"a", b"b"]), vec![b"a".to_vec(), b"b".to_vec()]);
+        assert_eq!(keys(&[b"MSET", b"a", b"1", b"b", b"2"]), vec![b"a".to_vec(), b"b".to_vec()]);
+        assert_eq!(keys(&[b"blpop", b"a", b"b", b"0"]), vec![b"a".to_vec(), b"b".to_vec()]);
+        assert_eq!(keys(&[b"zunionstore", b"d", b"2", b"a", b"b", b"weights", b"1", b"2"]), vec![b"d".to_vec(), b"a".to_vec(), b"b".to_vec()]);
+        assert_eq!(keys(&[b"xread", b"streams", b"s1", b"s2", b"0", b"0"]), vec![b"s1".to_vec(), b"s2".to_vec()]);
+        assert_eq!(keys(&[b"sintercard", b"2", b"a", b"b", b"limit", b"1"]), vec![b"a".to_vec(), b"b".to_vec()]);
+        assert_eq!(keys(&[b"ping"]), Vec::<Vec<u8>>::new());
+    }
+
+    #[test]
+    fn test_check_slots() {
+        assert_eq!(check_slots(&[b"ping"]).unwrap(), None);
+        assert_eq!(check_slots(&[b"mget", b"{u1}.a", b"{u1}.b"]).unwrap(), Some(cluster_slot(b"u1")));
+        assert_eq!(check_slots(&[b"object", b"encoding", b"foo"]).unwrap(), Some(cluster_slot(b"foo")));
+        assert_eq!(check_slots(&[b"xinfo", b"stream", b"foo"]).unwrap(), Some(cluster_slot(b"foo")));
+        match check_slots(&[b"sinterstore", b"foo", b"foo", b"bar"]) {
+            Err(RuisError::CrossSlot { slots, keys }) => {
+                assert_eq!(slots, vec![cluster_slot(b"foo"), cluster_slot(b"bar")]);
+                assert_eq!(keys, vec![b"bar".to_vec()]);
+            },
+            r => panic!("unexpected {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_is_read_only() {
+        assert!(is_read_only(&[b"GET", b"foo"]));
+        assert!(is_read_only(&[b"hgetall", b"foo"]));
+        assert!(!is_read_only(&[b"set", b"foo", b"bar"]));
+        assert!(!is_read_only(&[]));
+    }
+
+    #[test]
+    fn test_latency() {
+        let mut l = Latency::default();
+        assert_eq!(l.get(), Duration::from_secs(0));
+        l.observe(Duration::from_millis(10));
+        assert_eq!(l.get(), Duration::from_millis(10));
+        l.observe(Duration::from_millis(20));
+        assert_eq!(l.get(), Duration::from_millis(12));
+    }
+}
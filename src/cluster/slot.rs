@@ -0,0 +1,65 @@
+// the keyspace of a cluster is split into 16384 hash slots, a key belongs to
+// the slot CRC16(key) mod 16384.
+pub const SLOT_COUNT: usize = 16384;
+
+// returns the hash tag of the key: the part between the first { and the
+// following }, if it's not empty. only the hash tag is hashed when a key has
+// one, so the keys like {user1000}.following and {user1000}.followers are
+// guaranteed to be in the same slot, and can be used by multi-key commands.
+pub fn hash_tag(key: &[u8]) -> Option<&[u8]> {
+    let open = key.iter().position(|&b| b == b'{')?;
+    let close = key[open+1..].iter().position(|&b| b == b'}')?;
+    if close == 0 {
+        return None;
+    }
+    Some(&key[open+1..open+1+close])
+}
+
+// returns the hash slot of the key, as computed by the servers.
+pub fn cluster_slot(key: &[u8]) -> u16 {
+    let k = hash_tag(key).unwrap_or(key);
+    crc16(k) % SLOT_COUNT as u16
+}
+
+// CRC16-CCITT (XMODEM), as in redis's crc16.c.
+fn crc16(buf: &[u8]) -> u16 {
+    let mut crc: u16 = 0;
+    for &b in buf {
+        crc ^= (b as u16) << 8;
+        for _ in 0..8 {
+            if crc & 0x8000 != 0 {
+                crc = (crc << 1) ^ 0x1021;
+            } else {
+                crc <<= 1;
+            }
+        }
+    }
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash_tag() {
+        assert_eq!(hash_tag(b"{user1000}.following"), Some(&b"user1000"[..]));
+        assert_eq!(hash_tag(b"foo{bar}{zap}"), Some(&b"bar"[..]));
+        assert_eq!(hash_tag(b"foo{{bar}}zap"), Some(&b"{bar"[..]));
+        assert_eq!(hash_tag(b"foo{}{bar}"), None);
+        assert_eq!(hash_tag(b"foo{bar"), None);
+        assert_eq!(hash_tag(b"foo"), None);
+    }
+
+    #[test]
+    fn test_cluster_slot() {
+        assert_eq!(crc16(b"123456789"), 0x31c3);
+        assert_eq!(cluster_slot(b"foo"), 12182);
+        assert_eq!(cluster_slot(b"bar"), 5061);
+        assert_eq!(cluster_slot(b""), 0);
+        assert_eq!(cluster_slot(b"{user1000}.following"), cluster_slot(b"{user1000}.followers"));
+        assert_eq!(cluster_slot(b"{user1000}.following"), cluster_slot(b"user1000"));
+        assert_eq!(cluster_slot(b"foo{}{bar}"), crc16(b"foo{}{bar}") % 16384);
+        assert_eq!(cluster_slot(b"foo{{bar}}zap"), cluster_slot(b"{bar"));
+    }
+}
@@ -0,0 +1,484 @@
+use std::io::{BufRead, Write};
+
+use super::super::connection::GenericConnection;
+use super::super::types::{RespValue, RuisError};
+use super::slot::SLOT_COUNT;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeRole {
+    Master,
+    Replica,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SlotRange {
+    pub start: u16,
+    pub end: u16,
+}
+
+impl SlotRange {
+    pub fn contains(&self, slot: u16) -> bool {
+        self.start <= slot && slot <= self.end
+    }
+
+    pub fn count(&self) -> usize {
+        (self.end - self.start) as usize + 1
+    }
+}
+
+// the fields not reported by the command a topology is parsed from are left
+// with their defaults, eg. CLUSTER SLOTS has no flags nor epochs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClusterNode {
+    pub id: String,
+    // empty when the node does not know its own address, the address the
+    // command was sent to should be used instead.
+    pub host: String,
+    pub port: u16,
+    pub hostname: Option<String>,
+    pub role: NodeRole,
+    pub master_id: Option<String>,
+    pub flags: Vec<String>,
+    pub config_epoch: u64,
+    pub connected: bool,
+    // "online", "failed" or "loading", only reported by CLUSTER SHARDS.
+    pub health: Option<String>,
+    pub replication_offset: Option<i64>,
+    pub slots: Vec<SlotRange>,
+    // the slots being moved out to / in from another node, by the node id.
+    pub migrating: Vec<(u16, String)>,
+    pub importing: Vec<(u16, String)>,
+}
+
+impl ClusterNode {
+    fn new(role: NodeRole) -> Self {
+        Self {
+            id: String::new(),
+            host: String::new(),
+            port: 0,
+            hostname: None,
+            role,
+            master_id: None,
+            flags: vec![],
+            config_epoch: 0,
+            connected: true,
+            health: None,
+            replication_offset: None,
+            slots: vec![],
+            migrating: vec![],
+            importing: vec![],
+        }
+    }
+
+    pub fn addr(&self) -> String {
+        format!("{}:{}", self.host, self.port)
+    }
+
+    pub fn is_master(&self) -> bool {
+        self.role == NodeRole::Master
+    }
+
+    pub fn has_flag(&self, flag: &str) -> bool {
+        self.flags.iter().any(|f| f == flag)
+    }
+
+    // the node is considered failed by the cluster or by the node reporting
+    // the topology.
+    pub fn is_failed(&self) -> bool {
+        self.has_flag("fail") || self.has_flag("fail?") || self.health.as_deref() == Some("failed")
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ClusterTopology {
+    pub nodes: Vec<ClusterNode>,
+}
+
+impl ClusterTopology {
+    pub fn masters(&self) -> impl Iterator<Item = &ClusterNode> {
+        self.nodes.iter().filter(|n| n.is_master())
+    }
+
+    pub fn replicas_of<'a>(&'a self, master: &'a ClusterNode) -> impl Iterator<Item = &'a ClusterNode> {
+        self.nodes.iter().filter(move |n| {
+            match n.master_id {
+                Some(ref id) => !id.is_empty() && *id == master.id,
+                None => false,
+            }
+        })
+    }
+
+    pub fn slot_owner(&self, slot: u16) -> Option<&ClusterNode> {
+        self.masters().find(|n| n.slots.iter().any(|r| r.contains(slot)))
+    }
+
+    pub fn node_by_id(&self, id: &str) -> Option<&ClusterNode> {
+        self.nodes.iter().find(|n| n.id == id)
+    }
+
+    // fills the empty hosts with the host of the queried node.
+    pub fn resolve_empty_hosts(&mut self, host: &str) {
+        for node in self.nodes.iter_mut() {
+            if node.host.is_empty() {
+                node.host = host.to_string();
+            }
+        }
+    }
+
+    // CLUSTER SLOTS replies an entry per slot range:
+    //
+    //   1) (integer) 0
+    //   2) (integer) 5460
+    //   3) 1) "127.0.0.1"      <- the master
+    //      2) (integer) 30001
+    //      3) "09dbe9720cda62f7865eabc5fd8857c5d2678366"
+    //   4) 1) "127.0.0.1"      <- the replicas
+    //      ...
+    pub fn from_cluster_slots(v: RespValue) -> Result<Self, RuisError> {
+        let mut topo = ClusterTopology::default();
+        for range in into_array(v, "cluster slots")? {
+            let mut items = into_array(range, "cluster slots range")?.into_iter();
+            let start = expect_int(items.next(), "slot start")?;
+            let end = expect_int(items.next(), "slot end")?;
+            let range = slot_range(start, end)?;
+
+            let mut master_id = None;
+            for (i, entry) in items.enumerate() {
+                let mut fields = into_array(entry, "cluster slots node")?.into_iter();
+                let host = expect_string(fields.next(), "node host")?;
+                let port = expect_int(fields.next(), "node port")? as u16;
+                let id = match fields.next() {
+                    Some(v) => expect_string(Some(v), "node id")?,
+                    None => String::new(),
+                };
+                let hostname = match fields.next() {
+                    Some(meta) => map_get(&into_pairs(meta, "node metadata")?, "hostname"),
+                    None => None,
+                };
+
+                let role = if i == 0 { NodeRole::Master } else { NodeRole::Replica };
+                let node = topo.find_or_add(&id, &host, port, role);
+                node.hostname = hostname.or_else(|| node.hostname.take());
+                if i == 0 {
+                    node.slots.push(range);
+                    master_id = Some(id);
+                } else {
+                    node.master_id = master_id.clone();
+                }
+            }
+        }
+        Ok(topo)
+    }
+
+    // CLUSTER SHARDS (redis 7) replies a map per shard, with the "slots" as a
+    // flat list of the range bounds and the "nodes" as a list of maps.
+    pub fn from_cluster_shards(v: RespValue) -> Result<Self, RuisError> {
+        let mut topo = ClusterTopology::default();
+        for shard in into_array(v, "cluster shards")? {
+            let mut slots = vec![];
+            let mut nodes = vec![];
+            for (key, value) in into_pairs(shard, "cluster shard")? {
+                match key.as_str() {
+                    "slots" => {
+                        let bounds = into_array(value, "shard slots")?;
+                        for pair in bounds.chunks(2) {
+                            match pair {
+                                [RespValue::Int(start), RespValue::Int(end)] => slots.push(slot_range(*start, *end)?),
+                                _ => return Err(RuisError::Unexpected(format!("shard slots: {:?}", pair))),
+                            }
+                        }
+                    },
+                    "nodes" => nodes = into_array(value, "shard nodes")?,
+                    _ => {},
+                }
+            }
+
+            let mut shard_nodes = vec![];
+            for n in nodes {
+                let mut node = ClusterNode::new(NodeRole::Replica);
+                for (key, value) in into_pairs(n, "shard node")? {
+                    match key.as_str() {
+                        "id" => node.id = expect_string(Some(value), "node id")?,
+                        "port" => node.port = expect_int(Some(value), "node port")? as u16,
+                        "ip" => node.host = expect_string(Some(value), "node ip")?,
+                        "hostname" => node.hostname = Some(expect_string(Some(value), "node hostname")?),
+                        "role" => {
+                            let role = expect_string(Some(value), "node role")?;
+                            node.role = if role == "master" { NodeRole::Master } else { NodeRole::Replica };
+                        },
+                        "replication-offset" => node.replication_offset = Some(expect_int(Some(value), "replication offset")?),
+                        "health" => node.health = Some(expect_string(Some(value), "node health")?),
+                        _ => {},
+                    }
+                }
+                shard_nodes.push(node);
+            }
+
+            let master_id = shard_nodes.iter().find(|n| n.is_master()).map(|n| n.id.clone());
+            for mut node in shard_nodes {
+                if node.is_master() {
+                    node.slots = slots.clone();
+                } else {
+                    node.master_id = master_id.clone();
+                }
+                topo.nodes.push(node);
+            }
+        }
+        Ok(topo)
+    }
+
+    // CLUSTER NODES replies a line per node:
+    //
+    //   <id> <ip:port@cport[,hostname]> <flags> <master> <ping-sent> <pong-recv> <config-epoch> <link-state> <slot> ...
+    pub fn from_cluster_nodes(text: &[u8]) -> Result<Self, RuisError> {
+        let text = std::str::from_utf8(text).map_err(|_| RuisError::ParseFailed("cluster nodes: bad utf8".to_string()))?;
+        let mut topo = ClusterTopology::default();
+        for line in text.lines().map(|l| l.trim()).filter(|l| !l.is_empty()) {
+            topo.nodes.push(parse_nodes_line(line)?);
+        }
+        Ok(topo)
+    }
+
+    fn find_or_add(&mut self, id: &str, host: &str, port: u16, role: NodeRole) -> &mut ClusterNode {
+        let pos = self.nodes.iter().position(|n| {
+            if !id.is_empty() {
+                n.id == id
+            } else {
+                n.host == host && n.port == port
+            }
+        });
+        match pos {
+            Some(i) => &mut self.nodes[i],
+            None => {
+                let mut node = ClusterNode::new(role);
+                node.id = id.to_string();
+                node.host = host.to_string();
+                node.port = port;
+                self.nodes.push(node);
+                self.nodes.last_mut().unwrap()
+            },
+        }
+    }
+}
+
+fn parse_nodes_line(line: &str) -> Result<ClusterNode, RuisError> {
+    let malformed = || RuisError::ParseFailed(format!("malformed cluster nodes line: {}", line));
+    let fields: Vec<&str> = line.split(' ').collect();
+    if fields.len() < 8 {
+        return Err(malformed());
+    }
+
+    let flags: Vec<String> = fields[2].split(',').filter(|f| *f != "noflags").map(|f| f.to_string()).collect();
+    let role = if flags.iter().any(|f| f == "master") { NodeRole::Master } else { NodeRole::Replica };
+    let mut node = ClusterNode::new(role);
+    node.id = fields[0].to_string();
+
+    let mut addr_parts = fields[1].splitn(2, ',');
+    let addr = addr_parts.next().ok_or_else(malformed)?;
+    node.hostname = addr_parts.next().filter(|h| !h.is_empty()).map(|h| h.to_string());
+    let addr = addr.split('@').next().ok_or_else(malformed)?;
+    let (host, port) = addr.rsplit_once(':').ok_or_else(malformed)?;
+    node.host = host.to_string();
+    node.port = port.parse().map_err(|_| malformed())?;
+
+    node.flags = flags;
+    node.master_id = match fields[3] {
+        "-" => None,
+        id => Some(id.to_string()),
+    };
+    node.config_epoch = fields[6].parse().map_err(|_| malformed())?;
+    node.connected = fields[7] == "connected";
+
+    for slot in &fields[8..] {
+        if let Some(migration) = slot.strip_prefix('[').and_then(|s| s.strip_suffix(']')) {
+            if let Some((s, id)) = migration.split_once("->-") {
+                node.migrating.push((s.parse().map_err(|_| malformed())?, id.to_string()));
+            } else if let Some((s, id)) = migration.split_once("-<-") {
+                node.importing.push((s.parse().map_err(|_| malformed())?, id.to_string()));
+            } else {
+                return Err(malformed());
+            }
+            continue;
+        }
+        let (start, end) = slot.split_once('-').unwrap_or((slot, slot));
+        node.slots.push(slot_range(start.parse().map_err(|_| malformed())?, end.parse().map_err(|_| malformed())?)?);
+    }
+    Ok(node)
+}
+
+// the bounds are inclusive, within the slots of the cluster.
+fn slot_range(start: i64, end: i64) -> Result<SlotRange, RuisError> {
+    if start < 0 || start > end || end >= SLOT_COUNT as i64 {
+        return Err(RuisError::ParseFailed(format!("slot range {}-{}", start, end)));
+    }
+    Ok(SlotRange { start: start as u16, end: end as u16 })
+}
+
+fn into_array(v: RespValue, what: &str) -> Result<Vec<RespValue>, RuisError> {
+    match v {
+        RespValue::Array(arr) => Ok(arr),
+        v => Err(RuisError::Unexpected(format!("{}: {:?}", what, v))),
+    }
+}
+
+// the maps are replied as flat key value lists in RESP2.
+fn into_pairs(v: RespValue, what: &str) -> Result<Vec<(String, RespValue)>, RuisError> {
+    let arr = into_array(v, what)?;
+    if arr.len() % 2 != 0 {
+        return Err(RuisError::Unexpected(format!("{}: odd number of map items", what)));
+    }
+    let mut pairs = vec![];
+    let mut it = arr.into_iter();
+    while let (Some(k), Some(v)) = (it.next(), it.next()) {
+        pairs.push((expect_string(Some(k), what)?, v));
+    }
+    Ok(pairs)
+}
+
+fn map_get(pairs: &[(String, RespValue)], key: &str) -> Option<String> {
+    pairs.iter().find(|(k, _)| k == key).and_then(|(_, v)| match v {
+        RespValue::Bulk(bs) => Some(String::from_utf8_lossy(bs).to_string()),
+        _ => None,
+    })
+}
+
+fn expect_int(v: Option<RespValue>, what: &str) -> Result<i64, RuisError> {
+    match v {
+        Some(RespValue::Int(n)) => Ok(n),
+        v => Err(RuisError::Unexpected(format!("{}: {:?}", what, v))),
+    }
+}
+
+fn expect_string(v: Option<RespValue>, what: &str) -> Result<String, RuisError> {
+    match v {
+        Some(RespValue::Bulk(bs)) => Ok(String::from_utf8_lossy(&bs).to_string()),
+        v => Err(RuisError::Unexpected(format!("{}: {:?}", what, v))),
+    }
+}
+
+impl<W: Write, R: BufRead> GenericConnection<W, R> {
+    pub fn cluster_slots(&mut self) -> Result<ClusterTopology, RuisError> {
+        let v = self.execute(&[b"cluster", b"slots"])?.into_result()?;
+        ClusterTopology::from_cluster_slots(v)
+    }
+
+    pub fn cluster_shards(&mut self) -> Result<ClusterTopology, RuisError> {
+        let v = self.execute(&[b"cluster", b"shards"])?.into_result()?;
+        ClusterTopology::from_cluster_shards(v)
+    }
+
+    pub fn cluster_nodes(&mut self) -> Result<ClusterTopology, RuisError> {
+        match self.execute(&[b"cluster", b"nodes"])?.into_result()? {
+            RespValue::Bulk(text) => ClusterTopology::from_cluster_nodes(&text),
+            v => Err(RuisError::Unexpected(format!("cluster nodes: {:?}", v))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bulk(s: &str) -> RespValue {
+        RespValue::Bulk(s.as_bytes().to_vec())
+    }
+
+    #[test]
+    fn test_from_cluster_slots() {
+        let v = RespValue::Array(vec![
+            RespValue::Array(vec![
+                RespValue::Int(0), RespValue::Int(5460),
+                RespValue::Array(vec![bulk("127.0.0.1"), RespValue::Int(30001), bulk("m1"), RespValue::Array(vec![bulk("hostname"), bulk("host-1")])]),
+                RespValue::Array(vec![bulk("127.0.0.1"), RespValue::Int(30004), bulk("r1")]),
+            ]),
+            RespValue::Array(vec![
+                RespValue::Int(5461), RespValue::Int(10922),
+                RespValue::Array(vec![bulk(""), RespValue::Int(30002), bulk("m2")]),
+            ]),
+            RespValue::Array(vec![
+                RespValue::Int(10923), RespValue::Int(16383),
+                RespValue::Array(vec![bulk("127.0.0.1"), RespValue::Int(30001), bulk("m1")]),
+            ]),
+        ]);
+        let mut topo = ClusterTopology::from_cluster_slots(v).unwrap();
+        topo.resolve_empty_hosts("10.0.0.1");
+        assert_eq!(topo.nodes.len(), 3);
+        let m1 = topo.node_by_id("m1").unwrap();
+        assert_eq!(m1.addr(), "127.0.0.1:30001");
+        assert_eq!(m1.hostname.as_deref(), Some("host-1"));
+        assert_eq!(m1.slots, vec![SlotRange { start: 0, end: 5460 }, SlotRange { start: 10923, end: 16383 }]);
+        assert_eq!(topo.replicas_of(m1).map(|n| n.addr()).collect::<Vec<_>>(), vec!["127.0.0.1:30004"]);
+        assert_eq!(topo.slot_owner(6000).unwrap().addr(), "10.0.0.1:30002");
+        assert_eq!(topo.masters().count(), 2);
+
+        // the ranges out of the slots, or reversed, are malformed.
+        for (start, end) in [(-1, 5460), (0, 16384), (100, 99)] {
+            let v = RespValue::Array(vec![RespValue::Array(vec![
+                RespValue::Int(start), RespValue::Int(end),
+                RespValue::Array(vec![bulk("127.0.0.1"), RespValue::Int(30001), bulk("m1")]),
+            ])]);
+            assert!(matches!(ClusterTopology::from_cluster_slots(v), Err(RuisError::ParseFailed(_))));
+        }
+    }
+
+    #[test]
+    fn test_from_cluster_shards() {
+        let v = RespValue::Array(vec![
+            RespValue::Array(vec![
+                bulk("slots"), RespValue::Array(vec![RespValue::Int(0), RespValue::Int(5460), RespValue::Int(10923), RespValue::Int(10923)]),
+                bulk("nodes"), RespValue::Array(vec![
+                    RespValue::Array(vec![
+                        bulk("id"), bulk("m1"), bulk("port"), RespValue::Int(30001), bulk("ip"), bulk("127.0.0.1"),
+                        bulk("endpoint"), bulk("127.0.0.1"), bulk("role"), bulk("master"),
+                        bulk("replication-offset"), RespValue::Int(72156), bulk("health"), bulk("online"),
+                    ]),
+                    RespValue::Array(vec![
+                        bulk("id"), bulk("r1"), bulk("port"), RespValue::Int(30004), bulk("ip"), bulk("127.0.0.1"),
+                        bulk("role"), bulk("replica"), bulk("replication-offset"), RespValue::Int(72156), bulk("health"), bulk("failed"),
+                    ]),
+                ]),
+            ]),
+        ]);
+        let topo = ClusterTopology::from_cluster_shards(v).unwrap();
+        let m1 = topo.node_by_id("m1").unwrap();
+        assert!(m1.is_master());
+        assert_eq!(m1.slots, vec![SlotRange { start: 0, end: 5460 }, SlotRange { start: 10923, end: 10923 }]);
+        assert_eq!(m1.replication_offset, Some(72156));
+        let r1 = topo.node_by_id("r1").unwrap();
+        assert_eq!(r1.master_id.as_deref(), Some("m1"));
+        assert!(r1.is_failed());
+        assert!(r1.slots.is_empty());
+    }
+
+    #[test]
+    fn test_from_cluster_nodes() {
+        let text = b"07c37dfeb235213a872192d90877d0cd55635b91 127.0.0.1:30004@31004,host-4 slave e7d1eecce10fd6bb5eb35b9f99a514335d9ba9ca 0 1426238317239 4 connected\n\
+67ed2db8d677e59ec4a4cefb06858cf2a1a89fa1 127.0.0.1:30002@31002 master - 0 1426238316232 2 connected 5461-10922\n\
+e7d1eecce10fd6bb5eb35b9f99a514335d9ba9ca 127.0.0.1:30001@31001 myself,master - 0 0 1 connected 0-5460 [5461-<-67ed2db8d677e59ec4a4cefb06858cf2a1a89fa1]\n\
+6ec23923021cf3ffec47632106199cb7f496ce01 :0@0 master,fail?,noaddr - 1426238316232 0 3 disconnected 10923 10924-16383 [10923->-67ed2db8d677e59ec4a4cefb06858cf2a1a89fa1]\n";
+        let topo = ClusterTopology::from_cluster_nodes(text).unwrap();
+        assert_eq!(topo.nodes.len(), 4);
+
+        let replica = &topo.nodes[0];
+        assert_eq!(replica.role, NodeRole::Replica);
+        assert_eq!(replica.addr(), "127.0.0.1:30004");
+        assert_eq!(replica.hostname.as_deref(), Some("host-4"));
+        assert_eq!(replica.config_epoch, 4);
+
+        let myself = topo.node_by_id("e7d1eecce10fd6bb5eb35b9f99a514335d9ba9ca").unwrap();
+        assert!(myself.has_flag("myself"));
+        assert_eq!(myself.slots, vec![SlotRange { start: 0, end: 5460 }]);
+        assert_eq!(myself.importing, vec![(5461, "67ed2db8d677e59ec4a4cefb06858cf2a1a89fa1".to_string())]);
+        assert_eq!(topo.replicas_of(myself).count(), 1);
+
+        let failed = &topo.nodes[3];
+        assert!(failed.is_failed());
+        assert!(!failed.connected);
+        assert_eq!(failed.slots, vec![SlotRange { start: 10923, end: 10923 }, SlotRange { start: 10924, end: 16383 }]);
+        assert_eq!(failed.migrating, vec![(10923, "67ed2db8d677e59ec4a4cefb06858cf2a1a89fa1".to_string())]);
+
+        assert!(ClusterTopology::from_cluster_nodes(b"foo bar").is_err());
+        let line = b"e7d1eecce10fd6bb5eb35b9f99a514335d9ba9ca 127.0.0.1:30001@31001 master - 0 0 1 connected 5460-0";
+        assert!(matches!(ClusterTopology::from_cluster_nodes(line), Err(RuisError::ParseFailed(_))));
+    }
+}
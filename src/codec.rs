@@ -0,0 +1,49 @@
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+use super::types::RuisError;
+
+// Codec encodes the typed values into the payloads sent to redis, like the
+// messages of PUBLISH, and decodes them back on the receiving side.
+pub trait Codec {
+    fn encode<T: Serialize + ?Sized>(&self, value: &T) -> Result<Vec<u8>, RuisError>;
+    fn decode<T: DeserializeOwned>(&self, payload: &[u8]) -> Result<T, RuisError>;
+}
+
+#[cfg(feature = "serde_json")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JsonCodec;
+
+#[cfg(feature = "serde_json")]
+impl Codec for JsonCodec {
+    fn encode<T: Serialize + ?Sized>(&self, value: &T) -> Result<Vec<u8>, RuisError> {
+        serde_json::to_vec(value).map_err(|e| RuisError::CodecError(format!("json encode: {}", e)))
+    }
+
+    fn decode<T: DeserializeOwned>(&self, payload: &[u8]) -> Result<T, RuisError> {
+        serde_json::from_slice(payload).map_err(|e| RuisError::CodecError(format!("json decode: {}", e)))
+    }
+}
+
+#[cfg(all(test, feature = "serde_json"))]
+mod tests {
+    use serde::Deserialize;
+    use super::*;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Event {
+        id: u64,
+        name: String,
+    }
+
+    #[test]
+    fn test_json_codec() {
+        let ev = Event { id: 1, name: "created".to_string() };
+        let payload = JsonCodec.encode(&ev).unwrap();
+        assert_eq!(payload, br#"{"id":1,"name":"created"}"#.to_vec());
+        assert_eq!(JsonCodec.decode::<Event>(&payload).unwrap(), ev);
+
+        let r = JsonCodec.decode::<Event>(b"{}");
+        assert!(format!("{}", r.unwrap_err()).starts_with("codec err: json decode:"));
+    }
+}
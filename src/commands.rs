@@ -0,0 +1,122 @@
+use std::io::{BufRead, Write};
+
+use super::cluster::ClusterClient;
+use super::connection::GenericConnection;
+use super::convert::FromResp;
+use super::pipeline::Pipeline;
+use super::sentinel::SentinelClient;
+use super::types::{RespValue, RuisError};
+
+// Commands is implemented by the connections and the clients of each kind of
+// deployment, the code sending the commands does not need to know whether it
+// talks to a single server, a sentinel managed master or a cluster.
+pub trait Commands {
+    fn execute(&mut self, cmd: &[&[u8]]) -> Result<RespValue, RuisError>;
+
+    // returns the replies in the order of the commands.
+    fn execute_pipeline(&mut self, pipeline: &Pipeline) -> Result<Vec<RespValue>, RuisError>;
+
+    // the typed commands below return the error replies as
+    // RuisError::ServerError.
+
+    fn get(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>, RuisError> {
+        FromResp::from_resp(self.execute(&[b"get", key])?)
+    }
+
+    fn set(&mut self, key: &[u8], value: &[u8]) -> Result<(), RuisError> {
+        self.execute(&[b"set", key, value])?.into_result()?;
+        Ok(())
+    }
+
+    // returns the number of the keys deleted.
+    fn del(&mut self, keys: &[&[u8]]) -> Result<i64, RuisError> {
+        let mut cmd: Vec<&[u8]> = vec![b"del"];
+        cmd.extend_from_slice(keys);
+        FromResp::from_resp(self.execute(&cmd)?)
+    }
+
+    // returns the number of the keys existing, a key given twice counts
+    // twice.
+    fn exists(&mut self, keys: &[&[u8]]) -> Result<i64, RuisError> {
+        let mut cmd: Vec<&[u8]> = vec![b"exists"];
+        cmd.extend_from_slice(keys);
+        FromResp::from_resp(self.execute(&cmd)?)
+    }
+
+    // returns the value after the increment.
+    fn incr(&mut self, key: &[u8]) -> Result<i64, RuisError> {
+        FromResp::from_resp(self.execute(&[b"incr", key])?)
+    }
+
+    // returns false if the key does not exist.
+    fn expire(&mut self, key: &[u8], secs: u64) -> Result<bool, RuisError> {
+        let n: i64 = FromResp::from_resp(self.execute(&[b"expire", key, secs.to_string().as_bytes()])?)?;
+        Ok(n == 1)
+    }
+
+    // returns the seconds to live, -1 for the keys without an expire, -2 for
+    // the keys not existing.
+    fn ttl(&mut self, key: &[u8]) -> Result<i64, RuisError> {
+        FromResp::from_resp(self.execute(&[b"ttl", key])?)
+    }
+}
+
+impl<W: Write, R: BufRead> Commands for GenericConnection<W, R> {
+    fn execute(&mut self, cmd: &[&[u8]]) -> Result<RespValue, RuisError> {
+        GenericConnection::execute(self, cmd)
+    }
+
+    fn execute_pipeline(&mut self, pipeline: &Pipeline) -> Result<Vec<RespValue>, RuisError> {
+        GenericConnection::execute_pipeline(self, pipeline)
+    }
+}
+
+impl Commands for ClusterClient {
+    fn execute(&mut self, cmd: &[&[u8]]) -> Result<RespValue, RuisError> {
+        ClusterClient::execute(self, cmd)
+    }
+
+    fn execute_pipeline(&mut self, pipeline: &Pipeline) -> Result<Vec<RespValue>, RuisError> {
+        ClusterClient::execute_pipeline(self, pipeline)
+    }
+}
+
+impl Commands for SentinelClient {
+    fn execute(&mut self, cmd: &[&[u8]]) -> Result<RespValue, RuisError> {
+        SentinelClient::execute(self, cmd)
+    }
+
+    fn execute_pipeline(&mut self, pipeline: &Pipeline) -> Result<Vec<RespValue>, RuisError> {
+        SentinelClient::execute_pipeline(self, pipeline)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::client::Client;
+    use super::super::connection::TcpConnection;
+    use super::super::testing::TestServer;
+
+    fn exercise<C: Commands>(c: &mut C) {
+        assert_eq!(c.get(b"k").unwrap(), None);
+        c.set(b"k", b"v").unwrap();
+        assert_eq!(c.get(b"k").unwrap(), Some(b"v".to_vec()));
+        assert_eq!(c.incr(b"n").unwrap(), 1);
+        assert!(matches!(c.incr(b"k"), Err(RuisError::ServerError(_))));
+        assert_eq!(c.exists(&[b"k", b"n", b"missing"]).unwrap(), 2);
+        assert_eq!(c.ttl(b"k").unwrap(), -1);
+        assert!(c.expire(b"k", 100).unwrap());
+        assert!(!c.expire(b"missing", 100).unwrap());
+        assert!((1..=100).contains(&c.ttl(b"k").unwrap()));
+        assert_eq!(c.del(&[b"k", b"n", b"missing"]).unwrap(), 2);
+        assert_eq!(c.ttl(b"k").unwrap(), -2);
+    }
+
+    #[test]
+    fn test_typed_commands() {
+        let server = TestServer::new();
+        exercise(&mut TcpConnection::connect(&server.addr(), None).unwrap());
+        exercise(&mut Client::new(server.addr(), None));
+    }
+}
@@ -0,0 +1,152 @@
+// the bridge to the redis-rs crate, for the libraries written against it like
+// the locks and the session stores, to run over the ruis connections:
+//
+//   let mut conn = TcpConnection::connect("127.0.0.1:6379", None)?;
+//   let n: i64 = redis::cmd("INCR").arg("counter").query(&mut conn)?;
+
+use std::io::{BufRead, Write};
+
+use redis::{ConnectionLike, FromRedisValue, ParsingError, Parser, RedisResult, Value};
+
+use super::connection::GenericConnection;
+use super::types::RespValue;
+
+impl<W: Write, R: BufRead> ConnectionLike for GenericConnection<W, R> {
+    // the replies are parsed by redis-rs itself, as RespValue does not keep
+    // the status replies apart from the bulk strings, like +OK which some
+    // libraries check for.
+    fn req_packed_command(&mut self, cmd: &[u8]) -> RedisResult<Value> {
+        let (w, r) = self.raw_parts();
+        w.write_all(cmd)?;
+        w.flush()?;
+        Parser::new().parse_value(r)
+    }
+
+    // the replies before the offset are the ones of MULTI and the commands
+    // queued, the first error among them fails the transaction.
+    fn req_packed_commands(&mut self, cmd: &[u8], offset: usize, count: usize) -> RedisResult<Vec<Value>> {
+        let (w, r) = self.raw_parts();
+        w.write_all(cmd)?;
+        w.flush()?;
+        let mut parser = Parser::new();
+        let mut replies = Vec::with_capacity(count);
+        let mut queue_err = None;
+        for i in 0..offset + count {
+            match parser.parse_value(&mut *r)? {
+                Value::ServerError(e) if i < offset => queue_err = queue_err.or(Some(e)),
+                _ if i < offset => {},
+                v => replies.push(v),
+            }
+        }
+        match queue_err {
+            Some(e) => Err(e.into()),
+            None => Ok(replies),
+        }
+    }
+
+    // SELECT is not tracked, the connections of ruis stay on the db 0.
+    fn get_db(&self) -> i64 {
+        0
+    }
+
+    fn check_connection(&mut self) -> bool {
+        self.execute(&[b"ping"]).is_ok()
+    }
+
+    fn is_open(&self) -> bool {
+        true
+    }
+}
+
+impl From<RespValue> for Value {
+    fn from(v: RespValue) -> Value {
+        match v {
+            RespValue::Int(n) => Value::Int(n),
+            RespValue::NilBulk | RespValue::NilArray => Value::Nil,
+            RespValue::Bulk(b) => Value::BulkString(b),
+            RespValue::Array(items) => Value::Array(items.into_iter().map(Value::from).collect()),
+            // redis-rs only builds its server errors by parsing.
+            RespValue::Error(msg) => {
+                let mut line = Vec::with_capacity(msg.len() + 3);
+                line.push(b'-');
+                line.extend_from_slice(&msg);
+                line.extend_from_slice(b"\r\n");
+                redis::parse_redis_value(&line).unwrap_or(Value::Nil)
+            },
+        }
+    }
+}
+
+// the replies of redis-rs queries as RespValue, like
+// redis::cmd("GET").arg("k").query::<RespValue>(&mut conn). the RESP3 types
+// are flattened the way RESP2 sends them.
+impl FromRedisValue for RespValue {
+    fn from_redis_value(v: Value) -> Result<Self, ParsingError> {
+        Ok(match v {
+            Value::Nil => RespValue::NilBulk,
+            Value::Int(n) => RespValue::Int(n),
+            Value::BulkString(b) => RespValue::Bulk(b),
+            Value::SimpleString(s) => RespValue::Bulk(s.into_bytes()),
+            Value::Okay => RespValue::Bulk(b"OK".to_vec()),
+            Value::Boolean(b) => RespValue::Int(b as i64),
+            Value::Double(f) => RespValue::Bulk(f.to_string().into_bytes()),
+            Value::VerbatimString { text, .. } => RespValue::Bulk(text.into_bytes()),
+            Value::Array(items) | Value::Set(items) | Value::Push { data: items, .. } => {
+                RespValue::Array(items.into_iter().map(Self::from_redis_value).collect::<Result<_, _>>()?)
+            },
+            Value::Map(pairs) => {
+                let mut items = Vec::with_capacity(pairs.len() * 2);
+                for (k, v) in pairs {
+                    items.push(Self::from_redis_value(k)?);
+                    items.push(Self::from_redis_value(v)?);
+                }
+                RespValue::Array(items)
+            },
+            Value::Attribute { data, .. } => Self::from_redis_value(*data)?,
+            Value::ServerError(e) => RespValue::Error(e.to_string().into_bytes()),
+            v => return Err(format!("unsupported redis value {:?}", v).into()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::connection::TcpConnection;
+    use super::super::testing::TestServer;
+
+    #[test]
+    fn test_redis_rs_commands() {
+        let server = TestServer::new();
+        let mut conn = TcpConnection::connect(&server.addr(), None).unwrap();
+        let ok: String = redis::cmd("SET").arg("k").arg(41).query(&mut conn).unwrap();
+        assert_eq!(ok, "OK");
+        let n: i64 = redis::cmd("INCR").arg("k").query(&mut conn).unwrap();
+        assert_eq!(n, 42);
+        let v: RespValue = redis::cmd("GET").arg("k").query(&mut conn).unwrap();
+        assert_eq!(v, RespValue::Bulk(b"42".to_vec()));
+        let missing: Option<String> = redis::cmd("GET").arg("nope").query(&mut conn).unwrap();
+        assert_eq!(missing, None);
+        assert!(redis::cmd("HLEN").arg("k").query::<i64>(&mut conn).is_err());
+
+        let (a, b): (i64, String) = redis::pipe().cmd("INCR").arg("k").cmd("GET").arg("k").query(&mut conn).unwrap();
+        assert_eq!((a, b.as_str()), (43, "43"));
+        // the connection is still usable by ruis.
+        assert_eq!(conn.execute(&[b"get", b"k"]).unwrap(), RespValue::Bulk(b"43".to_vec()));
+    }
+
+    #[test]
+    fn test_value_conversions() {
+        let v = RespValue::Array(vec![RespValue::Int(1), RespValue::NilBulk, RespValue::Error(b"ERR bad".to_vec())]);
+        match Value::from(v) {
+            Value::Array(items) => {
+                assert_eq!(&items[..2], &[Value::Int(1), Value::Nil]);
+                assert!(matches!(items[2], Value::ServerError(ref e) if e.code() == "ERR"));
+            },
+            v => panic!("unexpected {:?}", v),
+        }
+        let pairs = Value::Map(vec![(Value::SimpleString("f".to_string()), Value::Int(1))]);
+        assert_eq!(RespValue::from_redis_value(pairs).unwrap(),
+            RespValue::Array(vec![RespValue::Bulk(b"f".to_vec()), RespValue::Int(1)]));
+    }
+}
@@ -1,9 +1,12 @@
+use std::collections::HashMap;
 use std::io;
 use std::net::{TcpStream};
 use std::io::{BufRead, BufReader, Write};
+use std::time::Duration;
 
-use super::resp::{RespWriter, RespReader};
-use super::types::{RespValue, RespError};
+use super::convert::FromResp;
+use super::resp::{RespLimits, RespWriter, RespReader};
+use super::types::{ErrorKind, RespFrame, RespValue, RuisError};
 
 pub struct GenericConnection<W: Write, R: BufRead> {
     w: RespWriter<W>,
@@ -13,48 +16,372 @@ pub struct GenericConnection<W: Write, R: BufRead> {
 impl<W: Write, R: BufRead> GenericConnection<W, R> {
     pub fn new(r: RespReader<R>, w: RespWriter<W>) -> Self {
         Self {
-            w: w,
-            r: r
+            w,
+            r,
         }
     }
 
-    pub fn auth(&mut self, password: &str) -> Result<RespValue, RespError> {
-       self.execute(&vec!["auth".as_bytes(), password.as_bytes()])
+    pub fn auth(&mut self, password: &str) -> Result<RespValue, RuisError> {
+        self.execute_args(&("auth", password))
     }
 
-    pub fn execute(&mut self, cmd: &[&[u8]]) -> Result<RespValue, RespError> {
+    // AUTH as an ACL user, the servers before 6.0 only accept the password.
+    pub fn auth_user(&mut self, username: &str, password: &str) -> Result<RespValue, RuisError> {
+        self.execute_args(&("auth", username, password))
+    }
+
+    // the handshake with HELLO, authenticating with the username and the
+    // password if given, returns what the server tells about itself. only the
+    // protocol 2 is supported, as the replies of the protocol 3 can not be
+    // parsed, a failed authentication is returned as RuisError::Auth.
+    pub fn hello(&mut self, protocol: u8, auth: Option<(&str, &str)>) -> Result<ServerHello, RuisError> {
+        if protocol != 2 {
+            return Err(RuisError::Unexpected(format!("hello: protocol {} is not supported", protocol)));
+        }
+        let reply = match auth {
+            Some((username, password)) => self.execute_args(&("hello", protocol as u32, "auth", username, password))?,
+            None => self.execute_args(&("hello", protocol as u32))?,
+        };
+        match reply {
+            RespValue::Error(ref msg) if matches!(reply.error_kind(), Some(ErrorKind::WrongPass) | Some(ErrorKind::NoAuth)) => {
+                Err(RuisError::Auth(String::from_utf8_lossy(msg).into_owned()))
+            },
+            reply => ServerHello::from_resp(reply),
+        }
+    }
+
+    // switches the connection to the logical database, the connections
+    // start on the database 0.
+    pub fn select(&mut self, db: i64) -> Result<(), RuisError> {
+        self.execute_args(&("select", db))?.into_result().map(|_| ())
+    }
+
+    // CLIENT SETNAME, the name shows in CLIENT LIST and can not hold spaces.
+    pub fn set_client_name(&mut self, name: &str) -> Result<(), RuisError> {
+        self.execute_args(&("client", "setname", name))?.into_result().map(|_| ())
+    }
+
+    pub fn client_name(&mut self) -> Result<Option<String>, RuisError> {
+        match self.execute(&[b"client", b"getname"])?.into_result()? {
+            RespValue::Bulk(name) => Ok(Some(String::from_utf8_lossy(&name).into_owned())),
+            RespValue::NilBulk => Ok(None),
+            v => Err(RuisError::Unexpected(format!("client getname: {:?}", v))),
+        }
+    }
+
+    // CLIENT SETINFO, which came with redis 7.2. the servers before it reply
+    // an error, which is ignored, the library info is only informative.
+    pub fn set_lib_info(&mut self, lib_name: &str, lib_ver: &str) -> Result<(), RuisError> {
+        self.execute_args(&("client", "setinfo", "lib-name", lib_name))?;
+        self.execute_args(&("client", "setinfo", "lib-ver", lib_ver))?;
+        Ok(())
+    }
+
+    // names the connection and tells the library, as set on the identity.
+    pub fn set_identity(&mut self, identity: &ClientIdentity) -> Result<(), RuisError> {
+        if let Some(ref name) = identity.name {
+            self.set_client_name(name)?;
+        }
+        if let Some((ref lib_name, ref lib_ver)) = identity.lib {
+            self.set_lib_info(lib_name, lib_ver)?;
+        }
+        Ok(())
+    }
+
+    pub fn client_id(&mut self) -> Result<i64, RuisError> {
+        match self.execute(&[b"client", b"id"])?.into_result()? {
+            RespValue::Int(id) => Ok(id),
+            v => Err(RuisError::Unexpected(format!("client id: {:?}", v))),
+        }
+    }
+
+    pub fn execute(&mut self, cmd: &[&[u8]]) -> Result<RespValue, RuisError> {
+        self.send(cmd)?;
+        self.receive()
+    }
+
+    // send and receive are the halves of execute, for the replies which do not
+    // follow the one-request/one-reply model, like the messages on a subscribed
+    // connection.
+    pub fn send(&mut self, cmd: &[&[u8]]) -> Result<(), RuisError> {
         self.w.write_bulks(cmd)?;
+        self.w.flush()
+    }
+
+    // writes the commands encoded by the caller, like a batch of them, in one
+    // write.
+    pub(crate) fn send_encoded(&mut self, buf: &[u8]) -> Result<(), RuisError> {
+        self.w.get_mut().write_all(buf)?;
+        self.w.flush()
+    }
+
+    pub fn receive(&mut self) -> Result<RespValue, RuisError> {
         self.r.read()
     }
+
+    // the limits on the replies read, see RespLimits for the defaults.
+    pub fn set_resp_limits(&mut self, limits: RespLimits) {
+        self.r.set_limits(limits);
+    }
+
+    // like execute, with the bulks of the reply sliced from a single buffer,
+    // see RespFrame.
+    pub fn execute_frame(&mut self, cmd: &[&[u8]]) -> Result<RespFrame, RuisError> {
+        self.send(cmd)?;
+        self.r.read_frame()
+    }
+
+    // the commands encoded by the callers, like the packed commands of
+    // redis-rs, and the replies parsed by them.
+    #[cfg(any(feature = "redis", test))]
+    pub(crate) fn raw_parts(&mut self) -> (&mut W, &mut R) {
+        (self.w.get_mut(), self.r.get_mut())
+    }
+}
+
+// maps the address a server advertises, like a cluster node or the master
+// reported by the sentinels, to the address to connect to.
+pub type RemapFn = Box<dyn Fn(&str) -> String + Send + Sync>;
+
+// the secrets are shown as *** in the Debug output, so they do not leak into
+// the logs.
+pub(crate) fn redacted(secret: &Option<String>) -> Option<&'static str> {
+    secret.as_ref().map(|_| "***")
+}
+
+// ClientIdentity is how the connections introduce themselves to the server,
+// which shows in CLIENT LIST and helps to tell the applications apart. the
+// library is told as ruis by default, set lib() for a wrapper of it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientIdentity {
+    name: Option<String>,
+    lib: Option<(String, String)>,
+}
+
+impl Default for ClientIdentity {
+    fn default() -> Self {
+        Self {
+            name: None,
+            lib: Some((LIB_NAME.to_string(), LIB_VER.to_string())),
+        }
+    }
+}
+
+impl ClientIdentity {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn name(mut self, name: &str) -> Self {
+        self.name = Some(name.to_string());
+        self
+    }
+
+    pub fn lib(mut self, lib_name: &str, lib_ver: &str) -> Self {
+        self.lib = Some((lib_name.to_string(), lib_ver.to_string()));
+        self
+    }
+
+    // CLIENT SETINFO is not sent, like for the proxies rejecting CLIENT.
+    pub fn no_lib(mut self) -> Self {
+        self.lib = None;
+        self
+    }
+}
+
+const LIB_NAME: &str = "ruis";
+const LIB_VER: &str = env!("CARGO_PKG_VERSION");
+
+// the reply of HELLO.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServerHello {
+    pub server: String,
+    pub version: String,
+    pub proto: i64,
+    // the id of the connection, as CLIENT ID.
+    pub id: i64,
+    // "standalone", "sentinel" or "cluster".
+    pub mode: String,
+    // "master" or "replica".
+    pub role: String,
+    // the names of the modules loaded.
+    pub modules: Vec<String>,
+}
+
+impl FromResp for ServerHello {
+    fn from_resp(v: RespValue) -> Result<Self, RuisError> {
+        let mut fields: HashMap<String, RespValue> = FromResp::from_resp(v)?;
+        let mut take = |name: &str| fields.remove(name).ok_or_else(|| RuisError::Unexpected(format!("hello: no {} in the reply", name)));
+        let modules: Vec<HashMap<String, RespValue>> = FromResp::from_resp(take("modules")?)?;
+        Ok(ServerHello {
+            server: FromResp::from_resp(take("server")?)?,
+            version: FromResp::from_resp(take("version")?)?,
+            proto: FromResp::from_resp(take("proto")?)?,
+            id: FromResp::from_resp(take("id")?)?,
+            mode: FromResp::from_resp(take("mode")?)?,
+            role: FromResp::from_resp(take("role")?)?,
+            modules: modules.into_iter().filter_map(|mut m| String::from_resp(m.remove("name")?).ok()).collect(),
+        })
+    }
+}
+
+pub(crate) fn check_auth(reply: RespValue) -> Result<(), RuisError> {
+    match reply {
+        RespValue::Error(msg) => Err(RuisError::Auth(String::from_utf8_lossy(&msg).into_owned())),
+        _ => Ok(()),
+    }
 }
 
 pub type TcpConnection = GenericConnection<std::net::TcpStream, BufReader<std::net::TcpStream>>;
 
 impl TcpConnection {
-    pub fn connect(addr: &str, password_opt: Option<&str>) -> io::Result<TcpConnection> {
+    pub fn connect(addr: &str, password_opt: Option<&str>) -> Result<TcpConnection, RuisError> {
         let ws = TcpStream::connect(addr)?;
+        // a large command goes out in several writes, and the small ones
+        // back to back, which Nagle would hold back waiting for the acks.
+        ws.set_nodelay(true)?;
         let rs = BufReader::new(ws.try_clone()?);
         let r = RespReader::new(rs);
         let w = RespWriter::new(ws);
         let mut conn = GenericConnection::new(r, w);
 
         if let Some(password) = password_opt {
-            conn.auth(password).or_else(|e|
-                Err(io::Error::new(io::ErrorKind::PermissionDenied, format!("failed on auth: {}", e)))
-            )?;
+            check_auth(conn.auth(password)?)?;
+        }
+        Ok(conn)
+    }
+
+    // connects as an ACL user.
+    pub fn connect_user(addr: &str, username: &str, password: &str) -> Result<TcpConnection, RuisError> {
+        let mut conn = TcpConnection::connect(addr, None)?;
+        check_auth(conn.auth_user(username, password)?)?;
+        Ok(conn)
+    }
+
+    pub fn stream(&self) -> &TcpStream {
+        self.w.get_ref()
+    }
+
+    // waits until a reply is ready to be read, returns false on timeout. a zero
+    // timeout only checks without blocking. only the waiting is bounded, once
+    // the reply starts arriving it's read as usual, so a reply is never left
+    // half read.
+    pub fn wait_readable(&mut self, timeout: Duration) -> Result<bool, RuisError> {
+        let reader = self.r.get_mut();
+        if !reader.buffer().is_empty() {
+            return Ok(true);
+        }
+
+        let stream = reader.get_ref().try_clone()?;
+        let prev_timeout = stream.read_timeout()?;
+        if timeout == Duration::from_secs(0) {
+            stream.set_nonblocking(true)?;
+        } else {
+            stream.set_read_timeout(Some(timeout))?;
+        }
+        let r = reader.fill_buf().map(|_| ());
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(prev_timeout)?;
+
+        match r {
+            // an EOF is readable too, the following read reports it.
+            Ok(()) => Ok(true),
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut => Ok(false),
+            Err(e) => Err(e.into()),
         }
-        return Ok(conn)
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::net::TcpListener;
+    use std::thread;
     use super::*;
+    use super::super::testing::{Mock, TestServer};
+
+    #[test]
+    fn test_auth_failed() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        thread::spawn(move || {
+            let stream = listener.accept().unwrap().0;
+            let mut r = RespReader::new(BufReader::new(stream.try_clone().unwrap()));
+            let mut w = RespWriter::new(stream);
+            while r.read().is_ok() {
+                w.write_error("WRONGPASS invalid username-password pair or user is disabled.").unwrap();
+            }
+        });
+        match TcpConnection::connect(&addr, Some("wrong")) {
+            Err(RuisError::Auth(msg)) => assert!(msg.starts_with("WRONGPASS")),
+            r => panic!("expected an auth error, got {:?}", r.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_connect_user() {
+        let server = TestServer::with_password("secret");
+        let mut conn = TcpConnection::connect_user(&server.addr(), "app", "secret").unwrap();
+        assert_eq!(conn.execute(&[b"ping"]).unwrap(), RespValue::Bulk(b"PONG".to_vec()));
+        assert!(matches!(TcpConnection::connect_user(&server.addr(), "app", "wrong"), Err(RuisError::Auth(_))));
+    }
+
+    #[test]
+    fn test_hello() {
+        let bulk = |s: &str| RespValue::Bulk(s.as_bytes().to_vec());
+        let module = RespValue::Array(vec![bulk("name"), bulk("search"), bulk("ver"), RespValue::Int(20812)]);
+        let reply = RespValue::Array(vec![
+            bulk("server"), bulk("redis"), bulk("version"), bulk("7.2.4"), bulk("proto"), RespValue::Int(2),
+            bulk("id"), RespValue::Int(7), bulk("mode"), bulk("standalone"), bulk("role"), bulk("master"),
+            bulk("modules"), RespValue::Array(vec![module]),
+        ]);
+        let mut w = RespWriter::new(vec![]);
+        w.write(&reply).unwrap();
+        w.write_error("WRONGPASS invalid username-password pair or user is disabled.").unwrap();
+        let mut conn = GenericConnection::new(RespReader::new(io::Cursor::new(w.into_inner())), RespWriter::new(vec![]));
+
+        let hello = conn.hello(2, Some(("app", "secret"))).unwrap();
+        assert_eq!((hello.version.as_str(), hello.id, hello.role.as_str()), ("7.2.4", 7, "master"));
+        assert_eq!(hello.modules, vec!["search".to_string()]);
+        assert!(matches!(conn.hello(2, None), Err(RuisError::Auth(_))));
+        assert!(conn.hello(3, None).is_err());
+        assert!(conn.raw_parts().0.starts_with(b"*5\r\n$5\r\nhello\r\n$1\r\n2\r\n$4\r\nauth\r\n$3\r\napp\r\n"));
+    }
+
+    #[test]
+    fn test_select() {
+        let server = TestServer::new();
+        let mut conn = TcpConnection::connect(&server.addr(), None).unwrap();
+        conn.execute(&[b"set", b"k", b"0"]).unwrap();
+        conn.select(1).unwrap();
+        assert_eq!(conn.execute(&[b"get", b"k"]).unwrap(), RespValue::NilBulk);
+        conn.select(0).unwrap();
+        assert_eq!(conn.execute(&[b"get", b"k"]).unwrap(), RespValue::Bulk(b"0".to_vec()));
+        assert!(matches!(conn.select(100), Err(RuisError::ServerError(_))));
+    }
+
+    #[test]
+    fn test_set_identity() {
+        let mock = Mock::new();
+        mock.expect(&[b"client", b"setname", b"worker-1"], RespValue::Bulk(b"OK".to_vec()))
+            // a server before 7.2.
+            .expect(&[b"client", b"setinfo", b"lib-name", b"ruis"], RespValue::Error(b"ERR unknown subcommand 'setinfo'".to_vec()))
+            .expect(&[b"client", b"setinfo", b"lib-ver", LIB_VER.as_bytes()], RespValue::Error(b"ERR unknown subcommand 'setinfo'".to_vec()))
+            .expect(&[b"client", b"getname"], RespValue::Bulk(b"worker-1".to_vec()))
+            .expect(&[b"client", b"setname", b"a b"], RespValue::Error(b"ERR Client names cannot contain spaces, newlines or special characters.".to_vec()));
+        let mut conn = mock.connection();
+        conn.set_identity(&ClientIdentity::new().name("worker-1")).unwrap();
+        assert_eq!(conn.client_name().unwrap(), Some("worker-1".to_string()));
+        assert!(matches!(conn.set_identity(&ClientIdentity::new().no_lib().name("a b")), Err(RuisError::ServerError(_))));
+        mock.assert_done();
+    }
 
     #[test]
     fn test_read() {
-        let mut conn = TcpConnection::connect("localhost:6379", None).unwrap();
-        let r = conn.execute(&vec!["ping".as_bytes()]).unwrap();
+        let server = TestServer::new();
+        let mut conn = TcpConnection::connect(&server.addr(), None).unwrap();
+        let r = conn.execute(&[b"ping"]).unwrap();
         assert_eq!(r, RespValue::Bulk(b"PONG".to_vec()));
+        let r = conn.execute_frame(&[b"ping"]).unwrap();
+        assert_eq!(r, RespFrame::Bulk(bytes::Bytes::from_static(b"PONG")));
     }
 }
@@ -0,0 +1,266 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::Hash;
+use std::io::{BufRead, Write};
+
+use super::connection::GenericConnection;
+use super::types::{RespValue, RuisError};
+
+// the bytes of the value kept in the errors, the longer values are cut.
+const PREVIEW_LEN: usize = 32;
+
+// a reply not convertible into the type asked for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConversionError {
+    // the rust type, like "i64".
+    pub expected: &'static str,
+    // the RespValue variant, like "Bulk".
+    pub actual: &'static str,
+    pub preview: String,
+    // the index of the command in the pipeline or the transaction.
+    pub index: Option<usize>,
+}
+
+impl ConversionError {
+    pub fn new(expected: &'static str, value: &RespValue) -> Self {
+        Self {
+            expected,
+            actual: variant_name(value),
+            preview: preview(value),
+            index: None,
+        }
+    }
+
+    pub fn at(mut self, index: usize) -> Self {
+        self.index = Some(index);
+        self
+    }
+}
+
+impl fmt::Display for ConversionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "expected {}, got {}", self.expected, self.actual)?;
+        if !self.preview.is_empty() {
+            write!(f, " {}", self.preview)?;
+        }
+        if let Some(i) = self.index {
+            write!(f, " in the reply of command #{}", i)?;
+        }
+        Ok(())
+    }
+}
+
+fn variant_name(v: &RespValue) -> &'static str {
+    match v {
+        RespValue::Int(_) => "Int",
+        RespValue::NilBulk => "NilBulk",
+        RespValue::NilArray => "NilArray",
+        RespValue::Bulk(_) => "Bulk",
+        RespValue::Array(_) => "Array",
+        RespValue::Error(_) => "Error",
+    }
+}
+
+fn preview(v: &RespValue) -> String {
+    match v {
+        RespValue::Bulk(bs) | RespValue::Error(bs) if bs.len() > PREVIEW_LEN => {
+            format!("{:?}...", String::from_utf8_lossy(&bs[..PREVIEW_LEN]))
+        },
+        RespValue::Bulk(bs) | RespValue::Error(bs) => format!("{:?}", String::from_utf8_lossy(bs)),
+        RespValue::Int(n) => n.to_string(),
+        RespValue::Array(items) => format!("of {} items", items.len()),
+        RespValue::NilBulk | RespValue::NilArray => String::new(),
+    }
+}
+
+// FromResp converts a reply into a rust type. the error replies convert into
+// RuisError::ServerError whatever the type.
+pub trait FromResp: Sized {
+    fn from_resp(v: RespValue) -> Result<Self, RuisError>;
+}
+
+// converts the reply of the index-th command of a pipeline, the conversion
+// errors tell the index.
+pub fn from_reply<T: FromResp>(v: RespValue, index: usize) -> Result<T, RuisError> {
+    T::from_resp(v).map_err(|e| match e {
+        RuisError::Conversion(e) => RuisError::Conversion(e.at(index)),
+        e => e,
+    })
+}
+
+pub(crate) fn mismatch<T>(expected: &'static str, v: RespValue) -> Result<T, RuisError> {
+    match v {
+        RespValue::Error(_) => Err(v.into_result().unwrap_err()),
+        v => Err(RuisError::Conversion(ConversionError::new(expected, &v))),
+    }
+}
+
+impl FromResp for RespValue {
+    fn from_resp(v: RespValue) -> Result<Self, RuisError> {
+        v.into_result()
+    }
+}
+
+impl FromResp for i64 {
+    fn from_resp(v: RespValue) -> Result<Self, RuisError> {
+        match v {
+            RespValue::Int(n) => Ok(n),
+            RespValue::Bulk(ref bs) => match std::str::from_utf8(bs).ok().and_then(|s| s.parse().ok()) {
+                Some(n) => Ok(n),
+                None => mismatch("i64", v),
+            },
+            v => mismatch("i64", v),
+        }
+    }
+}
+
+// the scores and the floats are sent as bulks, like "1.5" or "inf".
+impl FromResp for f64 {
+    fn from_resp(v: RespValue) -> Result<Self, RuisError> {
+        match v {
+            RespValue::Int(n) => Ok(n as f64),
+            RespValue::Bulk(ref bs) => match std::str::from_utf8(bs).ok().and_then(|s| s.parse().ok()) {
+                Some(n) => Ok(n),
+                None => mismatch("f64", v),
+            },
+            v => mismatch("f64", v),
+        }
+    }
+}
+
+impl FromResp for Vec<u8> {
+    fn from_resp(v: RespValue) -> Result<Self, RuisError> {
+        match v {
+            RespValue::Bulk(bs) => Ok(bs),
+            v => mismatch("Vec<u8>", v),
+        }
+    }
+}
+
+impl FromResp for String {
+    fn from_resp(v: RespValue) -> Result<Self, RuisError> {
+        match v {
+            RespValue::Bulk(bs) => String::from_utf8(bs).or_else(|e| mismatch("String", RespValue::Bulk(e.into_bytes()))),
+            RespValue::Int(n) => Ok(n.to_string()),
+            v => mismatch("String", v),
+        }
+    }
+}
+
+impl<T: FromResp> FromResp for Option<T> {
+    fn from_resp(v: RespValue) -> Result<Self, RuisError> {
+        match v {
+            RespValue::NilBulk | RespValue::NilArray => Ok(None),
+            v => T::from_resp(v).map(Some),
+        }
+    }
+}
+
+impl<T: FromResp> FromResp for Vec<T> {
+    fn from_resp(v: RespValue) -> Result<Self, RuisError> {
+        match v {
+            RespValue::Array(items) => items.into_iter().map(T::from_resp).collect(),
+            RespValue::NilArray => Ok(vec![]),
+            v => mismatch("Vec", v),
+        }
+    }
+}
+
+// the replies of the flat field-value lists, like HGETALL or CONFIG GET.
+impl<K: FromResp + Eq + Hash, V: FromResp> FromResp for HashMap<K, V> {
+    fn from_resp(v: RespValue) -> Result<Self, RuisError> {
+        match v {
+            RespValue::Array(items) if items.len() % 2 == 0 => {
+                let mut map = HashMap::with_capacity(items.len() / 2);
+                let mut it = items.into_iter();
+                while let (Some(k), Some(v)) = (it.next(), it.next()) {
+                    map.insert(K::from_resp(k)?, V::from_resp(v)?);
+                }
+                Ok(map)
+            },
+            RespValue::NilArray => Ok(HashMap::new()),
+            v => mismatch("HashMap", v),
+        }
+    }
+}
+
+// the arrays of a fixed number of items, like the reply of SCAN.
+macro_rules! tuple_from_resp {
+    ($n:expr, $($t:ident),+) => {
+        impl<$($t: FromResp),+> FromResp for ($($t,)+) {
+            fn from_resp(v: RespValue) -> Result<Self, RuisError> {
+                match v {
+                    RespValue::Array(items) if items.len() == $n => {
+                        let mut it = items.into_iter();
+                        // the length is checked above.
+                        Ok(($($t::from_resp(it.next().unwrap())?,)+))
+                    },
+                    v => mismatch(concat!("tuple of ", $n), v),
+                }
+            }
+        }
+    };
+}
+
+tuple_from_resp!(1, A);
+tuple_from_resp!(2, A, B);
+tuple_from_resp!(3, A, B, C);
+tuple_from_resp!(4, A, B, C, D);
+
+impl<W: Write, R: BufRead> GenericConnection<W, R> {
+    // executes the command and converts the reply, like
+    // conn.execute_as::<Option<String>>(&[b"get", b"k"]).
+    pub fn execute_as<T: FromResp>(&mut self, cmd: &[&[u8]]) -> Result<T, RuisError> {
+        T::from_resp(self.execute(cmd)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::connection::TcpConnection;
+    use super::super::testing::TestServer;
+
+    #[test]
+    fn test_from_resp() {
+        assert_eq!(i64::from_resp(RespValue::Bulk(b"42".to_vec())).unwrap(), 42);
+        assert_eq!(Option::<String>::from_resp(RespValue::NilBulk).unwrap(), None);
+        let v = RespValue::Array(vec![RespValue::Bulk(b"a".to_vec()), RespValue::NilBulk]);
+        assert_eq!(Vec::<Option<String>>::from_resp(v).unwrap(), vec![Some("a".to_string()), None]);
+        assert!(matches!(String::from_resp(RespValue::Error(b"WRONGTYPE".to_vec())), Err(RuisError::ServerError(_))));
+    }
+
+    #[test]
+    fn test_from_resp_collections() {
+        let v = RespValue::Array(vec![RespValue::Bulk(b"a".to_vec()), RespValue::Bulk(b"1".to_vec()), RespValue::Bulk(b"b".to_vec()), RespValue::Int(2)]);
+        let map = HashMap::<String, i64>::from_resp(v.clone()).unwrap();
+        assert_eq!((map["a"], map["b"]), (1, 2));
+        assert_eq!(<(String, i64, Vec<u8>, i64)>::from_resp(v.clone()).unwrap().1, 1);
+        assert!(matches!(<(String, i64)>::from_resp(v), Err(RuisError::Conversion(_))));
+        let odd = RespValue::Array(vec![RespValue::Bulk(b"a".to_vec())]);
+        assert!(HashMap::<String, String>::from_resp(odd).is_err());
+    }
+
+    #[test]
+    fn test_execute_as() {
+        let server = TestServer::new();
+        let mut conn = TcpConnection::connect(&server.addr(), None).unwrap();
+        conn.execute(&[b"hset", b"h", b"a", b"1", b"b", b"2"]).unwrap();
+        let map: HashMap<String, i64> = conn.execute_as(&[b"hgetall", b"h"]).unwrap();
+        assert_eq!(map.len(), 2);
+        assert_eq!(conn.execute_as::<Option<String>>(&[b"get", b"missing"]).unwrap(), None);
+        assert!(conn.execute_as::<i64>(&[b"hget", b"h", b"a"]).is_ok());
+        assert!(matches!(conn.execute_as::<i64>(&[b"hgetall", b"h"]), Err(RuisError::Conversion(_))));
+    }
+
+    #[test]
+    fn test_conversion_error() {
+        let long = RespValue::Bulk(vec![b'x'; 100]);
+        let err = match from_reply::<i64>(long, 2) {
+            Err(RuisError::Conversion(e)) => e,
+            r => panic!("expected a conversion error, got {:?}", r),
+        };
+        assert_eq!((err.expected, err.actual, err.index), ("i64", "Bulk", Some(2)));
+        assert_eq!(err.to_string(), format!("expected i64, got Bulk \"{}\"... in the reply of command #2", "x".repeat(32)));
+    }
+}
@@ -0,0 +1,298 @@
+use std::fmt::Write as _;
+use std::io::{self, BufRead, Read, Write};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::connection::GenericConnection;
+use super::resp::{RespReader, RespWriter};
+use super::types::RespValue;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    // written to the server.
+    Sent,
+    // read from the server.
+    Received,
+}
+
+// a frame seen on the wire, value is None if the bytes could not be decoded.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub direction: Direction,
+    pub timestamp: SystemTime,
+    pub value: Option<RespValue>,
+    pub raw: Vec<u8>,
+}
+
+pub trait FrameSink: Send + Sync {
+    fn frame(&self, frame: &Frame);
+}
+
+impl<F: Fn(&Frame) + Send + Sync> FrameSink for F {
+    fn frame(&self, frame: &Frame) {
+        self(frame)
+    }
+}
+
+// TextSink prints a line per frame like:
+//
+//   1700000000.123456 >> Array([Bulk('get'), Bulk('foo')])
+//
+// followed by the hexdump of the raw bytes if enabled.
+pub struct TextSink<W: Write + Send> {
+    out: Mutex<W>,
+    hexdump: bool,
+}
+
+impl<W: Write + Send> TextSink<W> {
+    pub fn new(out: W) -> Self {
+        Self {
+            out: Mutex::new(out),
+            hexdump: false,
+        }
+    }
+
+    pub fn hexdump(mut self, enabled: bool) -> Self {
+        self.hexdump = enabled;
+        self
+    }
+}
+
+impl<W: Write + Send> FrameSink for TextSink<W> {
+    fn frame(&self, frame: &Frame) {
+        let mut out = self.out.lock().unwrap();
+        let _ = writeln!(out, "{}", format_frame(frame));
+        if self.hexdump {
+            let _ = write!(out, "{}", hexdump(&frame.raw));
+        }
+        let _ = out.flush();
+    }
+}
+
+pub fn format_frame(frame: &Frame) -> String {
+    let ts = frame.timestamp.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let arrow = match frame.direction {
+        Direction::Sent => ">>",
+        Direction::Received => "<<",
+    };
+    let body = match frame.value {
+        Some(ref v) => format!("{:?}", v),
+        None => format!("malformed {:?}", String::from_utf8_lossy(&frame.raw)),
+    };
+    format!("{}.{:06} {} {}", ts.as_secs(), ts.subsec_micros(), arrow, body)
+}
+
+// 16 bytes per line, like `hexdump -C`.
+pub fn hexdump(bs: &[u8]) -> String {
+    let mut s = String::new();
+    for (i, chunk) in bs.chunks(16).enumerate() {
+        let _ = write!(s, "{:08x}  ", i * 16);
+        for j in 0..16 {
+            match chunk.get(j) {
+                Some(b) => { let _ = write!(s, "{:02x} ", b); },
+                None => s.push_str("   "),
+            }
+            if j == 7 {
+                s.push(' ');
+            }
+        }
+        s.push_str(" |");
+        s.extend(chunk.iter().map(|&b| if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' }));
+        s.push_str("|\n");
+    }
+    s
+}
+
+// collects the bytes passing through and emits the frames once complete.
+struct Decoder {
+    direction: Direction,
+    sink: Arc<dyn FrameSink>,
+    pending: Vec<u8>,
+}
+
+impl Decoder {
+    fn feed(&mut self, bs: &[u8]) {
+        self.pending.extend_from_slice(bs);
+        loop {
+            let (value, n) = match frame_len(&self.pending) {
+                Ok(None) => return,
+                Ok(Some(n)) => (RespReader::new(&self.pending[..n]).read().ok(), n),
+                // the stream can not be resynced, the rest goes out as is.
+                Err(()) => (None, self.pending.len()),
+            };
+            let raw: Vec<u8> = self.pending.drain(..n).collect();
+            self.sink.frame(&Frame {
+                direction: self.direction,
+                timestamp: SystemTime::now(),
+                value,
+                raw,
+            });
+        }
+    }
+}
+
+// the length of the first frame in buf, None if it is not complete yet.
+fn frame_len(buf: &[u8]) -> Result<Option<usize>, ()> {
+    let line_end = match buf.windows(2).position(|w| w == b"\r\n") {
+        Some(i) => i,
+        None => return Ok(None),
+    };
+    let header = line_end + 2;
+    let len = || -> Result<i64, ()> {
+        std::str::from_utf8(&buf[1..line_end]).ok().and_then(|s| s.parse().ok()).ok_or(())
+    };
+    match buf[0] {
+        b'+' | b'-' | b':' => Ok(Some(header)),
+        b'$' => match len()? {
+            -1 => Ok(Some(header)),
+            n if n < 0 => Err(()),
+            n if buf.len() >= header + n as usize + 2 => Ok(Some(header + n as usize + 2)),
+            _ => Ok(None),
+        },
+        b'*' => match len()? {
+            -1 => Ok(Some(header)),
+            n if n < 0 => Err(()),
+            n => {
+                let mut pos = header;
+                for _ in 0..n {
+                    match frame_len(&buf[pos..])? {
+                        Some(l) => pos += l,
+                        None => return Ok(None),
+                    }
+                }
+                Ok(Some(pos))
+            },
+        },
+        _ => Err(()),
+    }
+}
+
+// DumpReader passes the bytes read through, and tees the frames to the sink.
+pub struct DumpReader<R> {
+    inner: R,
+    decoder: Decoder,
+}
+
+impl<R: BufRead> DumpReader<R> {
+    pub fn new(inner: R, direction: Direction, sink: Arc<dyn FrameSink>) -> Self {
+        Self {
+            inner,
+            decoder: Decoder { direction, sink, pending: vec![] },
+        }
+    }
+}
+
+impl<R: BufRead> Read for DumpReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.fill_buf()?.read(buf)?;
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl<R: BufRead> BufRead for DumpReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        if let Ok(bs) = self.inner.fill_buf() {
+            self.decoder.feed(&bs[..amt.min(bs.len())]);
+        }
+        self.inner.consume(amt);
+    }
+}
+
+// DumpWriter passes the bytes written through, and tees the frames to the sink.
+pub struct DumpWriter<W> {
+    inner: W,
+    decoder: Decoder,
+}
+
+impl<W: Write> DumpWriter<W> {
+    pub fn new(inner: W, direction: Direction, sink: Arc<dyn FrameSink>) -> Self {
+        Self {
+            inner,
+            decoder: Decoder { direction, sink, pending: vec![] },
+        }
+    }
+}
+
+impl<W: Write> Write for DumpWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.decoder.feed(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+// RespDumper wraps the reader and the writer of a connection, the frames
+// written are dumped as Sent and the frames read as Received.
+pub struct RespDumper<W, R> {
+    writer: DumpWriter<W>,
+    reader: DumpReader<R>,
+}
+
+impl<W: Write, R: BufRead> RespDumper<W, R> {
+    pub fn new(w: W, r: R, sink: Arc<dyn FrameSink>) -> Self {
+        Self {
+            writer: DumpWriter::new(w, Direction::Sent, sink.clone()),
+            reader: DumpReader::new(r, Direction::Received, sink),
+        }
+    }
+
+    pub fn into_parts(self) -> (DumpWriter<W>, DumpReader<R>) {
+        (self.writer, self.reader)
+    }
+
+    pub fn into_connection(self) -> GenericConnection<DumpWriter<W>, DumpReader<R>> {
+        GenericConnection::new(RespReader::new(self.reader), RespWriter::new(self.writer))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use super::*;
+
+    fn recording() -> (Arc<dyn FrameSink>, Arc<Mutex<Vec<Frame>>>) {
+        let frames = Arc::new(Mutex::new(vec![]));
+        let frames2 = frames.clone();
+        (Arc::new(move |f: &Frame| frames2.lock().unwrap().push(f.clone())), frames)
+    }
+
+    #[test]
+    fn test_frame_len() {
+        assert_eq!(frame_len(b"+OK\r\n:1"), Ok(Some(5)));
+        assert_eq!(frame_len(b"$3\r\nfoo"), Ok(None));
+        assert_eq!(frame_len(b"*2\r\n$3\r\nfoo\r\n$-1\r\n"), Ok(Some(18)));
+        assert_eq!(frame_len(b"*2\r\n$3\r\nfoo\r\n"), Ok(None));
+        assert_eq!(frame_len(b"hello\r\n"), Err(()));
+    }
+
+    #[test]
+    fn test_dump_connection() {
+        let (sink, frames) = recording();
+        let dumper = RespDumper::new(vec![], Cursor::new(b"$3\r\nbar\r\n".to_vec()), sink);
+        let mut conn = dumper.into_connection();
+        assert_eq!(conn.execute(&[b"get", b"foo"]).unwrap(), RespValue::Bulk(b"bar".to_vec()));
+
+        let frames = frames.lock().unwrap();
+        let seen: Vec<(Direction, Option<RespValue>)> = frames.iter().map(|f| (f.direction, f.value.clone())).collect();
+        assert_eq!(seen, vec![
+            (Direction::Sent, Some(RespValue::Array(vec![RespValue::Bulk(b"get".to_vec()), RespValue::Bulk(b"foo".to_vec())]))),
+            (Direction::Received, Some(RespValue::Bulk(b"bar".to_vec()))),
+        ]);
+        assert_eq!(frames[1].raw, b"$3\r\nbar\r\n");
+        assert!(format_frame(&frames[1]).ends_with("<< Bulk('bar')"));
+    }
+
+    #[test]
+    fn test_hexdump() {
+        assert_eq!(hexdump(b"+OK\r\n"), "00000000  2b 4f 4b 0d 0a                                    |+OK..|\n");
+    }
+}
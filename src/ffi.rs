@@ -0,0 +1,222 @@
+// the C ABI of the client, built into the cdylib with the ffi feature, see
+// include/ruis.h for the declarations.
+//
+// the failed calls return NULL, and ruis_last_error() tells why.
+
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+use std::ptr;
+use std::slice;
+
+use super::connection::TcpConnection;
+use super::types::{RespValue, RuisError};
+
+pub struct RuisConnection {
+    conn: TcpConnection,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RuisValueKind {
+    Int = 0,
+    Nil = 1,
+    Bulk = 2,
+    Array = 3,
+    Error = 4,
+}
+
+// a reply. data and len are the bytes of Bulk and Error, elements and len the
+// items of Array, integer the value of Int.
+#[repr(C)]
+pub struct RuisValue {
+    pub kind: RuisValueKind,
+    pub integer: i64,
+    pub data: *mut u8,
+    pub elements: *mut RuisValue,
+    pub len: usize,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(msg: String) {
+    let msg = CString::new(msg.replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|e| *e.borrow_mut() = Some(msg));
+}
+
+fn fail<T>(e: RuisError) -> *mut T {
+    set_last_error(e.to_string());
+    ptr::null_mut()
+}
+
+fn into_c_value(v: RespValue) -> RuisValue {
+    let mut value = RuisValue {
+        kind: RuisValueKind::Nil,
+        integer: 0,
+        data: ptr::null_mut(),
+        elements: ptr::null_mut(),
+        len: 0,
+    };
+    match v {
+        RespValue::Int(n) => {
+            value.kind = RuisValueKind::Int;
+            value.integer = n;
+        },
+        RespValue::NilBulk | RespValue::NilArray => {},
+        RespValue::Bulk(b) => {
+            value.kind = RuisValueKind::Bulk;
+            (value.data, value.len) = into_raw_bytes(b);
+        },
+        RespValue::Error(b) => {
+            value.kind = RuisValueKind::Error;
+            (value.data, value.len) = into_raw_bytes(b);
+        },
+        RespValue::Array(items) => {
+            value.kind = RuisValueKind::Array;
+            let items: Box<[RuisValue]> = items.into_iter().map(into_c_value).collect();
+            value.len = items.len();
+            value.elements = Box::into_raw(items) as *mut RuisValue;
+        },
+    }
+    value
+}
+
+fn into_raw_bytes(b: Vec<u8>) -> (*mut u8, usize) {
+    let b = b.into_boxed_slice();
+    let len = b.len();
+    (Box::into_raw(b) as *mut u8, len)
+}
+
+unsafe fn free_c_value(v: &mut RuisValue) {
+    if !v.data.is_null() {
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(v.data, v.len)));
+    }
+    if !v.elements.is_null() {
+        let mut items = Box::from_raw(ptr::slice_from_raw_parts_mut(v.elements, v.len));
+        for item in items.iter_mut() {
+            free_c_value(item);
+        }
+    }
+}
+
+/// # Safety
+///
+/// addr is a NUL terminated string, password a NUL terminated string or NULL.
+#[no_mangle]
+pub unsafe extern "C" fn ruis_connect(addr: *const c_char, password: *const c_char) -> *mut RuisConnection {
+    if addr.is_null() {
+        set_last_error("NULL address".to_string());
+        return ptr::null_mut();
+    }
+    let addr = CStr::from_ptr(addr).to_string_lossy();
+    let password = match password.is_null() {
+        true => None,
+        false => Some(CStr::from_ptr(password).to_string_lossy()),
+    };
+    match TcpConnection::connect(&addr, password.as_deref()) {
+        Ok(conn) => Box::into_raw(Box::new(RuisConnection { conn })),
+        Err(e) => fail(e),
+    }
+}
+
+/// # Safety
+///
+/// conn comes from ruis_connect, argv and argvlen hold argc arguments and
+/// their lengths, as the arguments might hold NUL bytes. the value returned
+/// is freed by ruis_value_free.
+#[no_mangle]
+pub unsafe extern "C" fn ruis_execute(
+    conn: *mut RuisConnection,
+    argc: usize,
+    argv: *const *const u8,
+    argvlen: *const usize,
+) -> *mut RuisValue {
+    if conn.is_null() || argc == 0 || argv.is_null() || argvlen.is_null() {
+        set_last_error("NULL connection or no arguments".to_string());
+        return ptr::null_mut();
+    }
+    let (argv, argvlen) = (slice::from_raw_parts(argv, argc), slice::from_raw_parts(argvlen, argc));
+    let args: Vec<&[u8]> = argv.iter().zip(argvlen).map(|(&p, &len)| match len {
+        0 => &[][..],
+        _ => slice::from_raw_parts(p, len),
+    }).collect();
+    match (*conn).conn.execute(&args) {
+        Ok(v) => Box::into_raw(Box::new(into_c_value(v))),
+        Err(e) => fail(e),
+    }
+}
+
+/// # Safety
+///
+/// value comes from ruis_execute, or is NULL.
+#[no_mangle]
+pub unsafe extern "C" fn ruis_value_free(value: *mut RuisValue) {
+    if !value.is_null() {
+        let mut value = Box::from_raw(value);
+        free_c_value(&mut value);
+    }
+}
+
+/// # Safety
+///
+/// conn comes from ruis_connect, or is NULL.
+#[no_mangle]
+pub unsafe extern "C" fn ruis_close(conn: *mut RuisConnection) {
+    if !conn.is_null() {
+        drop(Box::from_raw(conn));
+    }
+}
+
+// the error of the last failed call on the thread, NULL if none. the string
+// stays valid until the next failed call on the thread.
+#[no_mangle]
+pub extern "C" fn ruis_last_error() -> *const c_char {
+    LAST_ERROR.with(|e| e.borrow().as_ref().map_or(ptr::null(), |s| s.as_ptr()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::testing::TestServer;
+
+    unsafe fn execute(conn: *mut RuisConnection, args: &[&[u8]]) -> *mut RuisValue {
+        let argv: Vec<*const u8> = args.iter().map(|a| a.as_ptr()).collect();
+        let argvlen: Vec<usize> = args.iter().map(|a| a.len()).collect();
+        ruis_execute(conn, args.len(), argv.as_ptr(), argvlen.as_ptr())
+    }
+
+    #[test]
+    fn test_ffi() {
+        let server = TestServer::new();
+        let addr = CString::new(server.addr()).unwrap();
+        unsafe {
+            let conn = ruis_connect(addr.as_ptr(), ptr::null());
+            assert!(!conn.is_null());
+
+            let v = execute(conn, &[b"lpush", b"l", b"b\0c", b"a"]);
+            assert_eq!(((*v).kind, (*v).integer), (RuisValueKind::Int, 2));
+            ruis_value_free(v);
+
+            let v = execute(conn, &[b"lrange", b"l", b"0", b"-1"]);
+            assert_eq!(((*v).kind, (*v).len), (RuisValueKind::Array, 2));
+            let second = &*(*v).elements.add(1);
+            assert_eq!(second.kind, RuisValueKind::Bulk);
+            assert_eq!(slice::from_raw_parts(second.data, second.len), b"b\0c");
+            ruis_value_free(v);
+
+            let v = execute(conn, &[b"get", b"missing"]);
+            assert_eq!((*v).kind, RuisValueKind::Nil);
+            ruis_value_free(v);
+            let v = execute(conn, &[b"hlen", b"l"]);
+            assert_eq!((*v).kind, RuisValueKind::Error);
+            ruis_value_free(v);
+            ruis_close(conn);
+
+            let bad = CString::new("127.0.0.1:1").unwrap();
+            assert!(ruis_connect(bad.as_ptr(), ptr::null()).is_null());
+            assert!(!CStr::from_ptr(ruis_last_error()).to_bytes().is_empty());
+        }
+    }
+}
@@ -0,0 +1,154 @@
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+
+use super::args::ToArgs;
+use super::connection::GenericConnection;
+use super::convert::FromResp;
+use super::types::{RespValue, RuisError};
+
+// ToRedisHash and FromRedisHash map a struct to the fields of a hash, they
+// are usually derived with the derive feature:
+//
+//   #[derive(ToRedisHash, FromRedisHash)]
+//   struct User {
+//       name: String,
+//       #[ruis(rename = "n")]
+//       visits: i64,
+//       // not set when None, None when missing.
+//       email: Option<String>,
+//   }
+//
+//   conn.hset_struct(b"user:1", &user)?;
+//   let user: Option<User> = conn.hget_struct(b"user:1")?;
+//
+// the values are encoded as the arguments of ToArgs, and decoded as the
+// bulks of FromResp.
+pub trait ToRedisHash {
+    fn to_redis_hash(&self) -> Result<HashFields, RuisError>;
+}
+
+pub trait FromRedisHash: Sized {
+    fn from_redis_hash(fields: HashMap<Vec<u8>, Vec<u8>>) -> Result<Self, RuisError>;
+}
+
+// the fields and the values, in the order of the struct.
+pub type HashFields = Vec<(Vec<u8>, Vec<u8>)>;
+
+// used by the derived ToRedisHash, a value has to be a single argument.
+#[doc(hidden)]
+pub fn push_field<T: ToArgs + ?Sized>(fields: &mut HashFields, name: &str, value: &T) -> Result<(), RuisError> {
+    let mut args = value.to_args();
+    if args.len() != 1 {
+        return Err(RuisError::CodecError(format!("hash field {}: {} arguments, expected one", name, args.len())));
+    }
+    fields.push((name.as_bytes().to_vec(), args.remove(0)));
+    Ok(())
+}
+
+// used by the derived FromRedisHash, a field missing is a nil, which only
+// converts into an Option.
+#[doc(hidden)]
+pub fn take_field<T: FromResp>(fields: &mut HashMap<Vec<u8>, Vec<u8>>, name: &str) -> Result<T, RuisError> {
+    let value = fields.remove(name.as_bytes()).map_or(RespValue::NilBulk, RespValue::Bulk);
+    T::from_resp(value).map_err(|e| RuisError::CodecError(format!("hash field {}: {}", name, e)))
+}
+
+impl<W: Write, R: BufRead> GenericConnection<W, R> {
+    // sets the fields from the pairs, like a HashMap or a slice of tuples,
+    // returns the number of the fields added, not counting the ones updated.
+    pub fn hset<I, F, V>(&mut self, key: &[u8], fields: I) -> Result<i64, RuisError>
+        where I: IntoIterator<Item = (F, V)>, F: AsRef<[u8]>, V: AsRef<[u8]> {
+        let fields: Vec<(F, V)> = fields.into_iter().collect();
+        let mut cmd: Vec<&[u8]> = Vec::with_capacity(fields.len() * 2 + 2);
+        cmd.extend_from_slice(&[b"hset", key]);
+        for (f, v) in &fields {
+            cmd.extend_from_slice(&[f.as_ref(), v.as_ref()]);
+        }
+        self.execute_as(&cmd)
+    }
+
+    pub fn hget(&mut self, key: &[u8], field: &[u8]) -> Result<Option<Vec<u8>>, RuisError> {
+        self.execute_as(&[b"hget", key, field])
+    }
+
+    // the values of the fields in their order, None for the fields missing.
+    pub fn hmget(&mut self, key: &[u8], fields: &[&[u8]]) -> Result<Vec<Option<Vec<u8>>>, RuisError> {
+        let mut cmd: Vec<&[u8]> = vec![b"hmget", key];
+        cmd.extend_from_slice(fields);
+        self.execute_as(&cmd)
+    }
+
+    // converts the fields and the values into any map, like
+    // HashMap<Vec<u8>, Vec<u8>> or HashMap<String, i64>, or a Vec of the
+    // flat field-value list. a key missing is an empty hash.
+    pub fn hgetall<T: FromResp>(&mut self, key: &[u8]) -> Result<T, RuisError> {
+        self.execute_as(&[b"hgetall", key])
+    }
+
+    // returns the number of the fields added, nothing is sent if the struct
+    // has no field to set.
+    pub fn hset_struct<T: ToRedisHash>(&mut self, key: &[u8], value: &T) -> Result<i64, RuisError> {
+        let fields = value.to_redis_hash()?;
+        if fields.is_empty() {
+            return Ok(0);
+        }
+        self.hset(key, fields)
+    }
+
+    // None if the hash does not exist, the fields not in the struct are
+    // ignored.
+    pub fn hget_struct<T: FromRedisHash>(&mut self, key: &[u8]) -> Result<Option<T>, RuisError> {
+        let fields: HashMap<Vec<u8>, Vec<u8>> = self.hgetall(key)?;
+        if fields.is_empty() {
+            return Ok(None);
+        }
+        T::from_redis_hash(fields).map(Some)
+    }
+
+    // returns the number of the fields deleted.
+    pub fn hdel(&mut self, key: &[u8], fields: &[&[u8]]) -> Result<i64, RuisError> {
+        let mut cmd: Vec<&[u8]> = vec![b"hdel", key];
+        cmd.extend_from_slice(fields);
+        self.execute_as(&cmd)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use super::*;
+    use super::super::connection::TcpConnection;
+    use super::super::resp::{RespReader, RespWriter};
+    use super::super::testing::TestServer;
+
+    #[test]
+    fn test_hash() {
+        let server = TestServer::new();
+        let mut conn = TcpConnection::connect(&server.addr(), None).unwrap();
+        let mut fields = HashMap::new();
+        fields.insert("name", "ruis".to_string());
+        fields.insert("visits", 3.to_string());
+        assert_eq!(conn.hset(b"h", &fields).unwrap(), 2);
+        assert_eq!(conn.hset(b"h", [(b"visits", b"4")]).unwrap(), 0);
+        assert_eq!(conn.hget(b"h", b"name").unwrap(), Some(b"ruis".to_vec()));
+        assert_eq!(conn.hget(b"h", b"missing").unwrap(), None);
+
+        let all: HashMap<Vec<u8>, Vec<u8>> = conn.hgetall(b"h").unwrap();
+        assert_eq!(all[&b"visits"[..]], b"4".to_vec());
+        let all: HashMap<String, String> = conn.hgetall(b"h").unwrap();
+        assert_eq!(all["name"], "ruis");
+        let empty: HashMap<String, String> = conn.hgetall(b"missing").unwrap();
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    fn test_hmget() {
+        let mut w = RespWriter::new(vec![]);
+        w.write(&RespValue::Array(vec![RespValue::Bulk(b"1".to_vec()), RespValue::NilBulk])).unwrap();
+        w.write(&RespValue::Int(1)).unwrap();
+        let mut c = GenericConnection::new(RespReader::new(io::Cursor::new(w.into_inner())), RespWriter::new(vec![]));
+        assert_eq!(c.hmget(b"h", &[b"a", b"b"]).unwrap(), vec![Some(b"1".to_vec()), None]);
+        assert_eq!(c.hdel(b"h", &[b"a"]).unwrap(), 1);
+        assert!(String::from_utf8_lossy(c.raw_parts().0).starts_with("*4\r\n$5\r\nhmget\r\n$1\r\nh\r\n$1\r\na\r\n$1\r\nb\r\n"));
+    }
+}
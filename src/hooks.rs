@@ -0,0 +1,92 @@
+use std::time::Duration;
+
+use super::cluster::command_key;
+use super::types::{RespValue, RuisError};
+
+// a command sent by the Client, as seen by the hooks.
+#[derive(Debug, Clone, Copy)]
+pub struct CommandInfo<'a> {
+    pub args: &'a [&'a [u8]],
+    // the address of the server the command is sent to, if known up front.
+    pub peer: Option<&'a str>,
+}
+
+impl<'a> CommandInfo<'a> {
+    // the command name in lower case.
+    pub fn name(&self) -> String {
+        self.args.first().map(|n| String::from_utf8_lossy(n).to_lowercase()).unwrap_or_default()
+    }
+
+    pub fn key(&self) -> Option<&'a [u8]> {
+        command_key(self.args)
+    }
+}
+
+// CommandHook gets called by the Client after each command, the error replies
+// of the server come as Ok(RespValue::Error). the commands of a pipeline are
+// reported one by one with the time of the whole pipeline.
+pub trait CommandHook: Send + Sync {
+    fn on_complete(&self, cmd: &CommandInfo, elapsed: Duration, result: Result<&RespValue, &RuisError>);
+}
+
+// a command whose round trip took longer than the threshold, including the
+// time waiting for a pooled connection.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SlowCommand {
+    pub name: String,
+    pub key: Option<String>,
+    pub peer: Option<String>,
+    pub elapsed: Duration,
+}
+
+// SlowCommandHook calls back on the commands slower than the threshold, to
+// catch the slowness of the network or the pool which SLOWLOG on the server
+// never sees.
+pub struct SlowCommandHook<F> {
+    threshold: Duration,
+    callback: F,
+}
+
+impl<F: Fn(&SlowCommand) + Send + Sync> SlowCommandHook<F> {
+    pub fn new(threshold: Duration, callback: F) -> Self {
+        Self {
+            threshold,
+            callback,
+        }
+    }
+}
+
+impl<F: Fn(&SlowCommand) + Send + Sync> CommandHook for SlowCommandHook<F> {
+    fn on_complete(&self, cmd: &CommandInfo, elapsed: Duration, _: Result<&RespValue, &RuisError>) {
+        if elapsed < self.threshold {
+            return;
+        }
+        (self.callback)(&SlowCommand {
+            name: cmd.name(),
+            key: cmd.key().map(|k| String::from_utf8_lossy(k).into_owned()),
+            peer: cmd.peer.map(|p| p.to_string()),
+            elapsed,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+    use super::*;
+
+    #[test]
+    fn test_slow_command_hook() {
+        let slow = Mutex::new(vec![]);
+        let hook = SlowCommandHook::new(Duration::from_millis(100), |c: &SlowCommand| slow.lock().unwrap().push(c.clone()));
+        let ok = RespValue::Bulk(b"OK".to_vec());
+        hook.on_complete(&CommandInfo { args: &[b"get", b"fast"], peer: None }, Duration::from_millis(5), Ok(&ok));
+        hook.on_complete(&CommandInfo { args: &[b"GET", b"slow"], peer: Some("127.0.0.1:6379") }, Duration::from_millis(150), Ok(&ok));
+        assert_eq!(*slow.lock().unwrap(), vec![SlowCommand {
+            name: "get".to_string(),
+            key: Some("slow".to_string()),
+            peer: Some("127.0.0.1:6379".to_string()),
+            elapsed: Duration::from_millis(150),
+        }]);
+    }
+}
@@ -0,0 +1,195 @@
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+use super::connection::GenericConnection;
+use super::pubsub::{Message, PubSub};
+use super::types::RuisError;
+
+// https://redis.io/docs/manual/keyspace-notifications/
+//
+// a change is published on two channels, with the key in one and the event
+// in the other:
+//
+//   __keyspace@<db>__:<key>    with the event as the payload
+//   __keyevent@<db>__:<event>  with the key as the payload
+
+const KEYSPACE_PREFIX: &[u8] = b"__keyspace@";
+const KEYEVENT_PREFIX: &[u8] = b"__keyevent@";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeyEvent {
+    Set,
+    Del,
+    // a timeout is set on the key.
+    Expire,
+    // the key is deleted as its timeout is reached.
+    Expired,
+    // the key is deleted for the maxmemory policy.
+    Evicted,
+    // the key is added, since 7.0.
+    New,
+    // the other events, like "lpush" or "rename_from".
+    Other(String),
+}
+
+impl KeyEvent {
+    pub fn parse(name: &[u8]) -> KeyEvent {
+        match name {
+            b"set" => KeyEvent::Set,
+            b"del" => KeyEvent::Del,
+            b"expire" => KeyEvent::Expire,
+            b"expired" => KeyEvent::Expired,
+            b"evicted" => KeyEvent::Evicted,
+            b"new" => KeyEvent::New,
+            name => KeyEvent::Other(String::from_utf8_lossy(name).into_owned()),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            KeyEvent::Set => "set",
+            KeyEvent::Del => "del",
+            KeyEvent::Expire => "expire",
+            KeyEvent::Expired => "expired",
+            KeyEvent::Evicted => "evicted",
+            KeyEvent::New => "new",
+            KeyEvent::Other(name) => name,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyspaceEvent {
+    pub event: KeyEvent,
+    pub key: Vec<u8>,
+    pub db: u32,
+}
+
+// returns None on the channels which are not of the notifications.
+pub fn parse_keyspace_event(channel: &[u8], payload: &[u8]) -> Option<KeyspaceEvent> {
+    let (keyspace, rest) = match (channel.strip_prefix(KEYSPACE_PREFIX), channel.strip_prefix(KEYEVENT_PREFIX)) {
+        (Some(rest), _) => (true, rest),
+        (_, Some(rest)) => (false, rest),
+        _ => return None,
+    };
+    let sep = rest.windows(3).position(|w| w == b"__:")?;
+    let db = std::str::from_utf8(&rest[..sep]).ok()?.parse().ok()?;
+    let name = &rest[sep + 3..];
+    let (event, key) = if keyspace { (payload, name) } else { (name, payload) };
+    Some(KeyspaceEvent {
+        event: KeyEvent::parse(event),
+        key: key.to_vec(),
+        db,
+    })
+}
+
+impl<W: Write, R: BufRead> GenericConnection<W, R> {
+    // the server publishes no notification until the classes of the events
+    // are set, like "KEA" for all of them on both channels, see the
+    // notify-keyspace-events of redis.conf. an empty string turns them off.
+    pub fn set_notify_keyspace_events(&mut self, flags: &str) -> Result<(), RuisError> {
+        self.execute_args(&("config", "set", "notify-keyspace-events", flags))?.into_result().map(|_| ())
+    }
+}
+
+// KeyspaceEvents receives the keyspace notifications watched. a change
+// watched both by its key and by its event is received twice, once from
+// each channel.
+pub struct KeyspaceEvents<W: Write, R: BufRead> {
+    pubsub: PubSub<W, R>,
+}
+
+fn db_pattern(db: Option<u32>) -> String {
+    db.map(|db| db.to_string()).unwrap_or_else(|| "*".to_string())
+}
+
+impl<W: Write, R: BufRead> KeyspaceEvents<W, R> {
+    pub fn new(conn: GenericConnection<W, R>) -> Self {
+        Self {
+            pubsub: PubSub::new(conn),
+        }
+    }
+
+    // the events on the keys matching the glob-style pattern, in the db or in
+    // all of them. needs the K flag.
+    pub fn watch_keys(&mut self, db: Option<u32>, pattern: &[u8]) -> Result<(), RuisError> {
+        let mut channel = format!("__keyspace@{}__:", db_pattern(db)).into_bytes();
+        channel.extend_from_slice(pattern);
+        self.pubsub.psubscribe(&channel)
+    }
+
+    // the keys the event happens on, in the db or in all of them. needs the E
+    // flag.
+    pub fn watch_event(&mut self, db: Option<u32>, event: &KeyEvent) -> Result<(), RuisError> {
+        let channel = format!("__keyevent@{}__:{}", db_pattern(db), event.name());
+        self.pubsub.psubscribe(channel.as_bytes())
+    }
+
+    pub fn next_event(&mut self) -> Result<KeyspaceEvent, RuisError> {
+        loop {
+            if let Some(event) = to_event(self.pubsub.next_message()?) {
+                return Ok(event);
+            }
+        }
+    }
+}
+
+impl KeyspaceEvents<TcpStream, BufReader<TcpStream>> {
+    // returns None if no event arrives within the timeout.
+    pub fn next_event_timeout(&mut self, timeout: Duration) -> Result<Option<KeyspaceEvent>, RuisError> {
+        Ok(self.pubsub.next_message_timeout(timeout)?.and_then(to_event))
+    }
+}
+
+impl<W: Write, R: BufRead> Iterator for KeyspaceEvents<W, R> {
+    type Item = Result<KeyspaceEvent, RuisError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        Some(self.next_event())
+    }
+}
+
+fn to_event(msg: Message) -> Option<KeyspaceEvent> {
+    parse_keyspace_event(&msg.channel, &msg.payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use super::*;
+    use super::super::resp::{RespReader, RespWriter};
+
+    #[test]
+    fn test_parse_keyspace_event() {
+        assert_eq!(parse_keyspace_event(b"__keyspace@0__:user:1", b"expired"), Some(KeyspaceEvent {
+            event: KeyEvent::Expired,
+            key: b"user:1".to_vec(),
+            db: 0,
+        }));
+        assert_eq!(parse_keyspace_event(b"__keyevent@12__:lpush", b"queue"), Some(KeyspaceEvent {
+            event: KeyEvent::Other("lpush".to_string()),
+            key: b"queue".to_vec(),
+            db: 12,
+        }));
+        assert_eq!(parse_keyspace_event(b"__keyspace@0__:a__:b", b"set").unwrap().key, b"a__:b".to_vec());
+        assert_eq!(parse_keyspace_event(b"news", b"set"), None);
+        assert_eq!(parse_keyspace_event(b"__keyspace@x__:k", b"set"), None);
+    }
+
+    #[test]
+    fn test_keyspace_events() {
+        let replies = b"*3\r\n$10\r\npsubscribe\r\n$17\r\n__keyspace@0__:u*\r\n:1\r\n\
+            *4\r\n$8\r\npmessage\r\n$1\r\n*\r\n$4\r\nnews\r\n$5\r\nhello\r\n\
+            *4\r\n$8\r\npmessage\r\n$17\r\n__keyspace@0__:u*\r\n$17\r\n__keyspace@0__:u1\r\n$3\r\ndel\r\n\
+            *4\r\n$8\r\npmessage\r\n$18\r\n__keyevent@*__:set\r\n$18\r\n__keyevent@3__:set\r\n$2\r\nu2\r\n";
+        let conn = GenericConnection::new(RespReader::new(io::Cursor::new(replies.to_vec())), RespWriter::new(vec![]));
+        let mut events = KeyspaceEvents::new(conn);
+        events.watch_keys(Some(0), b"u*").unwrap();
+        events.watch_event(None, &KeyEvent::Set).unwrap();
+
+        assert_eq!(events.next_event().unwrap(), KeyspaceEvent { event: KeyEvent::Del, key: b"u1".to_vec(), db: 0 });
+        assert_eq!(events.next_event().unwrap(), KeyspaceEvent { event: KeyEvent::Set, key: b"u2".to_vec(), db: 3 });
+        assert!(events.next_event().is_err());
+    }
+}
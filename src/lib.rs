@@ -1,4 +1,62 @@
-mod client;
-mod types;
-mod resp;
-mod connection;
\ No newline at end of file
+pub mod client;
+pub mod admin;
+pub mod commands;
+pub mod types;
+pub mod convert;
+pub mod args;
+pub mod resp;
+pub mod connection;
+pub mod url;
+#[cfg(feature = "tls")]
+pub mod tls;
+#[cfg(feature = "tokio")]
+pub mod aio;
+pub mod pool;
+pub mod reconnect;
+pub mod retry;
+pub mod multiplexed;
+pub mod tracking;
+pub mod cache;
+pub mod monitor;
+pub mod replica;
+pub mod dump;
+pub mod server;
+pub mod proxy;
+#[cfg(feature = "serde")]
+pub mod codec;
+#[cfg(feature = "serde")]
+pub mod serde_resp;
+pub mod pubsub;
+pub mod keyspace;
+pub mod cluster;
+pub mod sentinel;
+pub mod pipeline;
+pub mod scan;
+pub mod script;
+pub mod redlock;
+pub mod streams;
+pub mod blocking;
+pub mod bitmap;
+pub mod zset;
+pub mod hash;
+pub mod restore;
+pub mod rdb;
+pub mod transaction;
+pub mod hooks;
+pub mod metrics;
+pub mod audit;
+pub mod telemetry;
+#[cfg(feature = "tower")]
+pub mod service;
+#[cfg(feature = "redis")]
+pub mod compat;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "python")]
+mod python;
+pub mod testing;
+pub mod tools;
+
+pub use self::types::{ErrorKind, RuisError};
+#[cfg(feature = "derive")]
+pub use ruis_derive::{FromRedisHash, ToRedisHash};
@@ -0,0 +1,39 @@
+use std::time::Duration;
+
+use super::types::{RespValue, RuisError};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Success,
+    // the server replied an error.
+    ServerError,
+    // the command failed on the client side, like on io errors.
+    Failure,
+}
+
+impl Outcome {
+    pub fn of(result: Result<&RespValue, &RuisError>) -> Self {
+        match result {
+            Ok(RespValue::Error(_)) => Outcome::ServerError,
+            Ok(_) => Outcome::Success,
+            Err(_) => Outcome::Failure,
+        }
+    }
+}
+
+// MetricsSink receives the measurements of the client and the pools, to be
+// exported to prometheus, statsd and the like. all the methods default to
+// doing nothing.
+pub trait MetricsSink: Send + Sync {
+    fn command_started(&self, _name: &str) {}
+
+    fn command_completed(&self, _name: &str, _elapsed: Duration, _outcome: Outcome) {}
+
+    // the time taken to get a connection from the pool, including the connect
+    // time when no idle connection is reused.
+    fn pool_checkout(&self, _addr: &str, _elapsed: Duration, _reused: bool) {}
+
+    // a connection is opened, reconnect is true if the previous connection to
+    // the server failed.
+    fn connected(&self, _addr: &str, _reconnect: bool) {}
+}
@@ -0,0 +1,182 @@
+use std::io::{BufRead, Write};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use super::connection::GenericConnection;
+use super::types::{RespValue, RuisError};
+
+// a line of the MONITOR output, like:
+//
+//   1339518083.107412 [0 127.0.0.1:60866] "keys" "*"
+//
+// the client address is "lua" for the commands issued by scripts, and
+// "unix:/path/to/socket" for the unix socket clients.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MonitorEntry {
+    pub timestamp: SystemTime,
+    pub db: u32,
+    pub client_addr: String,
+    pub args: Vec<Vec<u8>>,
+}
+
+impl MonitorEntry {
+    pub fn command(&self) -> &[u8] {
+        self.args.first().map(|a| a.as_slice()).unwrap_or(b"")
+    }
+}
+
+pub struct Monitor<W: Write, R: BufRead> {
+    conn: GenericConnection<W, R>,
+}
+
+impl<W: Write, R: BufRead> GenericConnection<W, R> {
+    // the connection can not run other commands once MONITOR is issued, so it
+    // is consumed into an iterator over the monitored commands.
+    pub fn monitor(mut self) -> Result<Monitor<W, R>, RuisError> {
+        self.execute(&[b"monitor"])?.into_result()?;
+        Ok(Monitor {
+            conn: self,
+        })
+    }
+}
+
+impl<W: Write, R: BufRead> Monitor<W, R> {
+    pub fn next_entry(&mut self) -> Result<MonitorEntry, RuisError> {
+        match self.conn.receive()?.into_result()? {
+            RespValue::Bulk(line) => parse_monitor_line(&line),
+            v => Err(RuisError::Unexpected(format!("monitor: {:?}", v))),
+        }
+    }
+}
+
+impl<W: Write, R: BufRead> Iterator for Monitor<W, R> {
+    type Item = Result<MonitorEntry, RuisError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        Some(self.next_entry())
+    }
+}
+
+pub fn parse_monitor_line(line: &[u8]) -> Result<MonitorEntry, RuisError> {
+    let malformed = || RuisError::ParseFailed(format!("malformed monitor line: {}", String::from_utf8_lossy(line)));
+
+    let sp = line.iter().position(|&b| b == b' ').ok_or_else(malformed)?;
+    let timestamp = parse_timestamp(&line[..sp]).ok_or_else(malformed)?;
+
+    let rest = &line[sp+1..];
+    if rest.first() != Some(&b'[') {
+        return Err(malformed());
+    }
+    let close = rest.iter().position(|&b| b == b']').ok_or_else(malformed)?;
+    let source = std::str::from_utf8(&rest[1..close]).map_err(|_| malformed())?;
+    let mut parts = source.splitn(2, ' ');
+    let db = parts.next().and_then(|s| s.parse::<u32>().ok()).ok_or_else(malformed)?;
+    let client_addr = parts.next().ok_or_else(malformed)?.to_string();
+
+    let args = parse_quoted_args(&rest[close+1..]).ok_or_else(malformed)?;
+    Ok(MonitorEntry {
+        timestamp,
+        db,
+        client_addr,
+        args,
+    })
+}
+
+fn parse_timestamp(buf: &[u8]) -> Option<SystemTime> {
+    let s = std::str::from_utf8(buf).ok()?;
+    let mut parts = s.splitn(2, '.');
+    let secs = parts.next()?.parse::<u64>().ok()?;
+    let micros = match parts.next() {
+        Some(m) => m.parse::<u32>().ok()?,
+        None => 0,
+    };
+    Some(UNIX_EPOCH + Duration::new(secs, micros * 1000))
+}
+
+// the arguments are quoted and escaped like redis's sdscatrepr().
+fn parse_quoted_args(buf: &[u8]) -> Option<Vec<Vec<u8>>> {
+    let mut args = vec![];
+    let mut i = 0;
+    loop {
+        while i < buf.len() && buf[i] == b' ' {
+            i += 1;
+        }
+        if i >= buf.len() {
+            return Some(args);
+        }
+        if buf[i] != b'"' {
+            return None;
+        }
+        i += 1;
+
+        let mut arg = vec![];
+        loop {
+            match *buf.get(i)? {
+                b'"' => {
+                    i += 1;
+                    break;
+                },
+                b'\\' => {
+                    let ch = match *buf.get(i+1)? {
+                        b'n' => b'\n',
+                        b'r' => b'\r',
+                        b't' => b'\t',
+                        b'a' => 7,
+                        b'b' => 8,
+                        b'x' => {
+                            let hex = std::str::from_utf8(buf.get(i+2..i+4)?).ok()?;
+                            i += 2;
+                            u8::from_str_radix(hex, 16).ok()?
+                        },
+                        ch => ch,
+                    };
+                    arg.push(ch);
+                    i += 2;
+                },
+                ch => {
+                    arg.push(ch);
+                    i += 1;
+                },
+            }
+        }
+        args.push(arg);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use super::*;
+    use super::super::resp::{RespReader, RespWriter};
+
+    #[test]
+    fn test_parse_monitor_line() {
+        let entry = parse_monitor_line(b"1339518083.107412 [0 127.0.0.1:60866] \"keys\" \"*\"").unwrap();
+        assert_eq!(entry.timestamp, UNIX_EPOCH + Duration::new(1339518083, 107412000));
+        assert_eq!(entry.db, 0);
+        assert_eq!(entry.client_addr, "127.0.0.1:60866");
+        assert_eq!(entry.command(), b"keys");
+        assert_eq!(entry.args, vec![b"keys".to_vec(), b"*".to_vec()]);
+
+        let entry = parse_monitor_line(b"1339518087.877697 [3 lua] \"set\" \"a \\\"b\\\"\" \"\\x00\\n\"").unwrap();
+        assert_eq!(entry.db, 3);
+        assert_eq!(entry.client_addr, "lua");
+        assert_eq!(entry.args, vec![b"set".to_vec(), b"a \"b\"".to_vec(), b"\x00\n".to_vec()]);
+
+        let entry = parse_monitor_line(b"1339518087.877697 [0 unix:/tmp/redis.sock] \"ping\"").unwrap();
+        assert_eq!(entry.client_addr, "unix:/tmp/redis.sock");
+
+        assert!(parse_monitor_line(b"OK").is_err());
+        assert!(parse_monitor_line(b"1339518083.107412 [0 127.0.0.1:60866] \"keys").is_err());
+    }
+
+    #[test]
+    fn test_monitor() {
+        let replies = b"+OK\r\n+1339518083.107412 [0 127.0.0.1:60866] \"get\" \"foo\"\r\n";
+        let r = RespReader::new(io::Cursor::new(replies.to_vec()));
+        let conn = GenericConnection::new(r, RespWriter::new(vec![]));
+        let mut monitor = conn.monitor().unwrap();
+        let entry = monitor.next().unwrap().unwrap();
+        assert_eq!(entry.args, vec![b"get".to_vec(), b"foo".to_vec()]);
+        assert!(monitor.next().unwrap().is_err());
+    }
+}
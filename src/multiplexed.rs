@@ -0,0 +1,212 @@
+use std::collections::VecDeque;
+use std::io::{self, BufReader, Write};
+use std::net::{Shutdown, TcpStream};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use super::commands::Commands;
+use super::connection::TcpConnection;
+use super::pipeline::Pipeline;
+use super::resp::{RespReader, RespWriter};
+use super::types::{RespValue, RuisError};
+
+type ReplySender = SyncSender<Result<RespValue, RuisError>>;
+
+#[derive(Default)]
+struct Pending {
+    // the commands waiting for their replies, in the order they were written.
+    senders: VecDeque<ReplySender>,
+    // why the connection is closed, the commands fail right away then.
+    closed: Option<String>,
+}
+
+struct Inner {
+    writer: Mutex<TcpStream>,
+    pending: Arc<Mutex<Pending>>,
+}
+
+// the reader thread ends once the socket is shut down.
+impl Drop for Inner {
+    fn drop(&mut self) {
+        if let Ok(writer) = self.writer.get_mut() {
+            let _ = writer.shutdown(Shutdown::Both);
+        }
+    }
+}
+
+// MultiplexedConnection shares a single connection among the threads: the
+// commands are written as they come, and a reader thread hands out the
+// replies in the order of the commands, as redis replies in order. a thread
+// waits for its own reply only, not for the commands of the others, which
+// costs a lot less than a connection per thread.
+//
+// the clones share the connection. the commands blocking the connection, like
+// BLPOP, hold up the commands of all the threads behind them, and the ones
+// changing its state, like SUBSCRIBE or SELECT, are not supported.
+#[derive(Clone)]
+pub struct MultiplexedConnection {
+    inner: Arc<Inner>,
+}
+
+fn closed_error(reason: &str) -> RuisError {
+    io::Error::new(io::ErrorKind::ConnectionAborted, format!("multiplexed connection closed: {}", reason)).into()
+}
+
+impl MultiplexedConnection {
+    pub fn connect(addr: &str, password: Option<&str>) -> Result<MultiplexedConnection, RuisError> {
+        Self::new(TcpConnection::connect(addr, password)?)
+    }
+
+    // takes over a connection set up already, like with the database
+    // selected, with no reply left to read.
+    pub fn new(conn: TcpConnection) -> Result<MultiplexedConnection, RuisError> {
+        let writer = conn.stream().try_clone()?;
+        let reader = conn.stream().try_clone()?;
+        let pending = Arc::new(Mutex::new(Pending::default()));
+        let shared = pending.clone();
+        thread::Builder::new()
+            .name("ruis-multiplexed".to_string())
+            .spawn(move || read_replies(reader, shared))?;
+        Ok(MultiplexedConnection {
+            inner: Arc::new(Inner {
+                writer: Mutex::new(writer),
+                pending,
+            }),
+        })
+    }
+
+    pub fn execute(&self, cmd: &[&[u8]]) -> Result<RespValue, RuisError> {
+        let rx = self.send_batch(std::iter::once(cmd))?;
+        wait_reply(&rx[0])
+    }
+
+    // the commands of the pipeline are written at once, the ones of the other
+    // threads do not come in between.
+    pub fn execute_pipeline(&self, pipeline: &Pipeline) -> Result<Vec<RespValue>, RuisError> {
+        let rxs = self.send_batch(pipeline.commands())?;
+        rxs.iter().map(wait_reply).collect()
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.inner.pending.lock().unwrap().closed.is_some()
+    }
+
+    fn send_batch<'a, C, I>(&self, cmds: I) -> Result<Vec<Receiver<Result<RespValue, RuisError>>>, RuisError>
+        where C: AsRef<[&'a [u8]]>, I: IntoIterator<Item = C> {
+        let mut buf = vec![];
+        let mut n = 0;
+        {
+            let mut w = RespWriter::new(&mut buf);
+            for cmd in cmds {
+                w.write_bulks(cmd.as_ref())?;
+                n += 1;
+            }
+        }
+        // the writes and the order of the pending commands have to agree, so
+        // both are done under the lock of the writer.
+        let mut writer = self.inner.writer.lock().unwrap();
+        let rxs = {
+            let mut pending = self.inner.pending.lock().unwrap();
+            if let Some(ref reason) = pending.closed {
+                return Err(closed_error(reason));
+            }
+            (0..n).map(|_| {
+                let (tx, rx) = mpsc::sync_channel(1);
+                pending.senders.push_back(tx);
+                rx
+            }).collect()
+        };
+        if let Err(e) = writer.write_all(&buf) {
+            // the command might be written in part, the replies could not be
+            // told apart any more. the reader fails the pending commands.
+            let _ = writer.shutdown(Shutdown::Both);
+            self.inner.pending.lock().unwrap().closed.get_or_insert_with(|| e.to_string());
+            return Err(e.into());
+        }
+        Ok(rxs)
+    }
+}
+
+fn wait_reply(rx: &Receiver<Result<RespValue, RuisError>>) -> Result<RespValue, RuisError> {
+    rx.recv().unwrap_or_else(|_| Err(closed_error("the reader stopped")))
+}
+
+fn read_replies(stream: TcpStream, pending: Arc<Mutex<Pending>>) {
+    let mut r = RespReader::new(BufReader::new(stream));
+    let reason = loop {
+        match r.read() {
+            Ok(reply) => {
+                let sender = pending.lock().unwrap().senders.pop_front();
+                match sender {
+                    Some(tx) => {
+                        let _ = tx.send(Ok(reply));
+                    },
+                    None => break "a reply to no command".to_string(),
+                }
+            },
+            Err(e) => break e.to_string(),
+        }
+    };
+    let _ = r.get_ref().get_ref().shutdown(Shutdown::Both);
+    let mut pending = pending.lock().unwrap();
+    for tx in pending.senders.drain(..) {
+        let _ = tx.send(Err(closed_error(&reason)));
+    }
+    pending.closed.get_or_insert(reason);
+}
+
+impl Commands for MultiplexedConnection {
+    fn execute(&mut self, cmd: &[&[u8]]) -> Result<RespValue, RuisError> {
+        MultiplexedConnection::execute(self, cmd)
+    }
+
+    fn execute_pipeline(&mut self, pipeline: &Pipeline) -> Result<Vec<RespValue>, RuisError> {
+        MultiplexedConnection::execute_pipeline(self, pipeline)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+    use super::*;
+    use super::super::testing::TestServer;
+
+    #[test]
+    fn test_multiplexed() {
+        let server = TestServer::new();
+        let conn = MultiplexedConnection::connect(&server.addr(), None).unwrap();
+        let threads: Vec<_> = (0..8).map(|i| {
+            let mut conn = conn.clone();
+            thread::spawn(move || {
+                let key = format!("k{}", i);
+                for n in 0..50 {
+                    conn.set(key.as_bytes(), n.to_string().as_bytes()).unwrap();
+                    assert_eq!(conn.get(key.as_bytes()).unwrap(), Some(n.to_string().into_bytes()));
+                    conn.incr(b"total").unwrap();
+                }
+            })
+        }).collect();
+        for t in threads {
+            t.join().unwrap();
+        }
+        let mut pipeline = Pipeline::new();
+        pipeline.cmd(&[b"get", b"total"]).cmd(&[b"get", b"k7"]);
+        assert_eq!(conn.execute_pipeline(&pipeline).unwrap(), vec![RespValue::Bulk(b"400".to_vec()), RespValue::Bulk(b"49".to_vec())]);
+    }
+
+    #[test]
+    fn test_closed() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            drop(stream);
+        });
+        let conn = MultiplexedConnection::connect(&addr, None).unwrap();
+        server.join().unwrap();
+        assert!(conn.execute(&[b"ping"]).unwrap_err().is_connection_dropped());
+        assert!(conn.is_closed());
+        assert!(conn.execute(&[b"ping"]).unwrap_err().is_connection_dropped());
+    }
+}
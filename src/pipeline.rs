@@ -0,0 +1,119 @@
+use std::io::{BufRead, Write};
+
+use super::connection::GenericConnection;
+use super::resp::RespWriter;
+use super::types::{RespValue, RuisError};
+
+// Pipeline queues the commands to be sent in a batch, the replies are read
+// after all the commands are written, in the order the commands were queued.
+#[derive(Debug, Clone, Default)]
+pub struct Pipeline {
+    cmds: Vec<Vec<Vec<u8>>>,
+}
+
+impl Pipeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cmd(&mut self, args: &[&[u8]]) -> &mut Self {
+        self.cmds.push(args.iter().map(|a| a.to_vec()).collect());
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.cmds.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cmds.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.cmds.clear();
+    }
+
+    pub fn commands(&self) -> impl Iterator<Item = Vec<&[u8]>> {
+        self.cmds.iter().map(|args| args.iter().map(|a| a.as_slice()).collect())
+    }
+}
+
+impl<W: Write, R: BufRead> GenericConnection<W, R> {
+    // the error replies are returned in place, only the io and parse errors
+    // fail the whole pipeline.
+    pub fn execute_pipeline(&mut self, pipeline: &Pipeline) -> Result<Vec<RespValue>, RuisError> {
+        self.execute_batch(pipeline.commands())
+    }
+
+    // writes all the commands before reading the replies. the commands are
+    // encoded into a buffer first, so the batch goes out in one write instead
+    // of a write and a flush per command.
+    pub(crate) fn execute_batch<'a, C, I>(&mut self, cmds: I) -> Result<Vec<RespValue>, RuisError>
+        where C: AsRef<[&'a [u8]]>, I: IntoIterator<Item = C> {
+        let mut buf = vec![];
+        let mut n = 0;
+        {
+            let mut w = RespWriter::new(&mut buf);
+            for cmd in cmds {
+                w.write_bulks(cmd.as_ref())?;
+                n += 1;
+            }
+        }
+        if n > 0 {
+            self.send_encoded(&buf)?;
+        }
+        let mut replies = Vec::with_capacity(n);
+        for _ in 0..n {
+            replies.push(self.receive()?);
+        }
+        Ok(replies)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use super::*;
+    use super::super::resp::RespReader;
+
+    // counts the writes reaching the socket.
+    struct CountingWriter {
+        buf: Vec<u8>,
+        writes: usize,
+    }
+
+    impl Write for CountingWriter {
+        fn write(&mut self, b: &[u8]) -> io::Result<usize> {
+            self.writes += 1;
+            self.buf.extend_from_slice(b);
+            Ok(b.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_execute_pipeline() {
+        let r = RespReader::new(io::Cursor::new(b"+OK\r\n:2\r\n-ERR wrong type\r\n$-1\r\n".to_vec()));
+        let w = RespWriter::new(CountingWriter { buf: vec![], writes: 0 });
+        let mut conn = GenericConnection::new(r, w);
+
+        let mut pipeline = Pipeline::new();
+        pipeline.cmd(&[b"set", b"n", b"1"]).cmd(&[b"incr", b"n"]).cmd(&[b"hlen", b"n"]).cmd(&[b"get", b"missing"]);
+        let replies = conn.execute_pipeline(&pipeline).unwrap();
+        assert_eq!(replies, vec![
+            RespValue::Bulk(b"OK".to_vec()),
+            RespValue::Int(2),
+            RespValue::Error(b"ERR wrong type".to_vec()),
+            RespValue::NilBulk,
+        ]);
+        let (w, _) = conn.raw_parts();
+        assert_eq!(w.writes, 1);
+        assert!(w.buf.starts_with(b"*3\r\n$3\r\nset\r\n$1\r\nn\r\n$1\r\n1\r\n*2\r\n$4\r\nincr\r\n"));
+
+        assert_eq!(conn.execute_pipeline(&Pipeline::new()).unwrap(), vec![]);
+        assert_eq!(conn.raw_parts().0.writes, 1);
+    }
+}
@@ -0,0 +1,447 @@
+use std::fmt;
+use std::io::{BufRead, Write};
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use super::commands::Commands;
+use super::connection::{ClientIdentity, GenericConnection, TcpConnection, redacted};
+use super::metrics::MetricsSink;
+use super::pipeline::Pipeline;
+#[cfg(feature = "tls")]
+use super::tls::{TlsConfig, TlsConnection};
+use super::types::{RespValue, RuisError};
+
+const DEFAULT_MAX_IDLE: usize = 4;
+
+// opens a connection to the address, authenticated as the user if given, with
+// the password.
+type Connector<C> = Arc<dyn Fn(&str, Option<&str>, Option<&str>) -> Result<C, RuisError> + Send + Sync>;
+
+// ConnectionPool keeps the idle connections to a single node. a connection is
+// taken out by get() and returned by put() once the reply is read, the
+// connections broken by io errors are dropped instead of being returned. the
+// connections are over TCP, or over TLS with new_tls().
+pub struct ConnectionPool<C = TcpConnection> {
+    addr: String,
+    username: Option<String>,
+    password: Option<String>,
+    // the database selected on each new connection.
+    db: Option<i64>,
+    // the name and the library info set on each new connection.
+    identity: Option<ClientIdentity>,
+    max_idle: usize,
+    // sent on each new connection, like READONLY on the cluster replicas.
+    init_cmds: Vec<Vec<Vec<u8>>>,
+    metrics: Option<Arc<dyn MetricsSink>>,
+    connector: Connector<C>,
+    state: Mutex<PoolState<C>>,
+}
+
+impl<C> fmt::Debug for ConnectionPool<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ConnectionPool")
+            .field("addr", &self.addr)
+            .field("username", &self.username)
+            .field("password", &redacted(&self.password))
+            .field("db", &self.db)
+            .field("identity", &self.identity)
+            .field("max_idle", &self.max_idle)
+            .field("idle", &self.idle_len())
+            .field("failures", &self.failures())
+            .finish()
+    }
+}
+
+struct PoolState<C> {
+    idle: Vec<C>,
+    // the failures since the last success, a connection failure or an io
+    // error on a command counts.
+    failures: usize,
+    last_failure: Option<Instant>,
+}
+
+impl ConnectionPool {
+    pub fn new(addr: &str, password: Option<&str>) -> Self {
+        Self::with_connector(addr, password, Arc::new(|addr: &str, username: Option<&str>, password: Option<&str>| {
+            match (username, password) {
+                (Some(username), Some(password)) => TcpConnection::connect_user(addr, username, password),
+                (_, password) => TcpConnection::connect(addr, password),
+            }
+        }))
+    }
+}
+
+#[cfg(feature = "tls")]
+impl ConnectionPool<TlsConnection> {
+    // the connections over TLS, the name checked against the certificate is
+    // the host of addr unless set in the config.
+    pub fn new_tls(addr: &str, password: Option<&str>, config: TlsConfig) -> Self {
+        Self::with_connector(addr, password, Arc::new(move |addr: &str, username: Option<&str>, password: Option<&str>| {
+            match (username, password) {
+                (Some(username), Some(password)) => TlsConnection::connect_user(addr, username, password, &config),
+                (_, password) => TlsConnection::connect(addr, password, &config),
+            }
+        }))
+    }
+}
+
+impl<C> ConnectionPool<C> {
+    fn with_connector(addr: &str, password: Option<&str>, connector: Connector<C>) -> Self {
+        Self {
+            addr: addr.to_string(),
+            username: None,
+            password: password.map(|p| p.to_string()),
+            db: None,
+            identity: None,
+            max_idle: DEFAULT_MAX_IDLE,
+            init_cmds: vec![],
+            metrics: None,
+            connector,
+            state: Mutex::new(PoolState {
+                idle: vec![],
+                failures: 0,
+                last_failure: None,
+            }),
+        }
+    }
+
+    // authenticates the connections as the ACL user, with the password. the
+    // username is ignored without a password.
+    pub fn username(mut self, username: &str) -> Self {
+        self.username = Some(username.to_string());
+        self
+    }
+
+    // the connections are opened against the database, and opened again
+    // against it after an io error. the SELECTs run on a checked out
+    // connection are not undone, so such a connection is better discarded.
+    pub fn db(mut self, db: i64) -> Self {
+        self.db = Some(db);
+        self
+    }
+
+    pub fn identity(mut self, identity: ClientIdentity) -> Self {
+        self.identity = Some(identity);
+        self
+    }
+
+    // the connections returned beyond max_idle are closed.
+    pub fn max_idle(mut self, n: usize) -> Self {
+        self.max_idle = n;
+        self
+    }
+
+    pub fn init_cmd(mut self, cmd: &[&[u8]]) -> Self {
+        self.init_cmds.push(cmd.iter().map(|a| a.to_vec()).collect());
+        self
+    }
+
+    pub fn metrics(mut self, sink: Arc<dyn MetricsSink>) -> Self {
+        self.metrics = Some(sink);
+        self
+    }
+
+    pub fn addr(&self) -> &str {
+        &self.addr
+    }
+
+    pub fn put(&self, conn: C) {
+        let mut state = self.state.lock().unwrap();
+        state.failures = 0;
+        if state.idle.len() < self.max_idle {
+            state.idle.push(conn);
+        }
+    }
+
+    // the idle connections are likely broken as well when one of them failed,
+    // so they are all closed.
+    pub fn mark_failed(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.idle.clear();
+        state.failures += 1;
+        state.last_failure = Some(Instant::now());
+    }
+
+    pub fn is_healthy(&self) -> bool {
+        self.failures() == 0
+    }
+
+    pub fn failures(&self) -> usize {
+        self.state.lock().unwrap().failures
+    }
+
+    pub fn last_failure(&self) -> Option<Instant> {
+        self.state.lock().unwrap().last_failure
+    }
+
+    pub fn idle_len(&self) -> usize {
+        self.state.lock().unwrap().idle.len()
+    }
+}
+
+impl<W: Write, R: BufRead> ConnectionPool<GenericConnection<W, R>> {
+    // returns an idle connection, or opens a new one.
+    pub fn get(&self) -> Result<GenericConnection<W, R>, RuisError> {
+        let start = Instant::now();
+        let (idle, reconnect) = {
+            let mut state = self.state.lock().unwrap();
+            (state.idle.pop(), state.failures > 0)
+        };
+        let reused = idle.is_some();
+        let conn = match idle {
+            Some(conn) => conn,
+            None => {
+                let conn = self.open().inspect_err(|_| self.mark_failed())?;
+                if let Some(ref m) = self.metrics {
+                    m.connected(&self.addr, reconnect);
+                }
+                conn
+            },
+        };
+        if let Some(ref m) = self.metrics {
+            m.pool_checkout(&self.addr, start.elapsed(), reused);
+        }
+        Ok(conn)
+    }
+
+    fn open(&self) -> Result<GenericConnection<W, R>, RuisError> {
+        let mut conn = (self.connector)(&self.addr, self.username.as_deref(), self.password.as_deref())?;
+        if let Some(ref identity) = self.identity {
+            conn.set_identity(identity)?;
+        }
+        if let Some(db) = self.db {
+            conn.select(db)?;
+        }
+        for cmd in &self.init_cmds {
+            let args: Vec<&[u8]> = cmd.iter().map(|a| a.as_slice()).collect();
+            conn.execute(&args)?.into_result()?;
+        }
+        Ok(conn)
+    }
+}
+
+// the connection is dropped on io errors, as the replies might be out of sync,
+// like after a malformed reply or one over the limits.
+pub(crate) fn checkin<C, T>(pool: &ConnectionPool<C>, conn: C, r: &Result<T, RuisError>) {
+    match r {
+        Err(e) if e.leaves_stream_unusable() => pool.mark_failed(),
+        _ => pool.put(conn),
+    }
+}
+
+// PooledConnection is a connection checked out of a pool, which is put back
+// when dropped. the io errors of the commands run through Commands mark the
+// pool failed like on Client, the ones run on the connection itself need
+// discard() instead.
+pub struct PooledConnection<'a, C = TcpConnection> {
+    pool: &'a ConnectionPool<C>,
+    conn: Option<C>,
+    broken: bool,
+}
+
+impl<'a, C> PooledConnection<'a, C> {
+    pub(crate) fn new(pool: &'a ConnectionPool<C>, conn: C) -> Self {
+        Self {
+            pool,
+            conn: Some(conn),
+            broken: false,
+        }
+    }
+
+    // closes the connection instead of putting it back, like after an io
+    // error or a state change like SELECT.
+    pub fn discard(mut self) {
+        self.conn = None;
+    }
+
+    // takes the connection out of the pool for good, like for a MONITOR or a
+    // subscription.
+    pub fn detach(mut self) -> C {
+        self.conn.take().unwrap()
+    }
+
+    pub(crate) fn track<T>(&mut self, r: Result<T, RuisError>) -> Result<T, RuisError> {
+        if r.as_ref().is_err_and(|e| e.leaves_stream_unusable()) {
+            self.mark_broken();
+        }
+        r
+    }
+
+    // the connection is not put back when dropped, and the pool counts a
+    // failure.
+    pub(crate) fn mark_broken(&mut self) {
+        self.broken = true;
+    }
+}
+
+impl<C> Deref for PooledConnection<'_, C> {
+    type Target = C;
+
+    fn deref(&self) -> &C {
+        self.conn.as_ref().unwrap()
+    }
+}
+
+impl<C> DerefMut for PooledConnection<'_, C> {
+    fn deref_mut(&mut self) -> &mut C {
+        self.conn.as_mut().unwrap()
+    }
+}
+
+impl<W: Write, R: BufRead> Commands for PooledConnection<'_, GenericConnection<W, R>> {
+    fn execute(&mut self, cmd: &[&[u8]]) -> Result<RespValue, RuisError> {
+        let r = self.deref_mut().execute(cmd);
+        self.track(r)
+    }
+
+    fn execute_pipeline(&mut self, pipeline: &Pipeline) -> Result<Vec<RespValue>, RuisError> {
+        let r = self.deref_mut().execute_pipeline(pipeline);
+        self.track(r)
+    }
+}
+
+impl<C> Drop for PooledConnection<'_, C> {
+    fn drop(&mut self) {
+        if let Some(conn) = self.conn.take() {
+            if self.broken {
+                self.pool.mark_failed();
+            } else {
+                self.pool.put(conn);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufReader, Write};
+    use std::net::TcpListener;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+    use super::*;
+    use super::super::resp::RespReader;
+    use super::super::testing::TestServer;
+    use super::super::types::RespValue;
+
+    // replies +OK to everything, counting the accepted connections and the
+    // commands named cmd.
+    fn fake_server(cmd: &'static [u8]) -> (String, Arc<AtomicUsize>, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let (accepted, seen) = (Arc::new(AtomicUsize::new(0)), Arc::new(AtomicUsize::new(0)));
+        let (accepted2, seen2) = (accepted.clone(), seen.clone());
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                accepted2.fetch_add(1, Ordering::SeqCst);
+                let seen = seen2.clone();
+                thread::spawn(move || {
+                    let mut r = RespReader::new(BufReader::new(stream.try_clone().unwrap()));
+                    while let Ok(RespValue::Array(args)) = r.read() {
+                        if args.first() == Some(&RespValue::Bulk(cmd.to_vec())) {
+                            seen.fetch_add(1, Ordering::SeqCst);
+                        }
+                        stream.write_all(b"+OK\r\n").unwrap();
+                    }
+                });
+            }
+        });
+        (addr, accepted, seen)
+    }
+
+    #[test]
+    fn test_reuse() {
+        let (addr, accepted, seen) = fake_server(b"readonly");
+        let pool = ConnectionPool::new(&addr, None).max_idle(1).init_cmd(&[b"readonly"]);
+
+        let mut conn = pool.get().unwrap();
+        conn.execute(&[b"ping"]).unwrap();
+        pool.put(conn);
+        let conn = pool.get().unwrap();
+        assert_eq!(accepted.load(Ordering::SeqCst), 1);
+
+        // only max_idle connections are kept.
+        let other = pool.get().unwrap();
+        pool.put(conn);
+        pool.put(other);
+        assert_eq!(pool.idle_len(), 1);
+        assert_eq!(accepted.load(Ordering::SeqCst), 2);
+        assert_eq!(seen.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_health() {
+        let addr = {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            listener.local_addr().unwrap().to_string()
+        };
+        let pool = ConnectionPool::new(&addr, None);
+        assert!(pool.is_healthy());
+        assert!(pool.get().is_err());
+        assert!(pool.get().is_err());
+        assert_eq!(pool.failures(), 2);
+        assert!(pool.last_failure().is_some());
+
+        let (addr, _, _) = fake_server(b"ping");
+        let pool = ConnectionPool::new(&addr, None);
+        pool.put(pool.get().unwrap());
+        pool.mark_failed();
+        assert_eq!(pool.idle_len(), 0);
+        assert!(!pool.is_healthy());
+        pool.put(pool.get().unwrap());
+        assert!(pool.is_healthy());
+    }
+
+    #[test]
+    fn test_db() {
+        let server = TestServer::new();
+        let pool = ConnectionPool::new(&server.addr(), None).db(1);
+        let mut conn = pool.get().unwrap();
+        conn.execute(&[b"set", b"k", b"1"]).unwrap();
+        // a new connection starts on the database of the pool too.
+        pool.mark_failed();
+        let mut conn = pool.get().unwrap();
+        assert_eq!(conn.execute(&[b"get", b"k"]).unwrap(), RespValue::Bulk(b"1".to_vec()));
+        conn.select(0).unwrap();
+        assert_eq!(conn.execute(&[b"get", b"k"]).unwrap(), RespValue::NilBulk);
+    }
+
+    #[derive(Default)]
+    struct Counter {
+        checkouts: AtomicUsize,
+        reused: AtomicUsize,
+        reconnects: AtomicUsize,
+    }
+
+    impl MetricsSink for Counter {
+        fn pool_checkout(&self, _: &str, _: std::time::Duration, reused: bool) {
+            self.checkouts.fetch_add(1, Ordering::SeqCst);
+            if reused {
+                self.reused.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        fn connected(&self, _: &str, reconnect: bool) {
+            if reconnect {
+                self.reconnects.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+    }
+
+    #[test]
+    fn test_metrics() {
+        let (addr, _, _) = fake_server(b"ping");
+        let counter = Arc::new(Counter::default());
+        let pool = ConnectionPool::new(&addr, None).metrics(counter.clone());
+        pool.put(pool.get().unwrap());
+        let conn = pool.get().unwrap();
+        drop(conn);
+        pool.mark_failed();
+        pool.put(pool.get().unwrap());
+        assert_eq!(counter.checkouts.load(Ordering::SeqCst), 3);
+        assert_eq!(counter.reused.load(Ordering::SeqCst), 1);
+        assert_eq!(counter.reconnects.load(Ordering::SeqCst), 1);
+    }
+}
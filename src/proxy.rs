@@ -0,0 +1,151 @@
+use std::collections::HashMap;
+use std::io;
+use std::net::ToSocketAddrs;
+use std::sync::{Arc, Mutex};
+
+use super::connection::TcpConnection;
+use super::server::{CommandHandler, RespServer, Session};
+use super::types::RespValue;
+
+// what to do with a command received from a client.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    // sends the command, possibly rewritten, to the upstream.
+    Forward(Vec<Vec<u8>>),
+    // replies the client without asking the upstream, like an error to reject
+    // the command.
+    Reply(RespValue),
+}
+
+// ProxyHook observes and rewrites the traffic of the proxy, the defaults pass
+// everything through.
+pub trait ProxyHook: Send + Sync + 'static {
+    fn on_command(&self, _session: &Session, args: Vec<Vec<u8>>) -> Action {
+        Action::Forward(args)
+    }
+
+    fn on_reply(&self, _session: &Session, _args: &[Vec<u8>], reply: RespValue) -> RespValue {
+        reply
+    }
+}
+
+pub struct Passthrough;
+
+impl ProxyHook for Passthrough {}
+
+// RespProxy forwards the commands of each client over its own connection to
+// the upstream. the commands are forwarded one at a time, waiting for the
+// reply, so the modes pushing replies like SUBSCRIBE and MONITOR are not
+// supported.
+pub struct RespProxy<H> {
+    upstream: String,
+    hook: H,
+    conns: Mutex<HashMap<u64, Arc<Mutex<TcpConnection>>>>,
+}
+
+impl<H: ProxyHook> RespProxy<H> {
+    pub fn bind<A: ToSocketAddrs>(addr: A, upstream: &str, hook: H) -> io::Result<RespServer<Self>> {
+        let proxy = Self {
+            upstream: upstream.to_string(),
+            hook,
+            conns: Mutex::new(HashMap::new()),
+        };
+        RespServer::bind(addr, proxy)
+    }
+
+    fn upstream_conn(&self, session: &Session) -> Result<Arc<Mutex<TcpConnection>>, RespValue> {
+        if let Some(conn) = self.conns.lock().unwrap().get(&session.id) {
+            return Ok(conn.clone());
+        }
+        let conn = TcpConnection::connect(&self.upstream, None)
+            .map_err(|e| RespValue::Error(format!("ERR upstream {} unavailable: {}", self.upstream, e).into_bytes()))?;
+        let conn = Arc::new(Mutex::new(conn));
+        self.conns.lock().unwrap().insert(session.id, conn.clone());
+        Ok(conn)
+    }
+}
+
+impl<H: ProxyHook> CommandHandler for RespProxy<H> {
+    fn handle(&self, session: &mut Session, args: &[Vec<u8>]) -> RespValue {
+        let args = match self.hook.on_command(session, args.to_vec()) {
+            Action::Forward(args) => args,
+            Action::Reply(reply) => return reply,
+        };
+        let conn = match self.upstream_conn(session) {
+            Ok(conn) => conn,
+            Err(reply) => return reply,
+        };
+        let cmd: Vec<&[u8]> = args.iter().map(|a| a.as_slice()).collect();
+        let r = conn.lock().unwrap().execute(&cmd);
+        match r {
+            Ok(reply) => self.hook.on_reply(session, &args, reply),
+            // the replies might be out of sync with the commands now, so the
+            // client is disconnected as well.
+            Err(e) => {
+                self.conns.lock().unwrap().remove(&session.id);
+                session.closing = true;
+                RespValue::Error(format!("ERR upstream {} failed: {}", self.upstream, e).into_bytes())
+            },
+        }
+    }
+
+    fn disconnected(&self, session: &Session) {
+        self.conns.lock().unwrap().remove(&session.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use super::*;
+    use super::super::testing::TestServer;
+
+    #[derive(Default)]
+    struct Guard {
+        replies: AtomicUsize,
+    }
+
+    impl ProxyHook for Guard {
+        fn on_command(&self, _: &Session, args: Vec<Vec<u8>>) -> Action {
+            match args[0].to_ascii_lowercase().as_slice() {
+                b"del" => Action::Reply(RespValue::Error(b"ERR DEL is disabled by the proxy".to_vec())),
+                // prefixes the keys of GET and SET.
+                b"get" | b"set" => {
+                    let mut args = args;
+                    args[1] = [&b"tenant:"[..], &args[1]].concat();
+                    Action::Forward(args)
+                },
+                _ => Action::Forward(args),
+            }
+        }
+
+        fn on_reply(&self, _: &Session, _: &[Vec<u8>], reply: RespValue) -> RespValue {
+            self.replies.fetch_add(1, Ordering::SeqCst);
+            reply
+        }
+    }
+
+    #[test]
+    fn test_proxy() {
+        let upstream = TestServer::new();
+        let proxy = RespProxy::bind("127.0.0.1:0", &upstream.addr(), Guard::default()).unwrap().spawn().unwrap();
+        let mut conn = TcpConnection::connect(&proxy.addr().to_string(), None).unwrap();
+        conn.execute(&[b"set", b"a", b"1"]).unwrap();
+        assert_eq!(conn.execute(&[b"get", b"a"]).unwrap(), RespValue::Bulk(b"1".to_vec()));
+        assert_eq!(conn.execute(&[b"del", b"a"]).unwrap(), RespValue::Error(b"ERR DEL is disabled by the proxy".to_vec()));
+
+        let mut direct = TcpConnection::connect(&upstream.addr(), None).unwrap();
+        assert_eq!(direct.execute(&[b"get", b"tenant:a"]).unwrap(), RespValue::Bulk(b"1".to_vec()));
+    }
+
+    #[test]
+    fn test_upstream_down() {
+        let addr = TestServer::new().addr();
+        let proxy = RespProxy::bind("127.0.0.1:0", &addr, Passthrough).unwrap().spawn().unwrap();
+        let mut conn = TcpConnection::connect(&proxy.addr().to_string(), None).unwrap();
+        match conn.execute(&[b"ping"]).unwrap() {
+            RespValue::Error(msg) => assert!(msg.starts_with(b"ERR upstream")),
+            v => panic!("expected an error, got {:?}", v),
+        }
+    }
+}
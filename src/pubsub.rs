@@ -0,0 +1,315 @@
+use std::collections::BTreeSet;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "serde")]
+use serde::Serialize;
+#[cfg(feature = "serde")]
+use serde::de::DeserializeOwned;
+
+#[cfg(feature = "serde")]
+use super::codec::Codec;
+#[cfg(feature = "serde_json")]
+use super::codec::JsonCodec;
+use super::connection::GenericConnection;
+use super::types::{RespValue, RuisError};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    pub channel: Vec<u8>,
+    pub payload: Vec<u8>,
+    // the pattern matched, for the messages of the pattern subscriptions.
+    pub pattern: Option<Vec<u8>>,
+}
+
+impl Message {
+    #[cfg(feature = "serde_json")]
+    pub fn decode<T: DeserializeOwned>(&self) -> Result<T, RuisError> {
+        self.decode_with(&JsonCodec)
+    }
+
+    #[cfg(feature = "serde")]
+    pub fn decode_with<T: DeserializeOwned, C: Codec>(&self, codec: &C) -> Result<T, RuisError> {
+        codec.decode(&self.payload)
+    }
+}
+
+impl<W: Write, R: BufRead> GenericConnection<W, R> {
+    // returns the number of the clients received the message.
+    pub fn publish_raw(&mut self, channel: &[u8], payload: &[u8]) -> Result<i64, RuisError> {
+        match self.execute(&[b"publish", channel, payload])?.into_result()? {
+            RespValue::Int(n) => Ok(n),
+            v => Err(RuisError::Unexpected(format!("publish: {:?}", v))),
+        }
+    }
+
+    // publishes the value encoded as JSON, see publish_with() for the other
+    // encodings.
+    #[cfg(feature = "serde_json")]
+    pub fn publish<T: Serialize + ?Sized>(&mut self, channel: &[u8], value: &T) -> Result<i64, RuisError> {
+        self.publish_with(channel, value, &JsonCodec)
+    }
+
+    #[cfg(feature = "serde")]
+    pub fn publish_with<T: Serialize + ?Sized, C: Codec>(&mut self, channel: &[u8], value: &T, codec: &C) -> Result<i64, RuisError> {
+        let payload = codec.encode(value)?;
+        self.publish_raw(channel, &payload)
+    }
+}
+
+// what the subscriptions receive: the messages, and the subscriptions made
+// again on a new connection.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PubSubEvent {
+    Message(Message),
+    // the connection dropped and the channels and the patterns were subscribed
+    // again on a new one, the messages published meanwhile are lost.
+    Resubscribed {
+        channels: Vec<Vec<u8>>,
+        patterns: Vec<Vec<u8>>,
+    },
+}
+
+// PubSub takes over a connection to receive the messages published on the
+// subscribed channels. the channels and the patterns subscribed are kept, to
+// subscribe them again on a new connection with resubscribe().
+pub struct PubSub<W: Write, R: BufRead> {
+    conn: GenericConnection<W, R>,
+    channels: BTreeSet<Vec<u8>>,
+    patterns: BTreeSet<Vec<u8>>,
+}
+
+impl<W: Write, R: BufRead> PubSub<W, R> {
+    pub fn new(conn: GenericConnection<W, R>) -> Self {
+        Self {
+            conn,
+            channels: BTreeSet::new(),
+            patterns: BTreeSet::new(),
+        }
+    }
+
+    // the confirmation of the subscription is consumed by next_message(), as
+    // it might arrive after the messages of the channels subscribed before.
+    pub fn subscribe(&mut self, channel: &[u8]) -> Result<(), RuisError> {
+        self.channels.insert(channel.to_vec());
+        self.conn.send(&[b"subscribe", channel])
+    }
+
+    // subscribes the channels matching the glob-style pattern.
+    pub fn psubscribe(&mut self, pattern: &[u8]) -> Result<(), RuisError> {
+        self.patterns.insert(pattern.to_vec());
+        self.conn.send(&[b"psubscribe", pattern])
+    }
+
+    // the messages published before the confirmation arrives are still
+    // returned by next_message().
+    pub fn unsubscribe(&mut self, channel: &[u8]) -> Result<(), RuisError> {
+        self.channels.remove(channel);
+        self.conn.send(&[b"unsubscribe", channel])
+    }
+
+    pub fn punsubscribe(&mut self, pattern: &[u8]) -> Result<(), RuisError> {
+        self.patterns.remove(pattern);
+        self.conn.send(&[b"punsubscribe", pattern])
+    }
+
+    // unsubscribes all the channels and the patterns.
+    pub fn unsubscribe_all(&mut self) -> Result<(), RuisError> {
+        self.channels.clear();
+        self.patterns.clear();
+        self.conn.send(&[b"unsubscribe"])?;
+        self.conn.send(&[b"punsubscribe"])
+    }
+
+    pub fn channels(&self) -> &BTreeSet<Vec<u8>> {
+        &self.channels
+    }
+
+    pub fn patterns(&self) -> &BTreeSet<Vec<u8>> {
+        &self.patterns
+    }
+
+    // takes over the new connection, like after the old one dropped, and
+    // subscribes the channels and the patterns on it again.
+    pub fn resubscribe(&mut self, conn: GenericConnection<W, R>) -> Result<PubSubEvent, RuisError> {
+        self.conn = conn;
+        let channels: Vec<Vec<u8>> = self.channels.iter().cloned().collect();
+        let patterns: Vec<Vec<u8>> = self.patterns.iter().cloned().collect();
+        for (cmd, names) in [(&b"subscribe"[..], &channels), (&b"psubscribe"[..], &patterns)] {
+            if !names.is_empty() {
+                let mut args = vec![cmd];
+                args.extend(names.iter().map(|n| n.as_slice()));
+                self.conn.send(&args)?;
+            }
+        }
+        Ok(PubSubEvent::Resubscribed { channels, patterns })
+    }
+
+    pub fn next_message(&mut self) -> Result<Message, RuisError> {
+        loop {
+            let v = self.conn.receive()?.into_result()?;
+            if let Some(msg) = parse_message(v)? {
+                return Ok(msg);
+            }
+        }
+    }
+
+    #[cfg(feature = "serde_json")]
+    pub fn next_decoded<T: DeserializeOwned>(&mut self) -> Result<(Vec<u8>, T), RuisError> {
+        self.next_decoded_with(&JsonCodec)
+    }
+
+    // returns the channel and the decoded payload of the next message.
+    #[cfg(feature = "serde")]
+    pub fn next_decoded_with<T: DeserializeOwned, C: Codec>(&mut self, codec: &C) -> Result<(Vec<u8>, T), RuisError> {
+        let msg = self.next_message()?;
+        let value = msg.decode_with(codec)?;
+        Ok((msg.channel, value))
+    }
+}
+
+impl PubSub<TcpStream, BufReader<TcpStream>> {
+    // returns None if no message arrives within the timeout, which lets the
+    // consumer do some periodic work between the messages.
+    pub fn next_message_timeout(&mut self, timeout: Duration) -> Result<Option<Message>, RuisError> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if !self.conn.wait_readable(remaining)? {
+                return Ok(None);
+            }
+            let v = self.conn.receive()?.into_result()?;
+            if let Some(msg) = parse_message(v)? {
+                return Ok(Some(msg));
+            }
+        }
+    }
+
+    // returns the message already arrived, without blocking.
+    pub fn try_next(&mut self) -> Result<Option<Message>, RuisError> {
+        self.next_message_timeout(Duration::from_secs(0))
+    }
+}
+
+// returns None on the replies which are not messages, like the subscribe
+// confirmations.
+fn parse_message(v: RespValue) -> Result<Option<Message>, RuisError> {
+    let arr = match v {
+        RespValue::Array(arr) => arr,
+        v => return Err(RuisError::Unexpected(format!("pubsub: {:?}", v))),
+    };
+    let mut it = arr.into_iter();
+    match (it.next(), it.next(), it.next(), it.next(), it.next()) {
+        (Some(RespValue::Bulk(kind)), Some(RespValue::Bulk(channel)), Some(RespValue::Bulk(payload)), None, None) if kind == b"message" => {
+            Ok(Some(Message {
+                channel,
+                payload,
+                pattern: None,
+            }))
+        },
+        (Some(RespValue::Bulk(kind)), Some(RespValue::Bulk(pattern)), Some(RespValue::Bulk(channel)), Some(RespValue::Bulk(payload)), None) if kind == b"pmessage" => {
+            Ok(Some(Message {
+                channel,
+                payload,
+                pattern: Some(pattern),
+            }))
+        },
+        (Some(RespValue::Bulk(ref kind)), _, _, _, _) if kind == b"message" || kind == b"pmessage" => {
+            Err(RuisError::Unexpected("malformed pubsub message".to_string()))
+        },
+        _ => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use super::*;
+    use super::super::connection::TcpConnection;
+    use super::super::resp::{RespReader, RespWriter};
+
+    fn pubsub(replies: &[u8]) -> PubSub<Vec<u8>, io::Cursor<Vec<u8>>> {
+        let r = RespReader::new(io::Cursor::new(replies.to_vec()));
+        PubSub::new(GenericConnection::new(r, RespWriter::new(vec![])))
+    }
+
+    #[test]
+    fn test_next_message() {
+        let mut ps = pubsub(b"*3\r\n$9\r\nsubscribe\r\n$3\r\nfoo\r\n:1\r\n*3\r\n$7\r\nmessage\r\n$3\r\nfoo\r\n$3\r\nbar\r\n");
+        ps.subscribe(b"foo").unwrap();
+        let msg = ps.next_message().unwrap();
+        assert_eq!(msg, Message { channel: b"foo".to_vec(), payload: b"bar".to_vec(), pattern: None });
+    }
+
+    #[test]
+    fn test_next_pmessage() {
+        let mut ps = pubsub(b"*3\r\n$10\r\npsubscribe\r\n$2\r\nf*\r\n:1\r\n*4\r\n$8\r\npmessage\r\n$2\r\nf*\r\n$3\r\nfoo\r\n$3\r\nbar\r\n");
+        ps.psubscribe(b"f*").unwrap();
+        let msg = ps.next_message().unwrap();
+        assert_eq!(msg, Message { channel: b"foo".to_vec(), payload: b"bar".to_vec(), pattern: Some(b"f*".to_vec()) });
+    }
+
+    #[test]
+    fn test_unsubscribe() {
+        let mut ps = pubsub(b"*3\r\n$7\r\nmessage\r\n$3\r\nfoo\r\n$3\r\nbar\r\n*3\r\n$11\r\nunsubscribe\r\n$3\r\nfoo\r\n:0\r\n*3\r\n$7\r\nmessage\r\n$3\r\nbaz\r\n$3\r\nqux\r\n");
+        ps.unsubscribe(b"foo").unwrap();
+        ps.punsubscribe(b"f*").unwrap();
+        ps.unsubscribe_all().unwrap();
+        assert_eq!(ps.next_message().unwrap().payload, b"bar".to_vec());
+        assert_eq!(ps.next_message().unwrap().channel, b"baz".to_vec());
+    }
+
+    #[test]
+    fn test_resubscribe() {
+        let mut ps = pubsub(b"");
+        ps.subscribe(b"a").unwrap();
+        ps.subscribe(b"b").unwrap();
+        ps.psubscribe(b"p*").unwrap();
+        ps.psubscribe(b"q*").unwrap();
+        ps.unsubscribe(b"b").unwrap();
+        ps.punsubscribe(b"q*").unwrap();
+
+        let conn = GenericConnection::new(RespReader::new(io::Cursor::new(vec![])), RespWriter::new(vec![]));
+        assert_eq!(ps.resubscribe(conn).unwrap(), PubSubEvent::Resubscribed {
+            channels: vec![b"a".to_vec()],
+            patterns: vec![b"p*".to_vec()],
+        });
+        let written = ps.conn.raw_parts().0.clone();
+        assert_eq!(written, b"*2\r\n$9\r\nsubscribe\r\n$1\r\na\r\n*2\r\n$10\r\npsubscribe\r\n$2\r\np*\r\n".to_vec());
+
+        ps.unsubscribe_all().unwrap();
+        assert!(ps.channels().is_empty() && ps.patterns().is_empty());
+    }
+
+    #[test]
+    fn test_next_message_timeout() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let server = std::thread::spawn(move || {
+            let (mut s, _) = listener.accept().unwrap();
+            std::thread::sleep(Duration::from_millis(100));
+            s.write_all(b"*3\r\n$9\r\nsubscribe\r\n$3\r\nfoo\r\n:1\r\n").unwrap();
+            std::thread::sleep(Duration::from_millis(300));
+            s.write_all(b"*3\r\n$7\r\nmessage\r\n$3\r\nfoo\r\n$3\r\nbar\r\n").unwrap();
+            s
+        });
+
+        let mut ps = PubSub::new(TcpConnection::connect(&addr, None).unwrap());
+        assert_eq!(ps.try_next().unwrap(), None);
+        assert_eq!(ps.next_message_timeout(Duration::from_millis(200)).unwrap(), None);
+        let msg = ps.next_message_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(msg.unwrap().payload, b"bar".to_vec());
+        server.join().unwrap();
+    }
+
+    #[cfg(feature = "serde_json")]
+    #[test]
+    fn test_next_decoded() {
+        let mut ps = pubsub(b"*3\r\n$7\r\nmessage\r\n$3\r\nfoo\r\n$7\r\n[1,2,3]\r\n*3\r\n$7\r\nmessage\r\n$3\r\nfoo\r\n$3\r\nbar\r\n");
+        let (channel, v) = ps.next_decoded::<Vec<i32>>().unwrap();
+        assert_eq!(channel, b"foo".to_vec());
+        assert_eq!(v, vec![1, 2, 3]);
+        assert!(ps.next_decoded::<Vec<i32>>().is_err());
+    }
+}
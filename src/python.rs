@@ -0,0 +1,376 @@
+// the python module of the python feature, built into a wheel by maturin
+// with pyproject.toml:
+//
+//   import ruis
+//   client = ruis.Client("127.0.0.1:6379")
+//   client.set("k", 42, ex=60)
+//   client.pipeline().cmd("incr", "k").cmd("get", "k").execute()
+//
+// the replies are returned as int, bytes, None and list, the error replies
+// are raised as ruis.ResponseError and the other failures as ruis.Error. the
+// GIL is released while waiting on the network.
+
+use std::io::BufReader;
+use std::net::TcpStream;
+use std::time::Duration;
+
+use pyo3::create_exception;
+use pyo3::exceptions::PyException;
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict, PyList, PyString, PyTuple};
+
+use super::client::Client;
+use super::commands::Commands;
+use super::connection::TcpConnection;
+use super::pipeline::Pipeline;
+use super::pubsub::{Message, PubSub as RawPubSub};
+use super::types::{RespValue, RuisError};
+
+create_exception!(ruis, Error, PyException);
+create_exception!(ruis, ResponseError, Error);
+
+// how often a blocked PubSub iteration checks for Ctrl-C.
+const SIGNAL_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+fn py_err(e: RuisError) -> PyErr {
+    match e {
+        RuisError::ServerError(msg) => ResponseError::new_err(msg),
+        e => Error::new_err(e.to_string()),
+    }
+}
+
+// the arguments are taken as bytes, str, or anything str() makes sense of,
+// like the numbers.
+fn to_arg(obj: &Bound<'_, PyAny>) -> PyResult<Vec<u8>> {
+    if let Ok(b) = obj.cast::<PyBytes>() {
+        return Ok(b.as_bytes().to_vec());
+    }
+    if let Ok(s) = obj.cast::<PyString>() {
+        return Ok(s.to_str()?.as_bytes().to_vec());
+    }
+    Ok(obj.str()?.to_str()?.as_bytes().to_vec())
+}
+
+fn to_args(name: &str, args: &Bound<'_, PyTuple>) -> PyResult<Vec<Vec<u8>>> {
+    let mut cmd = vec![name.as_bytes().to_vec()];
+    for arg in args.iter() {
+        cmd.push(to_arg(&arg)?);
+    }
+    Ok(cmd)
+}
+
+fn to_py(py: Python<'_>, v: RespValue) -> PyResult<Py<PyAny>> {
+    Ok(match v {
+        RespValue::Int(n) => n.into_pyobject(py)?.into_any().unbind(),
+        RespValue::NilBulk | RespValue::NilArray => py.None(),
+        RespValue::Bulk(b) => PyBytes::new(py, &b).into_any().unbind(),
+        RespValue::Array(items) => {
+            let items = items.into_iter().map(|v| to_py(py, v)).collect::<PyResult<Vec<_>>>()?;
+            PyList::new(py, items)?.into_any().unbind()
+        },
+        RespValue::Error(msg) => return Err(ResponseError::new_err(String::from_utf8_lossy(&msg).into_owned())),
+    })
+}
+
+fn message_to_py(py: Python<'_>, msg: Message) -> PyResult<Py<PyAny>> {
+    let d = PyDict::new(py);
+    d.set_item("type", if msg.pattern.is_some() { "pmessage" } else { "message" })?;
+    d.set_item("pattern", msg.pattern.map(|p| PyBytes::new(py, &p)))?;
+    d.set_item("channel", PyBytes::new(py, &msg.channel))?;
+    d.set_item("data", PyBytes::new(py, &msg.payload))?;
+    Ok(d.into_any().unbind())
+}
+
+// the client of a single server, with a pool of connections.
+#[pyclass(name = "Client", module = "ruis")]
+struct PyClient {
+    client: Client,
+    addr: String,
+    password: Option<String>,
+}
+
+impl PyClient {
+    fn call(&mut self, py: Python<'_>, cmd: Vec<Vec<u8>>) -> PyResult<Py<PyAny>> {
+        let client = &mut self.client;
+        let v = py.detach(|| {
+            let args: Vec<&[u8]> = cmd.iter().map(|a| a.as_slice()).collect();
+            client.execute(&args)
+        }).map_err(py_err)?;
+        to_py(py, v)
+    }
+}
+
+#[pymethods]
+impl PyClient {
+    #[new]
+    #[pyo3(signature = (addr = "127.0.0.1:6379", password = None))]
+    fn new(addr: &str, password: Option<String>) -> Self {
+        Self {
+            client: Client::new(addr.to_string(), password.clone()),
+            addr: addr.to_string(),
+            password,
+        }
+    }
+
+    // any command, like client.execute("hincrby", "h", "f", 1).
+    #[pyo3(signature = (name, *args))]
+    fn execute(&mut self, py: Python<'_>, name: &str, args: &Bound<'_, PyTuple>) -> PyResult<Py<PyAny>> {
+        let cmd = to_args(name, args)?;
+        self.call(py, cmd)
+    }
+
+    fn ping(&mut self, py: Python<'_>) -> PyResult<Py<PyAny>> {
+        self.call(py, vec![b"ping".to_vec()])
+    }
+
+    fn get(&mut self, py: Python<'_>, key: &Bound<'_, PyAny>) -> PyResult<Py<PyAny>> {
+        self.call(py, vec![b"get".to_vec(), to_arg(key)?])
+    }
+
+    // returns False if nx or xx kept the value from being set.
+    #[pyo3(signature = (key, value, ex = None, px = None, nx = false, xx = false))]
+    #[allow(clippy::too_many_arguments)]
+    fn set(
+        &mut self,
+        py: Python<'_>,
+        key: &Bound<'_, PyAny>,
+        value: &Bound<'_, PyAny>,
+        ex: Option<u64>,
+        px: Option<u64>,
+        nx: bool,
+        xx: bool,
+    ) -> PyResult<bool> {
+        let mut cmd = vec![b"set".to_vec(), to_arg(key)?, to_arg(value)?];
+        if let Some(secs) = ex {
+            cmd.extend([b"ex".to_vec(), secs.to_string().into_bytes()]);
+        }
+        if let Some(ms) = px {
+            cmd.extend([b"px".to_vec(), ms.to_string().into_bytes()]);
+        }
+        if nx {
+            cmd.push(b"nx".to_vec());
+        }
+        if xx {
+            cmd.push(b"xx".to_vec());
+        }
+        Ok(!self.call(py, cmd)?.is_none(py))
+    }
+
+    #[pyo3(signature = (*keys))]
+    fn delete(&mut self, py: Python<'_>, keys: &Bound<'_, PyTuple>) -> PyResult<Py<PyAny>> {
+        let cmd = to_args("del", keys)?;
+        self.call(py, cmd)
+    }
+
+    #[pyo3(signature = (*keys))]
+    fn exists(&mut self, py: Python<'_>, keys: &Bound<'_, PyTuple>) -> PyResult<Py<PyAny>> {
+        let cmd = to_args("exists", keys)?;
+        self.call(py, cmd)
+    }
+
+    #[pyo3(signature = (key, amount = 1))]
+    fn incr(&mut self, py: Python<'_>, key: &Bound<'_, PyAny>, amount: i64) -> PyResult<Py<PyAny>> {
+        self.call(py, vec![b"incrby".to_vec(), to_arg(key)?, amount.to_string().into_bytes()])
+    }
+
+    fn expire(&mut self, py: Python<'_>, key: &Bound<'_, PyAny>, seconds: u64) -> PyResult<bool> {
+        let v = self.call(py, vec![b"expire".to_vec(), to_arg(key)?, seconds.to_string().into_bytes()])?;
+        v.extract::<i64>(py).map(|n| n == 1)
+    }
+
+    fn ttl(&mut self, py: Python<'_>, key: &Bound<'_, PyAny>) -> PyResult<Py<PyAny>> {
+        self.call(py, vec![b"ttl".to_vec(), to_arg(key)?])
+    }
+
+    fn hget(&mut self, py: Python<'_>, key: &Bound<'_, PyAny>, field: &Bound<'_, PyAny>) -> PyResult<Py<PyAny>> {
+        self.call(py, vec![b"hget".to_vec(), to_arg(key)?, to_arg(field)?])
+    }
+
+    fn hset(
+        &mut self,
+        py: Python<'_>,
+        key: &Bound<'_, PyAny>,
+        field: &Bound<'_, PyAny>,
+        value: &Bound<'_, PyAny>,
+    ) -> PyResult<Py<PyAny>> {
+        self.call(py, vec![b"hset".to_vec(), to_arg(key)?, to_arg(field)?, to_arg(value)?])
+    }
+
+    // the fields and the values as a dict of bytes.
+    fn hgetall(&mut self, py: Python<'_>, key: &Bound<'_, PyAny>) -> PyResult<Py<PyAny>> {
+        let items: Vec<Py<PyAny>> = self.call(py, vec![b"hgetall".to_vec(), to_arg(key)?])?.extract(py)?;
+        let d = PyDict::new(py);
+        for pair in items.chunks(2) {
+            d.set_item(&pair[0], &pair[1])?;
+        }
+        Ok(d.into_any().unbind())
+    }
+
+    #[pyo3(signature = (key, *values))]
+    fn lpush(&mut self, py: Python<'_>, key: &Bound<'_, PyAny>, values: &Bound<'_, PyTuple>) -> PyResult<Py<PyAny>> {
+        let mut cmd = vec![b"lpush".to_vec(), to_arg(key)?];
+        for value in values.iter() {
+            cmd.push(to_arg(&value)?);
+        }
+        self.call(py, cmd)
+    }
+
+    fn lrange(&mut self, py: Python<'_>, key: &Bound<'_, PyAny>, start: i64, stop: i64) -> PyResult<Py<PyAny>> {
+        self.call(py, vec![b"lrange".to_vec(), to_arg(key)?, start.to_string().into_bytes(), stop.to_string().into_bytes()])
+    }
+
+    fn publish(&mut self, py: Python<'_>, channel: &Bound<'_, PyAny>, message: &Bound<'_, PyAny>) -> PyResult<Py<PyAny>> {
+        self.call(py, vec![b"publish".to_vec(), to_arg(channel)?, to_arg(message)?])
+    }
+
+    fn pipeline(slf: Py<Self>) -> PyPipeline {
+        PyPipeline {
+            client: slf,
+            pipeline: Pipeline::new(),
+        }
+    }
+
+    // the subscriptions take a connection of their own.
+    fn pubsub(&self, py: Python<'_>) -> PyResult<PyPubSub> {
+        let (addr, password) = (self.addr.clone(), self.password.clone());
+        let conn = py.detach(|| TcpConnection::connect(&addr, password.as_deref())).map_err(py_err)?;
+        Ok(PyPubSub {
+            pubsub: RawPubSub::new(conn),
+        })
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Client({:?})", self.addr)
+    }
+}
+
+// the commands queued are sent at once by execute(), which returns the
+// replies in order and raises on the first error reply.
+#[pyclass(name = "Pipeline", module = "ruis")]
+struct PyPipeline {
+    client: Py<PyClient>,
+    pipeline: Pipeline,
+}
+
+#[pymethods]
+impl PyPipeline {
+    #[pyo3(signature = (name, *args))]
+    fn cmd<'py>(mut slf: PyRefMut<'py, Self>, name: &str, args: &Bound<'_, PyTuple>) -> PyResult<PyRefMut<'py, Self>> {
+        let cmd = to_args(name, args)?;
+        let args: Vec<&[u8]> = cmd.iter().map(|a| a.as_slice()).collect();
+        slf.pipeline.cmd(&args);
+        Ok(slf)
+    }
+
+    fn execute(&mut self, py: Python<'_>) -> PyResult<Py<PyAny>> {
+        let mut client = self.client.borrow_mut(py);
+        let (client, pipeline) = (&mut client.client, &self.pipeline);
+        let replies = py.detach(|| client.execute_pipeline(pipeline)).map_err(py_err)?;
+        self.pipeline.clear();
+        to_py(py, RespValue::Array(replies))
+    }
+
+    fn __len__(&self) -> usize {
+        self.pipeline.len()
+    }
+}
+
+// iterating blocks until the next message, get_message() waits up to the
+// timeout and returns None if no message arrives.
+#[pyclass(name = "PubSub", module = "ruis")]
+struct PyPubSub {
+    pubsub: RawPubSub<TcpStream, BufReader<TcpStream>>,
+}
+
+#[pymethods]
+impl PyPubSub {
+    #[pyo3(signature = (*channels))]
+    fn subscribe(&mut self, channels: &Bound<'_, PyTuple>) -> PyResult<()> {
+        for channel in channels.iter() {
+            self.pubsub.subscribe(&to_arg(&channel)?).map_err(py_err)?;
+        }
+        Ok(())
+    }
+
+    #[pyo3(signature = (*patterns))]
+    fn psubscribe(&mut self, patterns: &Bound<'_, PyTuple>) -> PyResult<()> {
+        for pattern in patterns.iter() {
+            self.pubsub.psubscribe(&to_arg(&pattern)?).map_err(py_err)?;
+        }
+        Ok(())
+    }
+
+    #[pyo3(signature = (timeout = 0.0))]
+    fn get_message(&mut self, py: Python<'_>, timeout: f64) -> PyResult<Option<Py<PyAny>>> {
+        let pubsub = &mut self.pubsub;
+        let timeout = Duration::from_secs_f64(timeout.max(0.0));
+        match py.detach(|| pubsub.next_message_timeout(timeout)).map_err(py_err)? {
+            Some(msg) => message_to_py(py, msg).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&mut self, py: Python<'_>) -> PyResult<Py<PyAny>> {
+        loop {
+            if let Some(msg) = self.get_message(py, SIGNAL_CHECK_INTERVAL.as_secs_f64())? {
+                return Ok(msg);
+            }
+            py.check_signals()?;
+        }
+    }
+}
+
+#[pymodule]
+fn ruis(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyClient>()?;
+    m.add_class::<PyPipeline>()?;
+    m.add_class::<PyPubSub>()?;
+    m.add("Error", m.py().get_type::<Error>())?;
+    m.add("ResponseError", m.py().get_type::<ResponseError>())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CString;
+    use super::*;
+    use super::super::testing::TestServer;
+
+    #[test]
+    fn test_python_module() {
+        let server = TestServer::new();
+        Python::initialize();
+        Python::attach(|py| {
+            let module = PyModule::new(py, "ruis").unwrap();
+            ruis(&module).unwrap();
+            let globals = PyDict::new(py);
+            globals.set_item("ruis", module).unwrap();
+            globals.set_item("addr", server.addr()).unwrap();
+            let script = CString::new(r#"
+client = ruis.Client(addr)
+assert client.set("k", 41)
+assert client.incr("k") == 42
+assert client.get(b"k") == b"42"
+assert client.get("missing") is None
+assert not client.set("k", 1, nx=True)
+client.hset("h", "f", "v")
+assert client.hgetall("h") == {b"f": b"v"}
+try:
+    client.execute("hlen", "k")
+    raise AssertionError("no error")
+except ruis.ResponseError as e:
+    assert "WRONGTYPE" in str(e)
+pipe = client.pipeline()
+assert pipe.cmd("incr", "k").cmd("get", "k").execute() == [43, b"43"]
+assert len(pipe) == 0
+
+assert client.pubsub().get_message(0.01) is None
+"#).unwrap();
+            py.run(&script, Some(&globals), None).unwrap();
+        });
+    }
+}
@@ -0,0 +1,41 @@
+// the CRC-64 of the RDB files and the DUMP payloads, the Jones polynomial
+// reflected, with no xor on the input nor the output.
+
+const POLY: u64 = 0x95ac_9329_ac4b_c9b5;
+
+const fn make_table() -> [u64; 256] {
+    let mut table = [0u64; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u64;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ POLY } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+static TABLE: [u64; 256] = make_table();
+
+pub(crate) fn update(mut crc: u64, data: &[u8]) -> u64 {
+    for &b in data {
+        crc = TABLE[((crc ^ b as u64) & 0xff) as usize] ^ (crc >> 8);
+    }
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crc64() {
+        // the check value of redis' crc64.c.
+        assert_eq!(update(0, b"123456789"), 0xe9c6_d914_c4b8_d9ca);
+        assert_eq!(update(update(0, b"1234"), b"56789"), 0xe9c6_d914_c4b8_d9ca);
+    }
+}
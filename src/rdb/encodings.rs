@@ -0,0 +1,247 @@
+// the compact encodings of the small collections, stored as a single string
+// in the RDB: ziplist, listpack, intset and zipmap, and the LZF compression
+// of the strings. the integers are returned as their decimal strings, the
+// way redis returns them.
+
+use std::convert::TryInto;
+
+use super::super::types::RuisError;
+
+fn corrupt(what: &str) -> RuisError {
+    RuisError::ParseFailed(format!("rdb: corrupt {}", what))
+}
+
+// a cursor over the bytes of an encoded value, failing on the reads past
+// its end instead of panicking.
+struct Bytes<'a> {
+    buf: &'a [u8],
+    pos: usize,
+    what: &'static str,
+}
+
+impl<'a> Bytes<'a> {
+    fn new(buf: &'a [u8], what: &'static str) -> Self {
+        Self { buf, pos: 0, what }
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'a [u8], RuisError> {
+        let end = self.pos.checked_add(n).filter(|&end| end <= self.buf.len()).ok_or_else(|| corrupt(self.what))?;
+        let bs = &self.buf[self.pos..end];
+        self.pos = end;
+        Ok(bs)
+    }
+
+    fn u8(&mut self) -> Result<u8, RuisError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn peek(&self) -> Result<u8, RuisError> {
+        self.buf.get(self.pos).copied().ok_or_else(|| corrupt(self.what))
+    }
+
+    fn le(&mut self, n: usize) -> Result<u64, RuisError> {
+        Ok(self.take(n)?.iter().rev().fold(0u64, |acc, &b| (acc << 8) | b as u64))
+    }
+
+    // a little endian integer of n bytes, sign extended.
+    fn le_signed(&mut self, n: usize) -> Result<i64, RuisError> {
+        let shift = 64 - 8 * n as u32;
+        Ok(((self.le(n)? << shift) as i64) >> shift)
+    }
+}
+
+fn int_bytes(n: i64) -> Vec<u8> {
+    n.to_string().into_bytes()
+}
+
+// zlbytes, zltail and zllen, then the entries each with the length of the
+// one before, up to 0xff.
+pub(crate) fn ziplist(buf: &[u8]) -> Result<Vec<Vec<u8>>, RuisError> {
+    let mut b = Bytes::new(buf, "ziplist");
+    b.take(10)?;
+    let mut items = vec![];
+    while b.peek()? != 0xff {
+        if b.u8()? == 0xfe {
+            b.take(4)?;
+        }
+        let enc = b.u8()?;
+        let item = match enc >> 6 {
+            0 => b.take((enc & 0x3f) as usize)?.to_vec(),
+            1 => {
+                let len = (((enc & 0x3f) as usize) << 8) | b.u8()? as usize;
+                b.take(len)?.to_vec()
+            },
+            2 => {
+                let len = u32::from_be_bytes(b.take(4)?.try_into().unwrap());
+                b.take(len as usize)?.to_vec()
+            },
+            _ => int_bytes(match enc {
+                0xc0 => b.le_signed(2)?,
+                0xd0 => b.le_signed(4)?,
+                0xe0 => b.le_signed(8)?,
+                0xf0 => b.le_signed(3)?,
+                0xfe => b.le_signed(1)?,
+                0xf1..=0xfd => (enc & 0x0f) as i64 - 1,
+                _ => return Err(corrupt("ziplist")),
+            }),
+        };
+        items.push(item);
+    }
+    Ok(items)
+}
+
+// the total bytes and the number of the elements, then the entries each
+// followed by its length backwards, up to 0xff.
+pub(crate) fn listpack(buf: &[u8]) -> Result<Vec<Vec<u8>>, RuisError> {
+    let mut b = Bytes::new(buf, "listpack");
+    b.take(6)?;
+    let mut items = vec![];
+    while b.peek()? != 0xff {
+        let start = b.pos;
+        let enc = b.u8()?;
+        let item = if enc & 0x80 == 0 {
+            int_bytes(enc as i64)
+        } else if enc & 0xc0 == 0x80 {
+            b.take((enc & 0x3f) as usize)?.to_vec()
+        } else if enc & 0xe0 == 0xc0 {
+            let n = (((enc & 0x1f) as i64) << 8) | b.u8()? as i64;
+            int_bytes(if n >= 1 << 12 { n - (1 << 13) } else { n })
+        } else if enc & 0xf0 == 0xe0 {
+            let len = (((enc & 0x0f) as usize) << 8) | b.u8()? as usize;
+            b.take(len)?.to_vec()
+        } else {
+            match enc {
+                0xf0 => {
+                    let len = b.le(4)? as usize;
+                    b.take(len)?.to_vec()
+                },
+                0xf1 => int_bytes(b.le_signed(2)?),
+                0xf2 => int_bytes(b.le_signed(3)?),
+                0xf3 => int_bytes(b.le_signed(4)?),
+                0xf4 => int_bytes(b.le_signed(8)?),
+                _ => return Err(corrupt("listpack")),
+            }
+        };
+        let size = b.pos - start;
+        let backlen = match size {
+            0..=127 => 1,
+            128..=16382 => 2,
+            16383..=2097150 => 3,
+            2097151..=268435454 => 4,
+            _ => 5,
+        };
+        b.take(backlen)?;
+        items.push(item);
+    }
+    Ok(items)
+}
+
+// the width of the integers, their number, then the integers sorted.
+pub(crate) fn intset(buf: &[u8]) -> Result<Vec<Vec<u8>>, RuisError> {
+    let mut b = Bytes::new(buf, "intset");
+    let width = b.le(4)? as usize;
+    if !matches!(width, 2 | 4 | 8) {
+        return Err(corrupt("intset"));
+    }
+    let len = b.le(4)?;
+    (0..len).map(|_| b.le_signed(width).map(int_bytes)).collect()
+}
+
+// the hashes of the versions before 2.6, the keys and the values each with
+// its length, the values with the number of the unused bytes after them.
+pub(crate) fn zipmap(buf: &[u8]) -> Result<Vec<Vec<u8>>, RuisError> {
+    let mut b = Bytes::new(buf, "zipmap");
+    b.u8()?;
+    let mut items = vec![];
+    loop {
+        let len = match b.u8()? {
+            0xff => break,
+            0xfe => b.le(4)? as usize,
+            n => n as usize,
+        };
+        let free = if items.len() % 2 == 1 { b.u8()? as usize } else { 0 };
+        items.push(b.take(len)?.to_vec());
+        b.take(free)?;
+    }
+    Ok(items)
+}
+
+pub(crate) fn lzf_decompress(input: &[u8], out_len: usize) -> Result<Vec<u8>, RuisError> {
+    let mut out = Vec::with_capacity(out_len.min(1 << 20));
+    let mut b = Bytes::new(input, "lzf string");
+    while b.pos < input.len() {
+        let ctrl = b.u8()? as usize;
+        if ctrl < 32 {
+            out.extend_from_slice(b.take(ctrl + 1)?);
+        } else {
+            let mut len = ctrl >> 5;
+            if len == 7 {
+                len += b.u8()? as usize;
+            }
+            let back = ((ctrl & 0x1f) << 8) + b.u8()? as usize + 1;
+            let start = out.len().checked_sub(back).ok_or_else(|| corrupt("lzf string"))?;
+            // the reference might overlap the bytes being copied.
+            for i in 0..len + 2 {
+                out.push(out[start + i]);
+            }
+        }
+        if out.len() > out_len {
+            return Err(corrupt("lzf string"));
+        }
+    }
+    if out.len() != out_len {
+        return Err(corrupt("lzf string"));
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strs(items: &[&str]) -> Vec<Vec<u8>> {
+        items.iter().map(|s| s.as_bytes().to_vec()).collect()
+    }
+
+    #[test]
+    fn test_ziplist() {
+        let mut zl = vec![0; 10];
+        zl.extend_from_slice(&[0x00, 0x03, b'a', b'b', b'c']);
+        zl.extend_from_slice(&[0x05, 0xf3]);
+        zl.extend_from_slice(&[0x02, 0xc0, 0x30, 0xf8]);
+        zl.extend_from_slice(&[0x04, 0xfe, 0x80]);
+        zl.push(0xff);
+        assert_eq!(ziplist(&zl).unwrap(), strs(&["abc", "2", "-2000", "-128"]));
+        assert!(ziplist(&zl[..zl.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_listpack() {
+        let mut lp = vec![0; 6];
+        lp.extend_from_slice(&[0x07, 0x01]);
+        lp.extend_from_slice(&[0x82, b'h', b'i', 0x03]);
+        lp.extend_from_slice(&[0xdf, 0xff, 0x02]);
+        lp.extend_from_slice(&[0xf1, 0x10, 0x27, 0x03]);
+        lp.push(0xff);
+        assert_eq!(listpack(&lp).unwrap(), strs(&["7", "hi", "-1", "10000"]));
+    }
+
+    #[test]
+    fn test_intset() {
+        let is = [2, 0, 0, 0, 2, 0, 0, 0, 0xff, 0xff, 0x10, 0x00];
+        assert_eq!(intset(&is).unwrap(), strs(&["-1", "16"]));
+        assert!(intset(&is[..10]).is_err());
+    }
+
+    #[test]
+    fn test_zipmap() {
+        let zm = [2, 1, b'a', 2, 1, b'x', b'y', 0, 0xff];
+        assert_eq!(zipmap(&zm).unwrap(), strs(&["a", "xy"]));
+    }
+
+    #[test]
+    fn test_lzf() {
+        assert_eq!(lzf_decompress(&[0x00, b'a', 0xe0, 0x00, 0x00], 10).unwrap(), b"aaaaaaaaaa".to_vec());
+        assert!(lzf_decompress(&[0x00, b'a', 0x20, 0x05], 4).is_err());
+    }
+}
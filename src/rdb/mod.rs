@@ -0,0 +1,596 @@
+// a streaming parser of the RDB files, the snapshots written by SAVE and
+// BGSAVE and sent to the replicas on a full sync, and of the DUMP payloads.
+// the records are read one at a time, so a large file is never held in
+// memory, only the key being read.
+//
+// https://github.com/redis/redis/blob/unstable/src/rdb.h
+
+use std::io::{self, BufReader, Read};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use super::hash::HashFields;
+use super::types::RuisError;
+
+mod crc64;
+mod encodings;
+
+// the newest version understood, the later files are refused rather than
+// misread.
+const MAX_VERSION: u32 = 12;
+
+const MODULE_CHARSET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+fn corrupt(msg: &str) -> RuisError {
+    RuisError::ParseFailed(format!("rdb: {}", msg))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RdbValue {
+    String(Vec<u8>),
+    List(Vec<Vec<u8>>),
+    Set(Vec<Vec<u8>>),
+    SortedSet(Vec<(Vec<u8>, f64)>),
+    Hash(HashFields),
+    // the entries are skipped, only the summary of the stream is kept.
+    Stream {
+        length: u64,
+        last_id: (u64, u64),
+        groups: Vec<Vec<u8>>,
+    },
+    // the value of a module type is opaque, it's skipped.
+    Module {
+        name: String,
+        version: u64,
+    },
+}
+
+impl RdbValue {
+    // the type as named by the TYPE command.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            RdbValue::String(_) => "string",
+            RdbValue::List(_) => "list",
+            RdbValue::Set(_) => "set",
+            RdbValue::SortedSet(_) => "zset",
+            RdbValue::Hash(_) => "hash",
+            RdbValue::Stream { .. } => "stream",
+            RdbValue::Module { .. } => "module",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RdbKey {
+    pub db: u64,
+    pub key: Vec<u8>,
+    pub expire_at: Option<SystemTime>,
+    // the seconds since the last access with the LRU eviction policies.
+    pub idle: Option<u64>,
+    // the access frequency with the LFU eviction policies.
+    pub freq: Option<u8>,
+    pub value: RdbValue,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RdbRecord {
+    // the metadata of the file, like redis-ver, ctime or used-mem.
+    Aux { key: Vec<u8>, value: Vec<u8> },
+    SelectDb(u64),
+    // the number of the keys, and of the keys with an expiry, in the db.
+    ResizeDb { keys: u64, expires: u64 },
+    Key(RdbKey),
+    // the code of a library of functions, as given to FUNCTION LOAD.
+    Function(Vec<u8>),
+}
+
+enum Length {
+    Len(u64),
+    // a string stored as an integer or compressed.
+    Encoded(u8),
+}
+
+// the reader of the file, with the checksum of the bytes read so far.
+struct Input<R> {
+    inner: R,
+    crc: u64,
+}
+
+impl<R: Read> Input<R> {
+    fn new(inner: R) -> Self {
+        Self { inner, crc: 0 }
+    }
+
+    fn read_exact(&mut self, buf: &mut [u8]) -> Result<(), RuisError> {
+        self.inner.read_exact(buf).map_err(eof)?;
+        self.crc = crc64::update(self.crc, buf);
+        Ok(())
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], RuisError> {
+        let mut buf = [0u8; N];
+        self.read_exact(&mut buf)?;
+        Ok(buf)
+    }
+
+    fn u8(&mut self) -> Result<u8, RuisError> {
+        Ok(self.array::<1>()?[0])
+    }
+
+    fn bytes(&mut self, n: u64) -> Result<Vec<u8>, RuisError> {
+        // the length is not trusted for the allocation, a corrupt one fails
+        // at the end of the file instead.
+        let mut buf = Vec::with_capacity(n.min(1 << 16) as usize);
+        (&mut self.inner).take(n).read_to_end(&mut buf)?;
+        if buf.len() as u64 != n {
+            return Err(corrupt("unexpected end of file"));
+        }
+        self.crc = crc64::update(self.crc, &buf);
+        Ok(buf)
+    }
+
+    fn length(&mut self) -> Result<Length, RuisError> {
+        let b = self.u8()?;
+        Ok(match b >> 6 {
+            0 => Length::Len((b & 0x3f) as u64),
+            1 => Length::Len((((b & 0x3f) as u64) << 8) | self.u8()? as u64),
+            2 => match b {
+                0x80 => Length::Len(u32::from_be_bytes(self.array()?) as u64),
+                0x81 => Length::Len(u64::from_be_bytes(self.array()?)),
+                _ => return Err(corrupt(&format!("bad length {:#x}", b))),
+            },
+            _ => Length::Encoded(b & 0x3f),
+        })
+    }
+
+    fn len(&mut self) -> Result<u64, RuisError> {
+        match self.length()? {
+            Length::Len(n) => Ok(n),
+            Length::Encoded(_) => Err(corrupt("encoded string where a length was expected")),
+        }
+    }
+
+    fn string(&mut self) -> Result<Vec<u8>, RuisError> {
+        match self.length()? {
+            Length::Len(n) => self.bytes(n),
+            Length::Encoded(0) => Ok((self.u8()? as i8).to_string().into_bytes()),
+            Length::Encoded(1) => Ok(i16::from_le_bytes(self.array()?).to_string().into_bytes()),
+            Length::Encoded(2) => Ok(i32::from_le_bytes(self.array()?).to_string().into_bytes()),
+            Length::Encoded(3) => {
+                let clen = self.len()?;
+                let ulen = self.len()?;
+                let data = self.bytes(clen)?;
+                encodings::lzf_decompress(&data, ulen as usize)
+            },
+            Length::Encoded(enc) => Err(corrupt(&format!("bad string encoding {}", enc))),
+        }
+    }
+
+    fn millis(&mut self) -> Result<u64, RuisError> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+
+    fn stream_id(&mut self) -> Result<(u64, u64), RuisError> {
+        Ok((self.len()?, self.len()?))
+    }
+
+    // the scores of the sorted sets before redis 3.2, as text.
+    fn old_double(&mut self) -> Result<f64, RuisError> {
+        match self.u8()? {
+            253 => Ok(f64::NAN),
+            254 => Ok(f64::INFINITY),
+            255 => Ok(f64::NEG_INFINITY),
+            n => parse_score(&self.bytes(n as u64)?),
+        }
+    }
+
+    fn strings(&mut self) -> Result<Vec<Vec<u8>>, RuisError> {
+        let n = self.len()?;
+        let mut items = Vec::with_capacity(n.min(1024) as usize);
+        for _ in 0..n {
+            items.push(self.string()?);
+        }
+        Ok(items)
+    }
+
+    fn value(&mut self, t: u8) -> Result<RdbValue, RuisError> {
+        Ok(match t {
+            0 => RdbValue::String(self.string()?),
+            1 => RdbValue::List(self.strings()?),
+            2 => RdbValue::Set(self.strings()?),
+            3 | 5 => {
+                let n = self.len()?;
+                let mut items = Vec::with_capacity(n.min(1024) as usize);
+                for _ in 0..n {
+                    let member = self.string()?;
+                    let score = if t == 3 { self.old_double()? } else { f64::from_le_bytes(self.array()?) };
+                    items.push((member, score));
+                }
+                RdbValue::SortedSet(items)
+            },
+            4 => {
+                // the number of the fields, each followed by its value.
+                let n = self.len()?;
+                let mut fields = Vec::with_capacity(n.min(1024) as usize);
+                for _ in 0..n {
+                    fields.push((self.string()?, self.string()?));
+                }
+                RdbValue::Hash(fields)
+            },
+            6 => return Err(corrupt("the module values of redis 4.0 release candidates are not supported")),
+            7 => {
+                let id = self.len()?;
+                self.skip_module_value()?;
+                RdbValue::Module { name: module_name(id), version: id & 0x3ff }
+            },
+            9 => RdbValue::Hash(pairs(encodings::zipmap(&self.string()?)?)?),
+            10 => RdbValue::List(encodings::ziplist(&self.string()?)?),
+            11 => RdbValue::Set(encodings::intset(&self.string()?)?),
+            12 => RdbValue::SortedSet(scored(encodings::ziplist(&self.string()?)?)?),
+            13 => RdbValue::Hash(pairs(encodings::ziplist(&self.string()?)?)?),
+            14 => {
+                let n = self.len()?;
+                let mut items = vec![];
+                for _ in 0..n {
+                    items.extend(encodings::ziplist(&self.string()?)?);
+                }
+                RdbValue::List(items)
+            },
+            15 | 19 | 21 => self.stream(t)?,
+            16 => RdbValue::Hash(pairs(encodings::listpack(&self.string()?)?)?),
+            17 => RdbValue::SortedSet(scored(encodings::listpack(&self.string()?)?)?),
+            18 => {
+                let n = self.len()?;
+                let mut items = vec![];
+                for _ in 0..n {
+                    let container = self.len()?;
+                    let node = self.string()?;
+                    match container {
+                        // a single large element.
+                        1 => items.push(node),
+                        2 => items.extend(encodings::listpack(&node)?),
+                        _ => return Err(corrupt(&format!("bad quicklist container {}", container))),
+                    }
+                }
+                RdbValue::List(items)
+            },
+            20 => RdbValue::Set(encodings::listpack(&self.string()?)?),
+            22..=25 => return Err(corrupt("the hashes with the field expiries are not supported")),
+            _ => return Err(corrupt(&format!("unknown value type {}", t))),
+        })
+    }
+
+    fn stream(&mut self, t: u8) -> Result<RdbValue, RuisError> {
+        for _ in 0..self.len()? {
+            // the master id of the node, then the listpack of its entries.
+            self.string()?;
+            self.string()?;
+        }
+        let length = self.len()?;
+        let last_id = self.stream_id()?;
+        if t >= 19 {
+            // the first id, the max deleted id and the entries added.
+            self.stream_id()?;
+            self.stream_id()?;
+            self.len()?;
+        }
+        let n = self.len()?;
+        let mut groups = Vec::with_capacity(n.min(1024) as usize);
+        for _ in 0..n {
+            groups.push(self.string()?);
+            self.stream_id()?;
+            if t >= 19 {
+                // the entries read.
+                self.len()?;
+            }
+            for _ in 0..self.len()? {
+                // the id, the delivery time and count of a pending entry.
+                self.array::<16>()?;
+                self.millis()?;
+                self.len()?;
+            }
+            for _ in 0..self.len()? {
+                self.string()?;
+                // the seen time, and the active time since redis 7.2.
+                self.millis()?;
+                if t >= 21 {
+                    self.millis()?;
+                }
+                for _ in 0..self.len()? {
+                    self.array::<16>()?;
+                }
+            }
+        }
+        Ok(RdbValue::Stream { length, last_id, groups })
+    }
+
+    // the values saved by the modules, tagged with their types up to the
+    // EOF opcode.
+    fn skip_module_value(&mut self) -> Result<(), RuisError> {
+        loop {
+            match self.len()? {
+                0 => return Ok(()),
+                1 | 2 => {
+                    self.len()?;
+                },
+                3 => {
+                    self.array::<4>()?;
+                },
+                4 => {
+                    self.array::<8>()?;
+                },
+                5 => {
+                    self.string()?;
+                },
+                op => return Err(corrupt(&format!("unknown module opcode {}", op))),
+            }
+        }
+    }
+}
+
+fn eof(err: io::Error) -> RuisError {
+    if err.kind() == io::ErrorKind::UnexpectedEof {
+        corrupt("unexpected end of file")
+    } else {
+        RuisError::IoError(err)
+    }
+}
+
+fn parse_score(s: &[u8]) -> Result<f64, RuisError> {
+    std::str::from_utf8(s).ok().and_then(|s| s.parse().ok())
+        .ok_or_else(|| corrupt(&format!("bad score {:?}", String::from_utf8_lossy(s))))
+}
+
+fn pairs(items: Vec<Vec<u8>>) -> Result<HashFields, RuisError> {
+    if !items.len().is_multiple_of(2) {
+        return Err(corrupt("odd number of the hash fields and values"));
+    }
+    let mut it = items.into_iter();
+    let mut out = vec![];
+    while let (Some(field), Some(value)) = (it.next(), it.next()) {
+        out.push((field, value));
+    }
+    Ok(out)
+}
+
+fn scored(items: Vec<Vec<u8>>) -> Result<Vec<(Vec<u8>, f64)>, RuisError> {
+    pairs(items)?.into_iter().map(|(member, score)| Ok((member, parse_score(&score)?))).collect()
+}
+
+// the 9 characters of the name in the upper 54 bits of the id, the version
+// in the lower 10.
+fn module_name(id: u64) -> String {
+    (0..9).rev().map(|i| MODULE_CHARSET[((id >> (10 + 6 * i)) & 63) as usize] as char).collect()
+}
+
+pub struct RdbParser<R> {
+    input: Input<BufReader<R>>,
+    version: u32,
+    db: u64,
+    done: bool,
+}
+
+impl<R: Read> RdbParser<R> {
+    // reads the header, the records follow with next_record.
+    pub fn new(r: R) -> Result<Self, RuisError> {
+        let mut input = Input::new(BufReader::new(r));
+        let header = input.array::<9>()?;
+        if &header[..5] != b"REDIS" {
+            return Err(corrupt("not an rdb file"));
+        }
+        let version = std::str::from_utf8(&header[5..]).ok().and_then(|s| s.parse().ok())
+            .ok_or_else(|| corrupt(&format!("bad version {:?}", String::from_utf8_lossy(&header[5..]))))?;
+        if version > MAX_VERSION {
+            return Err(corrupt(&format!("version {} is not supported", version)));
+        }
+        Ok(Self { input, version, db: 0, done: false })
+    }
+
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    // the next record, None at the end of the file. the checksum at the end
+    // is verified, unless it's 0 with rdbchecksum off.
+    pub fn next_record(&mut self) -> Result<Option<RdbRecord>, RuisError> {
+        if self.done {
+            return Ok(None);
+        }
+        let (mut expire_at, mut idle, mut freq) = (None, None, None);
+        loop {
+            match self.input.u8()? {
+                0xff => {
+                    self.done = true;
+                    if self.version >= 5 {
+                        let expected = self.input.crc;
+                        let crc = u64::from_le_bytes(self.input.array()?);
+                        if crc != 0 && crc != expected {
+                            return Err(corrupt("checksum mismatch"));
+                        }
+                    }
+                    return Ok(None);
+                },
+                0xfe => {
+                    self.db = self.input.len()?;
+                    return Ok(Some(RdbRecord::SelectDb(self.db)));
+                },
+                0xfd => expire_at = Some(UNIX_EPOCH + Duration::from_secs(u32::from_le_bytes(self.input.array()?) as u64)),
+                0xfc => expire_at = Some(UNIX_EPOCH + Duration::from_millis(self.input.millis()?)),
+                0xfb => {
+                    let keys = self.input.len()?;
+                    let expires = self.input.len()?;
+                    return Ok(Some(RdbRecord::ResizeDb { keys, expires }));
+                },
+                0xfa => {
+                    let key = self.input.string()?;
+                    let value = self.input.string()?;
+                    return Ok(Some(RdbRecord::Aux { key, value }));
+                },
+                0xf9 => freq = Some(self.input.u8()?),
+                0xf8 => idle = Some(self.input.len()?),
+                0xf7 => {
+                    // the module id, the opcode of the when and the when.
+                    self.input.len()?;
+                    self.input.len()?;
+                    self.input.len()?;
+                    self.input.skip_module_value()?;
+                },
+                0xf6 => return Err(corrupt("the functions of redis 7.0 release candidates are not supported")),
+                0xf5 => return Ok(Some(RdbRecord::Function(self.input.string()?))),
+                0xf4 => {
+                    // the slot, the number of its keys and of its expiries.
+                    self.input.len()?;
+                    self.input.len()?;
+                    self.input.len()?;
+                },
+                t => {
+                    let key = self.input.string()?;
+                    let value = self.input.value(t)?;
+                    return Ok(Some(RdbRecord::Key(RdbKey { db: self.db, key, expire_at, idle, freq, value })));
+                },
+            }
+        }
+    }
+}
+
+impl<R: Read> Iterator for RdbParser<R> {
+    type Item = Result<RdbRecord, RuisError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let record = self.next_record();
+        if record.is_err() {
+            // the position in the file is lost after an error.
+            self.done = true;
+        }
+        record.transpose()
+    }
+}
+
+// parses the payload returned by DUMP: the type, the value, the version of
+// the RDB, then the checksum of all that.
+pub fn parse_dump(payload: &[u8]) -> Result<RdbValue, RuisError> {
+    if payload.len() < 11 {
+        return Err(corrupt("dump payload too short"));
+    }
+    let (data, crc) = payload.split_at(payload.len() - 8);
+    if crc64::update(0, data) != u64::from_le_bytes([crc[0], crc[1], crc[2], crc[3], crc[4], crc[5], crc[6], crc[7]]) {
+        return Err(corrupt("checksum mismatch"));
+    }
+    let (body, version) = data.split_at(data.len() - 2);
+    let version = u16::from_le_bytes([version[0], version[1]]) as u32;
+    if version > MAX_VERSION {
+        return Err(corrupt(&format!("version {} is not supported", version)));
+    }
+    let mut input = Input::new(body);
+    let t = input.u8()?;
+    let value = input.value(t)?;
+    if !input.inner.is_empty() {
+        return Err(corrupt("trailing bytes after the dumped value"));
+    }
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_crc(mut data: Vec<u8>) -> Vec<u8> {
+        let crc = crc64::update(0, &data);
+        data.extend_from_slice(&crc.to_le_bytes());
+        data
+    }
+
+    fn string(s: &[u8]) -> Vec<u8> {
+        let mut out = vec![s.len() as u8];
+        out.extend_from_slice(s);
+        out
+    }
+
+    #[test]
+    fn test_parse_rdb() {
+        let mut rdb = b"REDIS0011".to_vec();
+        rdb.push(0xfa);
+        rdb.extend(string(b"redis-ver"));
+        rdb.extend(string(b"7.2.4"));
+        rdb.extend_from_slice(&[0xfe, 0x02, 0xfb, 0x03, 0x01]);
+        // a string with an expiry, its key an integer.
+        rdb.push(0xfc);
+        rdb.extend_from_slice(&1700000000123u64.to_le_bytes());
+        rdb.extend_from_slice(&[0x00, 0xc0, 0x7b]);
+        rdb.extend(string(b"v"));
+        // a set as an intset, with the LFU frequency.
+        rdb.extend_from_slice(&[0xf9, 0x05, 0x0b]);
+        rdb.extend(string(b"s"));
+        rdb.extend(string(&[2, 0, 0, 0, 2, 0, 0, 0, 0x01, 0x00, 0x02, 0x00]));
+        // a sorted set with binary scores and an LZF compressed member.
+        rdb.push(0x05);
+        rdb.extend(string(b"z"));
+        rdb.extend_from_slice(&[0x01, 0xc3, 0x05, 0x0a, 0x00, b'a', 0xe0, 0x00, 0x00]);
+        rdb.extend_from_slice(&1.5f64.to_le_bytes());
+        rdb.push(0xff);
+        let rdb = with_crc(rdb);
+
+        let mut parser = RdbParser::new(&rdb[..]).unwrap();
+        assert_eq!(parser.version(), 11);
+        let records: Vec<RdbRecord> = parser.by_ref().collect::<Result<_, _>>().unwrap();
+        assert_eq!(records, vec![
+            RdbRecord::Aux { key: b"redis-ver".to_vec(), value: b"7.2.4".to_vec() },
+            RdbRecord::SelectDb(2),
+            RdbRecord::ResizeDb { keys: 3, expires: 1 },
+            RdbRecord::Key(RdbKey {
+                db: 2,
+                key: b"123".to_vec(),
+                expire_at: Some(UNIX_EPOCH + Duration::from_millis(1700000000123)),
+                idle: None,
+                freq: None,
+                value: RdbValue::String(b"v".to_vec()),
+            }),
+            RdbRecord::Key(RdbKey {
+                db: 2,
+                key: b"s".to_vec(),
+                expire_at: None,
+                idle: None,
+                freq: Some(5),
+                value: RdbValue::Set(vec![b"1".to_vec(), b"2".to_vec()]),
+            }),
+            RdbRecord::Key(RdbKey {
+                db: 2,
+                key: b"z".to_vec(),
+                expire_at: None,
+                idle: None,
+                freq: None,
+                value: RdbValue::SortedSet(vec![(b"aaaaaaaaaa".to_vec(), 1.5)]),
+            }),
+        ]);
+        assert!(parser.next().is_none());
+
+        let mut corrupted = rdb.clone();
+        corrupted[20] ^= 1;
+        assert!(RdbParser::new(&corrupted[..]).unwrap().any(|r| r.is_err()));
+        assert!(RdbParser::new(&rdb[..rdb.len() - 12]).unwrap().last().unwrap().is_err());
+        assert!(RdbParser::new(&b"REDIS0099"[..]).is_err());
+    }
+
+    #[test]
+    fn test_parse_dump() {
+        let mut payload = vec![0x0e, 0x01];
+        let mut zl = vec![0; 10];
+        zl.extend_from_slice(&[0x00, 0x01, b'a', 0x03, 0xf3, 0xff]);
+        payload.extend(string(&zl));
+        payload.extend_from_slice(&[11, 0]);
+        let payload = with_crc(payload);
+        let value = parse_dump(&payload).unwrap();
+        assert_eq!(value, RdbValue::List(vec![b"a".to_vec(), b"2".to_vec()]));
+        assert_eq!(value.kind(), "list");
+
+        let mut corrupted = payload.clone();
+        corrupted[3] ^= 1;
+        assert!(parse_dump(&corrupted).is_err());
+        assert_eq!(module_name((0x3f << 58) | 7), "_AAAAAAAA");
+
+        let mut payload = vec![0x04, 0x01];
+        payload.extend(string(b"f"));
+        payload.extend(string(b"v"));
+        payload.extend_from_slice(&[11, 0]);
+        assert_eq!(parse_dump(&with_crc(payload)).unwrap(), RdbValue::Hash(vec![(b"f".to_vec(), b"v".to_vec())]));
+    }
+}
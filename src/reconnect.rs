@@ -0,0 +1,454 @@
+use std::fmt;
+use std::io::{self, BufReader};
+use std::net::TcpStream;
+use std::thread;
+use std::time::Duration;
+
+use super::commands::Commands;
+use super::connection::{TcpConnection, redacted};
+use super::pipeline::Pipeline;
+use super::pubsub::{PubSub, PubSubEvent};
+use super::retry::Backoff;
+use super::types::{RespValue, RuisError};
+
+const DEFAULT_MAX_RETRIES: usize = 3;
+
+pub struct ReconnectingConnectionBuilder {
+    addr: String,
+    password: Option<String>,
+    db: Option<i64>,
+    max_retries: usize,
+    backoff: Backoff,
+}
+
+impl fmt::Debug for ReconnectingConnectionBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReconnectingConnectionBuilder")
+            .field("addr", &self.addr)
+            .field("password", &redacted(&self.password))
+            .field("db", &self.db)
+            .field("max_retries", &self.max_retries)
+            .field("backoff", &self.backoff)
+            .finish()
+    }
+}
+
+impl ReconnectingConnectionBuilder {
+    pub fn new(addr: &str) -> Self {
+        Self {
+            addr: addr.to_string(),
+            password: None,
+            db: None,
+            max_retries: DEFAULT_MAX_RETRIES,
+            backoff: Backoff::default(),
+        }
+    }
+
+    pub fn password(mut self, password: &str) -> Self {
+        self.password = Some(password.to_string());
+        self
+    }
+
+    // the database selected on each connection.
+    pub fn db(mut self, db: i64) -> Self {
+        self.db = Some(db);
+        self
+    }
+
+    // how many times a command is retried on a new connection after the
+    // connection dropped.
+    pub fn max_retries(mut self, n: usize) -> Self {
+        self.max_retries = n;
+        self
+    }
+
+    // the wait before the first retry, doubled on each following retry up to
+    // max.
+    pub fn backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.backoff = Backoff::new(initial, max);
+        self
+    }
+
+    pub fn connect(self) -> Result<ReconnectingConnection, RuisError> {
+        let mut conn = ReconnectingConnection {
+            addr: self.addr,
+            password: self.password,
+            db: self.db,
+            max_retries: self.max_retries,
+            backoff: self.backoff,
+            conn: None,
+            opens: 0,
+        };
+        conn.conn()?;
+        Ok(conn)
+    }
+
+    // a subscriber subscribing its channels and patterns again when its
+    // connection drops.
+    pub fn connect_pubsub(self) -> Result<ReconnectingPubSub, RuisError> {
+        let mut conn = self.connect()?;
+        let pubsub = PubSub::new(conn.conn.take().unwrap());
+        Ok(ReconnectingPubSub {
+            conn,
+            pubsub,
+            broken: false,
+        })
+    }
+}
+
+// ReconnectingConnection reconnects when the connection is closed or reset,
+// like on a restart of the server, and retries the command on the new
+// connection. a command might have been run by the server before the
+// connection dropped, so the commands which are not idempotent, like INCR,
+// might run twice.
+pub struct ReconnectingConnection {
+    addr: String,
+    password: Option<String>,
+    db: Option<i64>,
+    max_retries: usize,
+    backoff: Backoff,
+    conn: Option<TcpConnection>,
+    opens: usize,
+}
+
+impl fmt::Debug for ReconnectingConnection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReconnectingConnection")
+            .field("addr", &self.addr)
+            .field("password", &redacted(&self.password))
+            .field("db", &self.db)
+            .field("connected", &self.conn.is_some())
+            .field("reconnects", &self.reconnects())
+            .finish_non_exhaustive()
+    }
+}
+
+// the connection has to be opened again: it dropped, or it could not be
+// opened as the server is not up yet.
+fn is_retriable(e: &RuisError) -> bool {
+    match e {
+        RuisError::IoError(ref err) if err.kind() == io::ErrorKind::ConnectionRefused => true,
+        e => e.is_connection_dropped(),
+    }
+}
+
+impl ReconnectingConnection {
+    pub fn connect(addr: &str, password: Option<&str>) -> Result<ReconnectingConnection, RuisError> {
+        let mut builder = ReconnectingConnectionBuilder::new(addr);
+        if let Some(password) = password {
+            builder = builder.password(password);
+        }
+        builder.connect()
+    }
+
+    pub fn execute(&mut self, cmd: &[&[u8]]) -> Result<RespValue, RuisError> {
+        self.with_retries(|conn| conn.execute(cmd))
+    }
+
+    // the whole pipeline is sent again on a new connection.
+    pub fn execute_pipeline(&mut self, pipeline: &Pipeline) -> Result<Vec<RespValue>, RuisError> {
+        self.with_retries(|conn| conn.execute_pipeline(pipeline))
+    }
+
+    // selects the database now, and on each reconnect.
+    pub fn select(&mut self, db: i64) -> Result<(), RuisError> {
+        self.with_retries(|conn| conn.select(db))?;
+        self.db = Some(db);
+        Ok(())
+    }
+
+    // how many times the connection was opened again.
+    pub fn reconnects(&self) -> usize {
+        self.opens.saturating_sub(1)
+    }
+
+    fn with_retries<T, F>(&mut self, mut f: F) -> Result<T, RuisError>
+        where F: FnMut(&mut TcpConnection) -> Result<T, RuisError> {
+        let mut attempt = 0;
+        loop {
+            let r = self.conn().and_then(&mut f);
+            match r {
+                Err(e) if is_retriable(&e) && attempt < self.max_retries => {
+                    self.conn = None;
+                    thread::sleep(self.backoff.delay(attempt));
+                    attempt += 1;
+                },
+                Err(e) => {
+                    // the reply of a command timed out might still arrive,
+                    // so the connection is not reused after any io error,
+                    // nor after a reply read in part.
+                    if e.leaves_stream_unusable() {
+                        self.conn = None;
+                    }
+                    return Err(e);
+                },
+                Ok(v) => return Ok(v),
+            }
+        }
+    }
+
+    fn conn(&mut self) -> Result<&mut TcpConnection, RuisError> {
+        if self.conn.is_none() {
+            let mut conn = TcpConnection::connect(&self.addr, self.password.as_deref())?;
+            if let Some(db) = self.db {
+                conn.select(db)?;
+            }
+            self.opens += 1;
+            self.conn = Some(conn);
+        }
+        Ok(self.conn.as_mut().unwrap())
+    }
+}
+
+// ReconnectingPubSub opens the connection again when it drops, with the
+// backoff and the retries of ReconnectingConnection, and subscribes the
+// channels and the patterns again, returning a Resubscribed event before the
+// messages on the new connection. the messages published while it was down
+// are lost. a subscription sent on a dropped connection is made on the
+// reconnect. when the retries run out the error is returned, and the next
+// call tries again.
+pub struct ReconnectingPubSub {
+    // the connection is taken over by the pubsub once opened.
+    conn: ReconnectingConnection,
+    pubsub: PubSub<TcpStream, BufReader<TcpStream>>,
+    broken: bool,
+}
+
+impl fmt::Debug for ReconnectingPubSub {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReconnectingPubSub")
+            .field("addr", &self.conn.addr)
+            .field("channels", &self.pubsub.channels().len())
+            .field("patterns", &self.pubsub.patterns().len())
+            .field("connected", &!self.broken)
+            .field("reconnects", &self.reconnects())
+            .finish_non_exhaustive()
+    }
+}
+
+impl ReconnectingPubSub {
+    pub fn subscribe(&mut self, channel: &[u8]) -> Result<(), RuisError> {
+        let r = self.pubsub.subscribe(channel);
+        self.check(r)
+    }
+
+    pub fn psubscribe(&mut self, pattern: &[u8]) -> Result<(), RuisError> {
+        let r = self.pubsub.psubscribe(pattern);
+        self.check(r)
+    }
+
+    pub fn unsubscribe(&mut self, channel: &[u8]) -> Result<(), RuisError> {
+        let r = self.pubsub.unsubscribe(channel);
+        self.check(r)
+    }
+
+    pub fn punsubscribe(&mut self, pattern: &[u8]) -> Result<(), RuisError> {
+        let r = self.pubsub.punsubscribe(pattern);
+        self.check(r)
+    }
+
+    pub fn next_event(&mut self) -> Result<PubSubEvent, RuisError> {
+        if self.broken {
+            return self.reconnect();
+        }
+        match self.pubsub.next_message() {
+            Err(e) if is_retriable(&e) => {
+                self.broken = true;
+                self.reconnect()
+            },
+            r => r.map(PubSubEvent::Message),
+        }
+    }
+
+    // returns None if no event arrives within the timeout.
+    pub fn next_event_timeout(&mut self, timeout: Duration) -> Result<Option<PubSubEvent>, RuisError> {
+        if self.broken {
+            return self.reconnect().map(Some);
+        }
+        match self.pubsub.next_message_timeout(timeout) {
+            Err(e) if is_retriable(&e) => {
+                self.broken = true;
+                self.reconnect().map(Some)
+            },
+            r => r.map(|msg| msg.map(PubSubEvent::Message)),
+        }
+    }
+
+    // how many times the connection was opened again.
+    pub fn reconnects(&self) -> usize {
+        self.conn.reconnects()
+    }
+
+    // the failed sends are returned, the subscriptions are made on the
+    // reconnect all the same.
+    fn check(&mut self, r: Result<(), RuisError>) -> Result<(), RuisError> {
+        if let Err(ref e) = r {
+            if is_retriable(e) {
+                self.broken = true;
+            }
+        }
+        r
+    }
+
+    fn reconnect(&mut self) -> Result<PubSubEvent, RuisError> {
+        let mut attempt = 0;
+        loop {
+            thread::sleep(self.conn.backoff.delay(attempt));
+            attempt += 1;
+            let r = self.conn.conn().map(|_| ()).and_then(|_| {
+                let conn = self.conn.conn.take().unwrap();
+                self.pubsub.resubscribe(conn)
+            });
+            match r {
+                Err(e) if is_retriable(&e) && attempt < self.conn.max_retries => {},
+                Err(e) => return Err(e),
+                Ok(event) => {
+                    self.broken = false;
+                    return Ok(event);
+                },
+            }
+        }
+    }
+}
+
+impl Iterator for ReconnectingPubSub {
+    type Item = Result<PubSubEvent, RuisError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        Some(self.next_event())
+    }
+}
+
+impl Commands for ReconnectingConnection {
+    fn execute(&mut self, cmd: &[&[u8]]) -> Result<RespValue, RuisError> {
+        ReconnectingConnection::execute(self, cmd)
+    }
+
+    fn execute_pipeline(&mut self, pipeline: &Pipeline) -> Result<Vec<RespValue>, RuisError> {
+        ReconnectingConnection::execute_pipeline(self, pipeline)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::net::TcpListener;
+    use std::time::Instant;
+    use super::*;
+    use super::super::pubsub::Message;
+    use super::super::resp::{RespLimits, RespReader};
+    use super::super::testing::TestServer;
+
+    #[test]
+    fn test_reconnect() {
+        let server = TestServer::with_password("secret");
+        let mut conn = ReconnectingConnectionBuilder::new(&server.addr())
+            .password("secret")
+            .backoff(Duration::from_millis(1), Duration::from_millis(10))
+            .connect()
+            .unwrap();
+        conn.execute(&[b"set", b"k", b"v"]).unwrap();
+        // the server closes the connection after QUIT.
+        conn.execute(&[b"quit"]).unwrap();
+        assert_eq!(conn.execute(&[b"get", b"k"]).unwrap(), RespValue::Bulk(b"v".to_vec()));
+        assert_eq!(conn.reconnects(), 1);
+
+        let mut pipeline = Pipeline::new();
+        pipeline.cmd(&[b"quit"]);
+        conn.execute_pipeline(&pipeline).unwrap();
+        pipeline.clear();
+        pipeline.cmd(&[b"get", b"k"]).cmd(&[b"incr", b"n"]);
+        assert_eq!(conn.execute_pipeline(&pipeline).unwrap()[1], RespValue::Int(1));
+        assert_eq!(conn.reconnects(), 2);
+
+        // the database is selected again on the new connection.
+        conn.select(1).unwrap();
+        conn.execute(&[b"quit"]).unwrap();
+        assert_eq!(conn.execute(&[b"get", b"k"]).unwrap(), RespValue::NilBulk);
+        assert_eq!(conn.reconnects(), 3);
+    }
+
+    #[test]
+    fn test_reconnect_limit_exceeded() {
+        let server = TestServer::new();
+        let mut conn = ReconnectingConnectionBuilder::new(&server.addr()).connect().unwrap();
+        conn.execute(&[b"set", b"big", b"0123456789"]).unwrap();
+        conn.conn.as_mut().unwrap().set_resp_limits(RespLimits::new().max_bulk_len(4));
+        assert!(matches!(conn.execute(&[b"get", b"big"]), Err(RuisError::LimitExceeded(_))));
+        // the rest of the bulk is not read as the reply of the next command.
+        assert_eq!(conn.execute(&[b"ping"]).unwrap(), RespValue::Bulk(b"PONG".to_vec()));
+        assert_eq!(conn.reconnects(), 1);
+    }
+
+    #[test]
+    fn test_reconnecting_pubsub() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let server = thread::spawn(move || {
+            let mut cmds = vec![];
+            // the first connection drops after a message, the second one
+            // stays up till the client is done.
+            for reply in [&b"*3\r\n$7\r\nmessage\r\n$1\r\na\r\n$1\r\n1\r\n"[..], b"*4\r\n$8\r\npmessage\r\n$2\r\np*\r\n$2\r\npq\r\n$1\r\n2\r\n"] {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut r = RespReader::new(BufReader::new(stream.try_clone().unwrap()));
+                let n = if cmds.is_empty() { 3 } else { 2 };
+                for _ in 0..n {
+                    cmds.push(r.read().unwrap());
+                }
+                stream.write_all(reply).unwrap();
+                if cmds.len() > 3 {
+                    let _ = r.read();
+                }
+            }
+            cmds
+        });
+
+        let mut ps = ReconnectingConnectionBuilder::new(&addr)
+            .backoff(Duration::from_millis(1), Duration::from_millis(10))
+            .connect_pubsub()
+            .unwrap();
+        ps.subscribe(b"a").unwrap();
+        ps.subscribe(b"b").unwrap();
+        ps.psubscribe(b"p*").unwrap();
+        let msg = Message { channel: b"a".to_vec(), payload: b"1".to_vec(), pattern: None };
+        assert_eq!(ps.next_event().unwrap(), PubSubEvent::Message(msg));
+        assert_eq!(ps.next_event().unwrap(), PubSubEvent::Resubscribed {
+            channels: vec![b"a".to_vec(), b"b".to_vec()],
+            patterns: vec![b"p*".to_vec()],
+        });
+        let msg = Message { channel: b"pq".to_vec(), payload: b"2".to_vec(), pattern: Some(b"p*".to_vec()) };
+        assert_eq!(ps.next_event_timeout(Duration::from_secs(5)).unwrap(), Some(PubSubEvent::Message(msg)));
+        assert_eq!(ps.reconnects(), 1);
+        drop(ps);
+
+        let cmd = |args: &[&[u8]]| RespValue::Array(args.iter().map(|a| RespValue::Bulk(a.to_vec())).collect());
+        assert_eq!(server.join().unwrap(), vec![
+            cmd(&[b"subscribe", b"a"]),
+            cmd(&[b"subscribe", b"b"]),
+            cmd(&[b"psubscribe", b"p*"]),
+            cmd(&[b"subscribe", b"a", b"b"]),
+            cmd(&[b"psubscribe", b"p*"]),
+        ]);
+    }
+
+    #[test]
+    fn test_backoff() {
+        let conn = ReconnectingConnection {
+            addr: "127.0.0.1:1".to_string(),
+            password: None,
+            db: None,
+            max_retries: 3,
+            backoff: Backoff::new(Duration::from_millis(10), Duration::from_millis(50)),
+            conn: None,
+            opens: 0,
+        };
+        let delays: Vec<u128> = (0..3).map(|i| conn.backoff.delay(i).as_millis()).collect();
+        assert_eq!(delays, vec![10, 20, 40]);
+
+        let start = Instant::now();
+        let r = ReconnectingConnectionBuilder::new("127.0.0.1:1").max_retries(2).backoff(Duration::from_millis(20), Duration::from_secs(1)).connect();
+        assert!(r.is_err());
+        // the connect on build is not retried.
+        assert!(start.elapsed() < Duration::from_millis(20));
+    }
+}
@@ -0,0 +1,206 @@
+use std::io;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::commands::Commands;
+use super::script::Script;
+use super::types::{RespValue, RuisError};
+
+// https://redis.io/docs/manual/patterns/distributed-locks/
+
+const DEFAULT_RETRY_COUNT: usize = 3;
+const DEFAULT_RETRY_DELAY: Duration = Duration::from_millis(200);
+
+// the key is only deleted, or its ttl extended, by the holder of the token.
+const UNLOCK_SCRIPT: &str = r#"if redis.call("get", KEYS[1]) == ARGV[1] then return redis.call("del", KEYS[1]) else return 0 end"#;
+const EXTEND_SCRIPT: &str = r#"if redis.call("get", KEYS[1]) == ARGV[1] then return redis.call("pexpire", KEYS[1], ARGV[2]) else return 0 end"#;
+
+// a lock held on the majority of the instances.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lock {
+    pub resource: Vec<u8>,
+    // the random value set on the key, which tells the holder.
+    pub token: String,
+    // the lock is considered lost after it, the ttl minus the time taken
+    // to acquire it and the clock drift.
+    pub valid_until: Instant,
+}
+
+impl Lock {
+    pub fn is_valid(&self) -> bool {
+        Instant::now() < self.valid_until
+    }
+}
+
+// RedLock locks a resource on the independent instances, like the masters of
+// separate deployments, the lock is held once the majority of them are
+// locked. with a single instance it's a plain SET NX lock. an instance
+// failing counts as not locked.
+pub struct RedLock<C: Commands> {
+    instances: Vec<C>,
+    retry_count: usize,
+    retry_delay: Duration,
+    unlock_script: Script,
+    extend_script: Script,
+}
+
+impl<C: Commands> RedLock<C> {
+    pub fn new(instances: Vec<C>) -> Self {
+        Self {
+            instances,
+            retry_count: DEFAULT_RETRY_COUNT,
+            retry_delay: DEFAULT_RETRY_DELAY,
+            unlock_script: Script::new(UNLOCK_SCRIPT),
+            extend_script: Script::new(EXTEND_SCRIPT),
+        }
+    }
+
+    // how many times the lock is tried again after failing, waiting up to
+    // the delay before each try. the waits are random, so the clients
+    // competing for a lock do not keep splitting the instances.
+    pub fn retry(mut self, count: usize, delay: Duration) -> Self {
+        self.retry_count = count;
+        self.retry_delay = delay;
+        self
+    }
+
+    // returns None if the lock is held by another client.
+    pub fn lock(&mut self, resource: &[u8], ttl: Duration) -> Result<Option<Lock>, RuisError> {
+        let token = random_token()?;
+        let ttl_ms = ttl.as_millis().to_string();
+        for attempt in 0..=self.retry_count {
+            if attempt > 0 {
+                thread::sleep(self.retry_delay.mul_f64(random_fraction()?));
+            }
+            let start = Instant::now();
+            let mut locked = 0;
+            for c in self.instances.iter_mut() {
+                if let Ok(RespValue::Bulk(_)) = c.execute(&[b"set", resource, token.as_bytes(), b"nx", b"px", ttl_ms.as_bytes()]) {
+                    locked += 1;
+                }
+            }
+            if let Some(valid_until) = self.valid_until(start, ttl, locked) {
+                return Ok(Some(Lock {
+                    resource: resource.to_vec(),
+                    token,
+                    valid_until,
+                }));
+            }
+            // the instances locked are released right away, not to block the
+            // other clients until the ttl.
+            self.release(resource, &token);
+        }
+        Ok(None)
+    }
+
+    // releases the lock on all the instances, the ones not locked by this
+    // client are left alone.
+    pub fn unlock(&mut self, lock: &Lock) {
+        self.release(&lock.resource, &lock.token);
+    }
+
+    // resets the ttl of the lock, returns false if the lock could not be
+    // extended on the majority of the instances, like after it expired.
+    pub fn extend(&mut self, lock: &mut Lock, ttl: Duration) -> bool {
+        let start = Instant::now();
+        let ttl_ms = ttl.as_millis().to_string();
+        let mut extended = 0;
+        for c in self.instances.iter_mut() {
+            if let Ok(1) = self.extend_script.invoke::<i64, _, _, _>(c, &[&lock.resource], &[lock.token.as_str(), ttl_ms.as_str()]) {
+                extended += 1;
+            }
+        }
+        match self.valid_until(start, ttl, extended) {
+            Some(valid_until) => {
+                lock.valid_until = valid_until;
+                true
+            },
+            None => false,
+        }
+    }
+
+    // the lock is only valid if the majority is locked before the ttl runs
+    // out, allowing for the drift of the clocks of the instances.
+    fn valid_until(&self, start: Instant, ttl: Duration, locked: usize) -> Option<Instant> {
+        let drift = ttl / 100 + Duration::from_millis(2);
+        let elapsed = start.elapsed();
+        if locked > self.instances.len() / 2 && elapsed + drift < ttl {
+            Some(start + ttl - drift)
+        } else {
+            None
+        }
+    }
+
+    fn release(&mut self, resource: &[u8], token: &str) {
+        for c in self.instances.iter_mut() {
+            let _ = self.unlock_script.invoke::<i64, _, _, _>(c, &[resource], &[token]);
+        }
+    }
+}
+
+fn random_bytes<const N: usize>() -> Result<[u8; N], RuisError> {
+    let mut buf = [0u8; N];
+    getrandom::getrandom(&mut buf).map_err(|e| io::Error::other(format!("getrandom: {}", e)))?;
+    Ok(buf)
+}
+
+fn random_token() -> Result<String, RuisError> {
+    Ok(random_bytes::<20>()?.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+// in [0, 1).
+fn random_fraction() -> Result<f64, RuisError> {
+    Ok(u32::from_le_bytes(random_bytes::<4>()?) as f64 / (u32::MAX as f64 + 1.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use super::*;
+    use super::super::connection::{GenericConnection, TcpConnection};
+    use super::super::resp::{RespReader, RespWriter};
+    use super::super::testing::TestServer;
+
+    #[test]
+    fn test_lock() {
+        let servers: Vec<TestServer> = (0..3).map(|_| TestServer::new()).collect();
+        let connect = || servers.iter().map(|s| TcpConnection::connect(&s.addr(), None).unwrap()).collect::<Vec<_>>();
+        let mut redlock = RedLock::new(connect());
+        let lock = redlock.lock(b"res", Duration::from_secs(10)).unwrap().unwrap();
+        assert!(lock.is_valid());
+        assert_eq!(lock.token.len(), 40);
+
+        let mut other = RedLock::new(connect()).retry(1, Duration::from_millis(10));
+        assert_eq!(other.lock(b"res", Duration::from_secs(10)).unwrap(), None);
+        assert!(other.lock(b"res2", Duration::from_secs(10)).unwrap().is_some());
+
+        // the majority is enough.
+        let mut conns = connect();
+        conns[0].execute(&[b"del", b"res"]).unwrap();
+        conns[1].execute(&[b"del", b"res"]).unwrap();
+        let mut redlock = RedLock::new(conns).retry(0, Duration::from_millis(10));
+        assert!(redlock.lock(b"res", Duration::from_secs(10)).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_unlock_extend() {
+        let conn = |replies: &[u8]| GenericConnection::new(RespReader::new(Cursor::new(replies.to_vec())), RespWriter::new(vec![]));
+        // locked, extended, unlocked.
+        let mut redlock = RedLock::new(vec![conn(b"+OK\r\n:1\r\n:1\r\n"), conn(b"+OK\r\n:0\r\n:0\r\n"), conn(b"$-1\r\n:1\r\n:1\r\n")]);
+        let mut lock = redlock.lock(b"res", Duration::from_secs(10)).unwrap().unwrap();
+        let valid_until = lock.valid_until;
+        assert!(redlock.extend(&mut lock, Duration::from_secs(20)));
+        assert!(lock.valid_until > valid_until);
+        redlock.unlock(&lock);
+
+        let written: Vec<String> = redlock.instances.iter_mut().map(|c| String::from_utf8(c.raw_parts().0.clone()).unwrap()).collect();
+        assert!(written[0].contains(&format!("$3\r\nres\r\n$40\r\n{}\r\n$2\r\nnx\r\n$2\r\npx\r\n$5\r\n10000\r\n", lock.token)));
+        assert!(written[0].contains(&format!("$3\r\nres\r\n$40\r\n{}\r\n$5\r\n20000\r\n", lock.token)));
+        assert_eq!(written[0].matches("evalsha").count(), 2);
+
+        // not extended on the majority.
+        let mut redlock = RedLock::new(vec![conn(b"+OK\r\n:0\r\n"), conn(b"+OK\r\n:0\r\n"), conn(b"+OK\r\n:1\r\n")]);
+        let mut lock = redlock.lock(b"res", Duration::from_secs(10)).unwrap().unwrap();
+        assert!(!redlock.extend(&mut lock, Duration::from_secs(20)));
+    }
+}
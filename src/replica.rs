@@ -0,0 +1,410 @@
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::time::{Duration, Instant};
+
+use super::resp::{RespReader, RespWriter};
+use super::types::{RespValue, RuisError};
+
+const DEFAULT_ACK_INTERVAL: Duration = Duration::from_secs(1);
+// the length of the delimiter of the diskless RDB transfers, "$EOF:<mark>".
+const EOF_MARK_LEN: usize = 40;
+
+// how the master answered PSYNC.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SyncMode {
+    // an RDB snapshot follows, then the commands from the offset on.
+    Full { replid: String, offset: u64 },
+    // the commands follow from the offset asked for.
+    Continue { replid: String },
+}
+
+// a write command propagated by the master. the offset is the replication
+// offset after the command, to resume from with PSYNC.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplicationEvent {
+    pub db: u32,
+    pub args: Vec<Vec<u8>>,
+    pub offset: u64,
+}
+
+impl ReplicationEvent {
+    pub fn command(&self) -> &[u8] {
+        self.args.first().map(|a| a.as_slice()).unwrap_or(b"")
+    }
+}
+
+// counts the bytes consumed, which is how far the replication offset moves.
+struct Counting<R> {
+    inner: R,
+    consumed: u64,
+}
+
+impl<R: BufRead> Read for Counting<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.fill_buf()?.read(buf)?;
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl<R: BufRead> BufRead for Counting<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.consumed += amt as u64;
+        self.inner.consume(amt);
+    }
+}
+
+pub struct ReplicaClientBuilder {
+    addr: String,
+    password: Option<String>,
+    listening_port: u16,
+    resume: Option<(String, u64)>,
+    ack_interval: Duration,
+}
+
+impl ReplicaClientBuilder {
+    pub fn new(addr: &str) -> Self {
+        Self {
+            addr: addr.to_string(),
+            password: None,
+            listening_port: 0,
+            resume: None,
+            ack_interval: DEFAULT_ACK_INTERVAL,
+        }
+    }
+
+    pub fn password(mut self, password: &str) -> Self {
+        self.password = Some(password.to_string());
+        self
+    }
+
+    // the port shown for this replica in INFO replication on the master.
+    pub fn listening_port(mut self, port: u16) -> Self {
+        self.listening_port = port;
+        self
+    }
+
+    // asks for the commands after the offset of the replication id, like
+    // after a disconnection. the master falls back to a full sync if the
+    // offset is out of its backlog.
+    pub fn resume(mut self, replid: &str, offset: u64) -> Self {
+        self.resume = Some((replid.to_string(), offset));
+        self
+    }
+
+    // the master drops the replicas not sending REPLCONF ACK for
+    // repl-timeout, the acks are sent as the events are read.
+    pub fn ack_interval(mut self, interval: Duration) -> Self {
+        self.ack_interval = interval;
+        self
+    }
+
+    // the handshake: AUTH, PING, REPLCONF and PSYNC.
+    pub fn connect(self) -> Result<ReplicaClient, RuisError> {
+        let stream = TcpStream::connect(&self.addr)?;
+        stream.set_nodelay(true)?;
+        let mut client = ReplicaClient {
+            r: RespReader::new(Counting { inner: BufReader::new(stream.try_clone()?), consumed: 0 }),
+            w: RespWriter::new(stream),
+            mode: SyncMode::Continue { replid: String::new() },
+            replid: String::new(),
+            base_offset: 0,
+            base_consumed: 0,
+            rdb_pending: false,
+            db: 0,
+            ack_interval: self.ack_interval,
+            last_ack: Instant::now(),
+        };
+        if let Some(ref password) = self.password {
+            if let RespValue::Error(msg) = client.call(&[b"auth", password.as_bytes()])? {
+                return Err(RuisError::Auth(String::from_utf8_lossy(&msg).into_owned()));
+            }
+        }
+        client.call(&[b"ping"])?.into_result()?;
+        let port = self.listening_port.to_string();
+        client.call(&[b"replconf", b"listening-port", port.as_bytes()])?.into_result()?;
+        client.call(&[b"replconf", b"capa", b"eof", b"capa", b"psync2"])?.into_result()?;
+
+        let (replid, offset) = match self.resume {
+            Some((ref replid, offset)) => (replid.clone(), offset.to_string()),
+            None => ("?".to_string(), "-1".to_string()),
+        };
+        let reply = match client.call(&[b"psync", replid.as_bytes(), offset.as_bytes()])?.into_result()? {
+            RespValue::Bulk(line) => String::from_utf8_lossy(&line).into_owned(),
+            v => return Err(RuisError::Unexpected(format!("psync: {:?}", v))),
+        };
+        let parts: Vec<&str> = reply.split(' ').collect();
+        client.mode = match parts.as_slice() {
+            ["FULLRESYNC", replid, offset] => SyncMode::Full {
+                replid: replid.to_string(),
+                offset: offset.parse().map_err(|_| RuisError::Unexpected(format!("psync: {}", reply)))?,
+            },
+            // the replication id is only given if it changed, like on a
+            // failover.
+            ["CONTINUE", replid] => SyncMode::Continue { replid: replid.to_string() },
+            ["CONTINUE"] => SyncMode::Continue { replid: replid.clone() },
+            _ => return Err(RuisError::Unexpected(format!("psync: {}", reply))),
+        };
+        match client.mode {
+            SyncMode::Full { ref replid, offset } => {
+                client.replid = replid.clone();
+                client.base_offset = offset;
+                client.rdb_pending = true;
+            },
+            SyncMode::Continue { ref replid } => {
+                client.replid = replid.clone();
+                client.base_offset = self.resume.map_or(0, |(_, offset)| offset);
+            },
+        }
+        client.base_consumed = client.r.get_ref().consumed;
+        Ok(client)
+    }
+}
+
+// ReplicaClient follows a master like a replica does, for the change data
+// capture. the master takes it as a replica, so it shows in INFO replication
+// and counts for min-replicas-to-write.
+pub struct ReplicaClient {
+    r: RespReader<Counting<BufReader<TcpStream>>>,
+    w: RespWriter<TcpStream>,
+    mode: SyncMode,
+    replid: String,
+    // the offset at base_consumed bytes read.
+    base_offset: u64,
+    base_consumed: u64,
+    rdb_pending: bool,
+    db: u32,
+    ack_interval: Duration,
+    last_ack: Instant,
+}
+
+impl ReplicaClient {
+    pub fn connect(addr: &str, password: Option<&str>) -> Result<ReplicaClient, RuisError> {
+        let mut builder = ReplicaClientBuilder::new(addr);
+        if let Some(password) = password {
+            builder = builder.password(password);
+        }
+        builder.connect()
+    }
+
+    pub fn sync_mode(&self) -> &SyncMode {
+        &self.mode
+    }
+
+    pub fn replid(&self) -> &str {
+        &self.replid
+    }
+
+    // the offset of the commands read so far.
+    pub fn offset(&self) -> u64 {
+        self.base_offset + (self.r.get_ref().consumed - self.base_consumed)
+    }
+
+    fn call(&mut self, cmd: &[&[u8]]) -> Result<RespValue, RuisError> {
+        self.w.write_bulks(cmd)?;
+        self.w.flush()?;
+        self.r.read()
+    }
+
+    // copies the RDB snapshot of a full sync into out, returns its length.
+    // it is skipped if the events are read first.
+    pub fn read_rdb<W: Write>(&mut self, out: &mut W) -> Result<u64, RuisError> {
+        if !self.rdb_pending {
+            return Ok(0);
+        }
+        // the master sends newlines to keep the link alive while it saves the
+        // snapshot.
+        let r = self.r.get_mut();
+        let mut line = vec![];
+        while line.is_empty() || line == b"\n" {
+            line.clear();
+            if r.read_until(b'\n', &mut line)? == 0 {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed").into());
+            }
+        }
+        let header = String::from_utf8_lossy(&line).trim_end().to_string();
+        let n = match header.strip_prefix("$EOF:") {
+            // the diskless transfers end with the mark, not knowing the length
+            // upfront.
+            Some(mark) if mark.len() == EOF_MARK_LEN => copy_until_mark(r, mark.as_bytes(), out)?,
+            Some(_) => return Err(RuisError::ParseFailed(format!("bad rdb header {:?}", header))),
+            None => {
+                let len: u64 = header.strip_prefix('$').and_then(|n| n.parse().ok())
+                    .ok_or_else(|| RuisError::ParseFailed(format!("bad rdb header {:?}", header)))?;
+                let n = io::copy(&mut r.by_ref().take(len), out)?;
+                if n < len {
+                    return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed").into());
+                }
+                n
+            },
+        };
+        self.rdb_pending = false;
+        // the offset counts from the end of the snapshot.
+        self.base_consumed = self.r.get_ref().consumed;
+        Ok(n)
+    }
+
+    // the next write command, the PINGs of the master and its REPLCONF
+    // GETACK are answered internally.
+    pub fn next_event(&mut self) -> Result<ReplicationEvent, RuisError> {
+        if self.rdb_pending {
+            self.read_rdb(&mut io::sink())?;
+        }
+        loop {
+            if self.last_ack.elapsed() >= self.ack_interval {
+                self.ack()?;
+            }
+            let args = match self.r.read()? {
+                RespValue::Array(items) => items.into_iter().map(|v| match v {
+                    RespValue::Bulk(b) => Ok(b),
+                    v => Err(RuisError::Unexpected(format!("replication stream: {:?}", v))),
+                }).collect::<Result<Vec<_>, _>>()?,
+                v => return Err(RuisError::Unexpected(format!("replication stream: {:?}", v))),
+            };
+            let name = args.first().map(|a| a.to_ascii_lowercase()).unwrap_or_default();
+            match name.as_slice() {
+                b"ping" => continue,
+                b"replconf" if args.get(1).is_some_and(|a| a.eq_ignore_ascii_case(b"getack")) => {
+                    self.ack()?;
+                    continue;
+                },
+                b"select" => {
+                    self.db = args.get(1).and_then(|a| std::str::from_utf8(a).ok()?.parse().ok())
+                        .ok_or_else(|| RuisError::Unexpected(format!("replication stream: bad select {:?}", args)))?;
+                    continue;
+                },
+                _ => {},
+            }
+            return Ok(ReplicationEvent {
+                db: self.db,
+                args,
+                offset: self.offset(),
+            });
+        }
+    }
+
+    // tells the master the offset processed.
+    pub fn ack(&mut self) -> Result<(), RuisError> {
+        let offset = self.offset().to_string();
+        self.w.write_bulks(&[b"replconf", b"ack", offset.as_bytes()])?;
+        self.w.flush()?;
+        self.last_ack = Instant::now();
+        Ok(())
+    }
+}
+
+impl Iterator for ReplicaClient {
+    type Item = Result<ReplicationEvent, RuisError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        Some(self.next_event())
+    }
+}
+
+// copies until the mark, which is not copied. the bytes after the mark are
+// left in the reader, as the commands might follow in the same packet.
+fn copy_until_mark<R: BufRead, W: Write>(r: &mut R, mark: &[u8], out: &mut W) -> Result<u64, RuisError> {
+    // the bytes of the last buffer which might be the start of the mark.
+    let mut tail: Vec<u8> = Vec::with_capacity(mark.len());
+    let mut n = 0;
+    loop {
+        let buf = r.fill_buf()?;
+        if buf.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed").into());
+        }
+        let carried = tail.len();
+        tail.extend_from_slice(buf);
+        let len = buf.len();
+        if let Some(pos) = tail.windows(mark.len()).position(|w| w == mark) {
+            out.write_all(&tail[..pos])?;
+            r.consume(pos + mark.len() - carried);
+            return Ok(n + pos as u64);
+        }
+        r.consume(len);
+        let keep = tail.len().min(mark.len() - 1);
+        let flush = tail.len() - keep;
+        out.write_all(&tail[..flush])?;
+        n += flush as u64;
+        tail.drain(..flush);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+    use std::thread;
+    use super::*;
+
+    // a master replying the handshake, then sending the script.
+    fn fake_master(psync_reply: &'static [u8], script: &'static [u8]) -> (String, thread::JoinHandle<Vec<u8>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let handle = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut r = RespReader::new(BufReader::new(stream.try_clone().unwrap()));
+            let mut w = stream;
+            let mut seen = vec![];
+            for _ in 0..4 {
+                if let RespValue::Array(args) = r.read().unwrap() {
+                    if let RespValue::Bulk(ref name) = args[0] {
+                        seen.extend_from_slice(name);
+                        seen.push(b' ');
+                    }
+                }
+                let reply: &[u8] = if seen.ends_with(b"psync ") { psync_reply } else { b"+OK\r\n" };
+                w.write_all(reply).unwrap();
+            }
+            w.write_all(script).unwrap();
+            // the acks.
+            let mut rest = vec![];
+            let _ = r.get_mut().read_to_end(&mut rest);
+            rest
+        });
+        (addr, handle)
+    }
+
+    #[test]
+    fn test_full_sync() {
+        let script = b"\n\n$5\r\nREDIS*1\r\n$4\r\nPING\r\n*2\r\n$6\r\nSELECT\r\n$1\r\n3\r\n*3\r\n$3\r\nset\r\n$1\r\na\r\n$1\r\n1\r\n";
+        let (addr, master) = fake_master(b"+FULLRESYNC 8de1787ba490483314a4d30f1c628bc5025eb761 100\r\n", script);
+        let mut client = ReplicaClientBuilder::new(&addr).ack_interval(Duration::from_secs(3600)).connect().unwrap();
+        assert_eq!(client.sync_mode(), &SyncMode::Full { replid: "8de1787ba490483314a4d30f1c628bc5025eb761".to_string(), offset: 100 });
+
+        let mut rdb = vec![];
+        assert_eq!(client.read_rdb(&mut rdb).unwrap(), 5);
+        assert_eq!(rdb, b"REDIS");
+        let event = client.next_event().unwrap();
+        assert_eq!((event.db, event.command()), (3, &b"set"[..]));
+        // the ping, the select and the set.
+        assert_eq!(event.offset, 100 + 14 + 23 + 27);
+        client.ack().unwrap();
+        drop(client);
+        assert_eq!(master.join().unwrap(), b"*3\r\n$8\r\nreplconf\r\n$3\r\nack\r\n$3\r\n164\r\n");
+    }
+
+    #[test]
+    fn test_diskless_and_continue() {
+        let mark = "x".repeat(EOF_MARK_LEN);
+        let mut out = vec![];
+        let stream = format!("hello worldhello{}*1\r\n", mark);
+        let n = copy_until_mark(&mut stream.as_bytes(), mark.as_bytes(), &mut out).unwrap();
+        assert_eq!((n, out.as_slice()), (16, &b"hello worldhello"[..]));
+        let mut chunked = io::BufReader::with_capacity(7, stream.as_bytes());
+        let mut out = vec![];
+        assert_eq!(copy_until_mark(&mut chunked, mark.as_bytes(), &mut out).unwrap(), 16);
+        let mut rest = vec![];
+        chunked.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, b"*1\r\n");
+
+        let script = b"*2\r\n$3\r\ndel\r\n$1\r\nk\r\n";
+        let (addr, _master) = fake_master(b"+CONTINUE\r\n", script);
+        let mut client = ReplicaClientBuilder::new(&addr).resume("abc", 1000).connect().unwrap();
+        assert_eq!(client.sync_mode(), &SyncMode::Continue { replid: "abc".to_string() });
+        let event = client.next().unwrap().unwrap();
+        assert_eq!((event.args.len(), event.offset), (2, 1000 + 20));
+    }
+}
@@ -1,76 +1,244 @@
-use std::io;
 use std::str::FromStr;
+use std::io;
 use std::io::{BufRead, Read};
-use std::io::{Write};
-use std::error::Error;
+use std::io::Write;
+use std::ops::Range;
+
+use bytes::{BufMut, Bytes, BytesMut};
 
-use super::types::{RespValue, RespError};
+use super::types::{RespFrame, RespValue, RuisError};
 
 // https://redis.io/topics/protocol
 
 pub struct RespReader<R: BufRead> {
-    reader: R
+    reader: R,
+    limits: RespLimits,
 }
 
+// the limits on the replies read, so a peer sending a huge length or a deep
+// nesting, by a bug or on purpose, can not exhaust the memory or the stack.
+// the lines, like of the errors, are limited to max_bulk_len too.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RespLimits {
+    max_bulk_len: usize,
+    max_array_len: usize,
+    max_depth: usize,
+}
+
+impl Default for RespLimits {
+    fn default() -> Self {
+        Self {
+            // the proto-max-bulk-len of redis.
+            max_bulk_len: 512 * 1024 * 1024,
+            max_array_len: i32::MAX as usize,
+            max_depth: 64,
+        }
+    }
+}
+
+impl RespLimits {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn max_bulk_len(mut self, n: usize) -> Self {
+        self.max_bulk_len = n;
+        self
+    }
+
+    pub fn max_array_len(mut self, n: usize) -> Self {
+        self.max_array_len = n;
+        self
+    }
+
+    // the arrays nested in the arrays, a flat array is of depth 1.
+    pub fn max_depth(mut self, n: usize) -> Self {
+        self.max_depth = n;
+        self
+    }
+
+    fn check_bulk_len(&self, n: i64) -> Result<usize, RuisError> {
+        match n {
+            n if n < 0 => Err(RuisError::ParseFailed("malformed length".to_string())),
+            n if n as u64 > self.max_bulk_len as u64 => {
+                Err(RuisError::LimitExceeded(format!("bulk of {} bytes, over {}", n, self.max_bulk_len)))
+            },
+            n => Ok(n as usize),
+        }
+    }
+
+    fn check_array_len(&self, n: i64, depth: usize) -> Result<usize, RuisError> {
+        if depth >= self.max_depth {
+            return Err(RuisError::LimitExceeded(format!("arrays nested deeper than {}", self.max_depth)));
+        }
+        match n {
+            n if n < 0 => Err(RuisError::ParseFailed("malformed length".to_string())),
+            n if n as u64 > self.max_array_len as u64 => {
+                Err(RuisError::LimitExceeded(format!("array of {} elements, over {}", n, self.max_array_len)))
+            },
+            n => Ok(n as usize),
+        }
+    }
+
+    // the longest line, with the type and the CRLF.
+    pub(crate) fn max_line_len(&self) -> usize {
+        self.max_bulk_len.saturating_add(3)
+    }
+}
+
+fn connection_closed() -> RuisError {
+    io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed").into()
+}
+
+// a command or a value is encoded into buf and written at once, instead of a
+// small write per part, each of which is a syscall and maybe a packet on an
+// unbuffered TcpStream.
 pub struct RespWriter<W: Write> {
     writer: W,
+    buf: Vec<u8>,
 }
 
+// the bulks from this length on are not copied into the buffer.
+const LARGE_BULK_LEN: usize = 64 * 1024;
+// the buffer grown beyond it, like by a large MSET, is freed after the write.
+const MAX_KEPT_BUF_LEN: usize = 1024 * 1024;
+
 impl<R: BufRead> RespReader<R> {
     pub fn new(r: R) -> Self {
         Self {
             reader: r,
+            limits: RespLimits::default(),
         }
     }
 
-    pub fn read(&mut self) -> Result<RespValue, RespError> {
-        let line = self.read_line()?;
+    pub fn set_limits(&mut self, limits: RespLimits) {
+        self.limits = limits;
+    }
+
+    pub fn limits(&self) -> RespLimits {
+        self.limits
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.reader
+    }
+
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.reader
+    }
+
+    pub fn read(&mut self) -> Result<RespValue, RuisError> {
+        self.read_value(0)
+    }
+
+    fn read_value(&mut self, depth: usize) -> Result<RespValue, RuisError> {
+        let line = self.read_typed_line()?;
         match line[0] as char {
             ':' => {
                 let n = self.parse_int(&line[1..])?;
-                return Ok(RespValue::Int(n));
+                Ok(RespValue::Int(n))
             },
             '+' => {
-                return Ok(RespValue::Bulk(line[1..].to_vec()));
+                Ok(RespValue::Bulk(line[1..].to_vec()))
             }
             '-' => {
-                return Ok(RespValue::Error(line[1..].to_vec()));
+                Ok(RespValue::Error(line[1..].to_vec()))
             }
             '$' => {
                 let n = self.parse_int(&line[1..])?;
                 if n == -1 {
                     return Ok(RespValue::NilBulk);
-                } else if n < 0 {
-                    return Err(RespError::ParseFailed(format!("malformed length")))
                 }
-                let s = self.read_bulk_string(n as usize)?;
-                return Ok(RespValue::Bulk(s))
+                let n = self.limits.check_bulk_len(n)?;
+                let s = self.read_bulk_string(n)?;
+                Ok(RespValue::Bulk(s))
             }
             '*' => {
                 let n = self.parse_int(&line[1..])?;
                 if n == -1 {
                     return Ok(RespValue::NilArray);
-                } else if n < 0 {
-                    return Err(RespError::ParseFailed(format!("malformed length")))
                 }
-                let arr = self.read_array(n as usize)?;
-                return Ok(RespValue::Array(arr));
+                let n = self.limits.check_array_len(n, depth)?;
+                let arr = self.read_array(n, depth + 1)?;
+                Ok(RespValue::Array(arr))
+            }
+            ch => {
+                Err(RuisError::ParseFailed(format!("unexpected token: {}", ch)))
+            }
+        }
+    }
+
+    // reads a reply like read(), the bulks are read into a buffer shared by
+    // the whole reply and returned as the slices of it.
+    pub fn read_frame(&mut self) -> Result<RespFrame, RuisError> {
+        let mut buf = BytesMut::new();
+        let shape = self.read_shape(&mut buf, 0)?;
+        Ok(shape.into_frame(&buf.freeze()))
+    }
+
+    fn read_shape(&mut self, buf: &mut BytesMut, depth: usize) -> Result<Shape, RuisError> {
+        let line = self.read_typed_line()?;
+        let mut push = |bs: &[u8]| {
+            let start = buf.len();
+            buf.extend_from_slice(bs);
+            start..buf.len()
+        };
+        match line[0] as char {
+            ':' => Ok(Shape::Int(self.parse_int(&line[1..])?)),
+            '+' => Ok(Shape::Bulk(push(&line[1..]))),
+            '-' => Ok(Shape::Error(push(&line[1..]))),
+            '$' => {
+                let n = self.parse_int(&line[1..])?;
+                if n == -1 {
+                    return Ok(Shape::NilBulk);
+                }
+                let n = self.limits.check_bulk_len(n)?;
+                let start = buf.len();
+                self.read_bulk_into(n, &mut (&mut *buf).writer())?;
+                Ok(Shape::Bulk(start..buf.len()))
             }
-            ch @ _ => {
-                Err(RespError::ParseFailed(format!("unexpected token: {}", ch)))
+            '*' => {
+                let n = self.parse_int(&line[1..])?;
+                if n == -1 {
+                    return Ok(Shape::NilArray);
+                }
+                let n = self.limits.check_array_len(n, depth)?;
+                let mut arr = vec![];
+                for _ in 0..n {
+                    arr.push(self.read_shape(buf, depth + 1)?);
+                }
+                Ok(Shape::Array(arr))
+            }
+            ch => {
+                Err(RuisError::ParseFailed(format!("unexpected token: {}", ch)))
             }
         }
     }
 
-    fn read_line(&mut self) -> Result<Vec<u8>, RespError> {
+    // the line starting a value, its first byte telling the type.
+    fn read_typed_line(&mut self) -> Result<Vec<u8>, RuisError> {
+        let line = self.read_line()?;
+        if line.is_empty() {
+            return Err(RuisError::ParseFailed("empty line".to_string()));
+        }
+        Ok(line)
+    }
+
+    fn read_line(&mut self) -> Result<Vec<u8>, RuisError> {
         let mut line: Vec<u8> = vec![];
 
-        self.reader.read_until('\n' as u8, &mut line).or_else(|e|
-            Err(RespError::ParseFailed(format!("io err: {}", e)))
-        )?;
+        let max_len = self.limits.max_line_len();
+        Read::take(&mut self.reader, max_len as u64).read_until(b'\n', &mut line)?;
+        if !line.ends_with(b"\n") {
+            if line.len() == max_len {
+                return Err(RuisError::LimitExceeded(format!("line longer than {} bytes", max_len)));
+            }
+            // the end of the stream came first, maybe in the middle of a line.
+            return Err(connection_closed());
+        }
 
-        if !line.ends_with(&['\r' as u8, '\n' as u8]) {
-            return Err(RespError::ParseFailed(format!("line not ends with CRLF")));
+        if !line.ends_with(b"\r\n") {
+            return Err(RuisError::ParseFailed("line not ends with CRLF".to_string()));
         }
 
         line.pop();
@@ -78,40 +246,193 @@ impl<R: BufRead> RespReader<R> {
         Ok(line)
     }
 
-    fn read_bulk_string(&mut self, l: usize) -> Result<Vec<u8>, RespError> {
-        let mut buf = vec![0u8; l];
-        self.reader.read_exact(&mut buf).or_else(|e|
-            Err(RespError::ParseFailed(format!("io err: {}", e)))
-        )?;
+    fn read_bulk_string(&mut self, l: usize) -> Result<Vec<u8>, RuisError> {
+        let mut buf = Vec::with_capacity(l.min(64 * 1024));
+        self.read_bulk_into(l, &mut buf)?;
+        Ok(buf)
+    }
+
+    // the buffer grows as the bytes arrive, instead of allocating the length
+    // told by the peer upfront.
+    fn read_bulk_into<W: Write>(&mut self, l: usize, out: &mut W) -> Result<(), RuisError> {
+        if io::copy(&mut Read::take(&mut self.reader, l as u64), out)? < l as u64 {
+            return Err(connection_closed());
+        }
 
         let line = self.read_line()?;
-        if line.len() != 0 {
-            return Err(RespError::ParseFailed(format!("bad bulk string format")))
+        if !line.is_empty() {
+            return Err(RuisError::ParseFailed("bad bulk string format".to_string()))
         }
-        return Ok(buf);
+        Ok(())
     }
 
-    fn read_array(&mut self, n: usize) -> Result<Vec<RespValue>, RespError> {
+    fn read_array(&mut self, n: usize, depth: usize) -> Result<Vec<RespValue>, RuisError> {
         let mut arr: Vec<RespValue> = vec![];
         for _ in 0..n {
-            let val = self.read()?;
+            let val = self.read_value(depth)?;
             arr.push(val)
         }
-        return Ok(arr);
+        Ok(arr)
+    }
+
+    fn parse_int(&mut self, buf: &[u8]) -> Result<i64, RuisError> {
+        parse_int(buf)
+    }
+}
+
+fn parse_int(buf: &[u8]) -> Result<i64, RuisError> {
+    if buf.is_empty() {
+        return Err(RuisError::ParseFailed("malformed integer".to_string()));
+    }
+
+    let s = std::str::from_utf8(buf).or(
+        Err(RuisError::ParseFailed("bad utf8".to_string()))
+    )?;
+    let n = i64::from_str(s).or(
+        Err(RuisError::ParseFailed("parse int failed".to_string()))
+    )?;
+    Ok(n)
+}
+
+// RespDecoder parses the replies out of the bytes fed by the caller, for the
+// event loops doing their own io, like on mio or io_uring, where a blocking
+// BufRead does not fit.
+#[derive(Debug, Default)]
+pub struct RespDecoder {
+    buf: BytesMut,
+    limits: RespLimits,
+}
+
+impl RespDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_limits(&mut self, limits: RespLimits) {
+        self.limits = limits;
+    }
+
+    // appends the bytes and decodes the first reply, returns None if it's
+    // not complete yet. a chunk might hold several replies, the ones after
+    // the first are returned by decode().
+    pub fn feed(&mut self, data: &[u8]) -> Result<Option<RespValue>, RuisError> {
+        self.buf.extend_from_slice(data);
+        self.decode()
+    }
+
+    // decodes the next reply out of the bytes fed so far. the stream can not
+    // be decoded any further after an error.
+    pub fn decode(&mut self) -> Result<Option<RespValue>, RuisError> {
+        Ok(self.decode_frame()?.map(RespValue::from))
+    }
+
+    // like decode(), with the bulks sliced from the bytes fed, see RespFrame.
+    pub fn decode_frame(&mut self) -> Result<Option<RespFrame>, RuisError> {
+        let mut pos = 0;
+        match parse_shape(&self.buf, &mut pos, &self.limits, 0)? {
+            Some(shape) => Ok(Some(shape.into_frame(&self.buf.split_to(pos).freeze()))),
+            None => Ok(None),
+        }
+    }
+
+    // the bytes fed but not decoded yet.
+    pub fn buffered_len(&self) -> usize {
+        self.buf.len()
+    }
+}
+
+// the line at pos without the CRLF, None if the line is not complete yet.
+fn line_at(buf: &[u8], pos: &mut usize, limits: &RespLimits) -> Result<Option<Range<usize>>, RuisError> {
+    let max_len = limits.max_line_len();
+    let end = match buf[*pos..].iter().take(max_len).position(|&b| b == b'\n') {
+        Some(i) => *pos + i,
+        None if buf.len() - *pos >= max_len => {
+            return Err(RuisError::LimitExceeded(format!("line longer than {} bytes", max_len)));
+        },
+        None => return Ok(None),
+    };
+    if end == *pos || buf[end - 1] != b'\r' {
+        return Err(RuisError::ParseFailed("line not ends with CRLF".to_string()));
     }
+    let line = *pos..end - 1;
+    *pos = end + 1;
+    Ok(Some(line))
+}
 
-    fn parse_int(&mut self, buf: &[u8]) -> Result<i64, RespError> {
-        if buf.len() == 0 {
-            return Err(RespError::ParseFailed(format!("malformed integer")));
+// parses the reply at pos, the ranges of the shape are of buf. returns None
+// if the reply is not complete yet.
+fn parse_shape(buf: &[u8], pos: &mut usize, limits: &RespLimits, depth: usize) -> Result<Option<Shape>, RuisError> {
+    let line = match line_at(buf, pos, limits)? {
+        Some(line) => line,
+        None => return Ok(None),
+    };
+    if line.is_empty() {
+        return Err(RuisError::ParseFailed("empty line".to_string()));
+    }
+    let rest = line.start + 1..line.end;
+    let shape = match buf[line.start] as char {
+        ':' => Shape::Int(parse_int(&buf[rest])?),
+        '+' => Shape::Bulk(rest),
+        '-' => Shape::Error(rest),
+        '$' => {
+            let n = parse_int(&buf[rest])?;
+            if n == -1 {
+                return Ok(Some(Shape::NilBulk));
+            }
+            let start = *pos;
+            let end = start + limits.check_bulk_len(n)?;
+            if buf.len() < end + 2 {
+                return Ok(None);
+            }
+            if &buf[end..end + 2] != b"\r\n" {
+                return Err(RuisError::ParseFailed("bad bulk string format".to_string()))
+            }
+            *pos = end + 2;
+            Shape::Bulk(start..end)
+        }
+        '*' => {
+            let n = parse_int(&buf[rest])?;
+            if n == -1 {
+                return Ok(Some(Shape::NilArray));
+            }
+            let n = limits.check_array_len(n, depth)?;
+            let mut arr = vec![];
+            for _ in 0..n {
+                match parse_shape(buf, pos, limits, depth + 1)? {
+                    Some(shape) => arr.push(shape),
+                    None => return Ok(None),
+                }
+            }
+            Shape::Array(arr)
+        }
+        ch => {
+            return Err(RuisError::ParseFailed(format!("unexpected token: {}", ch)))
         }
+    };
+    Ok(Some(shape))
+}
+
+// a reply read by read_frame() or decoded by RespDecoder, with the bulks as
+// the ranges of the buffer until it's complete.
+enum Shape {
+    Int(i64),
+    NilBulk,
+    NilArray,
+    Bulk(Range<usize>),
+    Array(Vec<Shape>),
+    Error(Range<usize>),
+}
 
-        let s = std::str::from_utf8(buf).or(
-            Err(RespError::ParseFailed(format!("bad utf8")))
-        )?;
-        let n = i64::from_str(s).or(
-            Err(RespError::ParseFailed(format!("parse int failed")))
-        )?;
-        return Ok(n);
+impl Shape {
+    fn into_frame(self, buf: &Bytes) -> RespFrame {
+        match self {
+            Shape::Int(n) => RespFrame::Int(n),
+            Shape::NilBulk => RespFrame::NilBulk,
+            Shape::NilArray => RespFrame::NilArray,
+            Shape::Bulk(r) => RespFrame::Bulk(buf.slice(r)),
+            Shape::Array(arr) => RespFrame::Array(arr.into_iter().map(|s| s.into_frame(buf)).collect()),
+            Shape::Error(r) => RespFrame::Error(buf.slice(r)),
+        }
     }
 }
 
@@ -119,6 +440,7 @@ impl<W: Write> RespWriter<W> {
     pub fn new(w: W) -> Self {
         Self {
             writer: w,
+            buf: vec![],
         }
     }
 
@@ -126,58 +448,100 @@ impl<W: Write> RespWriter<W> {
         self.writer
     }
 
-    pub fn write_int(&mut self, n: i64) -> Result<(), RespError> {
-        self.writer.write_fmt(format_args!(":{}\r\n", n))?;
-        Ok(())
+    pub fn get_ref(&self) -> &W {
+        &self.writer
     }
 
-    pub fn write_bulk(&mut self, b: &[u8]) -> Result<(), RespError> {
-        self.writer.write_fmt(format_args!("${}\r\n", b.len()))?;
-        self.writer.write_all(&b)?;
-        self.writer.write_fmt(format_args!("\r\n"))?;
-        Ok(())
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.writer
     }
 
-    pub fn write_bulks(&mut self, bs: &[&[u8]]) -> Result<(), RespError> {
-        self.writer.write_fmt(format_args!("*{}\r\n", bs.len()))?;
+    pub fn write_int(&mut self, n: i64) -> Result<(), RuisError> {
+        write!(self.buf, ":{}\r\n", n)?;
+        self.write_buf()
+    }
+
+    pub fn write_bulk(&mut self, b: &[u8]) -> Result<(), RuisError> {
+        self.push_bulk(b)?;
+        self.write_buf()
+    }
+
+    pub fn write_bulks(&mut self, bs: &[&[u8]]) -> Result<(), RuisError> {
+        write!(self.buf, "*{}\r\n", bs.len())?;
         for b in bs {
-            self.write_bulk(b)?
+            self.push_bulk(b)?
         }
-        Ok(())
+        self.write_buf()
     }
 
-    pub fn write_status(&mut self, s: &str) -> Result<(), RespError> {
-        self.writer.write_fmt(format_args!("+{}\r\n", s))?;
-        Ok(())
+    pub fn write_status(&mut self, s: &str) -> Result<(), RuisError> {
+        write!(self.buf, "+{}\r\n", s)?;
+        self.write_buf()
     }
 
-    pub fn write_error(&mut self, s: &str) -> Result<(), RespError> {
-        self.writer.write_fmt(format_args!("-{}\r\n", s))?;
-        Ok(())
+    pub fn write_error(&mut self, s: &str) -> Result<(), RuisError> {
+        write!(self.buf, "-{}\r\n", s)?;
+        self.write_buf()
     }
 
-    pub fn write_array(&mut self, arr: &[RespValue]) -> Result<(), RespError> {
-        self.writer.write_fmt(format_args!("*{}\r\n", arr.len()))?;
+    pub fn write_array(&mut self, arr: &[RespValue]) -> Result<(), RuisError> {
+        write!(self.buf, "*{}\r\n", arr.len())?;
         for v in arr {
-            self.write(v)?
+            self.push_value(v)?
         }
+        self.write_buf()
+    }
+
+    pub fn write(&mut self, v: &RespValue) -> Result<(), RuisError> {
+        self.push_value(v)?;
+        self.write_buf()
+    }
+
+    pub fn flush(&mut self) -> Result<(), RuisError> {
+        self.writer.flush()?;
         Ok(())
     }
 
-    pub fn write(&mut self, v: &RespValue) -> Result<(), RespError> {
+    fn push_value(&mut self, v: &RespValue) -> Result<(), RuisError> {
         match *v {
-            RespValue::Int(n) => self.write_int(n)?,
-            RespValue::Bulk(ref s) => self.write_bulk(s)?,
-            RespValue::Error(ref s) => self.write_error(&String::from_utf8_lossy(s))?,
-            RespValue::Array(ref arr) => self.write_array(arr)?,
-            RespValue::NilArray => self.writer.write_fmt(format_args!("*\r\n-1\r\n"))?,
-            RespValue::NilBulk => self.writer.write_fmt(format_args!("$\r\n-1\r\n"))?,
+            RespValue::Int(n) => write!(self.buf, ":{}\r\n", n)?,
+            RespValue::Bulk(ref s) => self.push_bulk(s)?,
+            RespValue::Error(ref s) => write!(self.buf, "-{}\r\n", String::from_utf8_lossy(s))?,
+            RespValue::Array(ref arr) => {
+                write!(self.buf, "*{}\r\n", arr.len())?;
+                for v in arr {
+                    self.push_value(v)?
+                }
+            },
+            RespValue::NilArray => self.buf.extend_from_slice(b"*-1\r\n"),
+            RespValue::NilBulk => self.buf.extend_from_slice(b"$-1\r\n"),
         }
         Ok(())
     }
 
-    pub fn flush(&mut self) -> Result<(), RespError> {
-        self.writer.flush()?;
+    // the large bulks are written as they are, after the parts encoded so
+    // far, instead of being copied.
+    fn push_bulk(&mut self, b: &[u8]) -> Result<(), RuisError> {
+        write!(self.buf, "${}\r\n", b.len())?;
+        if b.len() >= LARGE_BULK_LEN {
+            self.write_buf()?;
+            self.writer.write_all(b)?;
+        } else {
+            self.buf.extend_from_slice(b);
+        }
+        self.buf.extend_from_slice(b"\r\n");
+        Ok(())
+    }
+
+    // the buffer is emptied even if the write fails, so the next write does
+    // not send the leftovers.
+    fn write_buf(&mut self) -> Result<(), RuisError> {
+        let r = self.writer.write_all(&self.buf);
+        self.buf.clear();
+        if self.buf.capacity() > MAX_KEPT_BUF_LEN {
+            self.buf = vec![];
+        }
+        r?;
         Ok(())
     }
 }
@@ -185,6 +549,7 @@ impl<W: Write> RespWriter<W> {
 
 #[cfg(test)]
 mod tests {
+    use std::io;
     use super::*;
 
     #[test]
@@ -234,6 +599,26 @@ mod tests {
         assert_eq!(r.unwrap(), RespValue::Array(v));
     }
 
+    #[test]
+    fn test_read_eof() {
+        let r = RespReader::new(io::Cursor::new(b"")).read();
+        assert!(r.unwrap_err().is_connection_dropped());
+        let r = RespReader::new(io::Cursor::new(b"$6\r\nfoo")).read();
+        assert!(r.unwrap_err().is_connection_dropped());
+    }
+
+    #[test]
+    fn test_read_empty_line() {
+        let r = RespReader::new(io::Cursor::new(b"\r\n")).read();
+        assert!(matches!(r, Err(RuisError::ParseFailed(_))));
+        let r = RespReader::new(io::Cursor::new(b"*2\r\n:1\r\n\r\n")).read();
+        assert!(matches!(r, Err(RuisError::ParseFailed(_))));
+        let r = RespReader::new(io::Cursor::new(b"\r\n")).read_frame();
+        assert!(matches!(r, Err(RuisError::ParseFailed(_))));
+        let r = RespReader::new(io::Cursor::new(b"*2\r\n:1\r\n\r\n")).read_frame();
+        assert!(matches!(r, Err(RuisError::ParseFailed(_))));
+    }
+
     #[test]
     fn test_read_array_of_array() {
         let br = io::Cursor::new(b"*2\r\n*3\r\n:1\r\n:2\r\n:3\r\n*2\r\n+Foo\r\n-Bar\r\n".to_vec());
@@ -252,6 +637,134 @@ mod tests {
         assert_eq!(r.unwrap(), arr);
     }
 
+    #[test]
+    fn test_read_frame() {
+        let mut r = RespReader::new(io::Cursor::new(b"*4\r\n$3\r\nfoo\r\n$-1\r\n$3\r\nbar\r\n*1\r\n-ERR x\r\n:7\r\n$0\r\n\r\n".to_vec()));
+        let frame = r.read_frame().unwrap();
+        let arr = match frame {
+            RespFrame::Array(ref arr) => arr,
+            ref f => panic!("unexpected {:?}", f),
+        };
+        // the bulks are next to each other in the same buffer.
+        match (&arr[0], &arr[2]) {
+            (RespFrame::Bulk(foo), RespFrame::Bulk(bar)) => assert_eq!(foo.as_ptr().wrapping_add(3), bar.as_ptr()),
+            v => panic!("unexpected {:?}", v),
+        }
+        assert_eq!(RespValue::from(frame), RespValue::Array(vec![
+            RespValue::Bulk(b"foo".to_vec()),
+            RespValue::NilBulk,
+            RespValue::Bulk(b"bar".to_vec()),
+            RespValue::Array(vec![RespValue::Error(b"ERR x".to_vec())]),
+        ]));
+        assert_eq!(r.read_frame().unwrap(), RespFrame::Int(7));
+        assert_eq!(r.read_frame().unwrap(), RespFrame::Bulk(Bytes::new()));
+        assert!(r.read_frame().unwrap_err().is_connection_dropped());
+    }
+
+    #[test]
+    fn test_decoder() {
+        let input = b"*3\r\n$3\r\nfoo\r\n$-1\r\n:42\r\n+OK\r\n-ERR bad\r\n$0\r\n\r\n";
+        // fed a byte at a time, each reply comes out once its last byte is in.
+        let mut decoder = RespDecoder::new();
+        let mut values = vec![];
+        for b in input.iter() {
+            if let Some(v) = decoder.feed(&[*b]).unwrap() {
+                values.push(v);
+            }
+        }
+        assert_eq!(values, vec![
+            RespValue::Array(vec![RespValue::Bulk(b"foo".to_vec()), RespValue::NilBulk, RespValue::Int(42)]),
+            RespValue::Bulk(b"OK".to_vec()),
+            RespValue::Error(b"ERR bad".to_vec()),
+            RespValue::Bulk(vec![]),
+        ]);
+        assert_eq!(decoder.buffered_len(), 0);
+
+        // fed at once, the replies after the first are left to decode().
+        let mut decoder = RespDecoder::new();
+        assert_eq!(decoder.feed(b"$3\r\nfoo\r\n$3\r\nba").unwrap(), Some(RespValue::Bulk(b"foo".to_vec())));
+        assert_eq!(decoder.decode().unwrap(), None);
+        assert_eq!(decoder.buffered_len(), 6);
+        assert_eq!(decoder.feed(b"r\r\n").unwrap(), Some(RespValue::Bulk(b"bar".to_vec())));
+
+        for bad in [&b"$3\r\nfoobar\r\n"[..], b"?\r\n", b":x\r\n", b"+OK\n", b"\r\n"] {
+            assert!(matches!(RespDecoder::new().feed(bad), Err(RuisError::ParseFailed(_))), "{:?}", bad);
+        }
+    }
+
+    struct CountingWriter {
+        buf: Vec<u8>,
+        writes: usize,
+    }
+
+    impl Write for CountingWriter {
+        fn write(&mut self, b: &[u8]) -> io::Result<usize> {
+            self.writes += 1;
+            self.buf.extend_from_slice(b);
+            Ok(b.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_limits() {
+        let limits = RespLimits::new().max_bulk_len(8).max_array_len(2).max_depth(2);
+        let read = |input: &[u8]| {
+            let mut r = RespReader::new(io::Cursor::new(input.to_vec()));
+            r.set_limits(limits);
+            r.read()
+        };
+        // told, but never sent.
+        assert!(matches!(read(b"$999999999999\r\n"), Err(RuisError::LimitExceeded(_))));
+        assert!(matches!(read(b"*3\r\n:1\r\n:2\r\n:3\r\n"), Err(RuisError::LimitExceeded(_))));
+        assert!(matches!(read(b"*1\r\n*1\r\n*1\r\n:1\r\n"), Err(RuisError::LimitExceeded(_))));
+        assert!(matches!(read(b"+OKOKOKOKOK\r\n"), Err(RuisError::LimitExceeded(_))));
+        assert_eq!(read(b"*1\r\n*2\r\n$8\r\n12345678\r\n+OKOKOKOK\r\n").unwrap(), RespValue::Array(vec![RespValue::Array(vec![
+            RespValue::Bulk(b"12345678".to_vec()),
+            RespValue::Bulk(b"OKOKOKOK".to_vec()),
+        ])]));
+
+        let mut r = RespReader::new(io::Cursor::new(b"*1\r\n*1\r\n*1\r\n:1\r\n$-1\r\n".to_vec()));
+        r.set_limits(limits);
+        assert!(matches!(r.read_frame(), Err(RuisError::LimitExceeded(_))));
+
+        let mut decoder = RespDecoder::new();
+        decoder.set_limits(limits);
+        assert!(matches!(decoder.feed(b"$999999999999\r\n"), Err(RuisError::LimitExceeded(_))));
+        let mut decoder = RespDecoder::new();
+        decoder.set_limits(limits);
+        assert!(matches!(decoder.feed(b"+OKOKOKOKOKOK"), Err(RuisError::LimitExceeded(_))));
+
+        // the default limits let a large nesting through, but not an endless one.
+        let nested = b"*1\r\n".repeat(100_000);
+        assert!(matches!(RespReader::new(io::Cursor::new(nested.clone())).read(), Err(RuisError::LimitExceeded(_))));
+        assert!(matches!(RespDecoder::new().feed(&nested), Err(RuisError::LimitExceeded(_))));
+    }
+
+    #[test]
+    fn test_write_once() {
+        let mut w = RespWriter::new(CountingWriter { buf: vec![], writes: 0 });
+        w.write_bulks(&[b"set", b"foo", b"bar"]).unwrap();
+        assert_eq!(w.get_ref().writes, 1);
+        w.write(&RespValue::Array(vec![RespValue::Int(1), RespValue::Bulk(b"x".to_vec()), RespValue::NilBulk])).unwrap();
+        assert_eq!(w.get_ref().writes, 2);
+        assert_eq!(w.get_ref().buf, b"*3\r\n$3\r\nset\r\n$3\r\nfoo\r\n$3\r\nbar\r\n*3\r\n:1\r\n$1\r\nx\r\n$-1\r\n");
+
+        // the large bulk goes out on its own, between the parts before and
+        // after it.
+        let large = vec![b'v'; LARGE_BULK_LEN];
+        let mut w = RespWriter::new(CountingWriter { buf: vec![], writes: 0 });
+        w.write_bulks(&[b"set", b"k", &large]).unwrap();
+        assert_eq!(w.get_ref().writes, 3);
+        let buf = &w.get_ref().buf;
+        assert!(buf.starts_with(b"*3\r\n$3\r\nset\r\n$1\r\nk\r\n$65536\r\nvvv"));
+        assert!(buf.ends_with(b"vvv\r\n"));
+        assert_eq!(buf.len(), 28 + LARGE_BULK_LEN + 2);
+    }
+
     #[test]
     fn test_write_array() {
         let cw = io::Cursor::new(b"".to_vec());
@@ -264,4 +777,11 @@ mod tests {
         let cw = w.into_inner();
         assert_eq!(String::from_utf8_lossy(&cw.into_inner()), String::from("*2\r\n$3\r\nfoo\r\n$3\r\nbar\r\n"))
     }
+
+    #[test]
+    fn test_write_nil() {
+        let mut w = RespWriter::new(vec![]);
+        w.write(&RespValue::Array(vec![RespValue::NilBulk, RespValue::NilArray])).unwrap();
+        assert_eq!(w.get_ref(), b"*2\r\n$-1\r\n*-1\r\n");
+    }
 }
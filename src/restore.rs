@@ -0,0 +1,171 @@
+use std::io::{BufRead, Write};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use super::connection::GenericConnection;
+use super::types::{RespValue, RuisError};
+
+// the options of RESTORE, the key is restored without an expiry by default,
+// and fails with BUSYKEY if it exists.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RestoreOptions {
+    // in milliseconds, relative or since the epoch with ABSTTL.
+    ttl: u64,
+    absttl: bool,
+    replace: bool,
+    idle_time: Option<u64>,
+    freq: Option<u8>,
+}
+
+impl RestoreOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl.as_millis() as u64;
+        self.absttl = false;
+        self
+    }
+
+    // ABSTTL, the key expires at the time, it's not restored if the time
+    // passed already.
+    pub fn expire_at(mut self, at: SystemTime) -> Self {
+        // 0 would be no expiry.
+        self.ttl = at.duration_since(UNIX_EPOCH).map_or(1, |d| (d.as_millis() as u64).max(1));
+        self.absttl = true;
+        self
+    }
+
+    pub fn replace(mut self) -> Self {
+        self.replace = true;
+        self
+    }
+
+    // IDLETIME, the seconds since the last access for the LRU eviction.
+    pub fn idle_time(mut self, idle: Duration) -> Self {
+        self.idle_time = Some(idle.as_secs());
+        self
+    }
+
+    // FREQ, the access frequency for the LFU eviction.
+    pub fn freq(mut self, freq: u8) -> Self {
+        self.freq = Some(freq);
+        self
+    }
+}
+
+impl<W: Write, R: BufRead> GenericConnection<W, R> {
+    // the value of the key serialized in the format of redis, None if the
+    // key does not exist. the payload is only understood by the servers of
+    // the same or a later version.
+    pub fn dump(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>, RuisError> {
+        self.execute_as(&[b"dump", key])
+    }
+
+    pub fn restore(&mut self, key: &[u8], payload: &[u8], opts: &RestoreOptions) -> Result<(), RuisError> {
+        let ttl = opts.ttl.to_string();
+        let idle_time = opts.idle_time.map(|n| n.to_string());
+        let freq = opts.freq.map(|n| n.to_string());
+        let mut cmd: Vec<&[u8]> = vec![b"restore", key, ttl.as_bytes(), payload];
+        if opts.replace {
+            cmd.push(b"replace");
+        }
+        if opts.absttl {
+            cmd.push(b"absttl");
+        }
+        if let Some(idle_time) = &idle_time {
+            cmd.extend_from_slice(&[b"idletime", idle_time.as_bytes()]);
+        }
+        if let Some(freq) = &freq {
+            cmd.extend_from_slice(&[b"freq", freq.as_bytes()]);
+        }
+        self.execute(&cmd)?.into_result().map(|_| ())
+    }
+}
+
+// the ttl in milliseconds for RESTORE, 0 for no expiry, and the payload of a
+// key, None if it does not exist.
+pub(crate) type DumpedKey = Result<Option<(u64, Vec<u8>)>, RuisError>;
+
+// PTTL and DUMP of the keys in a single round trip. the errors of a key are
+// returned with it, the io errors fail the whole batch.
+pub(crate) fn dump_keys<W: Write, R: BufRead, K: AsRef<[u8]>>(src: &mut GenericConnection<W, R>, keys: &[K]) -> Result<Vec<DumpedKey>, RuisError> {
+    let replies = src.execute_batch(keys.iter().flat_map(|k| [[&b"pttl"[..], k.as_ref()], [&b"dump"[..], k.as_ref()]]))?;
+    Ok(replies.chunks(2).map(|pair| match (&pair[0], &pair[1]) {
+        (_, RespValue::NilBulk) | (RespValue::Int(-2), _) => Ok(None),
+        // -1 for no expiry.
+        (&RespValue::Int(ttl), RespValue::Bulk(payload)) => Ok(Some((ttl.max(0) as u64, payload.clone()))),
+        (RespValue::Error(_), _) => pair[0].clone().into_result().map(|_| None),
+        (_, RespValue::Error(_)) => pair[1].clone().into_result().map(|_| None),
+        (a, b) => Err(RuisError::Unexpected(format!("pttl and dump: {:?}, {:?}", a, b))),
+    }).collect())
+}
+
+// copies the key with its expiry from a server to another with DUMP and
+// RESTORE, like between the servers without MIGRATE access of each other.
+// returns false if the key does not exist on the source. a key existing on
+// the target fails with BUSYKEY unless replaced, the source is left as is.
+pub fn copy_key<W1, R1, W2, R2>(src: &mut GenericConnection<W1, R1>, dst: &mut GenericConnection<W2, R2>, key: &[u8], replace: bool) -> Result<bool, RuisError>
+    where W1: Write, R1: BufRead, W2: Write, R2: BufRead {
+    let (ttl, payload) = match dump_keys(src, &[key])?.remove(0)? {
+        Some(dumped) => dumped,
+        None => return Ok(false),
+    };
+    let mut opts = RestoreOptions::new().ttl(Duration::from_millis(ttl));
+    if replace {
+        opts = opts.replace();
+    }
+    dst.restore(key, &payload, &opts)?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use super::*;
+    use super::super::commands::Commands;
+    use super::super::connection::TcpConnection;
+    use super::super::resp::{RespReader, RespWriter};
+    use super::super::testing::TestServer;
+    use super::super::types::ErrorKind;
+
+    #[test]
+    fn test_restore_options() {
+        let mut c = GenericConnection::new(RespReader::new(io::Cursor::new(b"+OK\r\n".to_vec())), RespWriter::new(vec![]));
+        let opts = RestoreOptions::new().expire_at(UNIX_EPOCH + Duration::from_secs(1700000000)).replace().idle_time(Duration::from_secs(60)).freq(5);
+        c.restore(b"k", b"payload", &opts).unwrap();
+        let written = String::from_utf8_lossy(c.raw_parts().0).into_owned();
+        let args: Vec<&str> = written.split("\r\n").skip(2).step_by(2).collect();
+        assert_eq!(args, vec!["restore", "k", "1700000000000", "payload", "replace", "absttl", "idletime", "60", "freq", "5"]);
+    }
+
+    #[test]
+    fn test_copy_key() {
+        let (source, target) = (TestServer::new(), TestServer::new());
+        let mut src = TcpConnection::connect(&source.addr(), None).unwrap();
+        let mut dst = TcpConnection::connect(&target.addr(), None).unwrap();
+        src.hset(b"h", [(b"a", b"1")]).unwrap();
+        src.expire(b"h", 100).unwrap();
+        src.set(b"s", b"v").unwrap();
+
+        assert!(copy_key(&mut src, &mut dst, b"h", false).unwrap());
+        assert_eq!(dst.hget(b"h", b"a").unwrap(), Some(b"1".to_vec()));
+        assert!((99..=100).contains(&dst.ttl(b"h").unwrap()));
+        assert!(!copy_key(&mut src, &mut dst, b"missing", false).unwrap());
+
+        dst.set(b"s", b"old").unwrap();
+        match copy_key(&mut src, &mut dst, b"s", false) {
+            Err(RuisError::ServerError(msg)) => assert_eq!(ErrorKind::parse(msg.as_bytes()), ErrorKind::BusyKey),
+            r => panic!("expected BUSYKEY, got {:?}", r),
+        }
+        assert!(copy_key(&mut src, &mut dst, b"s", true).unwrap());
+        assert_eq!(dst.get(b"s").unwrap(), Some(b"v".to_vec()));
+        assert_eq!(dst.ttl(b"s").unwrap(), -1);
+        assert_eq!(src.get(b"s").unwrap(), Some(b"v".to_vec()));
+
+        let payload = src.dump(b"s").unwrap().unwrap();
+        dst.restore(b"s2", &payload, &RestoreOptions::new().ttl(Duration::from_secs(5))).unwrap();
+        assert!((4..=5).contains(&dst.ttl(b"s2").unwrap()));
+        assert_eq!(src.dump(b"missing").unwrap(), None);
+    }
+}
@@ -0,0 +1,182 @@
+use std::time::Duration;
+
+use super::types::{ErrorKind, RespValue, RuisError};
+
+const DEFAULT_MAX_ATTEMPTS: usize = 3;
+const DEFAULT_INITIAL_BACKOFF: Duration = Duration::from_millis(50);
+const DEFAULT_MAX_BACKOFF: Duration = Duration::from_secs(2);
+
+// the ways a command fails, by whether it might have run on the server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Failure {
+    // the connection could not be opened, the command was not sent.
+    Connect,
+    // the command could not be written, like on a pooled connection closed
+    // by the server meanwhile. redis only runs the commands received whole,
+    // so it did not run.
+    Write,
+    // the connection dropped while waiting for the reply, the command might
+    // have run.
+    Dropped,
+    // the reply timed out, the command might have run.
+    Timeout,
+    // the server can not serve the command for now, like on LOADING, BUSY,
+    // TRYAGAIN or CLUSTERDOWN, it did not run.
+    Unavailable,
+}
+
+// where the command was when it failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Stage {
+    Connect,
+    Write,
+    Read,
+}
+
+impl Failure {
+    // None for the failures not worth a retry, like a WRONGTYPE or a
+    // rejected password.
+    pub(crate) fn classify(stage: Stage, r: &Result<RespValue, RuisError>) -> Option<Failure> {
+        match r {
+            Ok(v) => Failure::of_reply(v),
+            Err(e) => Failure::of_error(stage, e),
+        }
+    }
+
+    pub(crate) fn of_reply(v: &RespValue) -> Option<Failure> {
+        match v.error_kind()? {
+            ErrorKind::Loading | ErrorKind::Busy | ErrorKind::TryAgain | ErrorKind::ClusterDown => Some(Failure::Unavailable),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn of_error(stage: Stage, e: &RuisError) -> Option<Failure> {
+        match (e, stage) {
+            (RuisError::IoError(_), Stage::Connect) => Some(Failure::Connect),
+            (RuisError::IoError(_), Stage::Write) => Some(Failure::Write),
+            (RuisError::IoError(_), Stage::Read) if e.is_timeout() => Some(Failure::Timeout),
+            (RuisError::IoError(_), Stage::Read) => Some(Failure::Dropped),
+            _ => None,
+        }
+    }
+}
+
+// Backoff is the wait before each retry, doubled from the initial one up to
+// the max.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Backoff {
+    initial: Duration,
+    max: Duration,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self::new(DEFAULT_INITIAL_BACKOFF, DEFAULT_MAX_BACKOFF)
+    }
+}
+
+impl Backoff {
+    pub(crate) fn new(initial: Duration, max: Duration) -> Self {
+        Self { initial, max }
+    }
+
+    // attempt counts from 0, for the first retry.
+    pub(crate) fn delay(&self, attempt: usize) -> Duration {
+        let factor = 1u32.checked_shl(attempt as u32).unwrap_or(u32::MAX);
+        self.initial.saturating_mul(factor).min(self.max)
+    }
+}
+
+// RetryPolicy tells which failures of a command are retried, and how many
+// times. by default only the ones where the command did not run are, as
+// Dropped and Timeout would run the commands which are not idempotent, like
+// INCR, twice. the waits between the attempts double from the initial
+// backoff up to the max one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryPolicy {
+    max_attempts: usize,
+    backoff: Backoff,
+    retry_on: Vec<Failure>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            backoff: Backoff::default(),
+            retry_on: vec![Failure::Connect, Failure::Write, Failure::Unavailable],
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // the commands are tried once.
+    pub fn none() -> Self {
+        Self::default().max_attempts(1)
+    }
+
+    // the attempts in total, with the first one.
+    pub fn max_attempts(mut self, n: usize) -> Self {
+        self.max_attempts = n.max(1);
+        self
+    }
+
+    pub fn backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.backoff = Backoff::new(initial, max);
+        self
+    }
+
+    // replaces the failures retried, like adding Dropped and Timeout for a
+    // workload of the reads only.
+    pub fn retry_on(mut self, failures: &[Failure]) -> Self {
+        self.retry_on = failures.to_vec();
+        self
+    }
+
+    // attempt counts from 0, for the first retry.
+    pub(crate) fn should_retry(&self, failure: Failure, attempt: usize) -> bool {
+        attempt + 1 < self.max_attempts && self.retry_on.contains(&failure)
+    }
+
+    pub(crate) fn backoff_for(&self, attempt: usize) -> Duration {
+        self.backoff.delay(attempt)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use super::*;
+
+    #[test]
+    fn test_classify() {
+        let io_err = |kind| Err(RuisError::IoError(io::Error::new(kind, "x")));
+        assert_eq!(Failure::classify(Stage::Connect, &io_err(io::ErrorKind::ConnectionRefused)), Some(Failure::Connect));
+        assert_eq!(Failure::classify(Stage::Write, &io_err(io::ErrorKind::BrokenPipe)), Some(Failure::Write));
+        assert_eq!(Failure::classify(Stage::Read, &io_err(io::ErrorKind::UnexpectedEof)), Some(Failure::Dropped));
+        assert_eq!(Failure::classify(Stage::Read, &io_err(io::ErrorKind::WouldBlock)), Some(Failure::Timeout));
+        assert_eq!(Failure::classify(Stage::Read, &Ok(RespValue::Error(b"LOADING loading the dataset".to_vec()))), Some(Failure::Unavailable));
+        assert_eq!(Failure::classify(Stage::Read, &Ok(RespValue::Error(b"WRONGTYPE x".to_vec()))), None);
+        assert_eq!(Failure::classify(Stage::Connect, &Err(RuisError::Auth("WRONGPASS".to_string()))), None);
+    }
+
+    #[test]
+    fn test_should_retry() {
+        let policy = RetryPolicy::new().backoff(Duration::from_millis(10), Duration::from_millis(30));
+        assert!(policy.should_retry(Failure::Write, 0));
+        assert!(policy.should_retry(Failure::Write, 1));
+        assert!(!policy.should_retry(Failure::Write, 2));
+        assert!(!policy.should_retry(Failure::Dropped, 0));
+        assert!(!RetryPolicy::none().should_retry(Failure::Connect, 0));
+        let delays: Vec<u128> = (0..3).map(|i| policy.backoff_for(i).as_millis()).collect();
+        assert_eq!(delays, vec![10, 20, 30]);
+        let backoff = Backoff::new(Duration::from_millis(10), Duration::from_millis(50));
+        let delays: Vec<u128> = (0..5).map(|i| backoff.delay(i).as_millis()).collect();
+        assert_eq!(delays, vec![10, 20, 40, 50, 50]);
+        assert_eq!(backoff.delay(100), Duration::from_millis(50));
+    }
+}
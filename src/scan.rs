@@ -0,0 +1,199 @@
+use std::collections::VecDeque;
+use std::io::{BufRead, Write};
+
+use super::connection::GenericConnection;
+use super::types::{RespValue, RuisError};
+
+// the MATCH and COUNT of the SCAN family, and the TYPE of SCAN.
+#[derive(Debug, Clone, Default)]
+pub struct ScanOptions {
+    pattern: Option<Vec<u8>>,
+    count: Option<usize>,
+    kind: Option<Vec<u8>>,
+}
+
+impl ScanOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // the glob-style pattern of the keys, or the members of a collection.
+    pub fn pattern(mut self, pattern: &[u8]) -> Self {
+        self.pattern = Some(pattern.to_vec());
+        self
+    }
+
+    // a hint of the items returned by each call.
+    pub fn count(mut self, n: usize) -> Self {
+        self.count = Some(n);
+        self
+    }
+
+    // only the keys of the type, like "hash". ignored by HSCAN, SSCAN and
+    // ZSCAN.
+    pub fn kind(mut self, kind: &str) -> Self {
+        self.kind = Some(kind.as_bytes().to_vec());
+        self
+    }
+}
+
+// the next cursor and the items of a reply of the SCAN family. the scan is
+// over when the next cursor is "0".
+pub(crate) fn parse_page(reply: RespValue) -> Result<(Vec<u8>, Vec<Vec<u8>>), RuisError> {
+    let mut it = match reply.into_result()? {
+        RespValue::Array(v) if v.len() == 2 => v.into_iter(),
+        v => return Err(RuisError::Unexpected(format!("scan: {:?}", v))),
+    };
+    match (it.next(), it.next()) {
+        (Some(RespValue::Bulk(next)), Some(RespValue::Array(batch))) => {
+            let items = batch.into_iter().filter_map(|k| match k {
+                RespValue::Bulk(k) => Some(k),
+                _ => None,
+            }).collect();
+            Ok((next, items))
+        },
+        v => Err(RuisError::Unexpected(format!("scan: {:?}", v))),
+    }
+}
+
+// ScanIter calls SCAN, SSCAN, HSCAN or ZSCAN with the next cursor each time
+// the items of the previous call run out. the server might return an item
+// more than once, like a key added while scanning.
+pub struct ScanIter<'a, W: Write, R: BufRead> {
+    conn: &'a mut GenericConnection<W, R>,
+    // the command and the key of the collection scanned, if any.
+    head: Vec<Vec<u8>>,
+    opts: ScanOptions,
+    cursor: Vec<u8>,
+    items: VecDeque<Vec<u8>>,
+    done: bool,
+}
+
+impl<'a, W: Write, R: BufRead> ScanIter<'a, W, R> {
+    fn new(conn: &'a mut GenericConnection<W, R>, head: Vec<Vec<u8>>, opts: ScanOptions) -> Self {
+        Self {
+            conn,
+            head,
+            opts,
+            cursor: b"0".to_vec(),
+            items: VecDeque::new(),
+            done: false,
+        }
+    }
+
+    fn fetch(&mut self) -> Result<(), RuisError> {
+        let count = self.opts.count.map(|n| n.to_string());
+        let mut cmd: Vec<&[u8]> = self.head.iter().map(|a| a.as_slice()).collect();
+        cmd.push(&self.cursor);
+        if let Some(pattern) = &self.opts.pattern {
+            cmd.extend_from_slice(&[b"match", pattern]);
+        }
+        if let Some(count) = &count {
+            cmd.extend_from_slice(&[b"count", count.as_bytes()]);
+        }
+        if let (Some(kind), true) = (&self.opts.kind, self.head.len() == 1) {
+            cmd.extend_from_slice(&[b"type", kind]);
+        }
+        let (next, items) = parse_page(self.conn.execute(&cmd)?)?;
+        self.done = next == b"0";
+        self.cursor = next;
+        self.items.extend(items);
+        Ok(())
+    }
+}
+
+impl<W: Write, R: BufRead> Iterator for ScanIter<'_, W, R> {
+    type Item = Result<Vec<u8>, RuisError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // a call might return no item while the scan goes on.
+        while self.items.is_empty() && !self.done {
+            if let Err(e) = self.fetch() {
+                self.done = true;
+                return Some(Err(e));
+            }
+        }
+        self.items.pop_front().map(Ok)
+    }
+}
+
+// the field-value pairs of HSCAN, or the member-score pairs of ZSCAN.
+pub struct ScanPairs<'a, W: Write, R: BufRead> {
+    inner: ScanIter<'a, W, R>,
+}
+
+impl<W: Write, R: BufRead> Iterator for ScanPairs<'_, W, R> {
+    type Item = Result<(Vec<u8>, Vec<u8>), RuisError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let first = match self.inner.next()? {
+            Ok(v) => v,
+            Err(e) => return Some(Err(e)),
+        };
+        match self.inner.next() {
+            Some(Ok(second)) => Some(Ok((first, second))),
+            Some(Err(e)) => Some(Err(e)),
+            None => Some(Err(RuisError::Unexpected("scan: odd number of items".to_string()))),
+        }
+    }
+}
+
+impl<W: Write, R: BufRead> GenericConnection<W, R> {
+    pub fn scan_iter(&mut self, opts: ScanOptions) -> ScanIter<'_, W, R> {
+        ScanIter::new(self, vec![b"scan".to_vec()], opts)
+    }
+
+    pub fn sscan_iter(&mut self, key: &[u8], opts: ScanOptions) -> ScanIter<'_, W, R> {
+        ScanIter::new(self, vec![b"sscan".to_vec(), key.to_vec()], opts)
+    }
+
+    pub fn hscan_iter(&mut self, key: &[u8], opts: ScanOptions) -> ScanPairs<'_, W, R> {
+        ScanPairs {
+            inner: ScanIter::new(self, vec![b"hscan".to_vec(), key.to_vec()], opts),
+        }
+    }
+
+    pub fn zscan_iter(&mut self, key: &[u8], opts: ScanOptions) -> ScanPairs<'_, W, R> {
+        ScanPairs {
+            inner: ScanIter::new(self, vec![b"zscan".to_vec(), key.to_vec()], opts),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use super::*;
+    use super::super::connection::TcpConnection;
+    use super::super::resp::{RespReader, RespWriter};
+    use super::super::testing::TestServer;
+
+    #[test]
+    fn test_scan_iter() {
+        let server = TestServer::new();
+        let mut conn = TcpConnection::connect(&server.addr(), None).unwrap();
+        for i in 0..25 {
+            conn.execute(&[b"set", format!("k{}", i).as_bytes(), b"v"]).unwrap();
+        }
+        conn.execute(&[b"set", b"other", b"v"]).unwrap();
+
+        let mut keys: Vec<Vec<u8>> = conn.scan_iter(ScanOptions::new().pattern(b"k*").count(7)).collect::<Result<_, _>>().unwrap();
+        keys.sort();
+        keys.dedup();
+        assert_eq!(keys.len(), 25);
+        assert_eq!(conn.scan_iter(ScanOptions::new()).count(), 26);
+    }
+
+    #[test]
+    fn test_hscan_iter() {
+        let replies = b"*2\r\n$1\r\n7\r\n*2\r\n$1\r\na\r\n$1\r\n1\r\n*2\r\n$1\r\n0\r\n*0\r\n*2\r\n$1\r\n0\r\n*2\r\n$1\r\nb\r\n$1\r\n2\r\n";
+        let r = RespReader::new(io::Cursor::new(replies.to_vec()));
+        let mut conn = GenericConnection::new(r, RespWriter::new(vec![]));
+        let pairs: Vec<_> = conn.hscan_iter(b"h", ScanOptions::new().pattern(b"*")).collect::<Result<_, _>>().unwrap();
+        assert_eq!(pairs, vec![(b"a".to_vec(), b"1".to_vec())]);
+        let mut it = conn.hscan_iter(b"h", ScanOptions::new().kind("hash"));
+        assert_eq!(it.next().unwrap().unwrap(), (b"b".to_vec(), b"2".to_vec()));
+        assert!(it.next().is_none());
+        assert_eq!(conn.raw_parts().0.as_slice(), &b"*5\r\n$5\r\nhscan\r\n$1\r\nh\r\n$1\r\n0\r\n$5\r\nmatch\r\n$1\r\n*\r\n*5\r\n$5\r\nhscan\r\n$1\r\nh\r\n$1\r\n7\r\n$5\r\nmatch\r\n$1\r\n*\r\n*3\r\n$5\r\nhscan\r\n$1\r\nh\r\n$1\r\n0\r\n"[..]);
+    }
+}
@@ -0,0 +1,91 @@
+use super::args::ToArgs;
+use super::commands::Commands;
+use super::convert::FromResp;
+use super::types::{ErrorKind, RespValue, RuisError};
+
+// Script runs a Lua script by its SHA1 with EVALSHA, so the source is only
+// sent when the server does not have it cached yet, like after a restart or
+// a SCRIPT FLUSH.
+#[derive(Debug, Clone)]
+pub struct Script {
+    code: String,
+    hash: String,
+}
+
+impl Script {
+    pub fn new(code: &str) -> Self {
+        Self {
+            code: code.to_string(),
+            hash: sha1_smol::Sha1::from(code).digest().to_string(),
+        }
+    }
+
+    // the SHA1 of the source in hex, as used by EVALSHA.
+    pub fn hash(&self) -> &str {
+        &self.hash
+    }
+
+    pub fn code(&self) -> &str {
+        &self.code
+    }
+
+    // runs the script with the keys and the args, like
+    // script.invoke::<i64, _, _, _>(&mut conn, &["counter"], &[1]). on
+    // NOSCRIPT the script is run with EVAL instead, which also caches it on
+    // the server for the following calls.
+    pub fn invoke<T, C, K, A>(&self, conn: &mut C, keys: &K, args: &A) -> Result<T, RuisError>
+        where T: FromResp, C: Commands, K: ToArgs + ?Sized, A: ToArgs + ?Sized {
+        let keys = keys.to_args();
+        let args = args.to_args();
+        let numkeys = keys.len().to_string();
+        let mut cmd: Vec<&[u8]> = vec![b"evalsha", self.hash.as_bytes(), numkeys.as_bytes()];
+        cmd.extend(keys.iter().map(|k| k.as_slice()));
+        cmd.extend(args.iter().map(|a| a.as_slice()));
+
+        let reply = conn.execute(&cmd)?;
+        if reply.error_kind() != Some(ErrorKind::NoScript) {
+            return T::from_resp(reply);
+        }
+        cmd[0] = b"eval";
+        cmd[1] = self.code.as_bytes();
+        T::from_resp(conn.execute(&cmd)?)
+    }
+
+    // loads the script into the cache of the server ahead of the calls.
+    pub fn load<C: Commands>(&self, conn: &mut C) -> Result<(), RuisError> {
+        match conn.execute(&[b"script", b"load", self.code.as_bytes()])?.into_result()? {
+            RespValue::Bulk(ref hash) if hash.eq_ignore_ascii_case(self.hash.as_bytes()) => Ok(()),
+            v => Err(RuisError::Unexpected(format!("script load: {:?}", v))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use super::*;
+    use super::super::connection::GenericConnection;
+    use super::super::resp::{RespReader, RespWriter};
+
+    #[test]
+    fn test_script_hash() {
+        assert_eq!(Script::new("return 1").hash(), "e0e1f9fabfc9d4800c877a703b823ac0578ff8db");
+    }
+
+    #[test]
+    fn test_invoke() {
+        let script = Script::new("return redis.call('incrby', KEYS[1], ARGV[1])");
+        let replies = b"-NOSCRIPT No matching script. Please use EVAL.\r\n:3\r\n:4\r\n-ERR value is not an integer\r\n";
+        let r = RespReader::new(io::Cursor::new(replies.to_vec()));
+        let mut conn = GenericConnection::new(r, RespWriter::new(vec![]));
+
+        assert_eq!(script.invoke::<i64, _, _, _>(&mut conn, &["n"], &[3]).unwrap(), 3);
+        assert_eq!(script.invoke::<i64, _, _, _>(&mut conn, &["n"], &[1]).unwrap(), 4);
+        assert!(matches!(script.invoke::<i64, _, _, _>(&mut conn, &["n"], &["x"]), Err(RuisError::ServerError(_))));
+
+        let written = String::from_utf8(conn.raw_parts().0.clone()).unwrap();
+        let sent: Vec<&str> = written.split("\r\n").filter(|l| l.starts_with("ev")).collect();
+        assert_eq!(sent, vec!["evalsha", "eval", "evalsha", "evalsha"]);
+        assert!(written.contains("65b45e1e1b8b36c8c3e885080b88ae484e64fde6\r\n$1\r\n1\r\n$1\r\nn\r\n$1\r\n3\r\n"));
+    }
+}
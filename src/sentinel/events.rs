@@ -0,0 +1,195 @@
+use std::io::BufReader;
+use std::net::TcpStream;
+use std::time::Duration;
+
+use super::SentinelClient;
+use super::super::connection::TcpConnection;
+use super::super::pubsub::{Message, PubSub};
+use super::super::types::RuisError;
+
+// https://redis.io/docs/management/sentinel/#pubsub-messages
+
+const EVENT_CHANNELS: &[&[u8]] = &[b"+switch-master", b"+sdown", b"-sdown", b"+odown", b"-odown"];
+const FAILOVER_STATE_PATTERN: &[u8] = b"+failover-state-*";
+
+// an instance in the sentinel events, formatted like:
+//
+//   <instance-type> <name> <ip> <port> @ <master-name> <master-ip> <master-port>
+//
+// the @ part is omitted when the instance is a master.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SentinelInstance {
+    // "master", "slave" or "sentinel".
+    pub kind: String,
+    pub name: String,
+    pub addr: String,
+    pub master_name: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SentinelEvent {
+    SwitchMaster { master_name: String, old_addr: String, new_addr: String },
+    // the instance is considered down by a sentinel, or cleared.
+    SDown(SentinelInstance),
+    SDownCleared(SentinelInstance),
+    // the master is agreed to be down by the quorum of the sentinels, or cleared.
+    ODown(SentinelInstance),
+    ODownCleared(SentinelInstance),
+    // the progress of a failover, like "select-slave" or "reconf-slaves".
+    FailoverState { state: String, instance: SentinelInstance },
+    // the events not recognized, or not parsed.
+    Other { channel: String, payload: String },
+}
+
+impl SentinelEvent {
+    // the master the event is about.
+    pub fn master_name(&self) -> Option<&str> {
+        match self {
+            SentinelEvent::SwitchMaster { master_name, .. } => Some(master_name),
+            SentinelEvent::SDown(i) | SentinelEvent::SDownCleared(i) | SentinelEvent::ODown(i) | SentinelEvent::ODownCleared(i) => Some(&i.master_name),
+            SentinelEvent::FailoverState { instance, .. } => Some(&instance.master_name),
+            SentinelEvent::Other { .. } => None,
+        }
+    }
+}
+
+pub fn parse_sentinel_event(channel: &[u8], payload: &[u8]) -> SentinelEvent {
+    let channel = String::from_utf8_lossy(channel).into_owned();
+    let payload = String::from_utf8_lossy(payload).into_owned();
+    let event = match channel.as_str() {
+        "+switch-master" => parse_switch_master(&payload),
+        "+sdown" => parse_instance(&payload).map(SentinelEvent::SDown),
+        "-sdown" => parse_instance(&payload).map(SentinelEvent::SDownCleared),
+        "+odown" => parse_instance(&payload).map(SentinelEvent::ODown),
+        "-odown" => parse_instance(&payload).map(SentinelEvent::ODownCleared),
+        c => c.strip_prefix("+failover-state-").and_then(|state| {
+            parse_instance(&payload).map(|instance| SentinelEvent::FailoverState { state: state.to_string(), instance })
+        }),
+    };
+    event.unwrap_or(SentinelEvent::Other { channel, payload })
+}
+
+// <master name> <oldip> <oldport> <newip> <newport>
+fn parse_switch_master(payload: &str) -> Option<SentinelEvent> {
+    let parts: Vec<&str> = payload.split(' ').collect();
+    match parts.as_slice() {
+        [name, old_ip, old_port, new_ip, new_port] => Some(SentinelEvent::SwitchMaster {
+            master_name: name.to_string(),
+            old_addr: format!("{}:{}", old_ip, old_port),
+            new_addr: format!("{}:{}", new_ip, new_port),
+        }),
+        _ => None,
+    }
+}
+
+fn parse_instance(payload: &str) -> Option<SentinelInstance> {
+    let mut parts = payload.split(' ');
+    let kind = parts.next()?.to_string();
+    let name = parts.next()?.to_string();
+    let addr = format!("{}:{}", parts.next()?, parts.next()?);
+    // +odown appends the quorum like "#quorum 2/2".
+    let master_name = match parts.next() {
+        Some("@") => parts.next()?.to_string(),
+        _ => name.clone(),
+    };
+    Some(SentinelInstance {
+        kind,
+        name,
+        addr,
+        master_name,
+    })
+}
+
+// SentinelEvents receives the events published by a sentinel, about all the
+// masters it monitors.
+pub struct SentinelEvents {
+    pubsub: PubSub<TcpStream, BufReader<TcpStream>>,
+}
+
+impl SentinelEvents {
+    pub fn next_event(&mut self) -> Result<SentinelEvent, RuisError> {
+        let msg = self.pubsub.next_message()?;
+        Ok(to_event(msg))
+    }
+
+    // returns None if no event arrives within the timeout.
+    pub fn next_event_timeout(&mut self, timeout: Duration) -> Result<Option<SentinelEvent>, RuisError> {
+        Ok(self.pubsub.next_message_timeout(timeout)?.map(to_event))
+    }
+}
+
+impl Iterator for SentinelEvents {
+    type Item = Result<SentinelEvent, RuisError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        Some(self.next_event())
+    }
+}
+
+fn to_event(msg: Message) -> SentinelEvent {
+    parse_sentinel_event(&msg.channel, &msg.payload)
+}
+
+impl SentinelClient {
+    // subscribes the events on the first sentinel reachable. the subscription
+    // ends when that sentinel goes down, subscribe again to move on to the
+    // others.
+    pub fn subscribe_events(&mut self) -> Result<SentinelEvents, RuisError> {
+        let mut last_err = RuisError::Unexpected("no sentinels".to_string());
+        for addr in &self.sentinels {
+            match TcpConnection::connect(addr, self.sentinel_password.as_deref()) {
+                Ok(conn) => {
+                    let mut pubsub = PubSub::new(conn);
+                    for channel in EVENT_CHANNELS {
+                        pubsub.subscribe(channel)?;
+                    }
+                    pubsub.psubscribe(FAILOVER_STATE_PATTERN)?;
+                    return Ok(SentinelEvents { pubsub });
+                },
+                Err(e) => last_err = e,
+            }
+        }
+        Err(last_err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn instance(kind: &str, name: &str, addr: &str, master_name: &str) -> SentinelInstance {
+        SentinelInstance {
+            kind: kind.to_string(),
+            name: name.to_string(),
+            addr: addr.to_string(),
+            master_name: master_name.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_parse_sentinel_event() {
+        assert_eq!(parse_sentinel_event(b"+switch-master", b"mymaster 127.0.0.1 6379 127.0.0.1 6380"), SentinelEvent::SwitchMaster {
+            master_name: "mymaster".to_string(),
+            old_addr: "127.0.0.1:6379".to_string(),
+            new_addr: "127.0.0.1:6380".to_string(),
+        });
+        assert_eq!(
+            parse_sentinel_event(b"+sdown", b"slave 127.0.0.1:6380 127.0.0.1 6380 @ mymaster 127.0.0.1 6379"),
+            SentinelEvent::SDown(instance("slave", "127.0.0.1:6380", "127.0.0.1:6380", "mymaster")),
+        );
+        assert_eq!(
+            parse_sentinel_event(b"+odown", b"master mymaster 127.0.0.1 6379 #quorum 2/2"),
+            SentinelEvent::ODown(instance("master", "mymaster", "127.0.0.1:6379", "mymaster")),
+        );
+        let event = parse_sentinel_event(b"+failover-state-select-slave", b"master mymaster 127.0.0.1 6379");
+        assert_eq!(event, SentinelEvent::FailoverState {
+            state: "select-slave".to_string(),
+            instance: instance("master", "mymaster", "127.0.0.1:6379", "mymaster"),
+        });
+        assert_eq!(event.master_name(), Some("mymaster"));
+        assert_eq!(parse_sentinel_event(b"+switch-master", b"bad"), SentinelEvent::Other {
+            channel: "+switch-master".to_string(),
+            payload: "bad".to_string(),
+        });
+    }
+}
@@ -0,0 +1,731 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{BufRead, Write};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::cluster::{Latency, ReadFrom, is_read_only};
+use super::connection::{ClientIdentity, GenericConnection, RemapFn, TcpConnection, redacted};
+use super::metrics::MetricsSink;
+use super::pipeline::Pipeline;
+use super::types::{ErrorKind, RespValue, RuisError};
+
+mod events;
+
+pub use self::events::{SentinelEvent, SentinelEvents, SentinelInstance, parse_sentinel_event};
+
+// https://redis.io/docs/reference/sentinel-clients/
+
+const DEFAULT_MAX_RETRIES: usize = 3;
+const DEFAULT_RETRY_DELAY: Duration = Duration::from_millis(200);
+const REPLICAS_REFRESH_INTERVAL: Duration = Duration::from_secs(10);
+
+// a replica as reported by SENTINEL REPLICAS.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SentinelReplica {
+    pub addr: String,
+    pub flags: Vec<String>,
+    // "ok" or "err", the state of the link to the master.
+    pub master_link_status: Option<String>,
+}
+
+impl SentinelReplica {
+    // flagged as down by the sentinel, or not replicating from the master.
+    pub fn is_down(&self) -> bool {
+        let down_flags = ["s_down", "o_down", "disconnected"];
+        self.flags.iter().any(|f| down_flags.contains(&f.as_str())) || self.master_link_status.as_deref() == Some("err")
+    }
+}
+
+impl<W: Write, R: BufRead> GenericConnection<W, R> {
+    // returns None if the sentinel does not monitor the master.
+    pub fn sentinel_get_master_addr(&mut self, master_name: &str) -> Result<Option<String>, RuisError> {
+        match self.execute(&[b"sentinel", b"get-master-addr-by-name", master_name.as_bytes()])?.into_result()? {
+            RespValue::NilBulk | RespValue::NilArray => Ok(None),
+            RespValue::Array(v) => match v.as_slice() {
+                [RespValue::Bulk(host), RespValue::Bulk(port)] => {
+                    Ok(Some(format!("{}:{}", String::from_utf8_lossy(host), String::from_utf8_lossy(port))))
+                },
+                _ => Err(RuisError::Unexpected(format!("sentinel master addr: {:?}", v))),
+            },
+            v => Err(RuisError::Unexpected(format!("sentinel master addr: {:?}", v))),
+        }
+    }
+
+    // SENTINEL REPLICAS replies a flat list of the field and value pairs per
+    // replica.
+    pub fn sentinel_replicas(&mut self, master_name: &str) -> Result<Vec<SentinelReplica>, RuisError> {
+        let replicas = match self.execute(&[b"sentinel", b"replicas", master_name.as_bytes()])?.into_result()? {
+            RespValue::Array(v) => v,
+            v => return Err(RuisError::Unexpected(format!("sentinel replicas: {:?}", v))),
+        };
+        let mut r = vec![];
+        for replica in replicas {
+            let fields = match replica {
+                RespValue::Array(fields) => fields,
+                v => return Err(RuisError::Unexpected(format!("sentinel replica: {:?}", v))),
+            };
+            let mut map = HashMap::new();
+            for pair in fields.chunks(2) {
+                if let [RespValue::Bulk(k), RespValue::Bulk(v)] = pair {
+                    map.insert(String::from_utf8_lossy(k).into_owned(), String::from_utf8_lossy(v).into_owned());
+                }
+            }
+            let (ip, port) = match (map.get("ip"), map.get("port")) {
+                (Some(ip), Some(port)) => (ip, port),
+                _ => return Err(RuisError::Unexpected(format!("sentinel replica without address: {:?}", map))),
+            };
+            r.push(SentinelReplica {
+                addr: format!("{}:{}", ip, port),
+                flags: map.get("flags").map(|f| f.split(',').map(|s| s.to_string()).collect()).unwrap_or_default(),
+                master_link_status: map.get("master-link-status").cloned(),
+            });
+        }
+        Ok(r)
+    }
+
+    // the first element of the ROLE reply: "master", "slave" or "sentinel".
+    pub fn role(&mut self) -> Result<String, RuisError> {
+        match self.execute(&[b"role"])?.into_result()? {
+            RespValue::Array(v) => match v.first() {
+                Some(RespValue::Bulk(role)) => Ok(String::from_utf8_lossy(role).into_owned()),
+                _ => Err(RuisError::Unexpected(format!("role: {:?}", v))),
+            },
+            v => Err(RuisError::Unexpected(format!("role: {:?}", v))),
+        }
+    }
+}
+
+pub struct SentinelClientBuilder {
+    sentinels: Vec<String>,
+    master_name: String,
+    password: Option<String>,
+    sentinel_password: Option<String>,
+    db: Option<i64>,
+    identity: Option<ClientIdentity>,
+    remap: Option<RemapFn>,
+    max_retries: usize,
+    retry_delay: Duration,
+    read_from: ReadFrom,
+    metrics: Option<Arc<dyn MetricsSink>>,
+}
+
+impl fmt::Debug for SentinelClientBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SentinelClientBuilder")
+            .field("sentinels", &self.sentinels)
+            .field("master_name", &self.master_name)
+            .field("password", &redacted(&self.password))
+            .field("sentinel_password", &redacted(&self.sentinel_password))
+            .field("db", &self.db)
+            .field("identity", &self.identity)
+            .field("max_retries", &self.max_retries)
+            .field("retry_delay", &self.retry_delay)
+            .field("read_from", &self.read_from)
+            .finish_non_exhaustive()
+    }
+}
+
+impl SentinelClientBuilder {
+    pub fn new(sentinels: &[&str], master_name: &str) -> Self {
+        Self {
+            sentinels: sentinels.iter().map(|s| s.to_string()).collect(),
+            master_name: master_name.to_string(),
+            password: None,
+            sentinel_password: None,
+            db: None,
+            identity: None,
+            remap: None,
+            max_retries: DEFAULT_MAX_RETRIES,
+            retry_delay: DEFAULT_RETRY_DELAY,
+            read_from: ReadFrom::Master,
+            metrics: None,
+        }
+    }
+
+    // the password of the master and the replicas.
+    pub fn password(mut self, password: &str) -> Self {
+        self.password = Some(password.to_string());
+        self
+    }
+
+    // the sentinels are usually configured with their own password, or none,
+    // the password of the data nodes is not sent to them.
+    pub fn sentinel_password(mut self, password: &str) -> Self {
+        self.sentinel_password = Some(password.to_string());
+        self
+    }
+
+    // the database selected on the connections to the master and the
+    // replicas, including the ones opened after a failover.
+    pub fn db(mut self, db: i64) -> Self {
+        self.db = Some(db);
+        self
+    }
+
+    // the name and the library info set on the connections to the master and
+    // the replicas, not on the ones to the sentinels.
+    pub fn identity(mut self, identity: ClientIdentity) -> Self {
+        self.identity = Some(identity);
+        self
+    }
+
+    // the sentinels report the addresses the servers announce, which might
+    // not be reachable from the client.
+    pub fn remap<F>(mut self, f: F) -> Self
+        where F: Fn(&str) -> String + Send + Sync + 'static {
+        self.remap = Some(Box::new(f));
+        self
+    }
+
+    // how many times a command is retried during a failover, the master is
+    // resolved again through the sentinels before each retry.
+    pub fn max_retries(mut self, n: usize) -> Self {
+        self.max_retries = n;
+        self
+    }
+
+    // the wait before each retry, which gives the sentinels the time to agree
+    // on the new master.
+    pub fn retry_delay(mut self, delay: Duration) -> Self {
+        self.retry_delay = delay;
+        self
+    }
+
+    // sends the read-only commands to the replicas reported by the sentinels,
+    // the replicas flagged as down are skipped.
+    pub fn read_from(mut self, read_from: ReadFrom) -> Self {
+        self.read_from = read_from;
+        self
+    }
+
+    // reports the connections to the master, a reconnect on each failover.
+    pub fn metrics(mut self, sink: Arc<dyn MetricsSink>) -> Self {
+        self.metrics = Some(sink);
+        self
+    }
+
+    pub fn connect(self) -> Result<SentinelClient, RuisError> {
+        let mut client = SentinelClient {
+            sentinels: self.sentinels,
+            master_name: self.master_name,
+            password: self.password,
+            sentinel_password: self.sentinel_password,
+            db: self.db,
+            identity: self.identity,
+            remap: self.remap,
+            max_retries: self.max_retries,
+            retry_delay: self.retry_delay,
+            read_from: self.read_from,
+            metrics: self.metrics,
+            master_addr: None,
+            master: None,
+            replicas: vec![],
+            replicas_refreshed: None,
+            replica_conns: HashMap::new(),
+            latencies: HashMap::new(),
+            round_robin: 0,
+        };
+        client.master()?;
+        Ok(client)
+    }
+}
+
+// SentinelClient asks the sentinels for the address of the master, and sends
+// the commands to it.
+pub struct SentinelClient {
+    sentinels: Vec<String>,
+    master_name: String,
+    password: Option<String>,
+    sentinel_password: Option<String>,
+    db: Option<i64>,
+    identity: Option<ClientIdentity>,
+    remap: Option<RemapFn>,
+    max_retries: usize,
+    retry_delay: Duration,
+    read_from: ReadFrom,
+    metrics: Option<Arc<dyn MetricsSink>>,
+    master_addr: Option<String>,
+    master: Option<TcpConnection>,
+    // the healthy replicas, a replica failing is removed until the next
+    // refresh.
+    replicas: Vec<String>,
+    replicas_refreshed: Option<Instant>,
+    replica_conns: HashMap<String, TcpConnection>,
+    latencies: HashMap<String, Latency>,
+    round_robin: usize,
+}
+
+impl fmt::Debug for SentinelClient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SentinelClient")
+            .field("sentinels", &self.sentinels)
+            .field("master_name", &self.master_name)
+            .field("password", &redacted(&self.password))
+            .field("sentinel_password", &redacted(&self.sentinel_password))
+            .field("db", &self.db)
+            .field("identity", &self.identity)
+            .field("read_from", &self.read_from)
+            .field("master_addr", &self.master_addr)
+            .field("replicas", &self.replicas)
+            .finish_non_exhaustive()
+    }
+}
+
+impl SentinelClient {
+    pub fn connect(sentinels: &[&str], master_name: &str, password: Option<&str>) -> Result<SentinelClient, RuisError> {
+        let mut builder = SentinelClientBuilder::new(sentinels, master_name);
+        if let Some(password) = password {
+            builder = builder.password(password);
+        }
+        builder.connect()
+    }
+
+    pub fn resolve_master(&mut self) -> Result<String, RuisError> {
+        let master_name = self.master_name.clone();
+        self.ask_sentinels(|conn| conn.sentinel_get_master_addr(&master_name))
+    }
+
+    // the replicas of the master, including the ones flagged as down.
+    pub fn replicas(&mut self) -> Result<Vec<SentinelReplica>, RuisError> {
+        let master_name = self.master_name.clone();
+        self.ask_sentinels(|conn| conn.sentinel_replicas(&master_name).map(Some))
+    }
+
+    // asks the sentinels in turn, the first one answering is moved to the
+    // front of the list so it's asked first the next time.
+    fn ask_sentinels<T, F>(&mut self, f: F) -> Result<T, RuisError>
+        where F: Fn(&mut TcpConnection) -> Result<Option<T>, RuisError> {
+        let mut last_err = RuisError::Unexpected("no sentinels".to_string());
+        for i in 0..self.sentinels.len() {
+            let r = TcpConnection::connect(&self.sentinels[i], self.sentinel_password.as_deref())
+                .and_then(|mut conn| f(&mut conn));
+            match r {
+                Ok(Some(v)) => {
+                    let sentinel = self.sentinels.remove(i);
+                    self.sentinels.insert(0, sentinel);
+                    return Ok(v);
+                },
+                Ok(None) => last_err = RuisError::Unexpected(format!("master {} is unknown to sentinel {}", self.master_name, self.sentinels[i])),
+                Err(e) => last_err = e,
+            }
+        }
+        Err(last_err)
+    }
+
+    // the address of the master as reported by the sentinels.
+    pub fn master_addr(&self) -> Option<&str> {
+        self.master_addr.as_deref()
+    }
+
+    // returns the connection to the master, resolved and connected on the
+    // first use. the server is checked to be a master, as the sentinels might
+    // report a stale address during a failover.
+    pub fn master(&mut self) -> Result<&mut TcpConnection, RuisError> {
+        if self.master.is_none() {
+            let addr = self.resolve_master()?;
+            let mut conn = self.connect_to(&addr)?;
+            let role = conn.role()?;
+            if role != "master" {
+                return Err(RuisError::Unexpected(format!("{} reported as master {} is a {}", addr, self.master_name, role)));
+            }
+            if let Some(ref m) = self.metrics {
+                m.connected(&addr, self.master_addr.is_some());
+            }
+            // the replicas are reloaded along with the master.
+            if self.master_addr.as_ref().is_some_and(|old| *old != addr) {
+                self.replicas_refreshed = None;
+            }
+            self.master_addr = Some(addr);
+            self.master = Some(conn);
+        }
+        Ok(self.master.as_mut().unwrap())
+    }
+
+    // the master is resolved again when it can not be connected, when the
+    // connection breaks, or when it turns out to be demoted to a replica. the
+    // command is retried if it's known not to be executed, or if it's a read
+    // which is safe to be executed twice.
+    pub fn execute(&mut self, cmd: &[&[u8]]) -> Result<RespValue, RuisError> {
+        if self.read_from != ReadFrom::Master && is_read_only(cmd) {
+            if let Some(reply) = self.execute_on_replica(cmd) {
+                return Ok(reply);
+            }
+        }
+
+        let mut attempt = 0;
+        loop {
+            let retry_err = match self.master() {
+                Err(e) => e,
+                Ok(conn) => match conn.execute(cmd) {
+                    Err(e) if e.leaves_stream_unusable() => {
+                        self.master = None;
+                        // a reply which could not be read would fail again.
+                        if !is_read_only(cmd) || !matches!(e, RuisError::IoError(_)) {
+                            return Err(e);
+                        }
+                        e
+                    },
+                    // the writes are rejected by the old master once it's
+                    // demoted by a failover.
+                    Ok(RespValue::Error(msg)) if ErrorKind::parse(&msg) == ErrorKind::ReadOnly => {
+                        self.master = None;
+                        RuisError::ServerError(String::from_utf8_lossy(&msg).into_owned())
+                    },
+                    r => return r,
+                },
+            };
+            if attempt >= self.max_retries {
+                return Err(retry_err);
+            }
+            attempt += 1;
+            thread::sleep(self.retry_delay);
+        }
+    }
+
+    // the pipeline is always sent to the master, and not retried as some of
+    // the commands might have been executed.
+    pub fn execute_pipeline(&mut self, pipeline: &Pipeline) -> Result<Vec<RespValue>, RuisError> {
+        let r = self.master()?.execute_pipeline(pipeline);
+        if r.as_ref().is_err_and(|e| e.leaves_stream_unusable()) {
+            self.master = None;
+        }
+        r
+    }
+
+    // returns None if no replica is healthy, the command is sent to the master
+    // then.
+    fn execute_on_replica(&mut self, cmd: &[&[u8]]) -> Option<RespValue> {
+        if self.replicas_refreshed.is_none_or(|t| t.elapsed() >= REPLICAS_REFRESH_INTERVAL) {
+            self.refresh_replicas();
+        }
+        while let Some(addr) = self.pick_replica() {
+            let start = Instant::now();
+            let r = match self.replica_conns.remove(&addr) {
+                Some(conn) => Ok(conn),
+                None => self.connect_to(&addr),
+            }.and_then(|mut conn| conn.execute(cmd).map(|reply| (conn, reply)));
+            match r {
+                Ok((conn, reply)) => {
+                    self.latencies.entry(addr.clone()).or_default().observe(start.elapsed());
+                    self.replica_conns.insert(addr, conn);
+                    return Some(reply);
+                },
+                Err(_) => self.replicas.retain(|r| *r != addr),
+            }
+        }
+        None
+    }
+
+    fn pick_replica(&mut self) -> Option<String> {
+        if self.replicas.is_empty() {
+            return None;
+        }
+        let picked = match self.read_from {
+            ReadFrom::ReplicaLowestLatency => {
+                let latencies = &self.latencies;
+                self.replicas.iter().min_by_key(|r| latencies.get(*r).map(|l| l.get()).unwrap_or_default())
+            },
+            _ => {
+                self.round_robin = self.round_robin.wrapping_add(1);
+                self.replicas.get(self.round_robin % self.replicas.len())
+            },
+        };
+        picked.cloned()
+    }
+
+    // on failures the reads go to the master until the next refresh.
+    fn refresh_replicas(&mut self) {
+        self.replicas_refreshed = Some(Instant::now());
+        let replicas = self.replicas().unwrap_or_default();
+        self.replicas = replicas.into_iter().filter(|r| !r.is_down()).map(|r| r.addr).collect();
+        let replicas = &self.replicas;
+        self.replica_conns.retain(|addr, _| replicas.contains(addr));
+        self.latencies.retain(|addr, _| replicas.contains(addr));
+    }
+
+    fn connect_to(&self, addr: &str) -> Result<TcpConnection, RuisError> {
+        let connect_addr = match self.remap {
+            Some(ref remap) => remap(addr),
+            None => addr.to_string(),
+        };
+        let mut conn = TcpConnection::connect(&connect_addr, self.password.as_deref())?;
+        if let Some(ref identity) = self.identity {
+            conn.set_identity(identity)?;
+        }
+        if let Some(db) = self.db {
+            conn.select(db)?;
+        }
+        Ok(conn)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use super::*;
+    use super::super::resp::{RespReader, RespWriter};
+    use super::super::testing::fake_node;
+
+    fn role_reply(role: &str) -> RespValue {
+        RespValue::Array(vec![RespValue::Bulk(role.as_bytes().to_vec())])
+    }
+
+    fn fake_sentinel(master: Option<String>) -> String {
+        fake_node(move |_, args| {
+            match master {
+                Some(ref addr) if args[1] == b"get-master-addr-by-name" && args[2] == b"mymaster" => {
+                    let (host, port) = addr.rsplit_once(':').unwrap();
+                    RespValue::Array(vec![RespValue::Bulk(host.as_bytes().to_vec()), RespValue::Bulk(port.as_bytes().to_vec())])
+                },
+                _ => RespValue::NilArray,
+            }
+        })
+    }
+
+    fn unused_addr() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.local_addr().unwrap().to_string()
+    }
+
+    #[test]
+    fn test_resolve_master() {
+        let master = fake_node(|me, args| {
+            match args[0].as_slice() {
+                b"role" => role_reply("master"),
+                _ => RespValue::Bulk(me.as_bytes().to_vec()),
+            }
+        });
+        let down = unused_addr();
+        let unaware = fake_sentinel(None);
+        let sentinel = fake_sentinel(Some(master.clone()));
+
+        let mut client = SentinelClient::connect(&[&down, &unaware, &sentinel], "mymaster", None).unwrap();
+        assert_eq!(client.master_addr(), Some(master.as_str()));
+        assert_eq!(client.sentinels[0], sentinel);
+        assert_eq!(client.execute(&[b"get", b"foo"]).unwrap(), RespValue::Bulk(master.as_bytes().to_vec()));
+    }
+
+    #[test]
+    fn test_verify_role() {
+        let replica = fake_node(|_, _| role_reply("slave"));
+        let sentinel = fake_sentinel(Some(replica));
+        assert!(SentinelClient::connect(&[&sentinel], "mymaster", None).is_err());
+        assert!(SentinelClient::connect(&[&fake_sentinel(None)], "mymaster", None).is_err());
+    }
+
+    #[test]
+    fn test_remap() {
+        let master = fake_node(|_, _| role_reply("master"));
+        let port = master.rsplit_once(':').unwrap().1.to_string();
+        let sentinel = fake_sentinel(Some(format!("172.17.0.2:{}", port)));
+        let client = SentinelClientBuilder::new(&[&sentinel], "mymaster")
+            .remap(|addr| addr.replace("172.17.0.2", "127.0.0.1"))
+            .connect()
+            .unwrap();
+        assert_eq!(client.master_addr(), Some(format!("172.17.0.2:{}", port).as_str()));
+    }
+
+    #[test]
+    fn test_failover() {
+        let failed_over = Arc::new(AtomicBool::new(false));
+        let failed_over2 = failed_over.clone();
+        let old = fake_node(move |_, args| {
+            let demoted = failed_over2.load(Ordering::SeqCst);
+            match args[0].as_slice() {
+                b"role" => role_reply(if demoted { "slave" } else { "master" }),
+                b"set" if demoted => RespValue::Error(b"READONLY You can't write against a read only replica.".to_vec()),
+                _ => RespValue::Bulk(b"old".to_vec()),
+            }
+        });
+        let new = fake_node(|_, args| {
+            match args[0].as_slice() {
+                b"role" => role_reply("master"),
+                _ => RespValue::Bulk(b"new".to_vec()),
+            }
+        });
+
+        // the sentinel reports the new master a few queries after the
+        // failover, and a dead address in between.
+        let reported = Arc::new(Mutex::new(old.clone()));
+        let reported2 = reported.clone();
+        let sentinel = fake_node(move |_, _| {
+            let addr = reported2.lock().unwrap().clone();
+            let (host, port) = addr.rsplit_once(':').unwrap();
+            RespValue::Array(vec![RespValue::Bulk(host.as_bytes().to_vec()), RespValue::Bulk(port.as_bytes().to_vec())])
+        });
+
+        let mut client = SentinelClientBuilder::new(&[&sentinel], "mymaster")
+            .retry_delay(Duration::from_millis(10))
+            .connect()
+            .unwrap();
+        assert_eq!(client.execute(&[b"set", b"foo", b"bar"]).unwrap(), RespValue::Bulk(b"old".to_vec()));
+
+        failed_over.store(true, Ordering::SeqCst);
+        *reported.lock().unwrap() = unused_addr();
+        let switcher = {
+            let reported = reported.clone();
+            let new = new.clone();
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(15));
+                *reported.lock().unwrap() = new;
+            })
+        };
+        assert_eq!(client.execute(&[b"set", b"foo", b"bar"]).unwrap(), RespValue::Bulk(b"new".to_vec()));
+        assert_eq!(client.master_addr(), Some(new.as_str()));
+        switcher.join().unwrap();
+    }
+
+    #[test]
+    fn test_give_up_retrying() {
+        let replica = fake_node(|_, args| {
+            match args[0].as_slice() {
+                b"role" => role_reply("master"),
+                _ => RespValue::Error(b"READONLY You can't write against a read only replica.".to_vec()),
+            }
+        });
+        let sentinel = fake_sentinel(Some(replica));
+        let mut client = SentinelClientBuilder::new(&[&sentinel], "mymaster")
+            .max_retries(2)
+            .retry_delay(Duration::from_millis(1))
+            .connect()
+            .unwrap();
+        match client.execute(&[b"set", b"foo", b"bar"]) {
+            Err(RuisError::ServerError(msg)) => assert!(msg.starts_with("READONLY")),
+            r => panic!("unexpected {:?}", r),
+        }
+    }
+
+    fn replicas_reply(replicas: &[(&str, &str)]) -> RespValue {
+        RespValue::Array(replicas.iter().map(|&(addr, flags)| {
+            let (ip, port) = addr.rsplit_once(':').unwrap();
+            let fields = ["name", addr, "ip", ip, "port", port, "flags", flags, "master-link-status", "ok"];
+            RespValue::Array(fields.iter().map(|f| RespValue::Bulk(f.as_bytes().to_vec())).collect())
+        }).collect())
+    }
+
+    #[test]
+    fn test_sentinel_replicas() {
+        let reply = replicas_reply(&[("10.0.0.1:6379", "slave"), ("10.0.0.2:6379", "s_down,slave,disconnected")]);
+        let mut body = vec![];
+        RespWriter::new(&mut body).write(&reply).unwrap();
+        let mut conn = GenericConnection::new(RespReader::new(std::io::Cursor::new(body)), RespWriter::new(vec![]));
+        let replicas = conn.sentinel_replicas("mymaster").unwrap();
+        assert_eq!(replicas.len(), 2);
+        assert_eq!(replicas[0].addr, "10.0.0.1:6379");
+        assert_eq!(replicas[0].master_link_status.as_deref(), Some("ok"));
+        assert!(!replicas[0].is_down());
+        assert_eq!(replicas[1].flags, vec!["s_down", "slave", "disconnected"]);
+        assert!(replicas[1].is_down());
+    }
+
+    #[test]
+    fn test_read_from_replicas() {
+        let serve = |role: &'static str| fake_node(move |me, args| {
+            match args[0].as_slice() {
+                b"role" => role_reply(role),
+                _ => RespValue::Bulk(me.as_bytes().to_vec()),
+            }
+        });
+        let master = serve("master");
+        let (r1, r2, flagged) = (serve("slave"), serve("slave"), serve("slave"));
+        let dead = unused_addr();
+
+        let replicas = replicas_reply(&[(&r1, "slave"), (&r2, "slave"), (&flagged, "s_down,slave"), (&dead, "slave")]);
+        let master_addr = master.clone();
+        let sentinel = fake_node(move |_, args| {
+            match args[1].as_slice() {
+                b"replicas" => replicas.clone(),
+                _ => {
+                    let (host, port) = master_addr.rsplit_once(':').unwrap();
+                    RespValue::Array(vec![RespValue::Bulk(host.as_bytes().to_vec()), RespValue::Bulk(port.as_bytes().to_vec())])
+                },
+            }
+        });
+
+        let mut client = SentinelClientBuilder::new(&[&sentinel], "mymaster")
+            .read_from(ReadFrom::ReplicaRoundRobin)
+            .connect()
+            .unwrap();
+        let mut served = vec![];
+        for _ in 0..6 {
+            match client.execute(&[b"get", b"foo"]).unwrap() {
+                RespValue::Bulk(addr) => served.push(String::from_utf8(addr).unwrap()),
+                v => panic!("unexpected {:?}", v),
+            }
+        }
+        assert!(served.contains(&r1) && served.contains(&r2));
+        assert!(served.iter().all(|addr| *addr == r1 || *addr == r2));
+        assert_eq!(client.execute(&[b"set", b"foo", b"bar"]).unwrap(), RespValue::Bulk(master.as_bytes().to_vec()));
+    }
+
+    #[test]
+    fn test_sentinel_password() {
+        let auths = Arc::new(Mutex::new(vec![]));
+        let master_auths = auths.clone();
+        let master = fake_node(move |_, args| {
+            match args[0].as_slice() {
+                b"auth" | b"select" => {
+                    master_auths.lock().unwrap().push(("master", args[1].clone()));
+                    RespValue::Bulk(b"OK".to_vec())
+                },
+                _ => role_reply("master"),
+            }
+        });
+        let (sentinel_auths, master_addr) = (auths.clone(), master.clone());
+        let sentinel = fake_node(move |_, args| {
+            match args[0].as_slice() {
+                b"auth" => {
+                    sentinel_auths.lock().unwrap().push(("sentinel", args[1].clone()));
+                    RespValue::Bulk(b"OK".to_vec())
+                },
+                _ => {
+                    let (host, port) = master_addr.rsplit_once(':').unwrap();
+                    RespValue::Array(vec![RespValue::Bulk(host.as_bytes().to_vec()), RespValue::Bulk(port.as_bytes().to_vec())])
+                },
+            }
+        });
+
+        SentinelClientBuilder::new(&[&sentinel], "mymaster")
+            .password("data")
+            .sentinel_password("watch")
+            .db(3)
+            .connect()
+            .unwrap();
+        let expected = vec![("sentinel", b"watch".to_vec()), ("master", b"data".to_vec()), ("master", b"3".to_vec())];
+        assert_eq!(*auths.lock().unwrap(), expected);
+    }
+
+    #[test]
+    fn test_subscribe_events() {
+        let master = fake_node(|_, _| role_reply("master"));
+        let master_addr = master.clone();
+        // the message is sent in place of the confirmation of the last
+        // subscription.
+        let sentinel = fake_node(move |_, args| {
+            let bulk = |s: &[u8]| RespValue::Bulk(s.to_vec());
+            match args[0].as_slice() {
+                b"subscribe" => RespValue::Array(vec![bulk(b"subscribe"), bulk(&args[1]), RespValue::Int(1)]),
+                b"psubscribe" => RespValue::Array(vec![
+                    bulk(b"message"),
+                    bulk(b"+switch-master"),
+                    bulk(b"mymaster 127.0.0.1 6379 127.0.0.1 6380"),
+                ]),
+                _ => {
+                    let (host, port) = master_addr.rsplit_once(':').unwrap();
+                    RespValue::Array(vec![bulk(host.as_bytes()), bulk(port.as_bytes())])
+                },
+            }
+        });
+
+        let mut client = SentinelClient::connect(&[&sentinel], "mymaster", None).unwrap();
+        let mut events = client.subscribe_events().unwrap();
+        match events.next_event_timeout(Duration::from_secs(5)).unwrap() {
+            Some(SentinelEvent::SwitchMaster { new_addr, .. }) => assert_eq!(new_addr, "127.0.0.1:6380"),
+            e => panic!("unexpected {:?}", e),
+        }
+    }
+}
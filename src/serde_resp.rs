@@ -0,0 +1,563 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+
+use serde::de::{self, DeserializeOwned, DeserializeSeed, IntoDeserializer, Visitor};
+use serde::ser::{self, Impossible, Serialize};
+
+use super::convert::{mismatch, ConversionError, FromResp};
+use super::types::{RespValue, RuisError};
+
+// the serde data model over RespValue, to read the replies into the structs
+// and to send the structs as the arguments:
+//
+//     let mut args = ("hset", "user:1").to_args();
+//     args.extend(to_args(&user)?);
+//     conn.execute_args(&args)?;
+//     let user = conn.execute_as::<Serde<User>>(&[b"hgetall", b"user:1"])?.0;
+//
+// the structs and the maps are the flat field-value lists of HSET and
+// HGETALL, the fields of None are left out. the numbers are read from the
+// bulks too, as redis replies most of them as strings. Vec<u8> is a list of
+// numbers in serde, serde_bytes makes it a single bulk.
+
+impl de::Error for RuisError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        RuisError::CodecError(msg.to_string())
+    }
+}
+
+impl ser::Error for RuisError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        RuisError::CodecError(msg.to_string())
+    }
+}
+
+pub fn from_value<T: DeserializeOwned>(v: RespValue) -> Result<T, RuisError> {
+    T::deserialize(Deserializer(v))
+}
+
+pub fn to_value<T: Serialize + ?Sized>(value: &T) -> Result<RespValue, RuisError> {
+    value.serialize(Serializer)
+}
+
+// the value flattened into the arguments of a command, nil can not be sent.
+pub fn to_args<T: Serialize + ?Sized>(value: &T) -> Result<Vec<Vec<u8>>, RuisError> {
+    let mut args = vec![];
+    flatten(to_value(value)?, &mut args)?;
+    Ok(args)
+}
+
+fn flatten(v: RespValue, args: &mut Vec<Vec<u8>>) -> Result<(), RuisError> {
+    match v {
+        RespValue::Bulk(bs) => args.push(bs),
+        RespValue::Int(n) => args.push(n.to_string().into_bytes()),
+        RespValue::Array(items) => {
+            for item in items {
+                flatten(item, args)?;
+            }
+        },
+        v => return Err(RuisError::Conversion(ConversionError::new("argument", &v))),
+    }
+    Ok(())
+}
+
+// Serde converts the replies with serde, like
+// conn.execute_as::<Serde<User>>(&[b"hgetall", b"user:1"]).
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Serde<T>(pub T);
+
+impl<T: DeserializeOwned> FromResp for Serde<T> {
+    fn from_resp(v: RespValue) -> Result<Self, RuisError> {
+        from_value(v).map(Serde)
+    }
+}
+
+struct Deserializer(RespValue);
+
+impl Deserializer {
+    fn parse<T: FromStr>(self, expected: &'static str) -> Result<T, RuisError> {
+        let parsed = match &self.0 {
+            RespValue::Int(n) => n.to_string().parse().ok(),
+            RespValue::Bulk(bs) => std::str::from_utf8(bs).ok().and_then(|s| s.parse().ok()),
+            _ => None,
+        };
+        match parsed {
+            Some(n) => Ok(n),
+            None => mismatch(expected, self.0),
+        }
+    }
+}
+
+macro_rules! deserialize_parsed {
+    ($($method:ident => $visit:ident($t:ty)),+) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, RuisError> {
+                visitor.$visit(self.parse::<$t>(stringify!($t))?)
+            }
+        )+
+    };
+}
+
+impl<'de> de::Deserializer<'de> for Deserializer {
+    type Error = RuisError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, RuisError> {
+        match self.0 {
+            RespValue::Int(n) => visitor.visit_i64(n),
+            RespValue::Bulk(bs) => match String::from_utf8(bs) {
+                Ok(s) => visitor.visit_string(s),
+                Err(e) => visitor.visit_byte_buf(e.into_bytes()),
+            },
+            RespValue::NilBulk | RespValue::NilArray => visitor.visit_none(),
+            RespValue::Array(items) => visitor.visit_seq(SeqAccess(items.into_iter())),
+            v => mismatch("any", v),
+        }
+    }
+
+    deserialize_parsed!(
+        deserialize_i8 => visit_i8(i8),
+        deserialize_i16 => visit_i16(i16),
+        deserialize_i32 => visit_i32(i32),
+        deserialize_i64 => visit_i64(i64),
+        deserialize_u8 => visit_u8(u8),
+        deserialize_u16 => visit_u16(u16),
+        deserialize_u32 => visit_u32(u32),
+        deserialize_u64 => visit_u64(u64),
+        deserialize_f32 => visit_f32(f32),
+        deserialize_f64 => visit_f64(f64)
+    );
+
+    // the integer replies, or "1" and "0" as set by the serializer.
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, RuisError> {
+        match self.0 {
+            RespValue::Int(n) => visitor.visit_bool(n != 0),
+            RespValue::Bulk(ref bs) if bs == b"1" || bs == b"true" => visitor.visit_bool(true),
+            RespValue::Bulk(ref bs) if bs == b"0" || bs == b"false" => visitor.visit_bool(false),
+            v => mismatch("bool", v),
+        }
+    }
+
+    fn deserialize_char<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, RuisError> {
+        self.deserialize_string(visitor)
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, RuisError> {
+        self.deserialize_string(visitor)
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, RuisError> {
+        match self.0 {
+            RespValue::Bulk(bs) => match String::from_utf8(bs) {
+                Ok(s) => visitor.visit_string(s),
+                Err(e) => mismatch("String", RespValue::Bulk(e.into_bytes())),
+            },
+            RespValue::Int(n) => visitor.visit_string(n.to_string()),
+            v => mismatch("String", v),
+        }
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, RuisError> {
+        self.deserialize_byte_buf(visitor)
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, RuisError> {
+        match self.0 {
+            RespValue::Bulk(bs) => visitor.visit_byte_buf(bs),
+            v => mismatch("bytes", v),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, RuisError> {
+        match self.0 {
+            RespValue::NilBulk | RespValue::NilArray => visitor.visit_none(),
+            v => visitor.visit_some(Deserializer(v)),
+        }
+    }
+
+    // any reply but an error, like the OK of SET.
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, RuisError> {
+        self.0.into_result()?;
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, RuisError> {
+        self.deserialize_unit(visitor)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, RuisError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, RuisError> {
+        match self.0 {
+            RespValue::Array(items) => visitor.visit_seq(SeqAccess(items.into_iter())),
+            RespValue::NilArray => visitor.visit_seq(SeqAccess(vec![].into_iter())),
+            v => mismatch("seq", v),
+        }
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, RuisError> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(self, _name: &'static str, _len: usize, visitor: V) -> Result<V::Value, RuisError> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, RuisError> {
+        match self.0 {
+            RespValue::Array(items) if items.len() % 2 == 0 => visitor.visit_map(SeqAccess(items.into_iter())),
+            RespValue::NilArray => visitor.visit_map(SeqAccess(vec![].into_iter())),
+            v => mismatch("map", v),
+        }
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(self, _name: &'static str, _fields: &'static [&'static str], visitor: V) -> Result<V::Value, RuisError> {
+        self.deserialize_map(visitor)
+    }
+
+    // only the unit variants, by their names.
+    fn deserialize_enum<V: Visitor<'de>>(self, _name: &'static str, _variants: &'static [&'static str], visitor: V) -> Result<V::Value, RuisError> {
+        match self.0 {
+            RespValue::Bulk(bs) => match String::from_utf8(bs) {
+                Ok(s) => visitor.visit_enum(s.into_deserializer()),
+                Err(e) => mismatch("enum", RespValue::Bulk(e.into_bytes())),
+            },
+            v => mismatch("enum", v),
+        }
+    }
+
+    fn deserialize_identifier<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, RuisError> {
+        self.deserialize_string(visitor)
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, RuisError> {
+        visitor.visit_unit()
+    }
+}
+
+// the items of an array, or its field-value pairs as a map.
+struct SeqAccess(std::vec::IntoIter<RespValue>);
+
+impl<'de> de::SeqAccess<'de> for SeqAccess {
+    type Error = RuisError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>, RuisError> {
+        match self.0.next() {
+            Some(v) => seed.deserialize(Deserializer(v)).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.0.len())
+    }
+}
+
+impl<'de> de::MapAccess<'de> for SeqAccess {
+    type Error = RuisError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>, RuisError> {
+        de::SeqAccess::next_element_seed(self, seed)
+    }
+
+    // the length is checked to be even before.
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, RuisError> {
+        seed.deserialize(Deserializer(self.0.next().unwrap()))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.0.len() / 2)
+    }
+}
+
+struct Serializer;
+
+fn unsupported(what: &str) -> RuisError {
+    RuisError::CodecError(format!("{} is not supported", what))
+}
+
+impl ser::Serializer for Serializer {
+    type Ok = RespValue;
+    type Error = RuisError;
+    type SerializeSeq = ArraySerializer;
+    type SerializeTuple = ArraySerializer;
+    type SerializeTupleStruct = ArraySerializer;
+    type SerializeTupleVariant = Impossible<RespValue, RuisError>;
+    type SerializeMap = ArraySerializer;
+    type SerializeStruct = ArraySerializer;
+    type SerializeStructVariant = Impossible<RespValue, RuisError>;
+
+    fn serialize_bool(self, v: bool) -> Result<RespValue, RuisError> {
+        Ok(RespValue::Bulk(if v { b"1".to_vec() } else { b"0".to_vec() }))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<RespValue, RuisError> {
+        Ok(RespValue::Int(v as i64))
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<RespValue, RuisError> {
+        Ok(RespValue::Int(v as i64))
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<RespValue, RuisError> {
+        Ok(RespValue::Int(v as i64))
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<RespValue, RuisError> {
+        Ok(RespValue::Int(v))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<RespValue, RuisError> {
+        Ok(RespValue::Int(v as i64))
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<RespValue, RuisError> {
+        Ok(RespValue::Int(v as i64))
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<RespValue, RuisError> {
+        Ok(RespValue::Int(v as i64))
+    }
+
+    // the ones over i64 are sent as their digits.
+    fn serialize_u64(self, v: u64) -> Result<RespValue, RuisError> {
+        Ok(i64::try_from(v).map(RespValue::Int).unwrap_or_else(|_| RespValue::Bulk(v.to_string().into_bytes())))
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<RespValue, RuisError> {
+        Ok(RespValue::Bulk(v.to_string().into_bytes()))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<RespValue, RuisError> {
+        Ok(RespValue::Bulk(v.to_string().into_bytes()))
+    }
+
+    fn serialize_char(self, v: char) -> Result<RespValue, RuisError> {
+        Ok(RespValue::Bulk(v.to_string().into_bytes()))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<RespValue, RuisError> {
+        Ok(RespValue::Bulk(v.as_bytes().to_vec()))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<RespValue, RuisError> {
+        Ok(RespValue::Bulk(v.to_vec()))
+    }
+
+    fn serialize_none(self) -> Result<RespValue, RuisError> {
+        Ok(RespValue::NilBulk)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<RespValue, RuisError> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<RespValue, RuisError> {
+        Ok(RespValue::NilBulk)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<RespValue, RuisError> {
+        Ok(RespValue::NilBulk)
+    }
+
+    fn serialize_unit_variant(self, _name: &'static str, _index: u32, variant: &'static str) -> Result<RespValue, RuisError> {
+        Ok(RespValue::Bulk(variant.as_bytes().to_vec()))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(self, _name: &'static str, value: &T) -> Result<RespValue, RuisError> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(self, _name: &'static str, _index: u32, _variant: &'static str, _value: &T) -> Result<RespValue, RuisError> {
+        Err(unsupported("newtype variant"))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<ArraySerializer, RuisError> {
+        Ok(ArraySerializer::new(len.unwrap_or(0)))
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<ArraySerializer, RuisError> {
+        Ok(ArraySerializer::new(len))
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, len: usize) -> Result<ArraySerializer, RuisError> {
+        Ok(ArraySerializer::new(len))
+    }
+
+    fn serialize_tuple_variant(self, _name: &'static str, _index: u32, _variant: &'static str, _len: usize) -> Result<Self::SerializeTupleVariant, RuisError> {
+        Err(unsupported("tuple variant"))
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<ArraySerializer, RuisError> {
+        Ok(ArraySerializer::new(len.unwrap_or(0) * 2))
+    }
+
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<ArraySerializer, RuisError> {
+        Ok(ArraySerializer::new(len * 2))
+    }
+
+    fn serialize_struct_variant(self, _name: &'static str, _index: u32, _variant: &'static str, _len: usize) -> Result<Self::SerializeStructVariant, RuisError> {
+        Err(unsupported("struct variant"))
+    }
+}
+
+struct ArraySerializer {
+    items: Vec<RespValue>,
+    key: Option<RespValue>,
+}
+
+impl ArraySerializer {
+    fn new(len: usize) -> Self {
+        Self {
+            items: Vec::with_capacity(len),
+            key: None,
+        }
+    }
+
+    // the fields of nil are left out, as a hash can not hold them.
+    fn push_field(&mut self, key: RespValue, value: RespValue) {
+        if !matches!(value, RespValue::NilBulk | RespValue::NilArray) {
+            self.items.push(key);
+            self.items.push(value);
+        }
+    }
+}
+
+impl ser::SerializeSeq for ArraySerializer {
+    type Ok = RespValue;
+    type Error = RuisError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), RuisError> {
+        self.items.push(to_value(value)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<RespValue, RuisError> {
+        Ok(RespValue::Array(self.items))
+    }
+}
+
+impl ser::SerializeTuple for ArraySerializer {
+    type Ok = RespValue;
+    type Error = RuisError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), RuisError> {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<RespValue, RuisError> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+impl ser::SerializeTupleStruct for ArraySerializer {
+    type Ok = RespValue;
+    type Error = RuisError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), RuisError> {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<RespValue, RuisError> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+impl ser::SerializeMap for ArraySerializer {
+    type Ok = RespValue;
+    type Error = RuisError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), RuisError> {
+        self.key = Some(to_value(key)?);
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), RuisError> {
+        let key = self.key.take().ok_or_else(|| RuisError::CodecError("map value without a key".to_string()))?;
+        let value = to_value(value)?;
+        self.push_field(key, value);
+        Ok(())
+    }
+
+    fn end(self) -> Result<RespValue, RuisError> {
+        Ok(RespValue::Array(self.items))
+    }
+}
+
+impl ser::SerializeStruct for ArraySerializer {
+    type Ok = RespValue;
+    type Error = RuisError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<(), RuisError> {
+        let value = to_value(value)?;
+        self.push_field(RespValue::Bulk(key.as_bytes().to_vec()), value);
+        Ok(())
+    }
+
+    fn end(self) -> Result<RespValue, RuisError> {
+        Ok(RespValue::Array(self.items))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+    use super::*;
+    use super::super::args::ToArgs;
+    use super::super::connection::TcpConnection;
+    use super::super::testing::TestServer;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    #[serde(rename_all = "lowercase")]
+    enum Role {
+        Admin,
+        Guest,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct User {
+        name: String,
+        age: u32,
+        email: Option<String>,
+        active: bool,
+        role: Role,
+    }
+
+    #[test]
+    fn test_to_args() {
+        let user = User { name: "bob".to_string(), age: 30, email: None, active: true, role: Role::Guest };
+        let args: Vec<Vec<u8>> = ["name", "bob", "age", "30", "active", "1", "role", "guest"].iter().map(|s| s.as_bytes().to_vec()).collect();
+        assert_eq!(to_args(&user).unwrap(), args);
+        assert_eq!(to_args(&[("a", 1), ("b", 2)]).unwrap(), ("a", "1", "b", "2").to_args());
+        assert!(to_args(&None::<String>).is_err());
+        assert!(to_value(&Some(Some(1))).is_ok());
+    }
+
+    #[test]
+    fn test_from_value() {
+        let v = RespValue::Array(vec![RespValue::Bulk(b"1".to_vec()), RespValue::Int(2), RespValue::NilBulk]);
+        assert_eq!(from_value::<Vec<Option<i64>>>(v.clone()).unwrap(), vec![Some(1), Some(2), None]);
+        assert_eq!(from_value::<(String, u8, Option<String>)>(v.clone()).unwrap(), ("1".to_string(), 2, None));
+        assert!(matches!(from_value::<Vec<i64>>(v), Err(RuisError::Conversion(_))));
+        assert!(matches!(from_value::<String>(RespValue::Error(b"ERR x".to_vec())), Err(RuisError::ServerError(_))));
+        assert!(matches!(from_value::<Role>(RespValue::Bulk(b"root".to_vec())), Err(RuisError::CodecError(_))));
+        assert_eq!(from_value::<()>(RespValue::Bulk(b"OK".to_vec())).unwrap(), ());
+    }
+
+    #[test]
+    fn test_hash_roundtrip() {
+        let server = TestServer::new();
+        let mut conn = TcpConnection::connect(&server.addr(), None).unwrap();
+        let user = User { name: "alice".to_string(), age: 41, email: Some("a@example.com".to_string()), active: false, role: Role::Admin };
+        let mut args = ("hset", "user:1").to_args();
+        args.extend(to_args(&user).unwrap());
+        conn.execute_args(&args).unwrap();
+        assert_eq!(conn.execute_as::<Serde<User>>(&[b"hgetall", b"user:1"]).unwrap().0, user);
+        assert_eq!(conn.execute_as::<Serde<Option<u32>>>(&[b"hget", b"user:1", b"age"]).unwrap().0, Some(41));
+
+        // the missing fields.
+        assert!(matches!(conn.execute_as::<Serde<User>>(&[b"hgetall", b"user:2"]), Err(RuisError::CodecError(_))));
+    }
+}
@@ -0,0 +1,266 @@
+use std::io::{self, BufRead, BufReader, BufWriter, Read};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::thread;
+
+use super::resp::{RespReader, RespWriter};
+use super::types::{RespValue, RuisError};
+
+// the state of a client connection, kept across its commands.
+#[derive(Debug, Clone)]
+pub struct Session {
+    pub id: u64,
+    pub peer: SocketAddr,
+    pub authenticated: bool,
+    pub db: u32,
+    // set by the handler to close the connection after the reply, like on QUIT.
+    pub closing: bool,
+}
+
+// CommandHandler serves the commands of all the connections, each connection
+// is served on its own thread.
+pub trait CommandHandler: Send + Sync + 'static {
+    fn handle(&self, session: &mut Session, args: &[Vec<u8>]) -> RespValue;
+
+    fn connected(&self, _session: &Session) {}
+
+    fn disconnected(&self, _session: &Session) {}
+}
+
+// RespServer accepts the connections speaking RESP, both the multibulk and
+// the inline commands like the ones typed in telnet.
+pub struct RespServer<H> {
+    listener: TcpListener,
+    handler: Arc<H>,
+    next_id: Arc<AtomicU64>,
+    stopped: Arc<AtomicBool>,
+}
+
+impl<H: CommandHandler> RespServer<H> {
+    pub fn bind<A: ToSocketAddrs>(addr: A, handler: H) -> io::Result<Self> {
+        Ok(Self {
+            listener: TcpListener::bind(addr)?,
+            handler: Arc::new(handler),
+            next_id: Arc::new(AtomicU64::new(1)),
+            stopped: Arc::new(AtomicBool::new(false)),
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    // serves on the current thread until the server is stopped.
+    pub fn run(self) -> io::Result<()> {
+        for stream in self.listener.incoming() {
+            if self.stopped.load(Ordering::SeqCst) {
+                break;
+            }
+            let stream = match stream {
+                Ok(s) => s,
+                Err(_) => continue,
+            };
+            let handler = self.handler.clone();
+            let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+            thread::spawn(move || {
+                let _ = serve_conn(stream, id, &*handler);
+            });
+        }
+        Ok(())
+    }
+
+    // serves on a background thread, until the handle is dropped.
+    pub fn spawn(self) -> io::Result<ServerHandle> {
+        let addr = self.local_addr()?;
+        let stopped = self.stopped.clone();
+        thread::spawn(move || self.run());
+        Ok(ServerHandle {
+            addr,
+            stopped,
+        })
+    }
+}
+
+pub struct ServerHandle {
+    addr: SocketAddr,
+    stopped: Arc<AtomicBool>,
+}
+
+impl ServerHandle {
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    // stops accepting the connections, the connections accepted are served
+    // until the clients close them.
+    pub fn stop(&self) {
+        if !self.stopped.swap(true, Ordering::SeqCst) {
+            // wakes up the accept loop to see the flag.
+            let _ = TcpStream::connect(self.addr);
+        }
+    }
+}
+
+impl Drop for ServerHandle {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+fn serve_conn<H: CommandHandler>(stream: TcpStream, id: u64, handler: &H) -> Result<(), RuisError> {
+    stream.set_nodelay(true)?;
+    let mut session = Session {
+        id,
+        peer: stream.peer_addr()?,
+        authenticated: false,
+        db: 0,
+        closing: false,
+    };
+    let mut r = RespReader::new(BufReader::new(stream.try_clone()?));
+    let mut w = RespWriter::new(BufWriter::new(stream.try_clone()?));
+    handler.connected(&session);
+    let result = loop {
+        let args = match read_command(&mut r) {
+            Ok(Some(args)) => args,
+            Ok(None) => continue,
+            Err(e) => break Err(e),
+        };
+        let reply = handler.handle(&mut session, &args);
+        w.write(&reply)?;
+        // the pipelined commands are replied in a single write.
+        if r.get_ref().buffer().is_empty() {
+            w.flush()?;
+        }
+        if session.closing {
+            w.flush()?;
+            let _ = stream.shutdown(Shutdown::Both);
+            break Ok(());
+        }
+    };
+    handler.disconnected(&session);
+    match result {
+        Err(ref e) if e.is_connection_dropped() => Ok(()),
+        r => r,
+    }
+}
+
+// reads a multibulk command, or an inline command line. returns None on the
+// empty lines and the empty multibulks, so the handlers always get a name.
+pub fn read_command<R: BufRead>(r: &mut RespReader<R>) -> Result<Option<Vec<Vec<u8>>>, RuisError> {
+    let first = match r.get_mut().fill_buf()?.first() {
+        Some(&b) => b,
+        None => return Err(connection_closed()),
+    };
+    if first != b'*' {
+        // the inline lines are limited like the lines of RESP.
+        let max_len = r.limits().max_line_len();
+        let mut line = vec![];
+        Read::take(r.get_mut(), max_len as u64).read_until(b'\n', &mut line)?;
+        if !line.ends_with(b"\n") {
+            if line.len() == max_len {
+                return Err(RuisError::LimitExceeded(format!("inline command longer than {} bytes", max_len)));
+            }
+            return Err(connection_closed());
+        }
+        let args = split_inline(&line);
+        return Ok(if args.is_empty() { None } else { Some(args) });
+    }
+    match r.read()? {
+        RespValue::Array(items) if items.is_empty() => Ok(None),
+        RespValue::Array(items) => items.into_iter().map(|v| match v {
+            RespValue::Bulk(b) => Ok(b),
+            v => Err(RuisError::ParseFailed(format!("expected bulk strings in the command, got {:?}", v))),
+        }).collect::<Result<Vec<_>, _>>().map(Some),
+        _ => Ok(None),
+    }
+}
+
+fn connection_closed() -> RuisError {
+    io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed").into()
+}
+
+// the arguments of an inline command are separated by spaces, and might be
+// quoted with "" or ''.
+fn split_inline(line: &[u8]) -> Vec<Vec<u8>> {
+    let mut args = vec![];
+    let mut cur: Option<Vec<u8>> = None;
+    let mut quote = None;
+    for &b in line {
+        match (quote, b) {
+            (None, b'"') | (None, b'\'') => {
+                quote = Some(b);
+                cur.get_or_insert_with(Vec::new);
+            },
+            (Some(q), b) if b == q => quote = None,
+            (None, b' ') | (None, b'\t') | (None, b'\r') | (None, b'\n') => {
+                if let Some(arg) = cur.take() {
+                    args.push(arg);
+                }
+            },
+            (_, b) => cur.get_or_insert_with(Vec::new).push(b),
+        }
+    }
+    if let Some(arg) = cur {
+        args.push(arg);
+    }
+    args
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use super::*;
+    use super::super::connection::TcpConnection;
+    use super::super::resp::RespLimits;
+
+    struct Echo;
+
+    impl CommandHandler for Echo {
+        fn handle(&self, session: &mut Session, args: &[Vec<u8>]) -> RespValue {
+            match args[0].to_ascii_lowercase().as_slice() {
+                b"quit" => {
+                    session.closing = true;
+                    RespValue::Bulk(b"OK".to_vec())
+                },
+                b"id" => RespValue::Int(session.id as i64),
+                _ => RespValue::Array(args.iter().map(|a| RespValue::Bulk(a.clone())).collect()),
+            }
+        }
+    }
+
+    #[test]
+    fn test_split_inline() {
+        assert_eq!(split_inline(b"set foo \"hello world\"\r\n"), vec![b"set".to_vec(), b"foo".to_vec(), b"hello world".to_vec()]);
+        assert_eq!(split_inline(b"get ''\r\n"), vec![b"get".to_vec(), vec![]]);
+        assert!(split_inline(b"\r\n").is_empty());
+    }
+
+    #[test]
+    fn test_read_command() {
+        let mut r = RespReader::new(io::Cursor::new(b"*0\r\n*1\r\n$4\r\nping\r\n".to_vec()));
+        assert_eq!(read_command(&mut r).unwrap(), None);
+        assert_eq!(read_command(&mut r).unwrap(), Some(vec![b"ping".to_vec()]));
+
+        // an inline command never ending.
+        let mut r = RespReader::new(io::Cursor::new(b"get ".repeat(100)));
+        r.set_limits(RespLimits::new().max_bulk_len(64));
+        assert!(matches!(read_command(&mut r), Err(RuisError::LimitExceeded(_))));
+        let mut r = RespReader::new(io::Cursor::new(b"get k".to_vec()));
+        assert!(read_command(&mut r).unwrap_err().is_connection_dropped());
+    }
+
+    #[test]
+    fn test_serve() {
+        let server = RespServer::bind("127.0.0.1:0", Echo).unwrap().spawn().unwrap();
+        let mut conn = TcpConnection::connect(&server.addr().to_string(), None).unwrap();
+        let reply = conn.execute(&[b"echo", b"hello"]).unwrap();
+        assert_eq!(reply, RespValue::Array(vec![RespValue::Bulk(b"echo".to_vec()), RespValue::Bulk(b"hello".to_vec())]));
+
+        let mut stream = TcpStream::connect(server.addr()).unwrap();
+        stream.write_all(b"\r\n*0\r\nping 'a b'\r\nquit\r\n").unwrap();
+        let mut out = vec![];
+        stream.read_to_end(&mut out).unwrap();
+        assert_eq!(out, b"*2\r\n$4\r\nping\r\n$3\r\na b\r\n$2\r\nOK\r\n");
+    }
+}
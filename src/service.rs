@@ -0,0 +1,131 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::thread;
+
+use futures_channel::oneshot;
+use tower_service::Service;
+
+use super::pool::{ConnectionPool, checkin};
+use super::types::{RespValue, RuisError};
+
+// a command with its arguments, the request of the tower services.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cmd {
+    args: Vec<Vec<u8>>,
+}
+
+impl Cmd {
+    pub fn new(name: &str) -> Self {
+        Self {
+            args: vec![name.as_bytes().to_vec()],
+        }
+    }
+
+    pub fn arg<A: AsRef<[u8]>>(mut self, arg: A) -> Self {
+        self.args.push(arg.as_ref().to_vec());
+        self
+    }
+
+    pub fn args(&self) -> Vec<&[u8]> {
+        self.args.iter().map(|a| a.as_slice()).collect()
+    }
+}
+
+impl From<&[&[u8]]> for Cmd {
+    fn from(args: &[&[u8]]) -> Self {
+        Self {
+            args: args.iter().map(|a| a.to_vec()).collect(),
+        }
+    }
+}
+
+// PoolService runs the commands on the connections of a pool as a
+// tower::Service, to wrap them in the tower middlewares like Timeout,
+// RateLimit or Retry. the error replies are returned as RespValue::Error like
+// on execute().
+//
+// the connections are blocking, so each call runs on a thread of its own and
+// the future completes as the reply is read.
+#[derive(Debug, Clone)]
+pub struct PoolService {
+    pool: Arc<ConnectionPool>,
+}
+
+impl PoolService {
+    pub fn new(pool: Arc<ConnectionPool>) -> Self {
+        Self {
+            pool,
+        }
+    }
+
+    pub fn pool(&self) -> &ConnectionPool {
+        &self.pool
+    }
+}
+
+impl Service<Cmd> for PoolService {
+    type Response = RespValue;
+    type Error = RuisError;
+    type Future = ResponseFuture;
+
+    // the pool opens the connections on demand, so it is always ready.
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), RuisError>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, cmd: Cmd) -> ResponseFuture {
+        let (tx, rx) = oneshot::channel();
+        let pool = self.pool.clone();
+        thread::spawn(move || {
+            let r = pool.get().and_then(|mut conn| {
+                let r = conn.execute(&cmd.args());
+                checkin(&pool, conn, &r);
+                r
+            });
+            // the caller might have dropped the future, like on a timeout.
+            let _ = tx.send(r);
+        });
+        ResponseFuture {
+            rx,
+        }
+    }
+}
+
+pub struct ResponseFuture {
+    rx: oneshot::Receiver<Result<RespValue, RuisError>>,
+}
+
+impl Future for ResponseFuture {
+    type Output = Result<RespValue, RuisError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match Pin::new(&mut self.rx).poll(cx) {
+            Poll::Ready(Ok(r)) => Poll::Ready(r),
+            Poll::Ready(Err(_)) => Poll::Ready(Err(RuisError::Unexpected("the command thread panicked".to_string()))),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_executor::block_on;
+    use super::*;
+    use super::super::testing::TestServer;
+
+    #[test]
+    fn test_pool_service() {
+        let server = TestServer::new();
+        let pool = Arc::new(ConnectionPool::new(&server.addr(), None));
+        let mut svc = PoolService::new(pool.clone());
+
+        let set = svc.call(Cmd::new("set").arg("k").arg(42.to_string()));
+        assert_eq!(block_on(set).unwrap(), RespValue::Bulk(b"OK".to_vec()));
+        let get = svc.call(Cmd::from(&[&b"get"[..], b"k"][..]));
+        assert_eq!(block_on(get).unwrap(), RespValue::Bulk(b"42".to_vec()));
+        assert!(matches!(block_on(svc.call(Cmd::new("incrby").arg("k"))).unwrap(), RespValue::Error(_)));
+        assert_eq!(pool.idle_len(), 1);
+    }
+}
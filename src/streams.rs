@@ -0,0 +1,198 @@
+use std::io::{BufRead, Write};
+use std::time::Duration;
+
+use super::connection::GenericConnection;
+use super::convert::FromResp;
+use super::types::{RespValue, RuisError};
+
+// an entry of a stream, the fields are kept in the order they were added.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StreamEntry {
+    pub id: String,
+    pub fields: Vec<(Vec<u8>, Vec<u8>)>,
+}
+
+impl StreamEntry {
+    pub fn get(&self, field: &[u8]) -> Option<&[u8]> {
+        self.fields.iter().find(|(f, _)| f == field).map(|(_, v)| v.as_slice())
+    }
+}
+
+impl FromResp for StreamEntry {
+    fn from_resp(v: RespValue) -> Result<Self, RuisError> {
+        let (id, fields): (String, Option<Vec<Vec<u8>>>) = FromResp::from_resp(v)?;
+        // the entries deleted while pending have nil fields on XREADGROUP.
+        let mut it = fields.unwrap_or_default().into_iter();
+        let mut pairs = vec![];
+        while let Some(f) = it.next() {
+            match it.next() {
+                Some(v) => pairs.push((f, v)),
+                None => return Err(RuisError::Unexpected(format!("stream entry {}: odd number of fields", id))),
+            }
+        }
+        Ok(StreamEntry {
+            id,
+            fields: pairs,
+        })
+    }
+}
+
+// the COUNT and BLOCK of XREAD and XREADGROUP.
+#[derive(Debug, Clone, Default)]
+pub struct StreamReadOptions {
+    count: Option<usize>,
+    block: Option<Duration>,
+    noack: bool,
+}
+
+impl StreamReadOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn count(mut self, n: usize) -> Self {
+        self.count = Some(n);
+        self
+    }
+
+    // waits up to the timeout for the entries when none is there yet, a zero
+    // timeout waits forever.
+    pub fn block(mut self, timeout: Duration) -> Self {
+        self.block = Some(timeout);
+        self
+    }
+
+    // the entries read by XREADGROUP are acked right away instead of being
+    // added to the pending list.
+    pub fn noack(mut self) -> Self {
+        self.noack = true;
+        self
+    }
+}
+
+// the entries read from each stream, in the order of the streams asked for.
+pub type StreamReadReply = Vec<(Vec<u8>, Vec<StreamEntry>)>;
+
+impl<W: Write, R: BufRead> GenericConnection<W, R> {
+    // returns the id of the entry, id is "*" for the id generated by the
+    // server.
+    pub fn xadd(&mut self, key: &[u8], id: &str, fields: &[(&[u8], &[u8])]) -> Result<String, RuisError> {
+        let mut cmd: Vec<&[u8]> = vec![b"xadd", key, id.as_bytes()];
+        for (f, v) in fields {
+            cmd.extend_from_slice(&[f, v]);
+        }
+        self.execute_as(&cmd)
+    }
+
+    // start and end are the ids, or "-" and "+" for the first and the last.
+    pub fn xrange(&mut self, key: &[u8], start: &str, end: &str, count: Option<usize>) -> Result<Vec<StreamEntry>, RuisError> {
+        let count = count.map(|n| n.to_string());
+        let mut cmd: Vec<&[u8]> = vec![b"xrange", key, start.as_bytes(), end.as_bytes()];
+        if let Some(count) = &count {
+            cmd.extend_from_slice(&[b"count", count.as_bytes()]);
+        }
+        self.execute_as(&cmd)
+    }
+
+    // streams are the keys with the id after which the entries are read, "$"
+    // for the entries added from now on. a BLOCK timing out returns no entry.
+    pub fn xread(&mut self, streams: &[(&[u8], &str)], opts: &StreamReadOptions) -> Result<StreamReadReply, RuisError> {
+        self.xread_cmd(&[b"xread"], streams, opts)
+    }
+
+    // the id is ">" for the entries never delivered to the group, or an id
+    // to read the pending entries of the consumer after it.
+    pub fn xreadgroup(&mut self, group: &str, consumer: &str, streams: &[(&[u8], &str)], opts: &StreamReadOptions) -> Result<StreamReadReply, RuisError> {
+        self.xread_cmd(&[b"xreadgroup", b"group", group.as_bytes(), consumer.as_bytes()], streams, opts)
+    }
+
+    // returns the number of the entries acked.
+    pub fn xack(&mut self, key: &[u8], group: &str, ids: &[&str]) -> Result<i64, RuisError> {
+        let mut cmd: Vec<&[u8]> = vec![b"xack", key, group.as_bytes()];
+        cmd.extend(ids.iter().map(|id| id.as_bytes()));
+        self.execute_as(&cmd)
+    }
+
+    pub fn xlen(&mut self, key: &[u8]) -> Result<i64, RuisError> {
+        self.execute_as(&[b"xlen", key])
+    }
+
+    fn xread_cmd(&mut self, head: &[&[u8]], streams: &[(&[u8], &str)], opts: &StreamReadOptions) -> Result<StreamReadReply, RuisError> {
+        let count = opts.count.map(|n| n.to_string());
+        let block = opts.block.map(|d| d.as_millis().to_string());
+        let mut cmd = head.to_vec();
+        if let Some(count) = &count {
+            cmd.extend_from_slice(&[b"count", count.as_bytes()]);
+        }
+        if let Some(block) = &block {
+            cmd.extend_from_slice(&[b"block", block.as_bytes()]);
+        }
+        if opts.noack {
+            cmd.push(b"noack");
+        }
+        cmd.push(b"streams");
+        cmd.extend(streams.iter().map(|(key, _)| *key));
+        cmd.extend(streams.iter().map(|(_, id)| id.as_bytes()));
+        self.execute_as::<Option<StreamReadReply>>(&cmd).map(Option::unwrap_or_default)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use super::*;
+    use super::super::resp::{RespReader, RespWriter};
+
+    fn bulk(s: &str) -> RespValue {
+        RespValue::Bulk(s.as_bytes().to_vec())
+    }
+
+    fn entry(id: &str, fields: &[&str]) -> RespValue {
+        RespValue::Array(vec![bulk(id), RespValue::Array(fields.iter().map(|f| bulk(f)).collect())])
+    }
+
+    fn conn(replies: &[RespValue]) -> GenericConnection<Vec<u8>, io::Cursor<Vec<u8>>> {
+        let mut w = RespWriter::new(vec![]);
+        for v in replies {
+            w.write(v).unwrap();
+        }
+        GenericConnection::new(RespReader::new(io::Cursor::new(w.into_inner())), RespWriter::new(vec![]))
+    }
+
+    #[test]
+    fn test_xadd_xrange() {
+        let mut c = conn(&[
+            bulk("1-0"),
+            RespValue::Array(vec![entry("1-0", &["a", "1", "b", "2"]), entry("2-0", &["a", "3"])]),
+            RespValue::Int(2),
+        ]);
+        assert_eq!(c.xadd(b"s", "*", &[(b"a", b"1"), (b"b", b"2")]).unwrap(), "1-0");
+        let entries = c.xrange(b"s", "-", "+", Some(10)).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].get(b"b"), Some(&b"2"[..]));
+        assert_eq!(entries[1], StreamEntry { id: "2-0".to_string(), fields: vec![(b"a".to_vec(), b"3".to_vec())] });
+        assert_eq!(c.xlen(b"s").unwrap(), 2);
+        assert!(String::from_utf8_lossy(c.raw_parts().0).contains("xrange\r\n$1\r\ns\r\n$1\r\n-\r\n$1\r\n+\r\n$5\r\ncount\r\n$2\r\n10\r\n"));
+    }
+
+    #[test]
+    fn test_xreadgroup() {
+        let deleted = RespValue::Array(vec![bulk("1-0"), RespValue::NilArray]);
+        let mut c = conn(&[
+            RespValue::Array(vec![RespValue::Array(vec![bulk("s"), RespValue::Array(vec![deleted, entry("2-0", &["a", "1"])])])]),
+            RespValue::NilArray,
+            RespValue::Int(1),
+        ]);
+        let opts = StreamReadOptions::new().count(2).block(Duration::from_millis(500));
+        let reply = c.xreadgroup("g", "c1", &[(b"s", ">")], &opts).unwrap();
+        assert_eq!(reply[0].0, b"s".to_vec());
+        assert_eq!(reply[0].1[0].fields, vec![]);
+        assert_eq!(reply[0].1[1].id, "2-0");
+        // BLOCK timed out.
+        assert_eq!(c.xread(&[(b"s", "$")], &opts).unwrap(), vec![]);
+        assert_eq!(c.xack(b"s", "g", &["2-0"]).unwrap(), 1);
+
+        let written = String::from_utf8_lossy(c.raw_parts().0).into_owned();
+        assert!(written.starts_with("*11\r\n$10\r\nxreadgroup\r\n$5\r\ngroup\r\n$1\r\ng\r\n$2\r\nc1\r\n$5\r\ncount\r\n$1\r\n2\r\n$5\r\nblock\r\n$3\r\n500\r\n$7\r\nstreams\r\n$1\r\ns\r\n$1\r\n>\r\n"));
+    }
+}
@@ -0,0 +1,112 @@
+use super::hooks::CommandInfo;
+
+// the attributes of the database client spans, see
+// https://opentelemetry.io/docs/specs/semconv/database/redis/
+//
+// the statement only keeps the command name and the key, the other arguments
+// are replaced by "?" as they might carry sensitive values.
+pub fn span_attributes(cmd: &CommandInfo) -> Vec<(&'static str, String)> {
+    let mut attrs = vec![
+        ("db.system", "redis".to_string()),
+        ("db.operation", cmd.name().to_uppercase()),
+        ("db.statement", sanitized_statement(cmd)),
+    ];
+    if let Some(peer) = cmd.peer {
+        let (host, port) = match peer.rsplit_once(':') {
+            Some((host, port)) => (host, Some(port)),
+            None => (peer, None),
+        };
+        attrs.push(("net.peer.name", host.to_string()));
+        if let Some(port) = port {
+            attrs.push(("net.peer.port", port.to_string()));
+        }
+    }
+    attrs
+}
+
+fn sanitized_statement(cmd: &CommandInfo) -> String {
+    let key = cmd.key();
+    let mut parts = vec![cmd.name().to_uppercase()];
+    for arg in cmd.args.iter().skip(1) {
+        if key == Some(*arg) && parts.len() == 1 {
+            parts.push(String::from_utf8_lossy(arg).into_owned());
+        } else {
+            parts.push("?".to_string());
+        }
+    }
+    parts.join(" ")
+}
+
+#[cfg(feature = "opentelemetry")]
+pub use self::otel::OpenTelemetryHook;
+
+#[cfg(feature = "opentelemetry")]
+mod otel {
+    use std::time::{Duration, SystemTime};
+
+    use opentelemetry::KeyValue;
+    use opentelemetry::global::{self, BoxedTracer};
+    use opentelemetry::trace::{Span, SpanKind, Status, Tracer};
+
+    use super::span_attributes;
+    use super::super::hooks::{CommandHook, CommandInfo};
+    use super::super::types::{RespValue, RuisError};
+
+    // OpenTelemetryHook emits a client span per command through the tracer
+    // registered globally.
+    pub struct OpenTelemetryHook {
+        tracer: BoxedTracer,
+    }
+
+    impl OpenTelemetryHook {
+        pub fn new() -> Self {
+            Self {
+                tracer: global::tracer("ruis"),
+            }
+        }
+    }
+
+    impl Default for OpenTelemetryHook {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    impl CommandHook for OpenTelemetryHook {
+        fn on_complete(&self, cmd: &CommandInfo, elapsed: Duration, result: Result<&RespValue, &RuisError>) {
+            let end = SystemTime::now();
+            let attrs: Vec<KeyValue> = span_attributes(cmd).into_iter().map(|(k, v)| KeyValue::new(k, v)).collect();
+            let mut span = self.tracer.span_builder(cmd.name().to_uppercase())
+                .with_kind(SpanKind::Client)
+                .with_start_time(end - elapsed)
+                .with_attributes(attrs)
+                .start(&self.tracer);
+            match result {
+                Ok(RespValue::Error(msg)) => span.set_status(Status::error(String::from_utf8_lossy(msg).into_owned())),
+                Err(e) => span.set_status(Status::error(e.to_string())),
+                Ok(_) => {},
+            }
+            span.end_with_timestamp(end);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_span_attributes() {
+        let cmd = CommandInfo { args: &[b"set", b"user:1", b"secret"], peer: Some("10.0.0.1:6379") };
+        assert_eq!(span_attributes(&cmd), vec![
+            ("db.system", "redis".to_string()),
+            ("db.operation", "SET".to_string()),
+            ("db.statement", "SET user:1 ?".to_string()),
+            ("net.peer.name", "10.0.0.1".to_string()),
+            ("net.peer.port", "6379".to_string()),
+        ]);
+
+        let cmd = CommandInfo { args: &[b"auth", b"password"], peer: None };
+        assert_eq!(span_attributes(&cmd)[2], ("db.statement", "AUTH ?".to_string()));
+    }
+}
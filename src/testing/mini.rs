@@ -0,0 +1,508 @@
+use std::collections::{HashMap, VecDeque};
+use std::convert::TryInto;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use super::super::audit::glob_match;
+use super::super::server::{CommandHandler, RespServer, ServerHandle, Session};
+use super::super::types::RespValue;
+
+// TestServer serves a subset of the redis commands from memory on a random
+// port, for the tests not to depend on a redis-server running:
+//
+//   PING, AUTH, QUIT, GET, SET, DEL, UNLINK, EXISTS, EXPIRE, TTL, INCR, INCRBY,
+//   HSET, HGET, HGETALL, LPUSH, LRANGE, SCAN, TYPE, STRLEN, HLEN, LLEN,
+//   MEMORY USAGE, PTTL, DUMP, RESTORE, SELECT
+//
+// the keys expire lazily when accessed. MEMORY USAGE is the bytes of the key
+// and its value plus 16 per entry, close enough to test the tools sizing the
+// keys. the DUMP payloads are of its own format, not restorable into redis.
+pub struct TestServer {
+    handle: ServerHandle,
+}
+
+impl TestServer {
+    pub fn new() -> Self {
+        Self::start(None)
+    }
+
+    // the clients have to AUTH with the password first.
+    pub fn with_password(password: &str) -> Self {
+        Self::start(Some(password.to_string()))
+    }
+
+    fn start(password: Option<String>) -> Self {
+        let store = Store {
+            password,
+            data: Mutex::new((0..DATABASES).map(|_| HashMap::new()).collect()),
+        };
+        let server = RespServer::bind("127.0.0.1:0", store).expect("bind the test server");
+        Self {
+            handle: server.spawn().expect("spawn the test server"),
+        }
+    }
+
+    pub fn addr(&self) -> String {
+        self.handle.addr().to_string()
+    }
+}
+
+impl Default for TestServer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+enum Value {
+    Str(Vec<u8>),
+    Hash(HashMap<Vec<u8>, Vec<u8>>),
+    List(VecDeque<Vec<u8>>),
+}
+
+struct Entry {
+    value: Value,
+    expires: Option<Instant>,
+    // the order the keys were added, the SCAN cursors are the positions in
+    // this order so the keys deleted during a scan do not shift the others.
+    seq: u64,
+}
+
+static NEXT_SEQ: AtomicU64 = AtomicU64::new(1);
+
+impl Entry {
+    fn new(value: Value) -> Self {
+        Self {
+            value,
+            expires: None,
+            seq: NEXT_SEQ.fetch_add(1, Ordering::Relaxed),
+        }
+    }
+}
+
+const DATABASES: usize = 16;
+
+struct Store {
+    password: Option<String>,
+    // the keys of each database, by the index SELECTed.
+    data: Mutex<Vec<HashMap<Vec<u8>, Entry>>>,
+}
+
+fn ok() -> RespValue {
+    RespValue::Bulk(b"OK".to_vec())
+}
+
+fn err(msg: &str) -> RespValue {
+    RespValue::Error(msg.as_bytes().to_vec())
+}
+
+fn wrong_type() -> RespValue {
+    err("WRONGTYPE Operation against a key holding the wrong kind of value")
+}
+
+fn not_integer() -> RespValue {
+    err("ERR value is not an integer or out of range")
+}
+
+fn int_arg(arg: &[u8]) -> Option<i64> {
+    std::str::from_utf8(arg).ok()?.parse().ok()
+}
+
+impl CommandHandler for Store {
+    fn handle(&self, session: &mut Session, args: &[Vec<u8>]) -> RespValue {
+        let name = String::from_utf8_lossy(&args[0]).to_lowercase();
+        match name.as_str() {
+            "auth" => return self.auth(session, &args[1..]),
+            "quit" => {
+                session.closing = true;
+                return ok();
+            },
+            _ => {},
+        }
+        if self.password.is_some() && !session.authenticated {
+            return err("NOAUTH Authentication required.");
+        }
+        let arity = match name.as_str() {
+            "ping" => 1,
+            "get" | "incr" | "ttl" | "pttl" | "dump" | "hgetall" | "scan" | "type" | "strlen" | "hlen" | "llen" | "select" => 2,
+            "del" | "unlink" | "exists" => 2,
+            "set" | "expire" | "incrby" | "hget" | "lpush" | "memory" => 3,
+            "hset" | "lrange" | "restore" => 4,
+            _ => return err(&format!("ERR unknown command '{}'", name)),
+        };
+        if args.len() < arity {
+            return err(&format!("ERR wrong number of arguments for '{}' command", name));
+        }
+
+        let mut dbs = self.data.lock().unwrap();
+        let data = &mut dbs[session.db as usize];
+        let now = Instant::now();
+        data.retain(|_, e| e.expires.is_none_or(|t| t > now));
+        match name.as_str() {
+            "ping" => match args.get(1) {
+                Some(msg) => RespValue::Bulk(msg.clone()),
+                None => RespValue::Bulk(b"PONG".to_vec()),
+            },
+            "get" => match data.get(&args[1]) {
+                None => RespValue::NilBulk,
+                Some(Entry { value: Value::Str(v), .. }) => RespValue::Bulk(v.clone()),
+                Some(_) => wrong_type(),
+            },
+            "select" => match int_arg(&args[1]) {
+                Some(db) if (0..DATABASES as i64).contains(&db) => {
+                    session.db = db as u32;
+                    ok()
+                },
+                Some(_) => err("ERR DB index is out of range"),
+                None => not_integer(),
+            },
+            "set" => set(data, args),
+            "scan" => scan(data, args),
+            "memory" if args[1].eq_ignore_ascii_case(b"usage") => match data.get(&args[2]) {
+                None => RespValue::NilBulk,
+                Some(e) => {
+                    let size = match e.value {
+                        Value::Str(ref v) => v.len() + 16,
+                        Value::Hash(ref h) => h.iter().map(|(k, v)| k.len() + v.len() + 16).sum(),
+                        Value::List(ref l) => l.iter().map(|v| v.len() + 16).sum(),
+                    };
+                    RespValue::Int((args[2].len() + 16 + size) as i64)
+                },
+            },
+            "memory" => err("ERR unknown subcommand"),
+            "type" => RespValue::Bulk(match data.get(&args[1]) {
+                None => b"none".to_vec(),
+                Some(Entry { value: Value::Str(_), .. }) => b"string".to_vec(),
+                Some(Entry { value: Value::Hash(_), .. }) => b"hash".to_vec(),
+                Some(Entry { value: Value::List(_), .. }) => b"list".to_vec(),
+            }),
+            "strlen" | "hlen" | "llen" => match (name.as_str(), data.get(&args[1])) {
+                (_, None) => RespValue::Int(0),
+                ("strlen", Some(Entry { value: Value::Str(v), .. })) => RespValue::Int(v.len() as i64),
+                ("hlen", Some(Entry { value: Value::Hash(h), .. })) => RespValue::Int(h.len() as i64),
+                ("llen", Some(Entry { value: Value::List(l), .. })) => RespValue::Int(l.len() as i64),
+                _ => wrong_type(),
+            },
+            "del" | "unlink" | "exists" => {
+                let n = args[1..].iter().filter(|k| match name.as_str() {
+                    "exists" => data.contains_key(*k),
+                    _ => data.remove(*k).is_some(),
+                }).count();
+                RespValue::Int(n as i64)
+            },
+            "expire" => match (data.get_mut(&args[1]), int_arg(&args[2])) {
+                (_, None) => not_integer(),
+                (None, _) => RespValue::Int(0),
+                (Some(e), Some(secs)) => {
+                    e.expires = Some(now + Duration::from_secs(secs.max(0) as u64));
+                    RespValue::Int(1)
+                },
+            },
+            "ttl" | "pttl" => match data.get(&args[1]) {
+                None => RespValue::Int(-2),
+                Some(Entry { expires: None, .. }) => RespValue::Int(-1),
+                Some(Entry { expires: Some(t), .. }) => {
+                    let left = t.saturating_duration_since(now);
+                    match name.as_str() {
+                        "ttl" => RespValue::Int(left.as_secs_f64().round() as i64),
+                        _ => RespValue::Int(left.as_millis() as i64),
+                    }
+                },
+            },
+            "dump" => match data.get(&args[1]) {
+                None => RespValue::NilBulk,
+                Some(e) => RespValue::Bulk(dump(&e.value)),
+            },
+            "restore" => {
+                let replace = match args.get(4) {
+                    None => false,
+                    Some(opt) if opt.eq_ignore_ascii_case(b"replace") => true,
+                    Some(_) => return err("ERR syntax error"),
+                };
+                let ttl = match int_arg(&args[2]) {
+                    Some(ms) if ms >= 0 => ms as u64,
+                    Some(_) => return err("ERR Invalid TTL value, must be >= 0"),
+                    None => return not_integer(),
+                };
+                if !replace && data.contains_key(&args[1]) {
+                    return err("BUSYKEY Target key name already exists.");
+                }
+                let value = match restore(&args[3]) {
+                    Some(v) => v,
+                    None => return err("ERR DUMP payload version or checksum are wrong"),
+                };
+                let mut entry = Entry::new(value);
+                if ttl > 0 {
+                    entry.expires = Some(now + Duration::from_millis(ttl));
+                }
+                data.insert(args[1].clone(), entry);
+                ok()
+            },
+            "incr" | "incrby" => {
+                let by = match name.as_str() {
+                    "incr" => Some(1),
+                    _ => int_arg(&args[2]),
+                };
+                let entry = data.entry(args[1].clone()).or_insert_with(|| Entry::new(Value::Str(b"0".to_vec())));
+                match (&mut entry.value, by) {
+                    (Value::Str(v), Some(by)) => match int_arg(v).and_then(|n| n.checked_add(by)) {
+                        Some(n) => {
+                            *v = n.to_string().into_bytes();
+                            RespValue::Int(n)
+                        },
+                        None => not_integer(),
+                    },
+                    (Value::Str(_), None) => not_integer(),
+                    _ => wrong_type(),
+                }
+            },
+            "hset" => {
+                if !args.len().is_multiple_of(2) {
+                    return err("ERR wrong number of arguments for 'hset' command");
+                }
+                let entry = data.entry(args[1].clone()).or_insert_with(|| Entry::new(Value::Hash(HashMap::new())));
+                match entry.value {
+                    Value::Hash(ref mut h) => {
+                        let added = args[2..].chunks(2).filter(|kv| h.insert(kv[0].clone(), kv[1].clone()).is_none()).count();
+                        RespValue::Int(added as i64)
+                    },
+                    _ => wrong_type(),
+                }
+            },
+            "hget" => match data.get(&args[1]) {
+                None => RespValue::NilBulk,
+                Some(Entry { value: Value::Hash(h), .. }) => h.get(&args[2]).map_or(RespValue::NilBulk, |v| RespValue::Bulk(v.clone())),
+                Some(_) => wrong_type(),
+            },
+            "hgetall" => match data.get(&args[1]) {
+                None => RespValue::Array(vec![]),
+                Some(Entry { value: Value::Hash(h), .. }) => {
+                    let mut fields: Vec<_> = h.iter().collect();
+                    fields.sort();
+                    RespValue::Array(fields.into_iter().flat_map(|(k, v)| vec![RespValue::Bulk(k.clone()), RespValue::Bulk(v.clone())]).collect())
+                },
+                Some(_) => wrong_type(),
+            },
+            "lpush" => {
+                let entry = data.entry(args[1].clone()).or_insert_with(|| Entry::new(Value::List(VecDeque::new())));
+                match entry.value {
+                    Value::List(ref mut l) => {
+                        for v in &args[2..] {
+                            l.push_front(v.clone());
+                        }
+                        RespValue::Int(l.len() as i64)
+                    },
+                    _ => wrong_type(),
+                }
+            },
+            "lrange" => match (data.get(&args[1]), int_arg(&args[2]), int_arg(&args[3])) {
+                (_, None, _) | (_, _, None) => not_integer(),
+                (None, _, _) => RespValue::Array(vec![]),
+                (Some(Entry { value: Value::List(l), .. }), Some(start), Some(stop)) => {
+                    let len = l.len() as i64;
+                    let norm = |i: i64| if i < 0 { (len + i).max(0) } else { i };
+                    let (start, stop) = (norm(start), norm(stop).min(len - 1));
+                    let items = (start..=stop).filter_map(|i| l.get(i as usize)).map(|v| RespValue::Bulk(v.clone()));
+                    RespValue::Array(items.collect())
+                },
+                (Some(_), _, _) => wrong_type(),
+            },
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl Store {
+    fn auth(&self, session: &mut Session, args: &[Vec<u8>]) -> RespValue {
+        // AUTH <password> or AUTH <username> <password>.
+        let password = match args {
+            [password] | [_, password] => password,
+            _ => return err("ERR wrong number of arguments for 'auth' command"),
+        };
+        match self.password {
+            None => err("ERR AUTH <password> called without any password configured for the default user. Are you sure your configuration is correct?"),
+            Some(ref p) if p.as_bytes() == password.as_slice() => {
+                session.authenticated = true;
+                ok()
+            },
+            Some(_) => err("WRONGPASS invalid username-password pair or user is disabled."),
+        }
+    }
+}
+
+// SET key value [EX seconds | PX milliseconds] [NX | XX]
+fn set(data: &mut HashMap<Vec<u8>, Entry>, args: &[Vec<u8>]) -> RespValue {
+    let mut expires = None;
+    let (mut nx, mut xx) = (false, false);
+    let mut opts = args[3..].iter();
+    while let Some(opt) = opts.next() {
+        match opt.to_ascii_lowercase().as_slice() {
+            b"nx" => nx = true,
+            b"xx" => xx = true,
+            unit @ (b"ex" | b"px") => match opts.next().and_then(|n| int_arg(n)) {
+                Some(n) if n > 0 => {
+                    let ttl = if unit == b"ex" { Duration::from_secs(n as u64) } else { Duration::from_millis(n as u64) };
+                    expires = Some(Instant::now() + ttl);
+                },
+                Some(_) => return err("ERR invalid expire time in 'set' command"),
+                None => return not_integer(),
+            },
+            _ => return err("ERR syntax error"),
+        }
+    }
+    let exists = data.contains_key(&args[1]);
+    if (nx && exists) || (xx && !exists) {
+        return RespValue::NilBulk;
+    }
+    // the overwritten keys keep their place in the scans.
+    let seq = data.get(&args[1]).map(|e| e.seq);
+    let entry = data.entry(args[1].clone()).or_insert_with(|| Entry::new(Value::Str(vec![])));
+    entry.value = Value::Str(args[2].clone());
+    entry.expires = expires;
+    entry.seq = seq.unwrap_or(entry.seq);
+    ok()
+}
+
+// the payload of DUMP is a type byte followed by the length-prefixed strings.
+fn dump(value: &Value) -> Vec<u8> {
+    let (tag, items): (u8, Vec<&Vec<u8>>) = match value {
+        Value::Str(v) => (b's', vec![v]),
+        Value::Hash(h) => (b'h', h.iter().flat_map(|(k, v)| vec![k, v]).collect()),
+        Value::List(l) => (b'l', l.iter().collect()),
+    };
+    let mut out = vec![tag];
+    for item in items {
+        out.extend_from_slice(&(item.len() as u32).to_be_bytes());
+        out.extend_from_slice(item);
+    }
+    out
+}
+
+fn restore(payload: &[u8]) -> Option<Value> {
+    let (&tag, mut rest) = payload.split_first()?;
+    let mut items = vec![];
+    while !rest.is_empty() {
+        let len = u32::from_be_bytes(rest.get(..4)?.try_into().ok()?) as usize;
+        items.push(rest.get(4..4 + len)?.to_vec());
+        rest = &rest[4 + len..];
+    }
+    match tag {
+        b's' if items.len() == 1 => items.pop().map(Value::Str),
+        b'h' if items.len().is_multiple_of(2) => Some(Value::Hash(items.chunks(2).map(|kv| (kv[0].clone(), kv[1].clone())).collect())),
+        b'l' => Some(Value::List(items.into_iter().collect())),
+        _ => None,
+    }
+}
+
+// SCAN cursor [MATCH pattern] [COUNT count], the cursor is the seq of the
+// next key to return.
+fn scan(data: &HashMap<Vec<u8>, Entry>, args: &[Vec<u8>]) -> RespValue {
+    let cursor = match int_arg(&args[1]) {
+        Some(n) if n >= 0 => n as u64,
+        _ => return err("ERR invalid cursor"),
+    };
+    let (mut pattern, mut count) = (None, 10);
+    let mut opts = args[2..].iter();
+    while let Some(opt) = opts.next() {
+        match (opt.to_ascii_lowercase().as_slice(), opts.next()) {
+            (b"match", Some(p)) => pattern = Some(p),
+            (b"count", Some(n)) => match int_arg(n) {
+                Some(n) if n > 0 => count = n as usize,
+                _ => return err("ERR syntax error"),
+            },
+            _ => return err("ERR syntax error"),
+        }
+    }
+    let mut keys: Vec<(u64, &Vec<u8>)> = data.iter().map(|(k, e)| (e.seq, k)).filter(|&(seq, _)| seq >= cursor).collect();
+    keys.sort();
+    let next = match keys.get(count) {
+        Some(&(seq, _)) => seq,
+        None => 0,
+    };
+    let batch = keys.into_iter().take(count)
+        .filter(|(_, k)| pattern.is_none_or(|p| glob_match(p, k)))
+        .map(|(_, k)| RespValue::Bulk(k.to_vec()));
+    RespValue::Array(vec![RespValue::Bulk(next.to_string().into_bytes()), RespValue::Array(batch.collect())])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::super::connection::TcpConnection;
+
+    fn bulk(s: &str) -> RespValue {
+        RespValue::Bulk(s.as_bytes().to_vec())
+    }
+
+    #[test]
+    fn test_commands() {
+        let server = TestServer::new();
+        let mut conn = TcpConnection::connect(&server.addr(), None).unwrap();
+        assert_eq!(conn.execute(&[b"ping"]).unwrap(), bulk("PONG"));
+        assert_eq!(conn.execute(&[b"set", b"foo", b"bar"]).unwrap(), bulk("OK"));
+        assert_eq!(conn.execute(&[b"set", b"foo", b"baz", b"nx"]).unwrap(), RespValue::NilBulk);
+        assert_eq!(conn.execute(&[b"get", b"foo"]).unwrap(), bulk("bar"));
+        assert_eq!(conn.execute(&[b"incr", b"n"]).unwrap(), RespValue::Int(1));
+        assert_eq!(conn.execute(&[b"incrby", b"n", b"41"]).unwrap(), RespValue::Int(42));
+        assert!(conn.execute(&[b"incr", b"foo"]).unwrap().error_kind().is_some());
+        assert_eq!(conn.execute(&[b"hset", b"h", b"a", b"1", b"b", b"2"]).unwrap(), RespValue::Int(2));
+        assert_eq!(conn.execute(&[b"hget", b"h", b"b"]).unwrap(), bulk("2"));
+        assert_eq!(conn.execute(&[b"lpush", b"l", b"a", b"b"]).unwrap(), RespValue::Int(2));
+        assert_eq!(conn.execute(&[b"lrange", b"l", b"0", b"-1"]).unwrap(), RespValue::Array(vec![bulk("b"), bulk("a")]));
+        assert_eq!(conn.execute(&[b"get", b"h"]).unwrap().error_kind(), Some(crate::ErrorKind::WrongType));
+        assert_eq!(conn.execute(&[b"type", b"l"]).unwrap(), bulk("list"));
+        assert_eq!(conn.execute(&[b"hlen", b"h"]).unwrap(), RespValue::Int(2));
+        assert_eq!(conn.execute(&[b"memory", b"usage", b"h"]).unwrap(), RespValue::Int(1 + 16 + 18 * 2));
+        // foo, n and h, in the order added.
+        let reply = conn.execute(&[b"scan", b"0", b"match", b"h*", b"count", b"3"]).unwrap();
+        let cursor = match reply {
+            RespValue::Array(ref v) if v[1] == RespValue::Array(vec![bulk("h")]) => match v[0] {
+                RespValue::Bulk(ref c) => c.clone(),
+                _ => panic!("unexpected cursor {:?}", v[0]),
+            },
+            v => panic!("unexpected scan reply {:?}", v),
+        };
+        let reply = conn.execute(&[b"scan", &cursor]).unwrap();
+        assert_eq!(reply, RespValue::Array(vec![bulk("0"), RespValue::Array(vec![bulk("l")])]));
+        assert_eq!(conn.execute(&[b"del", b"foo", b"h", b"none"]).unwrap(), RespValue::Int(2));
+        assert_eq!(conn.execute(&[b"get", b"foo"]).unwrap(), RespValue::NilBulk);
+    }
+
+    #[test]
+    fn test_expire() {
+        let server = TestServer::new();
+        let mut conn = TcpConnection::connect(&server.addr(), None).unwrap();
+        conn.execute(&[b"set", b"a", b"1", b"px", b"20"]).unwrap();
+        conn.execute(&[b"set", b"b", b"1"]).unwrap();
+        assert_eq!(conn.execute(&[b"expire", b"b", b"100"]).unwrap(), RespValue::Int(1));
+        assert_eq!(conn.execute(&[b"ttl", b"b"]).unwrap(), RespValue::Int(100));
+        std::thread::sleep(Duration::from_millis(30));
+        assert_eq!(conn.execute(&[b"exists", b"a", b"b"]).unwrap(), RespValue::Int(1));
+    }
+
+    #[test]
+    fn test_dump_restore() {
+        let server = TestServer::new();
+        let mut conn = TcpConnection::connect(&server.addr(), None).unwrap();
+        conn.execute(&[b"hset", b"h", b"a", b"1", b"b", b"2"]).unwrap();
+        let payload = match conn.execute(&[b"dump", b"h"]).unwrap() {
+            RespValue::Bulk(p) => p,
+            v => panic!("unexpected dump reply {:?}", v),
+        };
+        assert_eq!(conn.execute(&[b"restore", b"h", b"0", &payload]).unwrap().error_kind(), Some(crate::ErrorKind::BusyKey));
+        assert_eq!(conn.execute(&[b"restore", b"h2", b"5000", &payload]).unwrap(), bulk("OK"));
+        assert_eq!(conn.execute(&[b"hget", b"h2", b"b"]).unwrap(), bulk("2"));
+        assert!(matches!(conn.execute(&[b"pttl", b"h2"]).unwrap(), RespValue::Int(ms) if ms > 4900 && ms <= 5000));
+        assert!(conn.execute(&[b"restore", b"h3", b"0", b"garbage"]).unwrap().error_kind().is_some());
+    }
+
+    #[test]
+    fn test_auth() {
+        let server = TestServer::with_password("secret");
+        let mut conn = TcpConnection::connect(&server.addr(), None).unwrap();
+        assert_eq!(conn.execute(&[b"get", b"a"]).unwrap().error_kind(), Some(crate::ErrorKind::NoAuth));
+        assert!(TcpConnection::connect(&server.addr(), Some("wrong")).is_err());
+        let mut conn = TcpConnection::connect(&server.addr(), Some("secret")).unwrap();
+        assert_eq!(conn.execute(&[b"get", b"a"]).unwrap(), RespValue::NilBulk);
+    }
+}
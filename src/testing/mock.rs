@@ -0,0 +1,273 @@
+use std::collections::VecDeque;
+use std::io::{self, BufRead, Read, Write};
+use std::sync::{Arc, Mutex};
+
+use super::super::args::ToArgs;
+use super::super::connection::GenericConnection;
+use super::super::resp::{RespDecoder, RespReader, RespWriter};
+use super::super::types::RespValue;
+
+// MockConnection talks to no server, the commands sent are checked against
+// the ones scripted on its Mock and answered with their canned replies:
+//
+//   let mock = Mock::new();
+//   mock.expect(&[b"get", b"k"], RespValue::Bulk(b"v".to_vec()));
+//   let mut conn = mock.connection();
+//   assert_eq!(conn.get(b"k")?, Some(b"v".to_vec()));
+//   mock.assert_done();
+//
+// the commands are expected in order and compared byte by byte. a command
+// not expected is replied with an error and fails assert_done(). reading
+// past the replies, like receive() with nothing sent, fails as a closed
+// connection instead of blocking.
+pub type MockConnection = GenericConnection<MockWriter, MockReader>;
+
+struct Expected {
+    cmd: Vec<Vec<u8>>,
+    reply: RespValue,
+    // pushed after the reply, see push_reply().
+    pushed: Vec<RespValue>,
+}
+
+#[derive(Default)]
+struct Script {
+    expected: VecDeque<Expected>,
+    // the replies encoded, not read yet.
+    replies: Vec<u8>,
+    decoder: RespDecoder,
+    unexpected: Vec<String>,
+}
+
+fn describe(cmd: &[Vec<u8>]) -> String {
+    format!("{:?}", String::from_utf8_lossy(&cmd.join(&b' ')))
+}
+
+impl Script {
+    fn receive(&mut self, data: &[u8]) -> io::Result<()> {
+        let mut next = self.decoder.feed(data);
+        loop {
+            match next.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))? {
+                Some(RespValue::Array(args)) => {
+                    let cmd = args.into_iter().map(|arg| match arg {
+                        RespValue::Bulk(bs) => bs,
+                        arg => format!("{:?}", arg).into_bytes(),
+                    }).collect::<Vec<_>>();
+                    self.reply(cmd);
+                },
+                Some(v) => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("not a command: {:?}", v))),
+                None => return Ok(()),
+            }
+            next = self.decoder.decode();
+        }
+    }
+
+    fn reply(&mut self, cmd: Vec<Vec<u8>>) {
+        let reply = match self.expected.front() {
+            Some(expected) if expected.cmd == cmd => {
+                let expected = self.expected.pop_front().unwrap();
+                self.push(&expected.reply);
+                for reply in &expected.pushed {
+                    self.push(reply);
+                }
+                return;
+            },
+            Some(expected) => {
+                let msg = format!("expected {}, got {}", describe(&expected.cmd), describe(&cmd));
+                self.unexpected.push(msg.clone());
+                RespValue::Error(format!("ERR mock: {}", msg).into_bytes())
+            },
+            None => {
+                let msg = format!("unexpected {}", describe(&cmd));
+                self.unexpected.push(msg.clone());
+                RespValue::Error(format!("ERR mock: {}", msg).into_bytes())
+            },
+        };
+        self.push(&reply);
+    }
+
+    fn push(&mut self, reply: &RespValue) {
+        let mut w = RespWriter::new(vec![]);
+        w.write(reply).expect("encode into a vec");
+        self.replies.extend(w.into_inner());
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct Mock {
+    script: Arc<Mutex<Script>>,
+}
+
+impl Mock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn expect(&self, cmd: &[&[u8]], reply: RespValue) -> &Self {
+        self.expect_cmd(cmd.iter().map(|arg| arg.to_vec()).collect(), reply)
+    }
+
+    // like expect(), with the command built as execute_args() does.
+    pub fn expect_args<A: ToArgs + ?Sized>(&self, args: &A, reply: RespValue) -> &Self {
+        self.expect_cmd(args.to_args(), reply)
+    }
+
+    fn expect_cmd(&self, cmd: Vec<Vec<u8>>, reply: RespValue) -> &Self {
+        self.script.lock().unwrap().expected.push_back(Expected {
+            cmd,
+            reply,
+            pushed: vec![],
+        });
+        self
+    }
+
+    // a reply not asked for by any command, like a message on a subscribed
+    // connection. it follows the reply of the last command expected so far,
+    // or is readable right away if none is pending.
+    pub fn push_reply(&self, reply: RespValue) -> &Self {
+        let mut script = self.script.lock().unwrap();
+        match script.expected.back_mut() {
+            Some(expected) => expected.pushed.push(reply),
+            None => script.push(&reply),
+        }
+        self
+    }
+
+    // the connections of a mock share its script.
+    pub fn connection(&self) -> MockConnection {
+        let reader = MockReader {
+            script: self.script.clone(),
+            buf: vec![],
+            pos: 0,
+        };
+        let writer = MockWriter {
+            script: self.script.clone(),
+        };
+        GenericConnection::new(RespReader::new(reader), RespWriter::new(writer))
+    }
+
+    // panics if a command was not expected, or if an expected one was not
+    // sent.
+    pub fn assert_done(&self) {
+        let script = self.script.lock().unwrap();
+        if !script.unexpected.is_empty() {
+            panic!("mock: {}", script.unexpected.join("; "));
+        }
+        if let Some(expected) = script.expected.front() {
+            panic!("mock: {} commands not sent, the first is {}", script.expected.len(), describe(&expected.cmd));
+        }
+    }
+}
+
+pub struct MockReader {
+    script: Arc<Mutex<Script>>,
+    buf: Vec<u8>,
+    pos: usize,
+}
+
+impl Read for MockReader {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        let n = {
+            let available = self.fill_buf()?;
+            let n = available.len().min(out.len());
+            out[..n].copy_from_slice(&available[..n]);
+            n
+        };
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl BufRead for MockReader {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.pos == self.buf.len() {
+            self.buf = std::mem::take(&mut self.script.lock().unwrap().replies);
+            self.pos = 0;
+        }
+        Ok(&self.buf[self.pos..])
+    }
+
+    fn consume(&mut self, n: usize) {
+        self.pos += n;
+    }
+}
+
+pub struct MockWriter {
+    script: Arc<Mutex<Script>>,
+}
+
+impl Write for MockWriter {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.script.lock().unwrap().receive(data)?;
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::super::commands::Commands;
+    use super::super::super::pipeline::Pipeline;
+    use super::super::super::pubsub::PubSub;
+    use super::super::super::types::RuisError;
+
+    #[test]
+    fn test_mock() {
+        let mock = Mock::new();
+        mock.expect(&[b"set", b"k", b"v"], RespValue::Bulk(b"OK".to_vec()))
+            .expect_args(&("get", "k"), RespValue::Bulk(b"v".to_vec()))
+            .expect(&[b"incr", b"n"], RespValue::Int(1))
+            .expect(&[b"incr", b"n"], RespValue::Int(2));
+        let mut conn = mock.connection();
+        conn.set(b"k", b"v").unwrap();
+        assert_eq!(conn.get(b"k").unwrap(), Some(b"v".to_vec()));
+        let mut pipeline = Pipeline::new();
+        pipeline.cmd(&[b"incr", b"n"]).cmd(&[b"incr", b"n"]);
+        assert_eq!(conn.execute_pipeline(&pipeline).unwrap(), vec![RespValue::Int(1), RespValue::Int(2)]);
+        mock.assert_done();
+
+        // nothing left to read.
+        assert!(conn.receive().unwrap_err().is_connection_dropped());
+    }
+
+    #[test]
+    fn test_mock_push_reply() {
+        let mock = Mock::new();
+        let subscribed = RespValue::Array(vec![RespValue::Bulk(b"subscribe".to_vec()), RespValue::Bulk(b"news".to_vec()), RespValue::Int(1)]);
+        mock.expect(&[b"subscribe", b"news"], subscribed)
+            .push_reply(RespValue::Array(vec![RespValue::Bulk(b"message".to_vec()), RespValue::Bulk(b"news".to_vec()), RespValue::Bulk(b"hi".to_vec())]));
+        let mut pubsub = PubSub::new(mock.connection());
+        pubsub.subscribe(b"news").unwrap();
+        assert_eq!(pubsub.next_message().unwrap().payload, b"hi".to_vec());
+        mock.assert_done();
+
+        // the pushed replies follow the reply of the last command expected.
+        let mock = Mock::new();
+        mock.expect(&[b"get", b"k"], RespValue::Bulk(b"v".to_vec()))
+            .push_reply(RespValue::Int(1))
+            .expect(&[b"get", b"k"], RespValue::NilBulk);
+        let mut conn = mock.connection();
+        assert_eq!(conn.get(b"k").unwrap(), Some(b"v".to_vec()));
+        assert_eq!(conn.receive().unwrap(), RespValue::Int(1));
+        assert_eq!(conn.get(b"k").unwrap(), None);
+        mock.assert_done();
+        mock.push_reply(RespValue::Int(2));
+        assert_eq!(conn.receive().unwrap(), RespValue::Int(2));
+    }
+
+    #[test]
+    fn test_mock_unexpected() {
+        let mock = Mock::new();
+        mock.expect(&[b"get", b"a"], RespValue::NilBulk);
+        let mut conn = mock.connection();
+        match conn.get(b"b") {
+            Err(RuisError::ServerError(msg)) => assert_eq!(msg, "ERR mock: expected \"get a\", got \"get b\""),
+            r => panic!("expected a server error, got {:?}", r),
+        }
+        let r = std::panic::catch_unwind(|| mock.assert_done());
+        assert!(r.is_err());
+    }
+}
@@ -0,0 +1,12 @@
+// helpers for the tests of the code talking to redis, a server in memory or a
+// mocked connection, for this crate and its users.
+
+mod mini;
+mod mock;
+mod node;
+mod redis_server;
+
+pub use self::mini::TestServer;
+pub use self::mock::{Mock, MockConnection, MockReader, MockWriter};
+pub use self::node::fake_node;
+pub use self::redis_server::{RedisServer, RedisServerBuilder};
@@ -0,0 +1,46 @@
+use std::io::BufReader;
+use std::net::TcpListener;
+use std::sync::Arc;
+use std::thread;
+
+use super::super::resp::{RespReader, RespWriter};
+use super::super::types::RespValue;
+
+// serves each connection on a thread on a random port, answering the commands
+// with the handler, which is also passed the address of the node. it scripts
+// the cluster nodes, the sentinels and the like in the tests:
+//
+//   let addr = fake_node(|me, args| match args[0].as_slice() {
+//       b"role" => RespValue::Array(vec![RespValue::Bulk(b"master".to_vec())]),
+//       _ => RespValue::Bulk(me.as_bytes().to_vec()),
+//   });
+//
+// the arguments which are not bulks are passed empty. returns the address of
+// the node.
+pub fn fake_node<F>(handler: F) -> String
+    where F: Fn(&str, &[Vec<u8>]) -> RespValue + Send + Sync + 'static {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    let handler = Arc::new(handler);
+    let node_addr = addr.clone();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = stream.unwrap();
+            let handler = handler.clone();
+            let node_addr = node_addr.clone();
+            thread::spawn(move || {
+                let mut r = RespReader::new(BufReader::new(stream.try_clone().unwrap()));
+                let mut w = RespWriter::new(stream);
+                while let Ok(RespValue::Array(args)) = r.read() {
+                    let args: Vec<Vec<u8>> = args.into_iter().map(|a| match a {
+                        RespValue::Bulk(b) => b,
+                        _ => vec![],
+                    }).collect();
+                    w.write(&handler(&node_addr, &args)).unwrap();
+                    w.flush().unwrap();
+                }
+            });
+        }
+    });
+    addr
+}
@@ -0,0 +1,174 @@
+use std::fs;
+use std::io;
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::super::connection::TcpConnection;
+
+const DEFAULT_STARTUP_TIMEOUT: Duration = Duration::from_secs(10);
+
+// RedisServerBuilder configures a redis-server started for a test. the
+// persistence is off unless overridden.
+pub struct RedisServerBuilder {
+    program: String,
+    password: Option<String>,
+    configs: Vec<(String, String)>,
+    startup_timeout: Duration,
+}
+
+impl Default for RedisServerBuilder {
+    fn default() -> Self {
+        Self {
+            program: "redis-server".to_string(),
+            password: None,
+            configs: vec![
+                ("save".to_string(), "".to_string()),
+                ("appendonly".to_string(), "no".to_string()),
+            ],
+            startup_timeout: DEFAULT_STARTUP_TIMEOUT,
+        }
+    }
+}
+
+impl RedisServerBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // the path of the redis-server binary, found in PATH by default.
+    pub fn program(mut self, program: &str) -> Self {
+        self.program = program.to_string();
+        self
+    }
+
+    pub fn password(mut self, password: &str) -> Self {
+        self.password = Some(password.to_string());
+        self
+    }
+
+    // passed as "--name value" on the command line, like ("maxmemory", "10mb").
+    pub fn config(mut self, name: &str, value: &str) -> Self {
+        self.configs.retain(|(n, _)| n != name);
+        self.configs.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn startup_timeout(mut self, timeout: Duration) -> Self {
+        self.startup_timeout = timeout;
+        self
+    }
+
+    // starts the server on a free port and waits until it answers PING.
+    pub fn start(self) -> io::Result<RedisServer> {
+        let port = TcpListener::bind("127.0.0.1:0")?.local_addr()?.port();
+        let dir = std::env::temp_dir().join(format!("ruis-redis-{}-{}", std::process::id(), port));
+        fs::create_dir_all(&dir)?;
+
+        let mut cmd = Command::new(&self.program);
+        cmd.arg("--port").arg(port.to_string())
+            .arg("--bind").arg("127.0.0.1")
+            .arg("--dir").arg(&dir)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null());
+        if let Some(ref password) = self.password {
+            cmd.arg("--requirepass").arg(password);
+        }
+        for (name, value) in &self.configs {
+            cmd.arg(format!("--{}", name)).arg(value);
+        }
+        let child = match cmd.spawn() {
+            Ok(child) => child,
+            Err(e) => {
+                let _ = fs::remove_dir_all(&dir);
+                return Err(e);
+            },
+        };
+        // from here on the drop kills the child and removes the dir.
+        let mut server = RedisServer {
+            child,
+            addr: format!("127.0.0.1:{}", port),
+            dir,
+        };
+        server.wait_ready(self.password.as_deref(), self.startup_timeout)?;
+        Ok(server)
+    }
+}
+
+// RedisServer is a redis-server child process, killed along with its dir on
+// drop.
+pub struct RedisServer {
+    child: Child,
+    addr: String,
+    dir: PathBuf,
+}
+
+impl RedisServer {
+    pub fn start() -> io::Result<RedisServer> {
+        RedisServerBuilder::new().start()
+    }
+
+    pub fn addr(&self) -> String {
+        self.addr.clone()
+    }
+
+    pub fn dir(&self) -> &PathBuf {
+        &self.dir
+    }
+
+    fn wait_ready(&mut self, password: Option<&str>, timeout: Duration) -> io::Result<()> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(status) = self.child.try_wait()? {
+                return Err(io::Error::other(format!("redis-server exited on startup: {}", status)));
+            }
+            let ready = TcpConnection::connect(&self.addr, password)
+                .and_then(|mut conn| conn.execute(&[b"ping"])?.into_result())
+                .is_ok();
+            if ready {
+                return Ok(());
+            }
+            if Instant::now() > deadline {
+                return Err(io::Error::new(io::ErrorKind::TimedOut, format!("redis-server on {} not ready", self.addr)));
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+    }
+}
+
+impl Drop for RedisServer {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_missing_program() {
+        let r = RedisServerBuilder::new().program("/nonexistent/redis-server").start();
+        assert_eq!(r.err().map(|e| e.kind()), Some(io::ErrorKind::NotFound));
+    }
+
+    // skipped when redis-server is not installed.
+    #[test]
+    fn test_start() {
+        let server = match RedisServerBuilder::new().config("maxmemory", "10mb").start() {
+            Ok(server) => server,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return,
+            Err(e) => panic!("{}", e),
+        };
+        let mut conn = TcpConnection::connect(&server.addr(), None).unwrap();
+        let reply = conn.execute(&[b"config", b"get", b"maxmemory"]).unwrap();
+        assert_eq!(format!("{:?}", reply), "Array([Bulk('maxmemory'), Bulk('10485760')])");
+        let dir = server.dir().clone();
+        drop(server);
+        assert!(!dir.exists());
+    }
+}
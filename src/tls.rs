@@ -0,0 +1,276 @@
+// the TLS connections of the tls feature, over rustls, for the servers
+// reached with rediss:// like the managed redis services.
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::io::{self, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+use rustls::{ClientConfig, ClientConnection, RootCertStore, StreamOwned};
+
+use super::connection::{GenericConnection, check_auth};
+use super::resp::{RespReader, RespWriter};
+use super::types::{RespValue, RuisError};
+
+fn invalid_input<E: fmt::Display>(what: &str, e: E) -> RuisError {
+    io::Error::new(io::ErrorKind::InvalidInput, format!("{}: {}", what, e)).into()
+}
+
+// the CAs trusted and the name checked against the certificate of the server.
+// the CAs default to the webpki roots, the name to the host of the address.
+#[derive(Clone)]
+pub struct TlsConfig {
+    // None for the webpki roots.
+    roots: Option<RootCertStore>,
+    server_name: Option<String>,
+    client_cert: Option<(Vec<CertificateDer<'static>>, Arc<PrivateKeyDer<'static>>)>,
+}
+
+impl fmt::Debug for TlsConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TlsConfig")
+            .field("roots", &self.roots.as_ref().map_or(webpki_roots::TLS_SERVER_ROOTS.len(), |r| r.len()))
+            .field("server_name", &self.server_name)
+            .field("client_cert", &self.client_cert.as_ref().map(|_| "***"))
+            .finish()
+    }
+}
+
+impl Default for TlsConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TlsConfig {
+    pub fn new() -> Self {
+        Self {
+            roots: None,
+            server_name: None,
+            client_cert: None,
+        }
+    }
+
+    // trusts the CAs in the PEM instead of the webpki roots, like for the
+    // servers with a self signed certificate. called again, the CAs add up.
+    pub fn ca_pem(mut self, pem: &[u8]) -> Result<Self, RuisError> {
+        let certs = CertificateDer::pem_slice_iter(pem).collect::<Result<Vec<_>, _>>().map_err(|e| invalid_input("ca pem", e))?;
+        if certs.is_empty() {
+            return Err(invalid_input("ca pem", "no certificate"));
+        }
+        let roots = self.roots.get_or_insert_with(RootCertStore::empty);
+        for cert in certs {
+            roots.add(cert).map_err(|e| invalid_input("ca pem", e))?;
+        }
+        Ok(self)
+    }
+
+    pub fn ca_file<P: AsRef<Path>>(self, path: P) -> Result<Self, RuisError> {
+        let pem = std::fs::read(path)?;
+        self.ca_pem(&pem)
+    }
+
+    // the name sent as SNI and checked against the certificate, for when the
+    // address is not the name on the certificate, like an IP behind a proxy.
+    pub fn server_name(mut self, name: &str) -> Self {
+        self.server_name = Some(name.to_string());
+        self
+    }
+
+    // the certificate chain and the key presented to the servers which
+    // require the client certificates.
+    pub fn client_cert_pem(mut self, cert: &[u8], key: &[u8]) -> Result<Self, RuisError> {
+        let certs = CertificateDer::pem_slice_iter(cert).collect::<Result<Vec<_>, _>>().map_err(|e| invalid_input("client cert pem", e))?;
+        let key = PrivateKeyDer::from_pem_slice(key).map_err(|e| invalid_input("client key pem", e))?;
+        self.client_cert = Some((certs, Arc::new(key)));
+        Ok(self)
+    }
+
+    fn client_config(&self) -> Result<Arc<ClientConfig>, RuisError> {
+        let roots = match &self.roots {
+            Some(roots) => roots.clone(),
+            None => RootCertStore {
+                roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+            },
+        };
+        let builder = ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .map_err(|e| invalid_input("tls config", e))?
+            .with_root_certificates(roots);
+        let config = match &self.client_cert {
+            Some((certs, key)) => builder.with_client_auth_cert(certs.clone(), key.clone_key()).map_err(|e| invalid_input("client cert", e))?,
+            None => builder.with_no_client_auth(),
+        };
+        Ok(Arc::new(config))
+    }
+
+    // the configured name, or the host of addr without the port.
+    fn name_for(&self, addr: &str) -> Result<ServerName<'static>, RuisError> {
+        let name = match &self.server_name {
+            Some(name) => name.as_str(),
+            None => match addr.rsplit_once(':') {
+                Some((host, _)) => host.trim_start_matches('[').trim_end_matches(']'),
+                None => addr,
+            },
+        };
+        ServerName::try_from(name.to_string()).map_err(|e| invalid_input("server name", e))
+    }
+}
+
+// the TLS session over the socket, shared by the reader and the writer halves
+// of the connection. a connection is used by one thread at a time, so the
+// lock is never contended.
+#[derive(Clone)]
+pub struct TlsStream {
+    inner: Arc<Mutex<StreamOwned<ClientConnection, TcpStream>>>,
+}
+
+impl TlsStream {
+    pub fn connect(addr: &str, config: &TlsConfig) -> Result<TlsStream, RuisError> {
+        let name = config.name_for(addr)?;
+        let session = ClientConnection::new(config.client_config()?, name).map_err(|e| invalid_input("tls session", e))?;
+        let sock = TcpStream::connect(addr)?;
+        sock.set_nodelay(true)?;
+        let mut stream = StreamOwned::new(session, sock);
+        // completes the handshake here, so a certificate not trusted fails
+        // the connect and not the first command.
+        while stream.conn.is_handshaking() {
+            stream.conn.complete_io(&mut stream.sock)?;
+        }
+        Ok(TlsStream {
+            inner: Arc::new(Mutex::new(stream)),
+        })
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, StreamOwned<ClientConnection, TcpStream>> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Read for TlsStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.lock().read(buf)
+    }
+}
+
+impl Write for TlsStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.lock().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.lock().flush()
+    }
+}
+
+pub type TlsConnection = GenericConnection<TlsStream, BufReader<TlsStream>>;
+
+impl TlsConnection {
+    pub fn connect(addr: &str, password_opt: Option<&str>, config: &TlsConfig) -> Result<TlsConnection, RuisError> {
+        let ws = TlsStream::connect(addr, config)?;
+        let r = RespReader::new(BufReader::new(ws.clone()));
+        let w = RespWriter::new(ws);
+        let mut conn = GenericConnection::new(r, w);
+        if let Some(password) = password_opt {
+            if let RespValue::Error(msg) = conn.auth(password)? {
+                return Err(RuisError::Auth(String::from_utf8_lossy(&msg).into_owned()));
+            }
+        }
+        Ok(conn)
+    }
+
+    pub fn connect_user(addr: &str, username: &str, password: &str, config: &TlsConfig) -> Result<TlsConnection, RuisError> {
+        let mut conn = TlsConnection::connect(addr, None, config)?;
+        check_auth(conn.auth_user(username, password)?)?;
+        Ok(conn)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::BufRead;
+    use std::net::TcpListener;
+    use std::thread;
+    use rustls::{ServerConfig, ServerConnection};
+    use super::*;
+    use super::super::client::Client;
+    use super::super::commands::Commands;
+    use super::super::pool::ConnectionPool;
+
+    const CA: &[u8] = include_bytes!("testing/tls/ca.pem");
+    const SERVER_CERT: &[u8] = include_bytes!("testing/tls/server.pem");
+    const SERVER_KEY: &[u8] = include_bytes!("testing/tls/server.key");
+
+    // a TLS server which answers PONG to each command.
+    fn serve() -> String {
+        let certs = CertificateDer::pem_slice_iter(SERVER_CERT).collect::<Result<Vec<_>, _>>().unwrap();
+        let key = PrivateKeyDer::from_pem_slice(SERVER_KEY).unwrap();
+        let config = Arc::new(ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_no_client_auth()
+            .with_single_cert(certs, key)
+            .unwrap());
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        thread::spawn(move || {
+            for sock in listener.incoming() {
+                let session = ServerConnection::new(config.clone()).unwrap();
+                let mut stream = BufReader::new(StreamOwned::new(session, sock.unwrap()));
+                thread::spawn(move || {
+                    let mut line = String::new();
+                    while stream.read_line(&mut line).map(|n| n > 0).unwrap_or(false) {
+                        // the commands are arrays of one bulk, 3 lines each.
+                        if line.matches('\n').count() == 3 {
+                            let _ = stream.get_mut().write_all(b"+PONG\r\n");
+                            line.clear();
+                        }
+                    }
+                });
+            }
+        });
+        addr
+    }
+
+    #[test]
+    fn test_tls_connection() {
+        let addr = serve();
+        let config = TlsConfig::new().ca_pem(CA).unwrap();
+        let mut conn = TlsConnection::connect(&addr, None, &config).unwrap();
+        assert_eq!(conn.execute(&[b"ping"]).unwrap(), RespValue::Bulk(b"PONG".to_vec()));
+        assert_eq!(conn.execute(&[b"ping"]).unwrap(), RespValue::Bulk(b"PONG".to_vec()));
+
+        // the certificate is not signed by the webpki roots, nor issued to
+        // the name.
+        assert!(TlsConnection::connect(&addr, None, &TlsConfig::new()).is_err());
+        let config = config.server_name("redis.example.com");
+        assert!(TlsConnection::connect(&addr, None, &config).is_err());
+    }
+
+    #[test]
+    fn test_tls_pool() {
+        let addr = serve();
+        let pool = ConnectionPool::new_tls(&addr, None, TlsConfig::new().ca_pem(CA).unwrap());
+        let mut conn = pool.get().unwrap();
+        assert_eq!(conn.execute(&[b"ping"]).unwrap(), RespValue::Bulk(b"PONG".to_vec()));
+        pool.put(conn);
+        assert_eq!(pool.idle_len(), 1);
+
+        // rediss:// urls go over TLS, checked against the webpki roots.
+        let mut client = Client::open(&format!("rediss://{}", addr)).unwrap();
+        assert!(client.execute(&[b"ping"]).is_err());
+    }
+
+    #[test]
+    fn test_tls_config() {
+        assert!(TlsConfig::new().ca_pem(b"not a pem").is_err());
+        let config = TlsConfig::new();
+        assert!(matches!(config.name_for("localhost:6380").unwrap(), ServerName::DnsName(_)));
+        assert!(matches!(config.name_for("[::1]:6380").unwrap(), ServerName::IpAddress(_)));
+        assert_eq!(config.server_name("redis.local").name_for("10.0.0.1:6380").unwrap().to_str(), "redis.local");
+    }
+}
@@ -0,0 +1,210 @@
+use std::collections::BTreeMap;
+use std::io::{BufRead, Write};
+
+use super::super::connection::GenericConnection;
+use super::super::types::{RespValue, RuisError};
+use super::scan::scan_page;
+use super::stats::percentile;
+use super::throttle::RateLimiter;
+
+const DEFAULT_SCAN_COUNT: usize = 100;
+const DEFAULT_TOP: usize = 10;
+
+#[derive(Debug, Clone)]
+pub struct BigKeysOptions {
+    pattern: Option<Vec<u8>>,
+    scan_count: usize,
+    top: usize,
+    keys_per_sec: Option<u64>,
+}
+
+impl Default for BigKeysOptions {
+    fn default() -> Self {
+        Self {
+            pattern: None,
+            scan_count: DEFAULT_SCAN_COUNT,
+            top: DEFAULT_TOP,
+            keys_per_sec: None,
+        }
+    }
+}
+
+impl BigKeysOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // the MATCH pattern of the SCAN.
+    pub fn pattern(mut self, pattern: &str) -> Self {
+        self.pattern = Some(pattern.as_bytes().to_vec());
+        self
+    }
+
+    // the COUNT hint of each SCAN.
+    pub fn scan_count(mut self, n: usize) -> Self {
+        self.scan_count = n;
+        self
+    }
+
+    // the biggest keys kept per type.
+    pub fn top(mut self, n: usize) -> Self {
+        self.top = n;
+        self
+    }
+
+    // the keys checked per second at most, each key costs a TYPE and a size
+    // command besides its share of the SCAN.
+    pub fn keys_per_sec(mut self, n: u64) -> Self {
+        self.keys_per_sec = Some(n);
+        self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BigKey {
+    pub key: Vec<u8>,
+    pub size: u64,
+}
+
+// the sizes are in bytes for the strings, and in items for the others.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TypeStats {
+    pub key_type: String,
+    pub keys: u64,
+    pub total_size: u64,
+    pub p50: u64,
+    pub p90: u64,
+    pub p99: u64,
+    pub max: u64,
+    // the biggest first.
+    pub biggest: Vec<BigKey>,
+}
+
+impl TypeStats {
+    pub fn unit(&self) -> &'static str {
+        match self.key_type.as_str() {
+            "string" => "bytes",
+            "list" | "stream" => "items",
+            "hash" => "fields",
+            _ => "members",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BigKeysReport {
+    pub keys_scanned: u64,
+    // by the type name.
+    pub types: Vec<TypeStats>,
+}
+
+// the command sizing a key of the type, the types of the modules are counted
+// but not sized.
+fn size_command(key_type: &[u8]) -> Option<&'static [u8]> {
+    match key_type {
+        b"string" => Some(b"strlen"),
+        b"list" => Some(b"llen"),
+        b"set" => Some(b"scard"),
+        b"zset" => Some(b"zcard"),
+        b"hash" => Some(b"hlen"),
+        b"stream" => Some(b"xlen"),
+        _ => None,
+    }
+}
+
+impl<W: Write, R: BufRead> GenericConnection<W, R> {
+    // SCANs the keyspace like `redis-cli --bigkeys`, to find the biggest keys
+    // of each type. the TYPE and the sizes of each SCAN batch are pipelined.
+    pub fn big_keys(&mut self, opts: &BigKeysOptions) -> Result<BigKeysReport, RuisError> {
+        let mut limiter = RateLimiter::new(opts.keys_per_sec);
+        let mut sizes: BTreeMap<String, Vec<(u64, Vec<u8>)>> = BTreeMap::new();
+        let mut keys_scanned = 0;
+        let mut cursor = b"0".to_vec();
+        loop {
+            let (next, keys) = scan_page(self, &cursor, opts.pattern.as_deref(), opts.scan_count)?;
+            let types = self.execute_batch(keys.iter().map(|k| [&b"type"[..], k]))?;
+            let types: Vec<Vec<u8>> = types.into_iter().map(|t| match t {
+                RespValue::Bulk(t) => t,
+                _ => b"none".to_vec(),
+            }).collect();
+            let sized: Vec<(&Vec<u8>, &Vec<u8>, &[u8])> = keys.iter().zip(types.iter())
+                .filter_map(|(k, t)| size_command(t).map(|cmd| (k, t, cmd)))
+                .collect();
+            let replies = self.execute_batch(sized.iter().map(|&(k, _, cmd)| [cmd, k]))?;
+            for (&(key, key_type, _), reply) in sized.iter().zip(replies) {
+                // the keys deleted since the scan have no type left.
+                if let RespValue::Int(n) = reply {
+                    let key_type = String::from_utf8_lossy(key_type).into_owned();
+                    sizes.entry(key_type).or_default().push((n as u64, key.clone()));
+                }
+            }
+            // the module types are counted without a size.
+            for (key, key_type) in keys.iter().zip(types.iter()) {
+                if key_type != b"none" && size_command(key_type).is_none() {
+                    let key_type = String::from_utf8_lossy(key_type).into_owned();
+                    sizes.entry(key_type).or_default().push((0, key.clone()));
+                }
+            }
+            keys_scanned += keys.len() as u64;
+            limiter.wait(keys.len() as u64);
+            if next == b"0" {
+                break;
+            }
+            cursor = next;
+        }
+
+        let types = sizes.into_iter().map(|(key_type, mut keys)| {
+            keys.sort_by(|a, b| b.cmp(a));
+            let ascending: Vec<u64> = keys.iter().rev().map(|&(n, _)| n).collect();
+            TypeStats {
+                key_type,
+                keys: keys.len() as u64,
+                total_size: ascending.iter().sum(),
+                p50: percentile(&ascending, 50.0).unwrap_or(0),
+                p90: percentile(&ascending, 90.0).unwrap_or(0),
+                p99: percentile(&ascending, 99.0).unwrap_or(0),
+                max: ascending.last().copied().unwrap_or(0),
+                biggest: keys.into_iter().take(opts.top).map(|(size, key)| BigKey { key, size }).collect(),
+            }
+        }).collect();
+        Ok(BigKeysReport {
+            keys_scanned,
+            types,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::super::connection::TcpConnection;
+    use super::super::super::testing::TestServer;
+
+    #[test]
+    fn test_big_keys() {
+        let server = TestServer::new();
+        let mut conn = TcpConnection::connect(&server.addr(), None).unwrap();
+        for i in 1..=20u32 {
+            let value = "x".repeat(i as usize);
+            conn.execute(&[b"set", format!("s:{}", i).as_bytes(), value.as_bytes()]).unwrap();
+        }
+        conn.execute(&[b"lpush", b"l:1", b"a", b"b", b"c"]).unwrap();
+        conn.execute(&[b"hset", b"h:1", b"f", b"v"]).unwrap();
+
+        let report = conn.big_keys(&BigKeysOptions::new().scan_count(7).top(2)).unwrap();
+        assert_eq!(report.keys_scanned, 22);
+        let types: Vec<&str> = report.types.iter().map(|t| t.key_type.as_str()).collect();
+        assert_eq!(types, vec!["hash", "list", "string"]);
+
+        let strings = &report.types[2];
+        assert_eq!((strings.keys, strings.total_size, strings.p50, strings.p90, strings.max), (20, 210, 10, 18, 20));
+        assert_eq!(strings.biggest, vec![
+            BigKey { key: b"s:20".to_vec(), size: 20 },
+            BigKey { key: b"s:19".to_vec(), size: 19 },
+        ]);
+        assert_eq!((report.types[1].max, report.types[1].unit()), (3, "items"));
+
+        let report = conn.big_keys(&BigKeysOptions::new().pattern("l:*")).unwrap();
+        assert_eq!(report.types.len(), 1);
+    }
+}
@@ -0,0 +1,137 @@
+use std::io::{BufRead, Write};
+
+use super::super::connection::GenericConnection;
+use super::super::types::{RespValue, RuisError};
+use super::scan::scan_page;
+use super::throttle::RateLimiter;
+
+const DEFAULT_SCAN_COUNT: usize = 100;
+
+#[derive(Debug, Clone)]
+pub struct DeleteOptions {
+    pattern: Vec<u8>,
+    scan_count: usize,
+    keys_per_sec: Option<u64>,
+    dry_run: bool,
+}
+
+impl DeleteOptions {
+    // the keys matching the glob pattern are deleted, like "session:*".
+    pub fn new(pattern: &str) -> Self {
+        Self {
+            pattern: pattern.as_bytes().to_vec(),
+            scan_count: DEFAULT_SCAN_COUNT,
+            keys_per_sec: None,
+            dry_run: false,
+        }
+    }
+
+    // the COUNT hint of each SCAN, the keys found by a SCAN are unlinked in a
+    // single UNLINK.
+    pub fn scan_count(mut self, n: usize) -> Self {
+        self.scan_count = n;
+        self
+    }
+
+    pub fn keys_per_sec(mut self, n: u64) -> Self {
+        self.keys_per_sec = Some(n);
+        self
+    }
+
+    // only scans and reports the keys which would be deleted.
+    pub fn dry_run(mut self, enabled: bool) -> Self {
+        self.dry_run = enabled;
+        self
+    }
+}
+
+// passed to the progress callback after each batch, the counts are the totals
+// so far.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeleteProgress<'a> {
+    pub batch: &'a [Vec<u8>],
+    pub matched: u64,
+    // the keys unlinked, less than matched if some expired or were deleted
+    // since the scan. 0 on a dry run.
+    pub deleted: u64,
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DeleteSummary {
+    pub matched: u64,
+    pub deleted: u64,
+}
+
+impl<W: Write, R: BufRead> GenericConnection<W, R> {
+    // deletes the keys matching the pattern with SCAN and UNLINK, instead of
+    // blocking the server with KEYS. the keys written during the scan might
+    // be missed, as SCAN only guarantees the keys there all along.
+    pub fn delete_matching<F>(&mut self, opts: &DeleteOptions, mut progress: F) -> Result<DeleteSummary, RuisError>
+        where F: FnMut(&DeleteProgress) {
+        if opts.pattern.is_empty() {
+            return Err(RuisError::Unexpected("empty pattern to delete".to_string()));
+        }
+        let mut limiter = RateLimiter::new(opts.keys_per_sec);
+        let mut summary = DeleteSummary::default();
+        let mut cursor = b"0".to_vec();
+        loop {
+            let (next, keys) = scan_page(self, &cursor, Some(&opts.pattern), opts.scan_count)?;
+            if !keys.is_empty() {
+                summary.matched += keys.len() as u64;
+                if !opts.dry_run {
+                    let mut cmd: Vec<&[u8]> = vec![b"unlink"];
+                    cmd.extend(keys.iter().map(|k| k.as_slice()));
+                    match self.execute(&cmd)?.into_result()? {
+                        RespValue::Int(n) => summary.deleted += n as u64,
+                        v => return Err(RuisError::Unexpected(format!("unlink: {:?}", v))),
+                    }
+                }
+                progress(&DeleteProgress {
+                    batch: &keys,
+                    matched: summary.matched,
+                    deleted: summary.deleted,
+                    dry_run: opts.dry_run,
+                });
+                limiter.wait(keys.len() as u64);
+            }
+            if next == b"0" {
+                return Ok(summary);
+            }
+            cursor = next;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::super::connection::TcpConnection;
+    use super::super::super::testing::TestServer;
+
+    #[test]
+    fn test_delete_matching() {
+        let server = TestServer::new();
+        let mut conn = TcpConnection::connect(&server.addr(), None).unwrap();
+        for i in 0..25 {
+            conn.execute(&[b"set", format!("session:{}", i).as_bytes(), b"1"]).unwrap();
+        }
+        conn.execute(&[b"set", b"user:1", b"1"]).unwrap();
+
+        let opts = DeleteOptions::new("session:*").scan_count(10);
+        let mut batches = 0;
+        let summary = conn.delete_matching(&opts.clone().dry_run(true), |p| {
+            assert!(p.dry_run);
+            batches += 1;
+        }).unwrap();
+        assert_eq!(summary, DeleteSummary { matched: 25, deleted: 0 });
+        assert_eq!(batches, 3);
+        assert_eq!(conn.execute(&[b"exists", b"session:0"]).unwrap(), RespValue::Int(1));
+
+        let mut seen = vec![];
+        let summary = conn.delete_matching(&opts, |p| seen.push((p.batch.len(), p.deleted))).unwrap();
+        assert_eq!(summary, DeleteSummary { matched: 25, deleted: 25 });
+        assert_eq!(seen.last(), Some(&(5, 25)));
+        assert_eq!(conn.execute(&[b"exists", b"session:0", b"user:1"]).unwrap(), RespValue::Int(1));
+    }
+}
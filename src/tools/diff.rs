@@ -0,0 +1,246 @@
+use std::io::{BufRead, Write};
+use std::time::Duration;
+
+use super::super::connection::GenericConnection;
+use super::super::types::{RespValue, RuisError};
+use super::scan::scan_page;
+use super::throttle::RateLimiter;
+
+const DEFAULT_SCAN_COUNT: usize = 100;
+const DEFAULT_TTL_TOLERANCE: Duration = Duration::from_secs(2);
+
+// how the values are compared.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueCheck {
+    // only the keys and their TTLs.
+    None,
+    // the DUMP payloads, which carry the RDB version of the server, so the
+    // servers of different versions always mismatch.
+    Dump,
+    // DEBUG DIGEST-VALUE, computed by the servers without moving the values,
+    // but DEBUG is often disabled on the managed services.
+    Digest,
+}
+
+#[derive(Debug, Clone)]
+pub struct DiffOptions {
+    pattern: Option<Vec<u8>>,
+    scan_count: usize,
+    values: ValueCheck,
+    ttl_tolerance: Duration,
+    keys_per_sec: Option<u64>,
+}
+
+impl Default for DiffOptions {
+    fn default() -> Self {
+        Self {
+            pattern: None,
+            scan_count: DEFAULT_SCAN_COUNT,
+            values: ValueCheck::Dump,
+            ttl_tolerance: DEFAULT_TTL_TOLERANCE,
+            keys_per_sec: None,
+        }
+    }
+}
+
+impl DiffOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // the MATCH pattern of the SCANs.
+    pub fn pattern(mut self, pattern: &str) -> Self {
+        self.pattern = Some(pattern.as_bytes().to_vec());
+        self
+    }
+
+    pub fn scan_count(mut self, n: usize) -> Self {
+        self.scan_count = n;
+        self
+    }
+
+    pub fn values(mut self, check: ValueCheck) -> Self {
+        self.values = check;
+        self
+    }
+
+    // the TTLs differing by less are taken as equal, as they tick down during
+    // the walk.
+    pub fn ttl_tolerance(mut self, tolerance: Duration) -> Self {
+        self.ttl_tolerance = tolerance;
+        self
+    }
+
+    // the keys checked per second on each side.
+    pub fn keys_per_sec(mut self, n: u64) -> Self {
+        self.keys_per_sec = Some(n);
+        self
+    }
+}
+
+// a key expiring on one side but not on the other, or with a TTL off by more
+// than the tolerance. the TTLs are in milliseconds, None without expiry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TtlMismatch {
+    pub key: Vec<u8>,
+    pub source_ttl: Option<u64>,
+    pub target_ttl: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DiffReport {
+    // the keys of the source checked on the target.
+    pub compared: u64,
+    pub missing_in_target: Vec<Vec<u8>>,
+    pub missing_in_source: Vec<Vec<u8>>,
+    pub value_mismatches: Vec<Vec<u8>>,
+    pub ttl_mismatches: Vec<TtlMismatch>,
+}
+
+impl DiffReport {
+    pub fn is_empty(&self) -> bool {
+        self.missing_in_target.is_empty()
+            && self.missing_in_source.is_empty()
+            && self.value_mismatches.is_empty()
+            && self.ttl_mismatches.is_empty()
+    }
+}
+
+// the TTL in milliseconds of a PTTL reply, None on the keys without expiry,
+// Some(None) on the keys gone.
+fn ttl_of(reply: &RespValue) -> Result<Option<Option<u64>>, RuisError> {
+    match *reply {
+        RespValue::Int(-2) => Ok(None),
+        RespValue::Int(-1) => Ok(Some(None)),
+        RespValue::Int(ms) if ms >= 0 => Ok(Some(Some(ms as u64))),
+        ref v => Err(RuisError::Unexpected(format!("pttl: {:?}", v))),
+    }
+}
+
+// the replies to compare the values of the keys.
+fn check_values<W: Write, R: BufRead>(
+    conn: &mut GenericConnection<W, R>,
+    check: ValueCheck,
+    keys: &[Vec<u8>],
+) -> Result<Vec<RespValue>, RuisError> {
+    let replies = match check {
+        ValueCheck::None => return Ok(vec![RespValue::NilBulk; keys.len()]),
+        ValueCheck::Dump => conn.execute_batch(keys.iter().map(|k| [&b"dump"[..], k]))?,
+        ValueCheck::Digest => conn.execute_batch(keys.iter().map(|k| [&b"debug"[..], b"digest-value", k]))?,
+    };
+    match replies.iter().find(|r| matches!(r, RespValue::Error(_))) {
+        Some(e) => Err(e.clone().into_result().unwrap_err()),
+        None => Ok(replies),
+    }
+}
+
+// walks the keys of both servers, to verify a migration or a replica. the keys
+// written during the walk might show as differences.
+pub fn diff<W1, R1, W2, R2>(
+    source: &mut GenericConnection<W1, R1>,
+    target: &mut GenericConnection<W2, R2>,
+    opts: &DiffOptions,
+) -> Result<DiffReport, RuisError>
+    where W1: Write, R1: BufRead, W2: Write, R2: BufRead {
+    let mut report = DiffReport::default();
+    let mut limiter = RateLimiter::new(opts.keys_per_sec);
+    let tolerance = opts.ttl_tolerance.as_millis() as u64;
+
+    // the keys of the source, checked on the target.
+    let mut cursor = b"0".to_vec();
+    loop {
+        let (next, keys) = scan_page(source, &cursor, opts.pattern.as_deref(), opts.scan_count)?;
+        let source_ttls = source.execute_batch(keys.iter().map(|k| [&b"pttl"[..], k]))?;
+        let source_values = check_values(source, opts.values, &keys)?;
+        let target_ttls = target.execute_batch(keys.iter().map(|k| [&b"pttl"[..], k]))?;
+        let target_values = check_values(target, opts.values, &keys)?;
+        for (i, key) in keys.iter().enumerate() {
+            let source_ttl = match ttl_of(&source_ttls[i])? {
+                Some(ttl) => ttl,
+                // expired or deleted since the scan.
+                None => continue,
+            };
+            report.compared += 1;
+            let target_ttl = match ttl_of(&target_ttls[i])? {
+                Some(ttl) => ttl,
+                None => {
+                    report.missing_in_target.push(key.clone());
+                    continue;
+                },
+            };
+            if source_values[i] != target_values[i] {
+                report.value_mismatches.push(key.clone());
+            }
+            let ttl_differs = match (source_ttl, target_ttl) {
+                (Some(a), Some(b)) => a.max(b) - a.min(b) > tolerance,
+                (a, b) => a.is_some() != b.is_some(),
+            };
+            if ttl_differs {
+                report.ttl_mismatches.push(TtlMismatch { key: key.clone(), source_ttl, target_ttl });
+            }
+        }
+        limiter.wait(keys.len() as u64);
+        if next == b"0" {
+            break;
+        }
+        cursor = next;
+    }
+
+    // the keys of the target, only checked to exist on the source.
+    let mut limiter = RateLimiter::new(opts.keys_per_sec);
+    let mut cursor = b"0".to_vec();
+    loop {
+        let (next, keys) = scan_page(target, &cursor, opts.pattern.as_deref(), opts.scan_count)?;
+        let exists = source.execute_batch(keys.iter().map(|k| [&b"exists"[..], k]))?;
+        let keys_len = keys.len();
+        for (key, exists) in keys.into_iter().zip(exists) {
+            if exists == RespValue::Int(0) {
+                report.missing_in_source.push(key);
+            }
+        }
+        limiter.wait(keys_len as u64);
+        if next == b"0" {
+            break;
+        }
+        cursor = next;
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::super::connection::TcpConnection;
+    use super::super::super::testing::TestServer;
+
+    #[test]
+    fn test_diff() {
+        let (a, b) = (TestServer::new(), TestServer::new());
+        let mut source = TcpConnection::connect(&a.addr(), None).unwrap();
+        let mut target = TcpConnection::connect(&b.addr(), None).unwrap();
+        for conn in [&mut source, &mut target] {
+            for i in 0..20 {
+                conn.execute(&[b"set", format!("k:{}", i).as_bytes(), b"v"]).unwrap();
+            }
+            conn.execute(&[b"hset", b"h", b"f", b"v"]).unwrap();
+            conn.execute(&[b"expire", b"k:0", b"100"]).unwrap();
+        }
+        source.execute(&[b"set", b"only-source", b"v"]).unwrap();
+        target.execute(&[b"set", b"only-target", b"v"]).unwrap();
+        target.execute(&[b"hset", b"h", b"f", b"changed"]).unwrap();
+        target.execute(&[b"expire", b"k:1", b"100"]).unwrap();
+        target.execute(&[b"expire", b"k:0", b"50"]).unwrap();
+
+        let report = diff(&mut source, &mut target, &DiffOptions::new().scan_count(6)).unwrap();
+        assert_eq!(report.compared, 22);
+        assert_eq!(report.missing_in_target, vec![b"only-source".to_vec()]);
+        assert_eq!(report.missing_in_source, vec![b"only-target".to_vec()]);
+        assert_eq!(report.value_mismatches, vec![b"h".to_vec()]);
+        let ttls: Vec<(&[u8], bool, bool)> = report.ttl_mismatches.iter()
+            .map(|m| (m.key.as_slice(), m.source_ttl.is_some(), m.target_ttl.is_some())).collect();
+        assert_eq!(ttls, vec![(&b"k:0"[..], true, true), (&b"k:1"[..], false, true)]);
+
+        let report = diff(&mut source, &mut target, &DiffOptions::new().values(ValueCheck::None).pattern("h")).unwrap();
+        assert!(report.is_empty());
+    }
+}
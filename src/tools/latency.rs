@@ -0,0 +1,129 @@
+use std::collections::VecDeque;
+use std::io::{BufRead, Write};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::super::connection::GenericConnection;
+use super::super::types::RuisError;
+use super::stats::{LatencyStats, LatencySummary};
+
+#[derive(Debug, Clone)]
+pub struct LatencyMonitorOptions {
+    interval: Duration,
+    window: Duration,
+    report_every: Duration,
+}
+
+impl Default for LatencyMonitorOptions {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_millis(10),
+            window: Duration::from_secs(15),
+            report_every: Duration::from_secs(1),
+        }
+    }
+}
+
+impl LatencyMonitorOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // the pause between a reply and the next PING.
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    // the length of the sliding window, like the 15 seconds of
+    // `redis-cli --latency-history`.
+    pub fn window(mut self, window: Duration) -> Self {
+        self.window = window;
+        self
+    }
+
+    pub fn report_every(mut self, every: Duration) -> Self {
+        self.report_every = every;
+        self
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LatencyReport {
+    // the time since the monitor started.
+    pub elapsed: Duration,
+    // the round trips of the last window.
+    pub window: LatencySummary,
+    // the round trips since the monitor started, like `redis-cli --latency`.
+    pub total: LatencySummary,
+}
+
+impl<W: Write, R: BufRead> GenericConnection<W, R> {
+    // PINGs until on_report returns false, reporting the round trip times.
+    // comparing with the latency of the server itself, like the LATENCY
+    // events, tells the network apart from a slow server.
+    pub fn latency_monitor<F>(&mut self, opts: &LatencyMonitorOptions, mut on_report: F) -> Result<(), RuisError>
+        where F: FnMut(&LatencyReport) -> bool {
+        let started = Instant::now();
+        let mut window: VecDeque<(Instant, Duration)> = VecDeque::new();
+        let mut total = LatencyStats::new();
+        let mut next_report = started + opts.report_every;
+        loop {
+            let t = Instant::now();
+            self.execute(&[b"ping"])?.into_result()?;
+            let now = Instant::now();
+            let rtt = now - t;
+            window.push_back((now, rtt));
+            total.record(rtt);
+            while window.front().is_some_and(|&(at, _)| now.duration_since(at) > opts.window) {
+                window.pop_front();
+            }
+
+            if now >= next_report {
+                next_report += opts.report_every.max(Duration::from_millis(1));
+                let mut stats = LatencyStats::new();
+                for &(_, rtt) in &window {
+                    stats.record(rtt);
+                }
+                // both have the sample just taken.
+                let report = LatencyReport {
+                    elapsed: now - started,
+                    window: stats.summary().unwrap(),
+                    total: total.summary().unwrap(),
+                };
+                if !on_report(&report) {
+                    return Ok(());
+                }
+            }
+            thread::sleep(opts.interval);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::super::connection::TcpConnection;
+    use super::super::super::testing::TestServer;
+
+    #[test]
+    fn test_latency_monitor() {
+        let server = TestServer::new();
+        let mut conn = TcpConnection::connect(&server.addr(), None).unwrap();
+        let opts = LatencyMonitorOptions::new()
+            .interval(Duration::from_millis(1))
+            .window(Duration::from_millis(20))
+            .report_every(Duration::from_millis(30));
+        let mut reports = vec![];
+        conn.latency_monitor(&opts, |r| {
+            reports.push(*r);
+            reports.len() < 3
+        }).unwrap();
+        assert_eq!(reports.len(), 3);
+        let last = reports[2];
+        assert!(last.elapsed >= Duration::from_millis(90));
+        assert!(last.window.samples < last.total.samples);
+        assert!(last.total.min <= last.window.min && last.window.max <= last.total.max);
+        assert!(last.window.p50 <= last.window.p99);
+    }
+}
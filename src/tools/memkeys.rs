@@ -0,0 +1,230 @@
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+
+use super::super::audit::glob_match;
+use super::super::connection::GenericConnection;
+use super::super::types::{RespValue, RuisError};
+use super::scan::scan_page;
+use super::throttle::RateLimiter;
+
+const DEFAULT_SCAN_COUNT: usize = 100;
+const DEFAULT_SAMPLE_LIMIT: usize = 100_000;
+
+// the group of the keys without the delimiter.
+pub const NO_PREFIX: &str = "(no prefix)";
+
+#[derive(Debug, Clone)]
+pub struct MemKeysOptions {
+    pattern: Option<Vec<u8>>,
+    scan_count: usize,
+    sample_limit: usize,
+    usage_samples: Option<u64>,
+    keys_per_sec: Option<u64>,
+    delimiter: u8,
+    depth: usize,
+    groups: Vec<(String, Vec<u8>)>,
+}
+
+impl Default for MemKeysOptions {
+    fn default() -> Self {
+        Self {
+            pattern: None,
+            scan_count: DEFAULT_SCAN_COUNT,
+            sample_limit: DEFAULT_SAMPLE_LIMIT,
+            usage_samples: None,
+            keys_per_sec: None,
+            delimiter: b':',
+            depth: 1,
+            groups: vec![],
+        }
+    }
+}
+
+impl MemKeysOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // the MATCH pattern of the SCAN.
+    pub fn pattern(mut self, pattern: &str) -> Self {
+        self.pattern = Some(pattern.as_bytes().to_vec());
+        self
+    }
+
+    // the COUNT hint of each SCAN.
+    pub fn scan_count(mut self, n: usize) -> Self {
+        self.scan_count = n;
+        self
+    }
+
+    // the keys sized at most, the scan stops early on the big keyspaces.
+    pub fn sample_limit(mut self, n: usize) -> Self {
+        self.sample_limit = n;
+        self
+    }
+
+    // the SAMPLES of MEMORY USAGE, the nested values sampled to size the
+    // aggregate types. 0 sizes all of them, which is slow on the big keys.
+    pub fn usage_samples(mut self, n: u64) -> Self {
+        self.usage_samples = Some(n);
+        self
+    }
+
+    pub fn keys_per_sec(mut self, n: u64) -> Self {
+        self.keys_per_sec = Some(n);
+        self
+    }
+
+    // the keys not in a named group are grouped by their first depth
+    // segments split by the delimiter, like "user:*" for "user:42:name".
+    pub fn delimiter(mut self, delimiter: u8) -> Self {
+        self.delimiter = delimiter;
+        self
+    }
+
+    pub fn depth(mut self, depth: usize) -> Self {
+        self.depth = depth.max(1);
+        self
+    }
+
+    // a named group of the keys matching the glob, the groups are tried in
+    // the order added before the prefix grouping.
+    pub fn group(mut self, name: &str, glob: &str) -> Self {
+        self.groups.push((name.to_string(), glob.as_bytes().to_vec()));
+        self
+    }
+
+    fn group_of(&self, key: &[u8]) -> String {
+        if let Some((name, _)) = self.groups.iter().find(|(_, glob)| glob_match(glob, key)) {
+            return name.clone();
+        }
+        let ends: Vec<usize> = key.iter().enumerate().filter(|&(_, &b)| b == self.delimiter).map(|(i, _)| i).collect();
+        match ends.get(self.depth - 1).or(ends.last()) {
+            Some(&end) => format!("{}*", String::from_utf8_lossy(&key[..=end])),
+            None => NO_PREFIX.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GroupUsage {
+    pub group: String,
+    pub keys: u64,
+    pub bytes: u64,
+    // of the bytes of all the keys sampled, in [0, 1].
+    pub share: f64,
+    pub biggest_key: Vec<u8>,
+    pub biggest_bytes: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemKeysReport {
+    pub keys_sampled: u64,
+    pub bytes_sampled: u64,
+    // the most memory first.
+    pub groups: Vec<GroupUsage>,
+}
+
+impl<W: Write, R: BufRead> GenericConnection<W, R> {
+    // samples the keys with SCAN and MEMORY USAGE, and sums up the memory by
+    // the groups of the keys.
+    pub fn mem_keys(&mut self, opts: &MemKeysOptions) -> Result<MemKeysReport, RuisError> {
+        let mut limiter = RateLimiter::new(opts.keys_per_sec);
+        let mut groups: HashMap<String, GroupUsage> = HashMap::new();
+        let (mut keys_sampled, mut bytes_sampled) = (0, 0);
+        let samples = opts.usage_samples.map(|n| n.to_string());
+        let mut cursor = b"0".to_vec();
+        loop {
+            let (next, mut keys) = scan_page(self, &cursor, opts.pattern.as_deref(), opts.scan_count)?;
+            keys.truncate(opts.sample_limit.saturating_sub(keys_sampled as usize));
+            let usages = self.execute_batch(keys.iter().map(|k| {
+                let mut cmd: Vec<&[u8]> = vec![b"memory", b"usage", k];
+                if let Some(ref n) = samples {
+                    cmd.extend_from_slice(&[b"samples", n.as_bytes()]);
+                }
+                cmd
+            }))?;
+            for (key, usage) in keys.iter().zip(usages) {
+                let bytes = match usage {
+                    RespValue::Int(n) => n as u64,
+                    // deleted since the scan.
+                    RespValue::NilBulk => continue,
+                    v @ RespValue::Error(_) => return Err(v.into_result().unwrap_err()),
+                    v => return Err(RuisError::Unexpected(format!("memory usage: {:?}", v))),
+                };
+                let name = opts.group_of(key);
+                let g = groups.entry(name.clone()).or_insert_with(|| GroupUsage {
+                    group: name,
+                    keys: 0,
+                    bytes: 0,
+                    share: 0.0,
+                    biggest_key: vec![],
+                    biggest_bytes: 0,
+                });
+                g.keys += 1;
+                g.bytes += bytes;
+                if bytes > g.biggest_bytes {
+                    g.biggest_key = key.clone();
+                    g.biggest_bytes = bytes;
+                }
+                keys_sampled += 1;
+                bytes_sampled += bytes;
+            }
+            limiter.wait(keys.len() as u64);
+            if next == b"0" || keys_sampled as usize >= opts.sample_limit {
+                break;
+            }
+            cursor = next;
+        }
+
+        let mut groups: Vec<GroupUsage> = groups.into_values().map(|mut g| {
+            g.share = if bytes_sampled == 0 { 0.0 } else { g.bytes as f64 / bytes_sampled as f64 };
+            g
+        }).collect();
+        groups.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.group.cmp(&b.group)));
+        Ok(MemKeysReport {
+            keys_sampled,
+            bytes_sampled,
+            groups,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::super::connection::TcpConnection;
+    use super::super::super::testing::TestServer;
+
+    #[test]
+    fn test_group_of() {
+        let opts = MemKeysOptions::new().group("sessions", "sess*");
+        assert_eq!(opts.group_of(b"user:42:name"), "user:*");
+        assert_eq!(opts.group_of(b"session:1"), "sessions");
+        assert_eq!(opts.group_of(b"counter"), NO_PREFIX);
+        let opts = MemKeysOptions::new().depth(2).delimiter(b'/');
+        assert_eq!(opts.group_of(b"a/b/c"), "a/b/*");
+        assert_eq!(opts.group_of(b"a/b"), "a/*");
+    }
+
+    #[test]
+    fn test_mem_keys() {
+        let server = TestServer::new();
+        let mut conn = TcpConnection::connect(&server.addr(), None).unwrap();
+        for i in 0..3 {
+            // 6 + 16 + 10 + 16 bytes each.
+            conn.execute(&[b"set", format!("user:{}", i).as_bytes(), b"0123456789"]).unwrap();
+        }
+        // 5 + 16 + 20 + 16 bytes.
+        conn.execute(&[b"set", b"big:1", &[b'x'; 20]]).unwrap();
+
+        let report = conn.mem_keys(&MemKeysOptions::new().scan_count(2)).unwrap();
+        assert_eq!((report.keys_sampled, report.bytes_sampled), (4, 48 * 3 + 57));
+        let groups: Vec<(&str, u64, u64)> = report.groups.iter().map(|g| (g.group.as_str(), g.keys, g.bytes)).collect();
+        assert_eq!(groups, vec![("user:*", 3, 144), ("big:*", 1, 57)]);
+        assert!((report.groups[0].share - 144.0 / 201.0).abs() < 1e-9);
+
+        let report = conn.mem_keys(&MemKeysOptions::new().sample_limit(3)).unwrap();
+        assert_eq!(report.keys_sampled, 3);
+    }
+}
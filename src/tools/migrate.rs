@@ -0,0 +1,317 @@
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use super::super::connection::TcpConnection;
+use super::super::restore::dump_keys;
+use super::super::types::{ErrorKind, RespValue, RuisError};
+use super::scan::scan_page;
+use super::throttle::RateLimiter;
+
+const DEFAULT_SCAN_COUNT: usize = 100;
+const DEFAULT_WORKERS: usize = 4;
+const DEFAULT_RETRIES: u32 = 3;
+const DEFAULT_RETRY_BACKOFF: Duration = Duration::from_millis(100);
+
+// what to do with the keys already on the target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OnExisting {
+    // keeps the target value, the key counts as skipped.
+    Skip,
+    // overwrites with RESTORE REPLACE.
+    Replace,
+}
+
+#[derive(Debug, Clone)]
+pub struct MigrateOptions {
+    source_password: Option<String>,
+    target_password: Option<String>,
+    pattern: Option<Vec<u8>>,
+    scan_count: usize,
+    workers: usize,
+    retries: u32,
+    retry_backoff: Duration,
+    keys_per_sec: Option<u64>,
+    on_existing: OnExisting,
+}
+
+impl Default for MigrateOptions {
+    fn default() -> Self {
+        Self {
+            source_password: None,
+            target_password: None,
+            pattern: None,
+            scan_count: DEFAULT_SCAN_COUNT,
+            workers: DEFAULT_WORKERS,
+            retries: DEFAULT_RETRIES,
+            retry_backoff: DEFAULT_RETRY_BACKOFF,
+            keys_per_sec: None,
+            on_existing: OnExisting::Skip,
+        }
+    }
+}
+
+impl MigrateOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn source_password(mut self, password: &str) -> Self {
+        self.source_password = Some(password.to_string());
+        self
+    }
+
+    pub fn target_password(mut self, password: &str) -> Self {
+        self.target_password = Some(password.to_string());
+        self
+    }
+
+    // the MATCH pattern of the SCAN, all the keys by default.
+    pub fn pattern(mut self, pattern: &str) -> Self {
+        self.pattern = Some(pattern.as_bytes().to_vec());
+        self
+    }
+
+    // the COUNT hint of each SCAN, the keys of a SCAN are copied as a batch.
+    pub fn scan_count(mut self, n: usize) -> Self {
+        self.scan_count = n;
+        self
+    }
+
+    // the batches copied in parallel, each worker has its own connections.
+    pub fn workers(mut self, n: usize) -> Self {
+        self.workers = n.max(1);
+        self
+    }
+
+    // the retries of a batch failed on a connection error, reconnecting
+    // before each retry.
+    pub fn retries(mut self, n: u32, backoff: Duration) -> Self {
+        self.retries = n;
+        self.retry_backoff = backoff;
+        self
+    }
+
+    pub fn keys_per_sec(mut self, n: u64) -> Self {
+        self.keys_per_sec = Some(n);
+        self
+    }
+
+    pub fn on_existing(mut self, on_existing: OnExisting) -> Self {
+        self.on_existing = on_existing;
+        self
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MigrateSummary {
+    pub scanned: u64,
+    pub copied: u64,
+    // already on the target with OnExisting::Skip.
+    pub skipped: u64,
+    // expired or deleted on the source since the scan.
+    pub missing: u64,
+    // the keys not copied, with the reason.
+    pub failed: Vec<(Vec<u8>, String)>,
+}
+
+impl MigrateSummary {
+    // the keys done with, copied or not.
+    pub fn done(&self) -> u64 {
+        self.copied + self.skipped + self.missing + self.failed.len() as u64
+    }
+}
+
+// copies the keys from the source to the target with DUMP and RESTORE, keeping
+// the TTLs. the progress is called on the calling thread as the batches are
+// done, the last call has the summary returned.
+//
+// the keys written on the source during the migration might be missed or
+// copied with an older value, so the writes are better cut over afterwards
+// and checked with a diff.
+pub fn migrate<F>(source: &str, target: &str, opts: &MigrateOptions, mut progress: F) -> Result<MigrateSummary, RuisError>
+    where F: FnMut(&MigrateSummary) {
+    let mut scanner = TcpConnection::connect(source, opts.source_password.as_deref())?;
+    // fails early on a bad target, not in the workers.
+    TcpConnection::connect(target, opts.target_password.as_deref())?;
+
+    let (batch_tx, batch_rx) = mpsc::sync_channel::<Vec<Vec<u8>>>(opts.workers * 2);
+    let batch_rx = Arc::new(Mutex::new(batch_rx));
+    let (done_tx, done_rx) = mpsc::channel::<MigrateSummary>();
+    let mut workers = vec![];
+    for _ in 0..opts.workers {
+        let (batch_rx, done_tx) = (batch_rx.clone(), done_tx.clone());
+        let mut worker = Worker {
+            source: source.to_string(),
+            target: target.to_string(),
+            opts: opts.clone(),
+            conns: None,
+        };
+        workers.push(thread::spawn(move || loop {
+            let batch = match batch_rx.lock().unwrap().recv() {
+                Ok(batch) => batch,
+                Err(_) => return,
+            };
+            if done_tx.send(worker.copy_batch(&batch)).is_err() {
+                return;
+            }
+        }));
+    }
+    drop(done_tx);
+
+    let mut summary = MigrateSummary::default();
+    let mut add = |summary: &mut MigrateSummary, done: MigrateSummary| {
+        summary.copied += done.copied;
+        summary.skipped += done.skipped;
+        summary.missing += done.missing;
+        summary.failed.extend(done.failed);
+        progress(summary);
+    };
+    let mut limiter = RateLimiter::new(opts.keys_per_sec);
+    let mut cursor = b"0".to_vec();
+    let scanned = loop {
+        let (next, keys) = match scan_page(&mut scanner, &cursor, opts.pattern.as_deref(), opts.scan_count) {
+            Ok(page) => page,
+            Err(e) => break Err(e),
+        };
+        if !keys.is_empty() {
+            summary.scanned += keys.len() as u64;
+            limiter.wait(keys.len() as u64);
+            // blocks while the workers are behind.
+            if batch_tx.send(keys).is_err() {
+                break Err(RuisError::Unexpected("the migration workers exited".to_string()));
+            }
+        }
+        while let Ok(done) = done_rx.try_recv() {
+            add(&mut summary, done);
+        }
+        if next == b"0" {
+            break Ok(());
+        }
+        cursor = next;
+    };
+    drop(batch_tx);
+    for done in done_rx {
+        add(&mut summary, done);
+    }
+    for worker in workers {
+        let _ = worker.join();
+    }
+    scanned.map(|_| summary)
+}
+
+struct Worker {
+    source: String,
+    target: String,
+    opts: MigrateOptions,
+    conns: Option<(TcpConnection, TcpConnection)>,
+}
+
+impl Worker {
+    fn copy_batch(&mut self, keys: &[Vec<u8>]) -> MigrateSummary {
+        let mut attempt = 0;
+        loop {
+            match self.try_copy(keys) {
+                Ok(done) => return done,
+                Err(e) => {
+                    // the replies might be out of sync, the next try
+                    // reconnects.
+                    self.conns = None;
+                    if attempt >= self.opts.retries {
+                        return MigrateSummary {
+                            failed: keys.iter().map(|k| (k.clone(), e.to_string())).collect(),
+                            ..MigrateSummary::default()
+                        };
+                    }
+                    attempt += 1;
+                    thread::sleep(self.opts.retry_backoff * attempt);
+                },
+            }
+        }
+    }
+
+    fn try_copy(&mut self, keys: &[Vec<u8>]) -> Result<MigrateSummary, RuisError> {
+        if self.conns.is_none() {
+            let source = TcpConnection::connect(&self.source, self.opts.source_password.as_deref())?;
+            let target = TcpConnection::connect(&self.target, self.opts.target_password.as_deref())?;
+            self.conns = Some((source, target));
+        }
+        let (source, target) = self.conns.as_mut().unwrap();
+
+        let mut done = MigrateSummary::default();
+        let mut restores = vec![];
+        for (key, dumped) in keys.iter().zip(dump_keys(source, keys)?) {
+            match dumped {
+                Ok(None) => done.missing += 1,
+                Ok(Some((ttl, payload))) => restores.push((key, ttl.to_string(), payload)),
+                Err(e) => done.failed.push((key.clone(), e.to_string())),
+            }
+        }
+        let replace = self.opts.on_existing == OnExisting::Replace;
+        let replies = target.execute_batch(restores.iter().map(|(key, ttl, payload)| {
+            let mut cmd: Vec<&[u8]> = vec![b"restore", key, ttl.as_bytes(), payload];
+            if replace {
+                cmd.push(b"replace");
+            }
+            cmd
+        }))?;
+        for ((key, _, _), reply) in restores.iter().zip(replies) {
+            match reply {
+                RespValue::Error(ref msg) => match ErrorKind::parse(msg) {
+                    ErrorKind::BusyKey => done.skipped += 1,
+                    _ => done.failed.push((key.to_vec(), String::from_utf8_lossy(msg).into_owned())),
+                },
+                _ => done.copied += 1,
+            }
+        }
+        Ok(done)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::super::testing::TestServer;
+
+    #[test]
+    fn test_migrate() {
+        let (source, target) = (TestServer::new(), TestServer::with_password("secret"));
+        let mut src = TcpConnection::connect(&source.addr(), None).unwrap();
+        for i in 0..50 {
+            src.execute(&[b"set", format!("k:{}", i).as_bytes(), format!("v{}", i).as_bytes()]).unwrap();
+        }
+        src.execute(&[b"expire", b"k:0", b"100"]).unwrap();
+        src.execute(&[b"lpush", b"list", b"a", b"b"]).unwrap();
+        let mut dst = TcpConnection::connect(&target.addr(), Some("secret")).unwrap();
+        dst.execute(&[b"set", b"k:1", b"kept"]).unwrap();
+
+        let opts = MigrateOptions::new().target_password("secret").scan_count(7).workers(3);
+        let mut calls = 0;
+        let summary = migrate(&source.addr(), &target.addr(), &opts, |s| {
+            assert!(s.done() <= s.scanned);
+            calls += 1;
+        }).unwrap();
+        assert_eq!((summary.scanned, summary.copied, summary.skipped, summary.missing), (51, 50, 1, 0));
+        assert!(summary.failed.is_empty());
+        assert_eq!(calls, 8);
+        assert_eq!(dst.execute(&[b"get", b"k:1"]).unwrap(), RespValue::Bulk(b"kept".to_vec()));
+        assert_eq!(dst.execute(&[b"get", b"k:49"]).unwrap(), RespValue::Bulk(b"v49".to_vec()));
+        assert_eq!(dst.execute(&[b"ttl", b"k:0"]).unwrap(), RespValue::Int(100));
+        assert_eq!(dst.execute(&[b"ttl", b"k:2"]).unwrap(), RespValue::Int(-1));
+        assert_eq!(dst.execute(&[b"llen", b"list"]).unwrap(), RespValue::Int(2));
+
+        let opts = opts.on_existing(OnExisting::Replace).pattern("k:1");
+        let summary = migrate(&source.addr(), &target.addr(), &opts, |_| {}).unwrap();
+        assert_eq!((summary.scanned, summary.copied), (1, 1));
+        assert_eq!(dst.execute(&[b"get", b"k:1"]).unwrap(), RespValue::Bulk(b"v1".to_vec()));
+    }
+
+    #[test]
+    fn test_target_down() {
+        let source = TestServer::new();
+        // nothing listens on the port once the listener is dropped.
+        let target = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().to_string();
+        assert!(migrate(&source.addr(), &target, &MigrateOptions::new(), |_| {}).is_err());
+    }
+}
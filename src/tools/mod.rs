@@ -0,0 +1,20 @@
+// the operational utilities built on the client, shared by the binaries.
+
+mod bigkeys;
+mod delete;
+mod diff;
+mod latency;
+mod memkeys;
+mod migrate;
+mod scan;
+mod stats;
+mod throttle;
+
+pub use self::bigkeys::{BigKey, BigKeysOptions, BigKeysReport, TypeStats};
+pub use self::delete::{DeleteOptions, DeleteProgress, DeleteSummary};
+pub use self::diff::{DiffOptions, DiffReport, TtlMismatch, ValueCheck, diff};
+pub use self::latency::{LatencyMonitorOptions, LatencyReport};
+pub use self::memkeys::{GroupUsage, MemKeysOptions, MemKeysReport, NO_PREFIX};
+pub use self::migrate::{MigrateOptions, MigrateSummary, OnExisting, migrate};
+pub use self::stats::{LatencyStats, LatencySummary, percentile};
+pub use self::throttle::RateLimiter;
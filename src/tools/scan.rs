@@ -0,0 +1,21 @@
+use std::io::{BufRead, Write};
+
+use super::super::connection::GenericConnection;
+use super::super::scan::parse_page;
+use super::super::types::RuisError;
+
+// a SCAN call, returns the next cursor and the keys. the scan is over when the
+// next cursor is "0".
+pub(crate) fn scan_page<W: Write, R: BufRead>(
+    conn: &mut GenericConnection<W, R>,
+    cursor: &[u8],
+    pattern: Option<&[u8]>,
+    count: usize,
+) -> Result<(Vec<u8>, Vec<Vec<u8>>), RuisError> {
+    let count = count.to_string();
+    let mut cmd: Vec<&[u8]> = vec![b"scan", cursor, b"count", count.as_bytes()];
+    if let Some(pattern) = pattern {
+        cmd.extend_from_slice(&[b"match", pattern]);
+    }
+    parse_page(conn.execute(&cmd)?)
+}
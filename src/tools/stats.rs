@@ -0,0 +1,109 @@
+use std::time::Duration;
+
+// LatencyStats keeps all the samples, to report the exact percentiles.
+#[derive(Debug, Clone, Default)]
+pub struct LatencyStats {
+    samples: Vec<Duration>,
+    sorted: bool,
+}
+
+impl LatencyStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, d: Duration) {
+        self.samples.push(d);
+        self.sorted = false;
+    }
+
+    pub fn merge(&mut self, other: &LatencyStats) {
+        self.samples.extend_from_slice(&other.samples);
+        self.sorted = false;
+    }
+
+    pub fn count(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn min(&self) -> Option<Duration> {
+        self.samples.iter().min().copied()
+    }
+
+    pub fn max(&self) -> Option<Duration> {
+        self.samples.iter().max().copied()
+    }
+
+    pub fn mean(&self) -> Option<Duration> {
+        match self.samples.len() {
+            0 => None,
+            n => Some(self.samples.iter().sum::<Duration>() / n as u32),
+        }
+    }
+
+    // the nearest-rank percentile, p in [0, 100].
+    pub fn percentile(&mut self, p: f64) -> Option<Duration> {
+        if !self.sorted {
+            self.samples.sort();
+            self.sorted = true;
+        }
+        percentile(&self.samples, p)
+    }
+}
+
+// the figures of a set of latency samples.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LatencySummary {
+    pub samples: usize,
+    pub min: Duration,
+    pub avg: Duration,
+    pub max: Duration,
+    pub p50: Duration,
+    pub p90: Duration,
+    pub p99: Duration,
+}
+
+impl LatencyStats {
+    // None without samples.
+    pub fn summary(&mut self) -> Option<LatencySummary> {
+        Some(LatencySummary {
+            samples: self.count(),
+            min: self.min()?,
+            avg: self.mean()?,
+            max: self.max()?,
+            p50: self.percentile(50.0)?,
+            p90: self.percentile(90.0)?,
+            p99: self.percentile(99.0)?,
+        })
+    }
+}
+
+// the nearest-rank percentile of the sorted values, p in [0, 100].
+pub fn percentile<T: Copy>(sorted: &[T], p: f64) -> Option<T> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let rank = ((p / 100.0) * sorted.len() as f64).ceil() as usize;
+    Some(sorted[rank.clamp(1, sorted.len()) - 1])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_percentile() {
+        let mut stats = LatencyStats::new();
+        for ms in (1..=100).rev() {
+            stats.record(Duration::from_millis(ms));
+        }
+        assert_eq!(stats.percentile(50.0), Some(Duration::from_millis(50)));
+        assert_eq!(stats.percentile(99.0), Some(Duration::from_millis(99)));
+        assert_eq!(stats.percentile(100.0), Some(Duration::from_millis(100)));
+        assert_eq!(stats.percentile(0.0), Some(Duration::from_millis(1)));
+        assert_eq!(stats.mean(), Some(Duration::from_micros(50500)));
+        assert_eq!(LatencyStats::new().percentile(50.0), None);
+        let summary = stats.summary().unwrap();
+        assert_eq!((summary.samples, summary.p90, summary.max), (100, Duration::from_millis(90), Duration::from_millis(100)));
+    }
+}
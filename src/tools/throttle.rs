@@ -0,0 +1,54 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+// RateLimiter spaces the work out to a rate, to keep the scans of the tools
+// from hurting a busy server.
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    per_sec: Option<u64>,
+    started: Instant,
+    done: u64,
+}
+
+impl RateLimiter {
+    // None does not limit.
+    pub fn new(per_sec: Option<u64>) -> Self {
+        Self {
+            per_sec: per_sec.filter(|&n| n > 0),
+            started: Instant::now(),
+            done: 0,
+        }
+    }
+
+    // accounts n units of work done, and sleeps until the rate is back under
+    // the limit.
+    pub fn wait(&mut self, n: u64) {
+        self.done += n;
+        if let Some(per_sec) = self.per_sec {
+            let due = Duration::from_secs_f64(self.done as f64 / per_sec as f64);
+            let elapsed = self.started.elapsed();
+            if due > elapsed {
+                thread::sleep(due - elapsed);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_limiter() {
+        let t = Instant::now();
+        let mut limiter = RateLimiter::new(Some(1000));
+        for _ in 0..5 {
+            limiter.wait(10);
+        }
+        assert!(t.elapsed() >= Duration::from_millis(50));
+
+        let t = Instant::now();
+        RateLimiter::new(None).wait(1_000_000);
+        assert!(t.elapsed() < Duration::from_millis(50));
+    }
+}
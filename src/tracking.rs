@@ -0,0 +1,185 @@
+use std::io::{BufRead, Write};
+
+use super::connection::GenericConnection;
+use super::types::{RespValue, RuisError};
+
+// https://redis.io/topics/client-side-caching
+//
+// with RESP2 the invalidation messages can not be interleaved with the normal
+// replies, so they are redirected to another connection which subscribes the
+// __redis__:invalidate channel, that's what InvalidationListener does.
+
+pub const INVALIDATE_CHANNEL: &[u8] = b"__redis__:invalidate";
+
+#[derive(Debug, Clone, Default)]
+pub struct TrackingOptions {
+    redirect: Option<i64>,
+    bcast: bool,
+    prefixes: Vec<Vec<u8>>,
+    noloop: bool,
+}
+
+impl TrackingOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // the client id of the connection receiving the invalidation messages.
+    pub fn redirect(mut self, client_id: i64) -> Self {
+        self.redirect = Some(client_id);
+        self
+    }
+
+    // in BCAST mode the server does not remember the keys read by the client,
+    // instead it sends invalidations for every modified key matching one of the
+    // registered prefixes, or for every key when no prefix is registered.
+    pub fn bcast(mut self) -> Self {
+        self.bcast = true;
+        self
+    }
+
+    // registers a key prefix to be notified on, only meaningful in BCAST mode.
+    pub fn prefix(mut self, prefix: &[u8]) -> Self {
+        self.prefixes.push(prefix.to_vec());
+        self
+    }
+
+    // do not send invalidations for the keys modified by this connection itself.
+    pub fn noloop(mut self) -> Self {
+        self.noloop = true;
+        self
+    }
+
+    fn to_args(&self) -> Result<Vec<Vec<u8>>, RuisError> {
+        if !self.prefixes.is_empty() && !self.bcast {
+            return Err(RuisError::Unexpected("tracking prefixes require BCAST mode".to_string()));
+        }
+
+        let mut args: Vec<Vec<u8>> = vec![b"client".to_vec(), b"tracking".to_vec(), b"on".to_vec()];
+        if let Some(id) = self.redirect {
+            args.push(b"redirect".to_vec());
+            args.push(id.to_string().into_bytes());
+        }
+        if self.bcast {
+            args.push(b"bcast".to_vec());
+        }
+        for prefix in &self.prefixes {
+            args.push(b"prefix".to_vec());
+            args.push(prefix.clone());
+        }
+        if self.noloop {
+            args.push(b"noloop".to_vec());
+        }
+        Ok(args)
+    }
+}
+
+impl<W: Write, R: BufRead> GenericConnection<W, R> {
+    pub fn client_tracking_on(&mut self, opts: &TrackingOptions) -> Result<(), RuisError> {
+        let args = opts.to_args()?;
+        let cmd: Vec<&[u8]> = args.iter().map(|a| a.as_slice()).collect();
+        self.execute(&cmd)?.into_result()?;
+        Ok(())
+    }
+
+    pub fn client_tracking_off(&mut self) -> Result<(), RuisError> {
+        self.execute(&[b"client", b"tracking", b"off"])?.into_result()?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Invalidation {
+    Keys(Vec<Vec<u8>>),
+    // the server flushed its keyspace, every cached key is stale.
+    FlushAll,
+}
+
+pub struct InvalidationListener<W: Write, R: BufRead> {
+    conn: GenericConnection<W, R>,
+    client_id: i64,
+}
+
+impl<W: Write, R: BufRead> InvalidationListener<W, R> {
+    pub fn new(mut conn: GenericConnection<W, R>) -> Result<Self, RuisError> {
+        let client_id = conn.client_id()?;
+        match conn.execute(&[b"subscribe", INVALIDATE_CHANNEL])?.into_result()? {
+            RespValue::Array(ref arr) if arr.first() == Some(&RespValue::Bulk(b"subscribe".to_vec())) => {},
+            v => return Err(RuisError::Unexpected(format!("subscribe: {:?}", v))),
+        }
+        Ok(Self {
+            conn,
+            client_id,
+        })
+    }
+
+    // the id to pass to TrackingOptions::redirect on the tracked connections.
+    pub fn client_id(&self) -> i64 {
+        self.client_id
+    }
+
+    // blocks until the next invalidation message arrives.
+    pub fn next_invalidation(&mut self) -> Result<Invalidation, RuisError> {
+        loop {
+            let v = self.conn.receive()?.into_result()?;
+            if let Some(inv) = parse_invalidation(v)? {
+                return Ok(inv);
+            }
+        }
+    }
+}
+
+fn parse_invalidation(v: RespValue) -> Result<Option<Invalidation>, RuisError> {
+    let mut arr = match v {
+        RespValue::Array(arr) => arr,
+        v => return Err(RuisError::Unexpected(format!("invalidation: {:?}", v))),
+    };
+    if arr.len() != 3 || arr[0] != RespValue::Bulk(b"message".to_vec()) {
+        return Ok(None);
+    }
+    match arr.pop() {
+        Some(RespValue::NilBulk) | Some(RespValue::NilArray) => Ok(Some(Invalidation::FlushAll)),
+        Some(RespValue::Array(keys)) => {
+            let mut ks = vec![];
+            for key in keys {
+                match key {
+                    RespValue::Bulk(k) => ks.push(k),
+                    v => return Err(RuisError::Unexpected(format!("invalidated key: {:?}", v))),
+                }
+            }
+            Ok(Some(Invalidation::Keys(ks)))
+        },
+        v => Err(RuisError::Unexpected(format!("invalidation: {:?}", v))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use super::*;
+    use super::super::resp::{RespReader, RespWriter};
+
+    #[test]
+    fn test_tracking_args() {
+        let opts = TrackingOptions::new().redirect(42).bcast().prefix(b"user:").prefix(b"post:");
+        let args = opts.to_args().unwrap();
+        let expected: Vec<&[u8]> = vec![b"client", b"tracking", b"on", b"redirect", b"42", b"bcast", b"prefix", b"user:", b"prefix", b"post:"];
+        assert_eq!(args, expected);
+
+        let r = TrackingOptions::new().prefix(b"user:").to_args();
+        assert!(r.is_err());
+    }
+
+    #[test]
+    fn test_next_invalidation() {
+        let replies = b":7\r\n*3\r\n$9\r\nsubscribe\r\n$20\r\n__redis__:invalidate\r\n:1\r\n\
+            *3\r\n$7\r\nmessage\r\n$20\r\n__redis__:invalidate\r\n*2\r\n$6\r\nuser:1\r\n$6\r\nuser:2\r\n\
+            *3\r\n$7\r\nmessage\r\n$20\r\n__redis__:invalidate\r\n$-1\r\n";
+        let r = RespReader::new(io::Cursor::new(replies.to_vec()));
+        let w = RespWriter::new(vec![]);
+        let mut listener = InvalidationListener::new(GenericConnection::new(r, w)).unwrap();
+        assert_eq!(listener.client_id(), 7);
+        assert_eq!(listener.next_invalidation().unwrap(), Invalidation::Keys(vec![b"user:1".to_vec(), b"user:2".to_vec()]));
+        assert_eq!(listener.next_invalidation().unwrap(), Invalidation::FlushAll);
+    }
+}
@@ -0,0 +1,128 @@
+use std::io::{BufRead, Write};
+
+use super::connection::GenericConnection;
+use super::types::{RespValue, RuisError};
+
+// Transaction queues the commands to be run atomically between MULTI and
+// EXEC. the whole transaction goes out in one batch like a pipeline.
+#[derive(Debug, Clone, Default)]
+pub struct Transaction {
+    cmds: Vec<Vec<Vec<u8>>>,
+}
+
+impl Transaction {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cmd(&mut self, args: &[&[u8]]) -> &mut Self {
+        self.cmds.push(args.iter().map(|a| a.to_vec()).collect());
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.cmds.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cmds.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.cmds.clear();
+    }
+}
+
+impl<W: Write, R: BufRead> GenericConnection<W, R> {
+    // returns the replies of the commands, or None if the transaction was
+    // aborted as a watched key changed. the error replies of the commands
+    // failed on EXEC are returned in place, like on a pipeline, while a
+    // command rejected on queueing, like one with a wrong number of
+    // arguments, fails the whole transaction.
+    pub fn execute_transaction(&mut self, tx: &Transaction) -> Result<Option<Vec<RespValue>>, RuisError> {
+        let mut cmds: Vec<Vec<&[u8]>> = Vec::with_capacity(tx.len() + 2);
+        cmds.push(vec![b"multi"]);
+        cmds.extend(tx.cmds.iter().map(|args| args.iter().map(|a| a.as_slice()).collect()));
+        cmds.push(vec![b"exec"]);
+        let mut replies = self.execute_batch(&cmds)?;
+
+        let exec = replies.pop();
+        let mut it = replies.into_iter();
+        match it.next() {
+            Some(RespValue::Bulk(ref b)) if b == b"OK" => {},
+            Some(RespValue::Error(msg)) => return Err(RuisError::ServerError(String::from_utf8_lossy(&msg).into_owned())),
+            v => return Err(RuisError::Unexpected(format!("multi: {:?}", v))),
+        }
+        for (i, v) in it.enumerate() {
+            match v {
+                RespValue::Bulk(ref b) if b == b"QUEUED" => {},
+                RespValue::Error(msg) => {
+                    return Err(RuisError::ServerError(format!("{} (command #{} of the transaction)", String::from_utf8_lossy(&msg), i)));
+                },
+                v => return Err(RuisError::Unexpected(format!("queued: {:?}", v))),
+            }
+        }
+        match exec {
+            Some(RespValue::Array(items)) if items.len() == tx.len() => Ok(Some(items)),
+            Some(RespValue::NilArray) => Ok(None),
+            Some(RespValue::Error(msg)) => Err(RuisError::ServerError(String::from_utf8_lossy(&msg).into_owned())),
+            v => Err(RuisError::Unexpected(format!("exec: {:?}", v))),
+        }
+    }
+
+    // the transactions executed after watching the keys are aborted if any
+    // of the keys changes in between. EXEC unwatches all the keys.
+    pub fn watch(&mut self, keys: &[&[u8]]) -> Result<(), RuisError> {
+        let mut cmd: Vec<&[u8]> = vec![b"watch"];
+        cmd.extend_from_slice(keys);
+        self.execute(&cmd)?.into_result()?;
+        Ok(())
+    }
+
+    pub fn unwatch(&mut self) -> Result<(), RuisError> {
+        self.execute(&[b"unwatch"])?.into_result()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use super::*;
+    use super::super::resp::{RespReader, RespWriter};
+
+    fn conn(replies: &[u8]) -> GenericConnection<Vec<u8>, io::Cursor<Vec<u8>>> {
+        let r = RespReader::new(io::Cursor::new(replies.to_vec()));
+        GenericConnection::new(r, RespWriter::new(vec![]))
+    }
+
+    #[test]
+    fn test_execute_transaction() {
+        let mut tx = Transaction::new();
+        tx.cmd(&[b"set", b"n", b"1"]).cmd(&[b"incr", b"n"]).cmd(&[b"hlen", b"n"]);
+
+        let mut c = conn(b"+OK\r\n+QUEUED\r\n+QUEUED\r\n+QUEUED\r\n*3\r\n+OK\r\n:2\r\n-WRONGTYPE wrong kind\r\n");
+        let replies = c.execute_transaction(&tx).unwrap().unwrap();
+        assert_eq!(replies[1], RespValue::Int(2));
+        assert!(matches!(replies[2], RespValue::Error(_)));
+        assert_eq!(c.raw_parts().0.as_slice(), &b"*1\r\n$5\r\nmulti\r\n*3\r\n$3\r\nset\r\n$1\r\nn\r\n$1\r\n1\r\n*2\r\n$4\r\nincr\r\n$1\r\nn\r\n*2\r\n$4\r\nhlen\r\n$1\r\nn\r\n*1\r\n$4\r\nexec\r\n"[..]);
+
+        // a watched key changed.
+        let mut c = conn(b"+OK\r\n+QUEUED\r\n+QUEUED\r\n+QUEUED\r\n*-1\r\n");
+        assert_eq!(c.execute_transaction(&tx).unwrap(), None);
+
+        let mut c = conn(b"+OK\r\n+QUEUED\r\n-ERR wrong number of arguments\r\n+QUEUED\r\n-EXECABORT Transaction discarded\r\n");
+        match c.execute_transaction(&tx) {
+            Err(RuisError::ServerError(msg)) => assert!(msg.starts_with("ERR wrong number") && msg.contains("#1")),
+            r => panic!("{:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_watch() {
+        let mut c = conn(b"+OK\r\n+OK\r\n");
+        c.watch(&[b"a", b"b"]).unwrap();
+        c.unwatch().unwrap();
+        assert_eq!(c.raw_parts().0.as_slice(), &b"*3\r\n$5\r\nwatch\r\n$1\r\na\r\n$1\r\nb\r\n*1\r\n$7\r\nunwatch\r\n"[..]);
+    }
+}
@@ -1,6 +1,9 @@
-use std;
+use bytes::Bytes;
 
-#[derive(Eq,PartialEq)]
+use super::cluster::SLOT_COUNT;
+use super::convert::ConversionError;
+
+#[derive(Eq,PartialEq,Clone)]
 pub enum RespValue {
     Int(i64),
     NilBulk,
@@ -32,33 +35,265 @@ impl std::fmt::Debug for RespValue {
     }
 }
 
+impl RespValue {
+    // turns an error reply into Err, so callers expecting a normal reply can
+    // propagate the server errors with `?`.
+    pub fn into_result(self) -> Result<RespValue, RuisError> {
+        match self {
+            RespValue::Error(bs) => Err(RuisError::ServerError(String::from_utf8_lossy(&bs).to_string())),
+            v => Ok(v),
+        }
+    }
+
+    pub fn error_kind(&self) -> Option<ErrorKind> {
+        match self {
+            RespValue::Error(msg) => Some(ErrorKind::parse(msg)),
+            _ => None,
+        }
+    }
+}
+
+// RespFrame is a reply read by RespReader::read_frame(), its bulks and
+// errors are the slices of a single buffer holding the whole reply, instead
+// of a Vec each, which saves an allocation and a copy per bulk on the large
+// replies like of MGET or LRANGE.
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum RespFrame {
+    Int(i64),
+    NilBulk,
+    NilArray,
+    Bulk(Bytes),
+    Array(Vec<RespFrame>),
+    Error(Bytes),
+}
+
+impl RespFrame {
+    pub fn into_result(self) -> Result<RespFrame, RuisError> {
+        match self {
+            RespFrame::Error(bs) => Err(RuisError::ServerError(String::from_utf8_lossy(&bs).to_string())),
+            v => Ok(v),
+        }
+    }
+}
+
+// copies the bulks out of the shared buffer.
+impl From<RespFrame> for RespValue {
+    fn from(frame: RespFrame) -> RespValue {
+        match frame {
+            RespFrame::Int(n) => RespValue::Int(n),
+            RespFrame::NilBulk => RespValue::NilBulk,
+            RespFrame::NilArray => RespValue::NilArray,
+            RespFrame::Bulk(bs) => RespValue::Bulk(bs.to_vec()),
+            RespFrame::Array(arr) => RespValue::Array(arr.into_iter().map(RespValue::from).collect()),
+            RespFrame::Error(bs) => RespValue::Error(bs.to_vec()),
+        }
+    }
+}
+
+// the kind of an error reply, told by the code before the first space like
+// "WRONGTYPE Operation against a key holding the wrong kind of value".
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ErrorKind {
+    WrongType,
+    // the addr might be ":port" for the nodes which do not know their own ip.
+    Moved { slot: u16, addr: String },
+    Ask { slot: u16, addr: String },
+    // a script or a function is running too long.
+    Busy,
+    NoScript,
+    // the dataset is being loaded into memory.
+    Loading,
+    ClusterDown,
+    // the keys of a multi-key command are being migrated.
+    TryAgain,
+    // a write is sent to a replica.
+    ReadOnly,
+    OutOfMemory,
+    NoAuth,
+    // the username or the password is rejected.
+    WrongPass,
+    NoPerm,
+    // RESTORE onto a key existing.
+    BusyKey,
+    // XGROUP CREATE of a consumer group existing.
+    BusyGroup,
+    // the stream or the consumer group does not exist.
+    NoGroup,
+    // EXEC of a transaction with a command rejected when queued.
+    ExecAbort,
+    // the code and the rest of the message.
+    Other(String, String),
+}
+
+impl ErrorKind {
+    pub fn parse(msg: &[u8]) -> ErrorKind {
+        let msg = String::from_utf8_lossy(msg);
+        let (code, rest) = msg.split_once(' ').unwrap_or((&msg, ""));
+        match code {
+            "WRONGTYPE" => ErrorKind::WrongType,
+            "MOVED" | "ASK" => match parse_redirect(rest) {
+                Some((slot, addr)) if code == "MOVED" => ErrorKind::Moved { slot, addr },
+                Some((slot, addr)) => ErrorKind::Ask { slot, addr },
+                None => ErrorKind::Other(code.to_string(), rest.to_string()),
+            },
+            "BUSY" => ErrorKind::Busy,
+            "NOSCRIPT" => ErrorKind::NoScript,
+            "LOADING" => ErrorKind::Loading,
+            "CLUSTERDOWN" => ErrorKind::ClusterDown,
+            "TRYAGAIN" => ErrorKind::TryAgain,
+            "READONLY" => ErrorKind::ReadOnly,
+            "OOM" => ErrorKind::OutOfMemory,
+            "NOAUTH" => ErrorKind::NoAuth,
+            "WRONGPASS" => ErrorKind::WrongPass,
+            "NOPERM" => ErrorKind::NoPerm,
+            "BUSYKEY" => ErrorKind::BusyKey,
+            "BUSYGROUP" => ErrorKind::BusyGroup,
+            "NOGROUP" => ErrorKind::NoGroup,
+            "EXECABORT" => ErrorKind::ExecAbort,
+            _ => ErrorKind::Other(code.to_string(), rest.to_string()),
+        }
+    }
+}
+
+// "3999 127.0.0.1:6381", None for a slot out of range.
+fn parse_redirect(s: &str) -> Option<(u16, String)> {
+    let (slot, addr) = s.split_once(' ')?;
+    let slot: u16 = slot.parse().ok()?;
+    if slot as usize >= SLOT_COUNT {
+        return None;
+    }
+    Some((slot, addr.to_string()))
+}
+
+// RuisError is returned by all the APIs of the crate.
 #[derive(Debug)]
-pub enum RespError {
+pub enum RuisError {
     IoError(std::io::Error),
+    // the bytes received are not valid RESP.
     ParseFailed(String),
+    // the reply is over the RespLimits, like a bulk too large.
+    LimitExceeded(String),
+    // the reply is valid RESP, but not of the shape expected by the command.
     Unexpected(String),
+    // an error reply, see kind() for the typed ErrorKind.
+    ServerError(String),
+    CodecError(String),
+    // the reply can not be converted into the rust type asked for.
+    Conversion(ConversionError),
+    // the server rejected the credentials on connect.
+    Auth(String),
+    // the cluster can not serve the command, like when no node serves the slot
+    // or the redirects do not settle.
+    Cluster(String),
+    // the keys of a command hash to different slots in cluster mode, keys are
+    // the ones outside the slot of the first key.
+    CrossSlot { slots: Vec<u16>, keys: Vec<Vec<u8>> },
     Unknown
 }
 
-impl From<std::io::Error> for RespError {
+#[deprecated(note = "renamed to RuisError")]
+pub type RespError = RuisError;
+
+impl RuisError {
+    // the connection is closed or reset by the peer, it has to be reconnected.
+    pub fn is_connection_dropped(&self) -> bool {
+        use std::io::ErrorKind as Io;
+        match self {
+            RuisError::IoError(ref err) => matches!(err.kind(), Io::UnexpectedEof | Io::ConnectionReset | Io::ConnectionAborted | Io::BrokenPipe | Io::NotConnected),
+            _ => false,
+        }
+    }
+
+    // the connection is out of sync after the error, like on a reply over
+    // the limits or not parsed whole, its unread bytes would be read as the
+    // reply of the next command. the connection has to be dropped.
+    pub fn leaves_stream_unusable(&self) -> bool {
+        matches!(self, RuisError::IoError(_) | RuisError::ParseFailed(_) | RuisError::LimitExceeded(_))
+    }
+
+    // the read or the write timed out, the reply might still arrive later.
+    pub fn is_timeout(&self) -> bool {
+        match self {
+            RuisError::IoError(ref err) => matches!(err.kind(), std::io::ErrorKind::TimedOut | std::io::ErrorKind::WouldBlock),
+            _ => false,
+        }
+    }
+
+    // the kind of the server errors.
+    pub fn kind(&self) -> Option<ErrorKind> {
+        match self {
+            RuisError::ServerError(msg) => Some(ErrorKind::parse(msg.as_bytes())),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for RuisError {
     fn from(err: std::io::Error) -> Self {
-        RespError::IoError(err)
+        RuisError::IoError(err)
     }
 }
 
-impl std::fmt::Display for RespError {
+impl std::fmt::Display for RuisError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            RespError::IoError(ref err) => write!(f, "io err: {}", err),
-            RespError::ParseFailed(ref s) => write!(f, "parse failed: {}", s),
-            RespError::Unexpected(ref s) => write!(f, "unexpected: {}", s),
-            RespError::Unknown => write!(f, "unknown error"),
+            RuisError::IoError(ref err) => write!(f, "io err: {}", err),
+            RuisError::ParseFailed(ref s) => write!(f, "parse failed: {}", s),
+            RuisError::LimitExceeded(ref s) => write!(f, "limit exceeded: {}", s),
+            RuisError::Unexpected(ref s) => write!(f, "unexpected: {}", s),
+            RuisError::ServerError(ref s) => write!(f, "server err: {}", s),
+            RuisError::CodecError(ref s) => write!(f, "codec err: {}", s),
+            RuisError::Conversion(ref e) => write!(f, "conversion err: {}", e),
+            RuisError::Auth(ref s) => write!(f, "auth err: {}", s),
+            RuisError::Cluster(ref s) => write!(f, "cluster err: {}", s),
+            RuisError::CrossSlot { ref slots, ref keys } => {
+                let keys: Vec<_> = keys.iter().map(|k| String::from_utf8_lossy(k)).collect();
+                write!(f, "cross slot err: keys span slots {:?}, not in the slot of the first key: {}", slots, keys.join(", "))
+            },
+            RuisError::Unknown => write!(f, "unknown error"),
+        }
+    }
+}
+
+impl std::error::Error for RuisError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RuisError::IoError(ref err) => Some(err),
+            _ => None,
         }
     }
 }
 
-impl std::error::Error for RespError {
-    fn description(&self) -> &str {
-        "resp error"
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_kind() {
+        assert_eq!(ErrorKind::parse(b"WRONGTYPE Operation against a key holding the wrong kind of value"), ErrorKind::WrongType);
+        assert_eq!(ErrorKind::parse(b"MOVED 3999 127.0.0.1:6381"), ErrorKind::Moved { slot: 3999, addr: "127.0.0.1:6381".to_string() });
+        assert_eq!(ErrorKind::parse(b"ASK 3999 :6381"), ErrorKind::Ask { slot: 3999, addr: ":6381".to_string() });
+        assert_eq!(ErrorKind::parse(b"OOM command not allowed when used memory > 'maxmemory'."), ErrorKind::OutOfMemory);
+        assert_eq!(ErrorKind::parse(b"NOAUTH Authentication required."), ErrorKind::NoAuth);
+        assert_eq!(ErrorKind::parse(b"BUSYGROUP Consumer Group name already exists"), ErrorKind::BusyGroup);
+        assert_eq!(ErrorKind::parse(b"NOGROUP No such key 's' or consumer group 'g' in XREADGROUP with GROUP option"), ErrorKind::NoGroup);
+        assert_eq!(ErrorKind::parse(b"ERR unknown command"), ErrorKind::Other("ERR".to_string(), "unknown command".to_string()));
+        assert_eq!(ErrorKind::parse(b"MOVED bad"), ErrorKind::Other("MOVED".to_string(), "bad".to_string()));
+        assert_eq!(ErrorKind::parse(b"MOVED 16384 127.0.0.1:6381"), ErrorKind::Other("MOVED".to_string(), "16384 127.0.0.1:6381".to_string()));
+
+        assert_eq!(RespValue::Error(b"LOADING Redis is loading the dataset in memory".to_vec()).error_kind(), Some(ErrorKind::Loading));
+        assert_eq!(RespValue::Int(1).error_kind(), None);
+        let err = RespValue::Error(b"NOSCRIPT No matching script.".to_vec()).into_result().unwrap_err();
+        assert_eq!(err.kind(), Some(ErrorKind::NoScript));
+    }
+
+    #[test]
+    fn test_source() {
+        use std::error::Error;
+        let err = RuisError::from(std::io::Error::new(std::io::ErrorKind::TimedOut, "timed out"));
+        assert!(err.is_timeout());
+        assert!(!err.is_connection_dropped());
+        assert_eq!(err.source().unwrap().to_string(), "timed out");
+        assert!(RuisError::Unexpected("x".to_string()).source().is_none());
     }
 }
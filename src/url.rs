@@ -0,0 +1,165 @@
+use std::fmt;
+use std::io;
+use std::str::FromStr;
+
+use super::connection::redacted;
+use super::types::RuisError;
+
+const DEFAULT_HOST: &str = "127.0.0.1";
+const DEFAULT_PORT: u16 = 6379;
+
+// ConnectionInfo is what a connection url tells:
+//
+//   redis://[[username]:password@]host[:port][/db]
+//   rediss://[[username]:password@]host[:port][/db]
+//
+// rediss is for TLS. the username and the password are percent-decoded, the
+// port defaults to 6379 and the db to 0.
+#[derive(Clone, PartialEq, Eq)]
+pub struct ConnectionInfo {
+    // host:port, the IPv6 hosts in brackets.
+    pub addr: String,
+    pub db: i64,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub tls: bool,
+}
+
+impl fmt::Debug for ConnectionInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ConnectionInfo")
+            .field("addr", &self.addr)
+            .field("db", &self.db)
+            .field("username", &self.username)
+            .field("password", &redacted(&self.password))
+            .field("tls", &self.tls)
+            .finish()
+    }
+}
+
+fn invalid_url(url: &str, why: &str) -> RuisError {
+    // the url is not echoed, as it might hold the password.
+    let scheme = url.split("://").next().unwrap_or_default();
+    io::Error::new(io::ErrorKind::InvalidInput, format!("invalid {}:// url: {}", scheme, why)).into()
+}
+
+fn percent_decode(s: &str) -> Option<String> {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = std::str::from_utf8(bytes.get(i + 1..i + 3)?).ok()?;
+            out.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(out).ok()
+}
+
+impl ConnectionInfo {
+    pub fn parse(url: &str) -> Result<ConnectionInfo, RuisError> {
+        let (tls, rest) = match url.split_once("://") {
+            Some(("redis", rest)) => (false, rest),
+            Some(("rediss", rest)) => (true, rest),
+            _ => return Err(invalid_url(url, "the scheme is not redis or rediss")),
+        };
+        let (authority, path) = match rest.split_once('/') {
+            Some((authority, path)) => (authority, path),
+            None => (rest, ""),
+        };
+        let (userinfo, hostport) = match authority.rsplit_once('@') {
+            Some((userinfo, hostport)) => (Some(userinfo), hostport),
+            None => (None, authority),
+        };
+
+        let (mut username, mut password) = (None, None);
+        if let Some(userinfo) = userinfo {
+            let (user, pass) = match userinfo.split_once(':') {
+                Some((user, pass)) => (user, Some(pass)),
+                None => (userinfo, None),
+            };
+            let decode = |s: &str| percent_decode(s).ok_or_else(|| invalid_url(url, "bad percent-encoding in the credentials"));
+            if !user.is_empty() {
+                username = Some(decode(user)?);
+            }
+            password = pass.map(decode).transpose()?;
+        }
+
+        // the port follows the last colon, unless it's inside the brackets
+        // of an IPv6 host.
+        let (host, port) = match hostport.rsplit_once(':') {
+            Some((host, port)) if !port.contains(']') => {
+                let port = port.parse::<u16>().map_err(|_| invalid_url(url, "bad port"))?;
+                (host, port)
+            },
+            _ => (hostport, DEFAULT_PORT),
+        };
+        let host = if host.is_empty() { DEFAULT_HOST } else { host };
+
+        let db = match path {
+            "" => 0,
+            path => path.parse::<i64>().ok().filter(|&db| db >= 0).ok_or_else(|| invalid_url(url, "the path is not a db index"))?,
+        };
+
+        Ok(ConnectionInfo {
+            addr: format!("{}:{}", host, port),
+            db,
+            username,
+            password,
+            tls,
+        })
+    }
+
+    // the host of addr without the port nor the brackets, like the name
+    // checked against the certificate with rediss.
+    pub fn host(&self) -> &str {
+        let host = self.addr.rsplit_once(':').map_or(self.addr.as_str(), |(host, _)| host);
+        host.trim_start_matches('[').trim_end_matches(']')
+    }
+}
+
+impl FromStr for ConnectionInfo {
+    type Err = RuisError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ConnectionInfo::parse(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let info = ConnectionInfo::parse("redis://:p%40ss@redis.local:6380/2").unwrap();
+        assert_eq!(info, ConnectionInfo {
+            addr: "redis.local:6380".to_string(),
+            db: 2,
+            username: None,
+            password: Some("p@ss".to_string()),
+            tls: false,
+        });
+        assert!(!format!("{:?}", info).contains("p@ss"));
+
+        let info: ConnectionInfo = "rediss://app:secret@[::1]".parse().unwrap();
+        assert_eq!((info.addr.as_str(), info.db, info.username.as_deref(), info.tls), ("[::1]:6379", 0, Some("app"), true));
+        assert_eq!(info.host(), "::1");
+        assert_eq!(ConnectionInfo::parse("rediss://cache.example.com:6380").unwrap().host(), "cache.example.com");
+        assert_eq!(ConnectionInfo::parse("redis://").unwrap().addr, "127.0.0.1:6379");
+        assert_eq!(ConnectionInfo::parse("redis://[::1]:7000/").unwrap().addr, "[::1]:7000");
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        for url in &["http://host", "host:6379", "redis://host:port", "redis://host/db", "redis://host/-1", "redis://:%zz@host"] {
+            assert!(ConnectionInfo::parse(url).is_err(), "{}", url);
+        }
+        let err = ConnectionInfo::parse("redis://:hunter2@host:x").unwrap_err();
+        assert!(!err.to_string().contains("hunter2"));
+    }
+}
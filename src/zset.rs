@@ -0,0 +1,171 @@
+use std::io::{BufRead, Write};
+
+use super::connection::GenericConnection;
+use super::convert::FromResp;
+use super::types::{RespValue, RuisError};
+
+// a bound of ZRANGEBYSCORE, ZCOUNT and the like, encoded as "1.5", "(1.5",
+// "-inf" or "+inf".
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScoreBound {
+    Inclusive(f64),
+    Exclusive(f64),
+    NegInf,
+    PosInf,
+}
+
+impl ScoreBound {
+    fn to_arg(self) -> String {
+        match self {
+            ScoreBound::Inclusive(score) => score_arg(score),
+            ScoreBound::Exclusive(score) if score.is_infinite() => score_arg(score),
+            ScoreBound::Exclusive(score) => format!("({}", score),
+            ScoreBound::NegInf => "-inf".to_string(),
+            ScoreBound::PosInf => "+inf".to_string(),
+        }
+    }
+}
+
+impl From<f64> for ScoreBound {
+    fn from(score: f64) -> Self {
+        ScoreBound::Inclusive(score)
+    }
+}
+
+// the members with their scores, in the order of the reply.
+pub type ScoredMembers = Vec<(Vec<u8>, f64)>;
+
+// rust prints the infinities as "inf", the "+inf" and "-inf" of the redis
+// docs are sent instead.
+fn score_arg(score: f64) -> String {
+    if score == f64::INFINITY {
+        "+inf".to_string()
+    } else if score == f64::NEG_INFINITY {
+        "-inf".to_string()
+    } else {
+        score.to_string()
+    }
+}
+
+// the replies WITHSCORES are flat member-score lists on RESP2.
+fn scored(reply: RespValue) -> Result<ScoredMembers, RuisError> {
+    let items: Vec<RespValue> = FromResp::from_resp(reply)?;
+    if !items.len().is_multiple_of(2) {
+        return Err(RuisError::Unexpected(format!("withscores: odd number of items, {}", items.len())));
+    }
+    let mut members = Vec::with_capacity(items.len() / 2);
+    let mut it = items.into_iter();
+    while let (Some(member), Some(score)) = (it.next(), it.next()) {
+        members.push((FromResp::from_resp(member)?, FromResp::from_resp(score)?));
+    }
+    Ok(members)
+}
+
+impl<W: Write, R: BufRead> GenericConnection<W, R> {
+    // returns the number of the members added, not counting the ones whose
+    // score is updated.
+    pub fn zadd(&mut self, key: &[u8], members: &[(f64, &[u8])]) -> Result<i64, RuisError> {
+        let scores: Vec<String> = members.iter().map(|(score, _)| score_arg(*score)).collect();
+        let mut cmd: Vec<&[u8]> = vec![b"zadd", key];
+        for (score, (_, member)) in scores.iter().zip(members) {
+            cmd.extend_from_slice(&[score.as_bytes(), member]);
+        }
+        self.execute_as(&cmd)
+    }
+
+    pub fn zscore(&mut self, key: &[u8], member: &[u8]) -> Result<Option<f64>, RuisError> {
+        self.execute_as(&[b"zscore", key, member])
+    }
+
+    // the members by their rank, the negative ranks count from the end.
+    pub fn zrange(&mut self, key: &[u8], start: i64, stop: i64) -> Result<Vec<Vec<u8>>, RuisError> {
+        let (start, stop) = (start.to_string(), stop.to_string());
+        self.execute_as(&[b"zrange", key, start.as_bytes(), stop.as_bytes()])
+    }
+
+    pub fn zrange_withscores(&mut self, key: &[u8], start: i64, stop: i64) -> Result<ScoredMembers, RuisError> {
+        let (start, stop) = (start.to_string(), stop.to_string());
+        scored(self.execute(&[b"zrange", key, start.as_bytes(), stop.as_bytes(), b"withscores"])?)
+    }
+
+    // limit is the offset and the count of the members returned.
+    pub fn zrangebyscore<B: Into<ScoreBound>>(&mut self, key: &[u8], min: B, max: B, limit: Option<(usize, usize)>) -> Result<Vec<Vec<u8>>, RuisError> {
+        let reply = self.zrangebyscore_cmd(key, min.into(), max.into(), limit, false)?;
+        FromResp::from_resp(reply)
+    }
+
+    pub fn zrangebyscore_withscores<B: Into<ScoreBound>>(&mut self, key: &[u8], min: B, max: B, limit: Option<(usize, usize)>) -> Result<ScoredMembers, RuisError> {
+        scored(self.zrangebyscore_cmd(key, min.into(), max.into(), limit, true)?)
+    }
+
+    // pops the members with the lowest scores, one without a count.
+    pub fn zpopmin(&mut self, key: &[u8], count: Option<usize>) -> Result<ScoredMembers, RuisError> {
+        self.zpop(b"zpopmin", key, count)
+    }
+
+    pub fn zpopmax(&mut self, key: &[u8], count: Option<usize>) -> Result<ScoredMembers, RuisError> {
+        self.zpop(b"zpopmax", key, count)
+    }
+
+    fn zpop(&mut self, name: &[u8], key: &[u8], count: Option<usize>) -> Result<ScoredMembers, RuisError> {
+        let count = count.map(|n| n.to_string());
+        let mut cmd: Vec<&[u8]> = vec![name, key];
+        if let Some(count) = &count {
+            cmd.push(count.as_bytes());
+        }
+        scored(self.execute(&cmd)?)
+    }
+
+    fn zrangebyscore_cmd(&mut self, key: &[u8], min: ScoreBound, max: ScoreBound, limit: Option<(usize, usize)>, withscores: bool) -> Result<RespValue, RuisError> {
+        let (min, max) = (min.to_arg(), max.to_arg());
+        let limit = limit.map(|(offset, count)| (offset.to_string(), count.to_string()));
+        let mut cmd: Vec<&[u8]> = vec![b"zrangebyscore", key, min.as_bytes(), max.as_bytes()];
+        if withscores {
+            cmd.push(b"withscores");
+        }
+        if let Some((offset, count)) = &limit {
+            cmd.extend_from_slice(&[b"limit", offset.as_bytes(), count.as_bytes()]);
+        }
+        self.execute(&cmd)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::testing::Mock;
+
+    fn bulk(s: &str) -> RespValue {
+        RespValue::Bulk(s.as_bytes().to_vec())
+    }
+
+    #[test]
+    fn test_zadd_zrange() {
+        let mock = Mock::new();
+        mock.expect(&[b"zadd", b"z", b"1.5", b"a", b"+inf", b"b"], RespValue::Int(2))
+            .expect(&[b"zrange", b"z", b"0", b"-1", b"withscores"], RespValue::Array(vec![bulk("a"), bulk("1.5"), bulk("b"), bulk("inf")]))
+            .expect(&[b"zscore", b"z", b"c"], bulk("-inf"))
+            .expect(&[b"zscore", b"z", b"d"], RespValue::NilBulk);
+        let mut c = mock.connection();
+        assert_eq!(c.zadd(b"z", &[(1.5, b"a"), (f64::INFINITY, b"b")]).unwrap(), 2);
+        assert_eq!(c.zrange_withscores(b"z", 0, -1).unwrap(), vec![(b"a".to_vec(), 1.5), (b"b".to_vec(), f64::INFINITY)]);
+        assert_eq!(c.zscore(b"z", b"c").unwrap(), Some(f64::NEG_INFINITY));
+        assert_eq!(c.zscore(b"z", b"d").unwrap(), None);
+        mock.assert_done();
+    }
+
+    #[test]
+    fn test_zrangebyscore() {
+        let mock = Mock::new();
+        mock.expect(&[b"zrangebyscore", b"z", b"-inf", b"(2"], RespValue::Array(vec![bulk("a")]))
+            .expect(&[b"zrangebyscore", b"z", b"2", b"3.5", b"withscores", b"limit", b"0", b"10"], RespValue::Array(vec![bulk("b"), bulk("2")]))
+            .expect(&[b"zpopmin", b"z", b"2"], RespValue::Array(vec![bulk("a"), bulk("1"), bulk("b")]))
+            .expect(&[b"zpopmax", b"z"], RespValue::Array(vec![]));
+        let mut c = mock.connection();
+        assert_eq!(c.zrangebyscore(b"z", ScoreBound::NegInf, ScoreBound::Exclusive(2.0), None).unwrap(), vec![b"a".to_vec()]);
+        assert_eq!(c.zrangebyscore_withscores(b"z", 2.0, 3.5, Some((0, 10))).unwrap(), vec![(b"b".to_vec(), 2.0)]);
+        assert!(c.zpopmin(b"z", Some(2)).is_err());
+        assert_eq!(c.zpopmax(b"z", None).unwrap(), vec![]);
+        mock.assert_done();
+    }
+}